@@ -99,6 +99,34 @@ TEST_CASE("line_sender c api basics")
     CHECK(server.msgs().front() == "test,t1=v1 f1=0.5 10000000\n");
 }
 
+TEST_CASE("line_sender c api protocol version")
+{
+    ::line_sender_error* err = nullptr;
+    on_scope_exit error_free_guard{[&]{
+            if (err)
+                ::line_sender_error_free(err);
+        }};
+    ::line_sender_buffer* buffer = ::line_sender_buffer_with_protocol_version(
+        ::line_sender_protocol_version_2);
+    CHECK(buffer != nullptr);
+    on_scope_exit buffer_free_guard{[&]{
+            ::line_sender_buffer_free(buffer);
+        }};
+    ::line_sender_table_name table_name{0, nullptr};
+    CHECK(::line_sender_table_name_init(&table_name, 4, "test", &err));
+    ::line_sender_column_name ts1_name{0, nullptr};
+    CHECK(::line_sender_column_name_init(&ts1_name, 3, "ts1", &err));
+    ::line_sender_column_name ts2_name{0, nullptr};
+    CHECK(::line_sender_column_name_init(&ts2_name, 3, "ts2", &err));
+    CHECK(::line_sender_buffer_table(buffer, table_name, &err));
+    CHECK(::line_sender_buffer_column_ts_micros(buffer, ts1_name, 12345, &err));
+    CHECK(::line_sender_buffer_column_ts_nanos(buffer, ts2_name, 12345678, &err));
+    CHECK(::line_sender_buffer_at_nanos(buffer, 10000000, &err));
+    size_t len = 0;
+    const char* buf = ::line_sender_buffer_peek(buffer, &len);
+    CHECK(std::string{buf, len} == "test ts1=12345t,ts2=12345678n 10000000\n");
+}
+
 TEST_CASE("Opts service API tests") {
     // We just check these compile and link.
 
@@ -215,10 +243,10 @@ TEST_CASE("State machine testing -- flush without data.")
 
     questdb::ingress::line_sender_buffer buffer;
     CHECK(buffer.size() == 0);
-    CHECK_THROWS_WITH_AS(
-        sender.flush(buffer),
-        "State error: Bad call to `flush`, should have called `table` instead.",
-        questdb::ingress::line_sender_error);
+
+    // Flushing an empty buffer is a no-op.
+    sender.flush(buffer);
+    CHECK(buffer.size() == 0);
     CHECK(!sender.must_close());
     sender.close();
 }
@@ -332,7 +360,7 @@ TEST_CASE("Validation of bad chars in key names.")
         CHECK_THROWS_WITH_AS(
             questdb::ingress::column_name_view{column_name},
             "Bad string \"a\\0b\": Column names "
-            "can't contain a '\\0' character, "
+            "can't contain the control character U+0000, "
             "which was found at byte position 1.",
             questdb::ingress::line_sender_error);
     }
@@ -717,6 +745,38 @@ TEST_CASE("Test timestamp column.")
     CHECK(server.msgs()[0] == exp);
 }
 
+TEST_CASE("Test timestamp column with protocol version 2.")
+{
+    questdb::ingress::test::mock_server server;
+    questdb::ingress::line_sender sender{
+        questdb::ingress::protocol::tcp,
+        "localhost",
+        server.port()};
+
+    questdb::ingress::line_sender_buffer buffer{
+        questdb::ingress::protocol_version::v2};
+    buffer
+        .table("test")
+        .column("ts1", questdb::ingress::timestamp_micros{12345})
+        .column("ts2", questdb::ingress::timestamp_nanos{12345678})
+        .at(questdb::ingress::timestamp_nanos{10000000});
+
+    const std::string exp{"test ts1=12345t,ts2=12345678n 10000000\n"};
+    CHECK(buffer.peek() == exp);
+
+    questdb::ingress::line_sender_buffer copy{buffer};
+    CHECK(copy.peek() == exp);
+
+    sender.flush(buffer);
+    CHECK(buffer.size() == 0);
+
+    server.accept();
+    sender.close();
+
+    CHECK(server.recv() == 1);
+    CHECK(server.msgs()[0] == exp);
+}
+
 TEST_CASE("test timestamp_micros and timestamp_nanos::now()") {
     // Explicit in tests, just to be sure we haven't messed up the return types :-)
     questdb::ingress::timestamp_micros micros_now{questdb::ingress::timestamp_micros::now()};
@@ -802,14 +862,9 @@ TEST_CASE("Empty Buffer") {
 
     questdb::ingress::test::mock_server server;
     questdb::ingress::line_sender sender{questdb::ingress::protocol::tcp, "localhost", server.port()};
-    CHECK_THROWS_WITH_AS(
-        sender.flush(b1),
-        "State error: Bad call to `flush`, should have called `table` instead.",
-        questdb::ingress::line_sender_error);
-    CHECK_THROWS_WITH_AS(
-        sender.flush_and_keep(b1),
-        "State error: Bad call to `flush`, should have called `table` instead.",
-        questdb::ingress::line_sender_error);
+    sender.flush(b1);
+    sender.flush_and_keep(b1);
+    CHECK(b1.size() == 0);
 }
 
 TEST_CASE("Opts from conf") {
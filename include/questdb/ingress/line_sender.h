@@ -47,37 +47,55 @@ typedef struct line_sender_error line_sender_error;
 typedef enum line_sender_error_code
 {
     /** The host, port, or interface was incorrect. */
-    line_sender_error_could_not_resolve_addr,
+    line_sender_error_could_not_resolve_addr = 0,
 
     /** Called methods in the wrong order. E.g. `symbol` after `column`. */
-    line_sender_error_invalid_api_call,
+    line_sender_error_invalid_api_call = 1,
 
     /** A network error connecting or flushing data out. */
-    line_sender_error_socket_error,
+    line_sender_error_socket_error = 2,
 
     /** The string or symbol field is not encoded in valid UTF-8. */
-    line_sender_error_invalid_utf8,
+    line_sender_error_invalid_utf8 = 3,
 
     /** The table name or column name contains bad characters. */
-    line_sender_error_invalid_name,
+    line_sender_error_invalid_name = 4,
 
     /** The supplied timestamp is invalid. */
-    line_sender_error_invalid_timestamp,
+    line_sender_error_invalid_timestamp = 5,
 
     /** Error during the authentication process. */
-    line_sender_error_auth_error,
+    line_sender_error_auth_error = 6,
 
     /** Error during TLS handshake. */
-    line_sender_error_tls_error,
+    line_sender_error_tls_error = 7,
 
     /** The server does not support ILP over HTTP. */
-    line_sender_error_http_not_supported,
+    line_sender_error_http_not_supported = 8,
 
     /** Error sent back from the server during flush. */
-    line_sender_error_server_flush_error,
+    line_sender_error_server_flush_error = 9,
 
     /** Bad configuration. */
-    line_sender_error_config_error,
+    line_sender_error_config_error = 10,
+
+    /** Completing a row would grow the buffer past its maximum size. */
+    line_sender_error_buffer_too_large = 11,
+
+    /** Writing to a file or other output failed. */
+    line_sender_error_io_error = 12,
+
+    /** Reading or writing a spill file failed. */
+    line_sender_error_spill_error = 13,
+
+    /** The ILP passed to the decoder is malformed. */
+    line_sender_error_malformed_ilp = 14,
+
+    /** A symbol or string value contains a control character. */
+    line_sender_error_invalid_value = 15,
+
+    /** A sender worker's queue is full. */
+    line_sender_error_queue_full = 16,
 } line_sender_error_code;
 
 /** The protocol used to connect with. */
@@ -96,6 +114,16 @@ typedef enum line_sender_protocol
     line_sender_protocol_https,
 } line_sender_protocol;
 
+/** The version of the InfluxDB Line Protocol (ILP) encoding a buffer uses. */
+typedef enum line_sender_protocol_version
+{
+    /** The original encoding: Timestamp columns are sent in microseconds. */
+    line_sender_protocol_version_1 = 1,
+
+    /** Timestamp columns given in nanoseconds are sent at full precision. */
+    line_sender_protocol_version_2 = 2,
+} line_sender_protocol_version;
+
 /** Possible sources of the root certificates used to validate the server's TLS certificate. */
 typedef enum line_sender_ca {
     /** Use the set of root certificates provided by the `webpki` crate. */
@@ -287,6 +315,16 @@ line_sender_buffer* line_sender_buffer_new();
 LINESENDER_API
 line_sender_buffer* line_sender_buffer_with_max_name_len(size_t max_name_len);
 
+/**
+ * Construct a `line_sender_buffer` that encodes rows for the given ILP
+ * protocol version, with a `max_name_len` of `127`.
+ * `line_sender_buffer_new()` encodes rows for version 1, which all QuestDB
+ * servers understand.
+ */
+LINESENDER_API
+line_sender_buffer* line_sender_buffer_with_protocol_version(
+    line_sender_protocol_version version);
+
 /** Release the `line_sender_buffer` object. */
 LINESENDER_API
 void line_sender_buffer_free(line_sender_buffer* buffer);
@@ -903,6 +941,10 @@ void line_sender_close(line_sender* sender);
  * server. In the case of an error, the server will quietly disconnect: consult the
  * server logs for error messages.
  *
+ * A row left incomplete, without a call to `line_sender_buffer_at_nanos()` or
+ * similar, fails the flush and is discarded. The complete rows before it stay
+ * in the buffer.
+ *
  * HTTP should be the first choice, but use TCP if you need to continuously send
  * data to the server at a high rate.
  *
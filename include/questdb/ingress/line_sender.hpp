@@ -46,37 +46,55 @@ namespace questdb::ingress
     enum class line_sender_error_code
     {
         /** The host, port, or interface was incorrect. */
-        could_not_resolve_addr,
+        could_not_resolve_addr = 0,
 
         /** Called methods in the wrong order. E.g. `symbol` after `column`. */
-        invalid_api_call,
+        invalid_api_call = 1,
 
         /** A network error connecting or flushing data out. */
-        socket_error,
+        socket_error = 2,
 
         /** The string or symbol field is not encoded in valid UTF-8. */
-        invalid_utf8,
+        invalid_utf8 = 3,
 
         /** The table name or column name contains bad characters. */
-        invalid_name,
+        invalid_name = 4,
 
         /** The supplied timestamp is invalid. */
-        invalid_timestamp,
+        invalid_timestamp = 5,
 
         /** Error during the authentication process. */
-        auth_error,
+        auth_error = 6,
 
         /** Error during TLS handshake. */
-        tls_error,
+        tls_error = 7,
 
         /** The server does not support ILP over HTTP. */
-        http_not_supported,
+        http_not_supported = 8,
 
         /** Error sent back from the server during flush. */
-        server_flush_error,
+        server_flush_error = 9,
 
         /** Bad configuration. */
-        config_error,
+        config_error = 10,
+
+        /** Completing a row would grow the buffer past its maximum size. */
+        buffer_too_large = 11,
+
+        /** Writing to a file or other output failed. */
+        io_error = 12,
+
+        /** Reading or writing a spill file failed. */
+        spill_error = 13,
+
+        /** The ILP passed to the decoder is malformed. */
+        malformed_ilp = 14,
+
+        /** A symbol or string value contains a control character. */
+        invalid_value = 15,
+
+        /** A sender worker's queue is full. */
+        queue_full = 16,
     };
 
     /** The protocol used to connect with. */
@@ -95,6 +113,16 @@ namespace questdb::ingress
         https,
     };
 
+    /** The version of the InfluxDB Line Protocol (ILP) encoding a buffer uses. */
+    enum class protocol_version
+    {
+        /** The original encoding: Timestamp columns are sent in microseconds. */
+        v1 = 1,
+
+        /** Timestamp columns given in nanoseconds are sent at full precision. */
+        v2 = 2,
+    };
+
     /* Possible sources of the root certificates used to validate the server's TLS certificate. */
     enum class ca {
         /** Use the set of root certificates provided by the `webpki` crate. */
@@ -325,6 +353,21 @@ namespace questdb::ingress
             : _impl{nullptr}
             , _init_buf_size{init_buf_size}
             , _max_name_len{max_name_len}
+            , _protocol_version{protocol_version::v1}
+        {
+        }
+
+        /**
+         * Construct a buffer that encodes rows for the given ILP protocol
+         * version, with a `max_name_len` of `127`.
+         */
+        explicit line_sender_buffer(
+            protocol_version version,
+            size_t init_buf_size = 64 * 1024) noexcept
+            : _impl{nullptr}
+            , _init_buf_size{init_buf_size}
+            , _max_name_len{127}
+            , _protocol_version{version}
         {
         }
 
@@ -332,12 +375,14 @@ namespace questdb::ingress
             : _impl{::line_sender_buffer_clone(other._impl)}
             , _init_buf_size{other._init_buf_size}
             , _max_name_len{other._max_name_len}
+            , _protocol_version{other._protocol_version}
         {}
 
         line_sender_buffer(line_sender_buffer&& other) noexcept
             : _impl{other._impl}
             , _init_buf_size{other._init_buf_size}
             , _max_name_len{other._max_name_len}
+            , _protocol_version{other._protocol_version}
         {
             other._impl = nullptr;
         }
@@ -353,6 +398,7 @@ namespace questdb::ingress
                     _impl = nullptr;
                 _init_buf_size = other._init_buf_size;
                 _max_name_len = other._max_name_len;
+                _protocol_version = other._protocol_version;
             }
             return *this;
         }
@@ -365,6 +411,7 @@ namespace questdb::ingress
                 _impl = other._impl;
                 _init_buf_size = other._init_buf_size;
                 _max_name_len = other._max_name_len;
+                _protocol_version = other._protocol_version;
                 other._impl = nullptr;
             }
             return *this;
@@ -727,7 +774,11 @@ namespace questdb::ingress
         {
             if (!_impl)
             {
-                _impl = ::line_sender_buffer_with_max_name_len(_max_name_len);
+                if (_protocol_version == protocol_version::v1)
+                    _impl = ::line_sender_buffer_with_max_name_len(_max_name_len);
+                else
+                    _impl = ::line_sender_buffer_with_protocol_version(
+                        static_cast<::line_sender_protocol_version>(_protocol_version));
                 ::line_sender_buffer_reserve(_impl, _init_buf_size);
             }
         }
@@ -735,6 +786,7 @@ namespace questdb::ingress
         ::line_sender_buffer* _impl;
         size_t _init_buf_size;
         size_t _max_name_len;
+        protocol_version _protocol_version;
 
         friend class line_sender;
     };
@@ -1202,6 +1254,10 @@ namespace questdb::ingress
          * server. In the case of an error, the server will quietly disconnect: consult the
          * server logs for error messages.
          *
+         * A row left incomplete, without a call to `at()` or `at_now()`, fails
+         * the flush and is discarded. The complete rows before it stay in the
+         * buffer.
+         *
          * HTTP should be the first choice, but use TCP if you need to continuously send
          * data to the server at a high rate.
          *
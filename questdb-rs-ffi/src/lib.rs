@@ -35,8 +35,8 @@ use std::str;
 
 use questdb::{
     ingress::{
-        Buffer, CertificateAuthority, ColumnName, Protocol, Sender, SenderBuilder, TableName,
-        TimestampMicros, TimestampNanos,
+        Buffer, CertificateAuthority, ColumnName, Protocol, ProtocolVersion, Sender, SenderBuilder,
+        TableName, TimestampMicros, TimestampNanos,
     },
     Error, ErrorCode,
 };
@@ -100,66 +100,99 @@ macro_rules! upd_opts {
 pub struct line_sender_error(Error);
 
 /// Category of error.
+///
+/// The values are those of `ErrorCode::as_i32` and will not change.
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub enum line_sender_error_code {
     /// The host, port, or interface was incorrect.
-    line_sender_error_could_not_resolve_addr,
+    line_sender_error_could_not_resolve_addr = 0,
 
     /// Called methods in the wrong order. E.g. `symbol` after `column`.
-    line_sender_error_invalid_api_call,
+    line_sender_error_invalid_api_call = 1,
 
     /// A network error connecting or flushing data out.
-    line_sender_error_socket_error,
+    line_sender_error_socket_error = 2,
 
     /// The string or symbol field is not encoded in valid UTF-8.
-    line_sender_error_invalid_utf8,
+    line_sender_error_invalid_utf8 = 3,
 
     /// The table name or column name contains bad characters.
-    line_sender_error_invalid_name,
+    line_sender_error_invalid_name = 4,
 
     /// The supplied timestamp is invalid.
-    line_sender_error_invalid_timestamp,
+    line_sender_error_invalid_timestamp = 5,
 
     /// Error during the authentication process.
-    line_sender_error_auth_error,
+    line_sender_error_auth_error = 6,
 
     /// Error during TLS handshake.
-    line_sender_error_tls_error,
+    line_sender_error_tls_error = 7,
 
     /// The server does not support ILP over HTTP.
-    line_sender_error_http_not_supported,
+    line_sender_error_http_not_supported = 8,
 
     /// Error sent back from the server during flush.
-    line_sender_error_server_flush_error,
+    line_sender_error_server_flush_error = 9,
 
     /// Bad configuration.
-    line_sender_error_config_error,
-}
+    line_sender_error_config_error = 10,
+
+    /// Completing a row would grow the buffer past its maximum size.
+    line_sender_error_buffer_too_large = 11,
+
+    /// Writing to a file or other output failed.
+    line_sender_error_io_error = 12,
+
+    /// Reading or writing a spill file failed.
+    line_sender_error_spill_error = 13,
+
+    /// The ILP passed to the decoder is malformed.
+    line_sender_error_malformed_ilp = 14,
+
+    /// A symbol or string value contains a control character.
+    line_sender_error_invalid_value = 15,
+
+    /// A sender worker's queue is full.
+    line_sender_error_queue_full = 16,
+}
+
+/// The C error codes, indexed by their `ErrorCode::as_i32` value.
+const ERROR_CODES: [line_sender_error_code; 17] = [
+    line_sender_error_code::line_sender_error_could_not_resolve_addr,
+    line_sender_error_code::line_sender_error_invalid_api_call,
+    line_sender_error_code::line_sender_error_socket_error,
+    line_sender_error_code::line_sender_error_invalid_utf8,
+    line_sender_error_code::line_sender_error_invalid_name,
+    line_sender_error_code::line_sender_error_invalid_timestamp,
+    line_sender_error_code::line_sender_error_auth_error,
+    line_sender_error_code::line_sender_error_tls_error,
+    line_sender_error_code::line_sender_error_http_not_supported,
+    line_sender_error_code::line_sender_error_server_flush_error,
+    line_sender_error_code::line_sender_error_config_error,
+    line_sender_error_code::line_sender_error_buffer_too_large,
+    line_sender_error_code::line_sender_error_io_error,
+    line_sender_error_code::line_sender_error_spill_error,
+    line_sender_error_code::line_sender_error_malformed_ilp,
+    line_sender_error_code::line_sender_error_invalid_value,
+    line_sender_error_code::line_sender_error_queue_full,
+];
+
+// Keep the C values in sync with the stable `ErrorCode::as_i32` values: Each
+// `ErrorCode` value must index the C code with that same value.
+const _: () = {
+    let mut index = 0;
+    while index < ERROR_CODES.len() {
+        assert!(ERROR_CODES[index] as i32 == index as i32);
+        assert!(ErrorCode::from_i32(index as i32).is_some());
+        index += 1;
+    }
+    assert!(ErrorCode::from_i32(ERROR_CODES.len() as i32).is_none());
+};
 
 impl From<ErrorCode> for line_sender_error_code {
     fn from(code: ErrorCode) -> Self {
-        match code {
-            ErrorCode::CouldNotResolveAddr => {
-                line_sender_error_code::line_sender_error_could_not_resolve_addr
-            }
-            ErrorCode::InvalidApiCall => line_sender_error_code::line_sender_error_invalid_api_call,
-            ErrorCode::SocketError => line_sender_error_code::line_sender_error_socket_error,
-            ErrorCode::InvalidUtf8 => line_sender_error_code::line_sender_error_invalid_utf8,
-            ErrorCode::InvalidName => line_sender_error_code::line_sender_error_invalid_name,
-            ErrorCode::InvalidTimestamp => {
-                line_sender_error_code::line_sender_error_invalid_timestamp
-            }
-            ErrorCode::AuthError => line_sender_error_code::line_sender_error_auth_error,
-            ErrorCode::TlsError => line_sender_error_code::line_sender_error_tls_error,
-            ErrorCode::HttpNotSupported => {
-                line_sender_error_code::line_sender_error_http_not_supported
-            }
-            ErrorCode::ServerFlushError => {
-                line_sender_error_code::line_sender_error_server_flush_error
-            }
-            ErrorCode::ConfigError => line_sender_error_code::line_sender_error_config_error,
-        }
+        ERROR_CODES[code.as_i32() as usize]
     }
 }
 
@@ -202,6 +235,26 @@ impl From<line_sender_protocol> for Protocol {
     }
 }
 
+/// The version of the InfluxDB Line Protocol (ILP) encoding a buffer uses.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub enum line_sender_protocol_version {
+    /// The original encoding: Timestamp columns are sent in microseconds.
+    line_sender_protocol_version_1 = 1,
+
+    /// Timestamp columns given in nanoseconds are sent at full precision.
+    line_sender_protocol_version_2 = 2,
+}
+
+impl From<line_sender_protocol_version> for ProtocolVersion {
+    fn from(version: line_sender_protocol_version) -> Self {
+        match version {
+            line_sender_protocol_version::line_sender_protocol_version_1 => ProtocolVersion::V1,
+            line_sender_protocol_version::line_sender_protocol_version_2 => ProtocolVersion::V2,
+        }
+    }
+}
+
 /// Possible sources of the root certificates used to validate the server's TLS certificate.
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -469,7 +522,8 @@ pub unsafe extern "C" fn line_sender_table_name_init(
 
     let str_name = str::from_utf8_unchecked(slice::from_raw_parts(buf as *const u8, len));
 
-    bubble_err_to_c!(err_out, TableName::new(str_name));
+    // The buffer checks the name's length against its own `max_name_len`.
+    bubble_err_to_c!(err_out, TableName::try_from(str_name));
 
     (*name).len = len;
     (*name).buf = buf;
@@ -482,7 +536,7 @@ pub unsafe extern "C" fn line_sender_table_name_assert(
     buf: *const c_char,
 ) -> line_sender_table_name {
     let u8str = line_sender_utf8_assert(len, buf);
-    match TableName::new(u8str.as_str()) {
+    match TableName::try_from(u8str.as_str()) {
         Ok(_) => line_sender_table_name { len, buf },
         Err(msg) => {
             panic!("{}", msg);
@@ -515,7 +569,8 @@ pub unsafe extern "C" fn line_sender_column_name_init(
 
     let str_name = str::from_utf8_unchecked(slice::from_raw_parts(buf as *const u8, len));
 
-    bubble_err_to_c!(err_out, ColumnName::new(str_name));
+    // The buffer checks the name's length against its own `max_name_len`.
+    bubble_err_to_c!(err_out, ColumnName::try_from(str_name));
 
     (*name).len = len;
     (*name).buf = buf;
@@ -528,7 +583,7 @@ pub unsafe extern "C" fn line_sender_column_name_assert(
     buf: *const c_char,
 ) -> line_sender_table_name {
     let u8str = line_sender_utf8_assert(len, buf);
-    match ColumnName::new(u8str.as_str()) {
+    match ColumnName::try_from(u8str.as_str()) {
         Ok(_) => line_sender_table_name { len, buf },
         Err(msg) => {
             panic!("{}", msg);
@@ -561,6 +616,18 @@ pub unsafe extern "C" fn line_sender_buffer_with_max_name_len(
     Box::into_raw(Box::new(line_sender_buffer(buffer)))
 }
 
+/// Construct a `line_sender_buffer` that encodes rows for the given ILP
+/// protocol version, with a `max_name_len` of `127`.
+/// `line_sender_buffer_new()` encodes rows for version 1, which all QuestDB
+/// servers understand.
+#[no_mangle]
+pub unsafe extern "C" fn line_sender_buffer_with_protocol_version(
+    version: line_sender_protocol_version,
+) -> *mut line_sender_buffer {
+    let buffer = Buffer::with_protocol_version(version.into());
+    Box::into_raw(Box::new(line_sender_buffer(buffer)))
+}
+
 /// Release the `line_sender_buffer` object.
 #[no_mangle]
 pub unsafe extern "C" fn line_sender_buffer_free(buffer: *mut line_sender_buffer) {
@@ -683,7 +750,7 @@ pub unsafe extern "C" fn line_sender_buffer_peek(
     len_out: *mut size_t,
 ) -> *const c_char {
     let buffer = unwrap_buffer(buffer);
-    let buf: &[u8] = buffer.as_str().as_bytes();
+    let buf: &[u8] = buffer.as_bytes();
     *len_out = buf.len();
     buf.as_ptr() as *const c_char
 }
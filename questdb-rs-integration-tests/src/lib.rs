@@ -0,0 +1,232 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Round-trip tests against a real QuestDB server started in Docker.
+//!
+//! They only run when the `QDB_INTEGRATION_TESTS` environment variable is set,
+//! e.g.:
+//!
+//! ```console
+//! cd questdb-rs-integration-tests
+//! QDB_INTEGRATION_TESTS=1 cargo test
+//! ```
+//!
+//! The image defaults to [`DEFAULT_IMAGE_TAG`] and can be overridden with the
+//! `QDB_IMAGE_TAG` environment variable.
+
+#![cfg(test)]
+
+use questdb::ingress::{Protocol, SenderBuilder, TimestampMicros, TimestampNanos};
+use serde_json::Value;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use testcontainers::core::{IntoContainerPort, WaitFor};
+use testcontainers::runners::SyncRunner;
+use testcontainers::{Container, GenericImage, ImageExt};
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+const DEFAULT_IMAGE_TAG: &str = "8.2.3";
+const HTTP_PORT: u16 = 9000;
+const ILP_TCP_PORT: u16 = 9009;
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+struct QuestDb {
+    // Stops and removes the container when dropped.
+    _container: Container<GenericImage>,
+    host: String,
+    http_port: u16,
+    ilp_tcp_port: u16,
+}
+
+impl QuestDb {
+    /// Start a server, or return `None` if the integration tests are disabled.
+    fn start() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if std::env::var_os("QDB_INTEGRATION_TESTS").is_none() {
+            eprintln!("Skipping: Set QDB_INTEGRATION_TESTS to run against QuestDB in Docker.");
+            return Ok(None);
+        }
+        let tag = std::env::var("QDB_IMAGE_TAG").unwrap_or_else(|_| DEFAULT_IMAGE_TAG.to_string());
+        let container = GenericImage::new("questdb/questdb", &tag)
+            .with_exposed_port(HTTP_PORT.tcp())
+            .with_exposed_port(ILP_TCP_PORT.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("server-main enjoy"))
+            .with_startup_timeout(TIMEOUT)
+            .start()?;
+        Ok(Some(Self {
+            host: container.get_host()?.to_string(),
+            http_port: container.get_host_port_ipv4(HTTP_PORT)?,
+            ilp_tcp_port: container.get_host_port_ipv4(ILP_TCP_PORT)?,
+            _container: container,
+        }))
+    }
+
+    fn sender(&self, protocol: Protocol) -> SenderBuilder {
+        let port = match protocol {
+            Protocol::Tcp | Protocol::Tcps => self.ilp_tcp_port,
+            Protocol::Http | Protocol::Https => self.http_port,
+        };
+        SenderBuilder::new(protocol, self.host.as_str(), port)
+    }
+
+    /// Run a query via the `/exec` endpoint and return the JSON response.
+    fn query(&self, sql: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let url = format!("http://{}:{}/exec", self.host, self.http_port);
+        let body = match ureq::get(&url).query("query", sql).call() {
+            Ok(response) => response.into_string()?,
+            Err(ureq::Error::Status(_, response)) => response.into_string()?,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Poll the table until it holds `rows` rows, returning the query's dataset.
+    ///
+    /// Rows sent over TCP are committed asynchronously, and WAL tables apply
+    /// them after the commit, so they may not be visible straight away.
+    fn wait_for_rows(
+        &self,
+        sql: &str,
+        rows: usize,
+    ) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let response = self.query(sql)?;
+            if let Some(dataset) = response["dataset"].as_array() {
+                if dataset.len() >= rows {
+                    return Ok(dataset.clone());
+                }
+            }
+            if Instant::now() > deadline {
+                return Err(format!("Timed out waiting for {} rows: {}", rows, response).into());
+            }
+            sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+struct Row {
+    sym: &'static str,
+    flag: bool,
+    int: i64,
+    float: f64,
+    text: &'static str,
+    ts_micros: i64,
+    at_nanos: i64,
+}
+
+// Values chosen to catch encoding mismatches: floats that don't round-trip in
+// short decimal form, integer extremes, strings and symbols that need escaping
+// and non-ASCII text.
+const ROWS: &[Row] = &[
+    Row {
+        sym: "plain",
+        flag: true,
+        int: 0,
+        float: 0.1,
+        text: "hello",
+        ts_micros: 0,
+        at_nanos: 1_700_000_000_000_000_000,
+    },
+    Row {
+        sym: "with space, comma=equals",
+        flag: false,
+        int: i64::MAX,
+        float: 1.0 / 3.0,
+        text: "quote \" backslash \\ comma , equals =",
+        ts_micros: 1_700_000_000_123_456,
+        at_nanos: 1_700_000_000_000_001_000,
+    },
+    Row {
+        sym: "naïve ✓",
+        flag: true,
+        int: i64::MIN + 1,
+        float: -1.7976931348623157e308,
+        text: "multi\nline\ttext ✓",
+        ts_micros: 1,
+        at_nanos: 1_700_000_000_999_999_000,
+    },
+    Row {
+        sym: "tiny",
+        flag: false,
+        int: -42,
+        float: 5e-324,
+        text: "",
+        ts_micros: 253_402_300_799_999_999,
+        at_nanos: 1_700_000_001_000_000_000,
+    },
+];
+
+fn round_trip(protocol: Protocol, table: &str) -> TestResult {
+    let Some(db) = QuestDb::start()? else {
+        return Ok(());
+    };
+    let mut sender = db.sender(protocol).build()?;
+    let mut buffer = sender.new_buffer();
+    for row in ROWS {
+        buffer
+            .table(table)?
+            .symbol("sym", row.sym)?
+            .column_bool("flag", row.flag)?
+            .column_i64("int", row.int)?
+            .column_f64("float", row.float)?
+            .column_str("text", row.text)?
+            .column_ts("ts", TimestampMicros::new(row.ts_micros))?
+            .at(TimestampNanos::new(row.at_nanos))?;
+    }
+    sender.flush(&mut buffer)?;
+    drop(sender);
+
+    let sql = format!(
+        concat!(
+            "select sym, flag, int, float, text, ",
+            "cast(ts as long) ts, cast(timestamp as long) designated ",
+            "from '{}' order by timestamp"
+        ),
+        table
+    );
+    let dataset = db.wait_for_rows(&sql, ROWS.len())?;
+    assert_eq!(dataset.len(), ROWS.len());
+    for (row, stored) in ROWS.iter().zip(dataset) {
+        assert_eq!(stored[0], row.sym);
+        assert_eq!(stored[1], row.flag);
+        assert_eq!(stored[2], row.int);
+        assert_eq!(stored[3].as_f64(), Some(row.float));
+        assert_eq!(stored[4], row.text);
+        assert_eq!(stored[5], row.ts_micros);
+        // The designated timestamp is stored in microseconds.
+        assert_eq!(stored[6], row.at_nanos / 1000);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_round_trip_tcp() -> TestResult {
+    round_trip(Protocol::Tcp, "integration_tcp")
+}
+
+#[test]
+fn test_round_trip_http() -> TestResult {
+    round_trip(Protocol::Http, "integration_http")
+}
@@ -0,0 +1,188 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Measures how fast a [`Buffer`] encodes numeric columns, timestamps and
+//! strings.
+//!
+//! The `core_fmt` baseline encodes the same numbers via `write!`, as the
+//! buffer did before it switched to the `itoa` and `ryu` crates. The strings
+//! are either clean, as most are, or need escaping throughout. The `names`
+//! group compares rows of ten columns named by plain strings, validated and
+//! escaped on each call, against names validated once with `new_unchecked`,
+//! and against owned names also escaped once. Some of the names need escaping.
+//!
+//! Run with `cargo bench --bench buffer`.
+
+use std::fmt::Write;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use questdb::ingress::{
+    Buffer, ColumnName, OwnedColumnName, OwnedTableName, TableName, TimestampNanos,
+};
+
+const ROWS: u64 = 10_000;
+
+fn value(row: u64) -> (i64, f64, i64) {
+    let int = (row as i64).wrapping_mul(7_919) - 5_000_000;
+    let float = int as f64 / 1_024.0 + 1e-9 * row as f64;
+    let nanos = 1_700_000_000_000_000_000 + row as i64;
+    (int, float, nanos)
+}
+
+fn encode_buffer(buffer: &mut Buffer) -> questdb::Result<()> {
+    // Validated once, so that the names don't weigh on the comparison.
+    let table = TableName::new("trades")?;
+    let qty = ColumnName::new("qty")?;
+    let price = ColumnName::new("price")?;
+    buffer.clear();
+    for row in 0..ROWS {
+        let (int, float, nanos) = value(row);
+        buffer
+            .table(table)?
+            .column_i64(qty, int)?
+            .column_f64(price, float)?
+            .at(TimestampNanos::new(nanos))?;
+    }
+    Ok(())
+}
+
+fn encode_core_fmt(output: &mut String) {
+    output.clear();
+    for row in 0..ROWS {
+        let (int, float, nanos) = value(row);
+        writeln!(output, "trades qty={int}i,price={float:?} {nanos}").unwrap();
+    }
+}
+
+fn bench_numbers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("numbers");
+    group.throughput(Throughput::Elements(ROWS));
+
+    let mut buffer = Buffer::new();
+    group.bench_function("buffer", |b| {
+        b.iter(|| {
+            encode_buffer(&mut buffer).unwrap();
+            black_box(buffer.len())
+        })
+    });
+
+    let mut output = String::new();
+    group.bench_function("core_fmt", |b| {
+        b.iter(|| {
+            encode_core_fmt(&mut output);
+            black_box(output.len())
+        })
+    });
+    group.finish();
+}
+
+fn bench_strings(c: &mut Criterion) {
+    let clean = "The_quick_brown_fox_jumps_over_the_lazy_dog.".repeat(100);
+    let dirty = "key=value, \"quoted\"\\n".repeat(200);
+    let table = TableName::new("logs").unwrap();
+    let tag = ColumnName::new("tag").unwrap();
+    let message = ColumnName::new("message").unwrap();
+
+    let mut group = c.benchmark_group("strings");
+    for (name, value) in [("clean", &clean), ("dirty", &dirty)] {
+        group.throughput(Throughput::Bytes(2 * value.len() as u64));
+        let mut buffer = Buffer::new();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                buffer.clear();
+                buffer
+                    .table(table)
+                    .and_then(|buffer| buffer.symbol(tag, value.as_str()))
+                    .and_then(|buffer| buffer.column_str(message, value.as_str()))
+                    .and_then(|buffer| buffer.at_now())
+                    .unwrap();
+                black_box(buffer.len())
+            })
+        });
+    }
+    group.finish();
+}
+
+const TABLE: &str = "candles 1m";
+
+const COLUMNS: [&str; 10] = [
+    "open", "high", "low", "close", "volume", "best bid", "best ask", "spread", "vwap", "trades",
+];
+
+fn bench_names(c: &mut Criterion) {
+    let mut group = c.benchmark_group("names");
+    group.throughput(Throughput::Elements(ROWS));
+
+    let mut buffer = Buffer::new();
+    group.bench_function("str", |b| {
+        b.iter(|| {
+            buffer.clear();
+            for row in 0..ROWS {
+                buffer.table(TABLE).unwrap();
+                for column in COLUMNS {
+                    buffer.column_f64(column, row as f64).unwrap();
+                }
+                buffer.at_now().unwrap();
+            }
+            black_box(buffer.len())
+        })
+    });
+
+    let table = TableName::new_unchecked(TABLE);
+    let columns = COLUMNS.map(ColumnName::new_unchecked);
+    group.bench_function("unchecked", |b| {
+        b.iter(|| {
+            buffer.clear();
+            for row in 0..ROWS {
+                buffer.table(table).unwrap();
+                for column in columns {
+                    buffer.column_f64(column, row as f64).unwrap();
+                }
+                buffer.at_now().unwrap();
+            }
+            black_box(buffer.len())
+        })
+    });
+
+    let table = OwnedTableName::new(TABLE).unwrap();
+    let columns = COLUMNS.map(|name| OwnedColumnName::new(name).unwrap());
+    group.bench_function("owned", |b| {
+        b.iter(|| {
+            buffer.clear();
+            for row in 0..ROWS {
+                buffer.table(&table).unwrap();
+                for column in &columns {
+                    buffer.column_f64(column, row as f64).unwrap();
+                }
+                buffer.at_now().unwrap();
+            }
+            black_box(buffer.len())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_numbers, bench_strings, bench_names);
+criterion_main!(benches);
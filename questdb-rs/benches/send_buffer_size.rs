@@ -0,0 +1,103 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Measures how fast an ILP/TCP sender flushes a bulk load with the default
+//! socket send buffer and with a larger one set via
+//! [`SenderBuilder::send_buffer_size`].
+//!
+//! By default, the rows go to a local server thread that discards them: Over
+//! loopback the buffer size barely matters. Set `QDB_BENCH_ADDR` to the
+//! `host:port` of a remote QuestDB ILP/TCP endpoint to measure a high-latency
+//! link instead.
+//!
+//! Run with `cargo bench --bench send_buffer_size`.
+
+use std::hint::black_box;
+use std::io::Read;
+use std::net::TcpListener;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use questdb::ingress::{Buffer, Protocol, Sender, SenderBuilder, TimestampNanos};
+
+const ROWS: i64 = 100_000;
+
+/// Start a server that accepts connections and discards everything it reads.
+fn spawn_sink_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut chunk = vec![0u8; 64 * 1024];
+                while !matches!(stream.read(&mut chunk), Ok(0) | Err(_)) {}
+            });
+        }
+    });
+    addr
+}
+
+fn bulk_load() -> questdb::Result<Buffer> {
+    let mut buffer = Buffer::new();
+    for row in 0..ROWS {
+        buffer
+            .table("bulk_load")?
+            .symbol("region", "eu-west-1")?
+            .column_i64("seq", row)?
+            .column_f64("value", row as f64 / 3.0)?
+            .at(TimestampNanos::new(1_700_000_000_000_000_000 + row))?;
+    }
+    Ok(buffer)
+}
+
+fn connect(addr: &str, send_buffer_size: Option<usize>) -> questdb::Result<Sender> {
+    let (host, port) = addr
+        .rsplit_once(':')
+        .expect("QDB_BENCH_ADDR must be host:port");
+    let mut builder = SenderBuilder::new(Protocol::Tcp, host, port);
+    if let Some(size) = send_buffer_size {
+        builder = builder.send_buffer_size(size)?;
+    }
+    builder.build()
+}
+
+fn bench_send_buffer_size(c: &mut Criterion) {
+    let addr = std::env::var("QDB_BENCH_ADDR").unwrap_or_else(|_| spawn_sink_server());
+    let buffer = bulk_load().unwrap();
+
+    let mut group = c.benchmark_group("send_buffer_size");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.sample_size(10);
+    for (name, size) in [("default", None), ("4MiB", Some(4 * 1024 * 1024))] {
+        let mut sender = connect(&addr, size).unwrap();
+        group.bench_function(BenchmarkId::new("flush", name), |b| {
+            b.iter(|| sender.flush_and_keep(black_box(&buffer)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_send_buffer_size);
+criterion_main!(benches);
@@ -96,6 +96,7 @@ pub mod json_tests {
             // This file is auto-generated by build.rs.
 
             use crate::{Result, ingress::{Buffer}};
+            use crate::ingress::decoder::{parse_lines, ParsedValue};
             use crate::tests::{TestResult};
 
             fn matches_any_line(line: &str, expected: &[&str]) -> bool {
@@ -190,6 +191,55 @@ pub mod json_tests {
                         "    assert!(matches_any_line(buffer.as_str(), &any));"
                     )?;
                 }
+
+                // The decoder must read back the original inputs.
+                writeln!(
+                    output,
+                    "    let row = parse_lines(buffer.as_str()).next().unwrap()?;"
+                )?;
+                writeln!(output, "    assert_eq!(row.table(), {:?});", spec.table)?;
+                writeln!(
+                    output,
+                    "    assert_eq!(row.symbols().len(), {});",
+                    spec.symbols.len()
+                )?;
+                for (index, symbol) in spec.symbols.iter().enumerate() {
+                    writeln!(
+                        output,
+                        "    assert_eq!(row.symbols()[{}], ({:?}.to_owned(), {:?}.to_owned()));",
+                        index, symbol.name, symbol.value
+                    )?;
+                }
+                writeln!(
+                    output,
+                    "    assert_eq!(row.columns().len(), {});",
+                    spec.columns.len()
+                )?;
+                for (index, column) in spec.columns.iter().enumerate() {
+                    let (name, value) = match column {
+                        Column::String(column) => (
+                            &column.name,
+                            format!("ParsedValue::Str({:?}.to_owned())", column.value),
+                        ),
+                        Column::Long(column) => (
+                            &column.name,
+                            format!("ParsedValue::I64({:?})", column.value),
+                        ),
+                        Column::Double(column) => (
+                            &column.name,
+                            format!("ParsedValue::F64({:?})", column.value),
+                        ),
+                        Column::Boolean(column) => (
+                            &column.name,
+                            format!("ParsedValue::Bool({:?})", column.value),
+                        ),
+                    };
+                    writeln!(
+                        output,
+                        "    assert_eq!(row.columns()[{}], ({:?}.to_owned(), {}));",
+                        index, name, value
+                    )?;
+                }
             } else {
                 writeln!(output, "        Ok(())")?;
                 writeln!(output, "    }}().unwrap_err();")?;
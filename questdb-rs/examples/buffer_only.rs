@@ -0,0 +1,49 @@
+//! Serialize rows without the crate's networking, leaving sending the bytes to
+//! a closure: For example, a callback into JavaScript from a WASM module.
+//!
+//! This builds with `default-features = false`, including for the
+//! `wasm32-unknown-unknown` target. There, the system clock isn't available,
+//! so take timestamps from the host instead of calling `TimestampNanos::now`.
+
+use questdb::{
+    ingress::{Buffer, TimestampNanos},
+    Result,
+};
+
+/// Record the readings, handing the bytes of each batch of up to `batch_size`
+/// rows to `flush`.
+fn ingest<F>(readings: &[(&str, f64, i64)], batch_size: usize, mut flush: F) -> Result<()>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut buffer = Buffer::new();
+    for &(id, temperature, epoch_nanos) in readings {
+        buffer
+            .table("sensors")?
+            .symbol("id", id)?
+            .column_f64("temperature", temperature)?
+            .at(TimestampNanos::new(epoch_nanos))?;
+        if buffer.row_count() >= batch_size {
+            flush(buffer.as_bytes())?;
+            buffer.clear();
+        }
+    }
+    if !buffer.is_empty() {
+        flush(buffer.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let readings = [
+        ("toronto1", 20.0, 1_700_000_000_000_000_000),
+        ("toronto2", 21.5, 1_700_000_000_000_000_000),
+        ("toronto1", 20.3, 1_700_000_001_000_000_000),
+    ];
+    ingest(&readings, 2, |bytes| {
+        // Post the bytes to QuestDB's `/write` endpoint, or over a TCP
+        // connection to its ILP port.
+        print!("{}", String::from_utf8_lossy(bytes));
+        Ok(())
+    })
+}
@@ -1,4 +1,6 @@
 use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::Arc;
 
 macro_rules! fmt {
     ($code:ident, $($arg:tt)*) => {
@@ -11,7 +13,11 @@ macro_rules! fmt {
 /// Category of error.
 ///
 /// Accessible via Error's [`code`](Error::code) method.
+///
+/// New error codes may be added in minor releases, so matches on this enum
+/// need a wildcard arm.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum ErrorCode {
     /// The host, port, or interface was incorrect.
     CouldNotResolveAddr,
@@ -48,13 +54,114 @@ pub enum ErrorCode {
 
     /// Bad configuration.
     ConfigError,
+
+    /// Completing a row would grow the buffer past its maximum size.
+    BufferTooLarge,
+
+    /// Writing to an [`io::Write`](std::io::Write) failed, e.g. in
+    /// [`Buffer::flush_to`](crate::ingress::Buffer::flush_to).
+    IoError,
+
+    /// Reading or writing a spill file failed.
+    SpillError,
+
+    /// The ILP passed to the [decoder](crate::ingress::decoder) is malformed.
+    MalformedIlp,
+
+    /// A symbol or string value contains a control character, with
+    /// [`Buffer::set_reject_control_chars_in_values`](crate::ingress::Buffer::set_reject_control_chars_in_values)
+    /// on.
+    InvalidValue,
+
+    /// A worker's queue is full, with the `OverflowPolicy::Error` policy.
+    QueueFull,
+}
+
+impl ErrorCode {
+    /// A stable numeric value for the error code, e.g. to pass it across an FFI
+    /// boundary or to log it.
+    ///
+    /// The values don't depend on the declaration order of the variants and
+    /// will not change. New error codes get new values.
+    ///
+    /// | Code                                               | Value |
+    /// |----------------------------------------------------|-------|
+    /// | [`CouldNotResolveAddr`](Self::CouldNotResolveAddr) | 0     |
+    /// | [`InvalidApiCall`](Self::InvalidApiCall)           | 1     |
+    /// | [`SocketError`](Self::SocketError)                 | 2     |
+    /// | [`InvalidUtf8`](Self::InvalidUtf8)                 | 3     |
+    /// | [`InvalidName`](Self::InvalidName)                 | 4     |
+    /// | [`InvalidTimestamp`](Self::InvalidTimestamp)       | 5     |
+    /// | [`AuthError`](Self::AuthError)                     | 6     |
+    /// | [`TlsError`](Self::TlsError)                       | 7     |
+    /// | [`HttpNotSupported`](Self::HttpNotSupported)       | 8     |
+    /// | [`ServerFlushError`](Self::ServerFlushError)       | 9     |
+    /// | [`ConfigError`](Self::ConfigError)                 | 10    |
+    /// | [`BufferTooLarge`](Self::BufferTooLarge)           | 11    |
+    /// | [`IoError`](Self::IoError)                         | 12    |
+    /// | [`SpillError`](Self::SpillError)                   | 13    |
+    /// | [`MalformedIlp`](Self::MalformedIlp)               | 14    |
+    /// | [`InvalidValue`](Self::InvalidValue)               | 15    |
+    /// | [`QueueFull`](Self::QueueFull)                     | 16    |
+    pub const fn as_i32(self) -> i32 {
+        match self {
+            ErrorCode::CouldNotResolveAddr => 0,
+            ErrorCode::InvalidApiCall => 1,
+            ErrorCode::SocketError => 2,
+            ErrorCode::InvalidUtf8 => 3,
+            ErrorCode::InvalidName => 4,
+            ErrorCode::InvalidTimestamp => 5,
+            ErrorCode::AuthError => 6,
+            ErrorCode::TlsError => 7,
+            ErrorCode::HttpNotSupported => 8,
+            ErrorCode::ServerFlushError => 9,
+            ErrorCode::ConfigError => 10,
+            ErrorCode::BufferTooLarge => 11,
+            ErrorCode::IoError => 12,
+            ErrorCode::SpillError => 13,
+            ErrorCode::MalformedIlp => 14,
+            ErrorCode::InvalidValue => 15,
+            ErrorCode::QueueFull => 16,
+        }
+    }
+
+    /// The error code with the given [`as_i32`](ErrorCode::as_i32) value, or
+    /// `None` if there's no such code.
+    pub const fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(ErrorCode::CouldNotResolveAddr),
+            1 => Some(ErrorCode::InvalidApiCall),
+            2 => Some(ErrorCode::SocketError),
+            3 => Some(ErrorCode::InvalidUtf8),
+            4 => Some(ErrorCode::InvalidName),
+            5 => Some(ErrorCode::InvalidTimestamp),
+            6 => Some(ErrorCode::AuthError),
+            7 => Some(ErrorCode::TlsError),
+            8 => Some(ErrorCode::HttpNotSupported),
+            9 => Some(ErrorCode::ServerFlushError),
+            10 => Some(ErrorCode::ConfigError),
+            11 => Some(ErrorCode::BufferTooLarge),
+            12 => Some(ErrorCode::IoError),
+            13 => Some(ErrorCode::SpillError),
+            14 => Some(ErrorCode::MalformedIlp),
+            15 => Some(ErrorCode::InvalidValue),
+            16 => Some(ErrorCode::QueueFull),
+            _ => None,
+        }
+    }
 }
 
 /// An error that occurred when using QuestDB client library.
-#[derive(Debug, PartialEq)]
+///
+/// Errors caused by a failed I/O operation, such as connecting or writing to
+/// the socket, keep the original [`io::Error`]: See
+/// [`io_error_kind`](Error::io_error_kind), or walk the chain with
+/// [`source`](std::error::Error::source).
+#[derive(Debug, Clone)]
 pub struct Error {
     code: ErrorCode,
     msg: String,
+    source: Option<Arc<io::Error>>,
 }
 
 impl Error {
@@ -63,9 +170,16 @@ impl Error {
         Error {
             code,
             msg: msg.into(),
+            source: None,
         }
     }
 
+    /// Attach the I/O error that caused this error.
+    pub(crate) fn with_io_source(mut self, source: io::Error) -> Error {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
     /// Get the error code (category) of this error.
     pub fn code(&self) -> ErrorCode {
         self.code
@@ -75,6 +189,30 @@ impl Error {
     pub fn msg(&self) -> &str {
         &self.msg
     }
+
+    /// The I/O error that caused this error, if any.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.source.as_deref()
+    }
+
+    /// The kind of the I/O error that caused this error, if any.
+    ///
+    /// Use it to tell, for example, a timeout
+    /// ([`WouldBlock`](io::ErrorKind::WouldBlock) or
+    /// [`TimedOut`](io::ErrorKind::TimedOut)) from a dropped connection
+    /// ([`ConnectionReset`](io::ErrorKind::ConnectionReset)) without parsing
+    /// the message.
+    pub fn io_error_kind(&self) -> Option<io::ErrorKind> {
+        self.io_error().map(io::Error::kind)
+    }
+}
+
+/// Errors are equal if they have the same code and message. Their sources
+/// aren't compared, as `io::Error` can't be.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code && self.msg == other.msg
+    }
 }
 
 impl Display for Error {
@@ -83,7 +221,13 @@ impl Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|io_err| io_err as &(dyn std::error::Error + 'static))
+    }
+}
 
 /// A specialized `Result` type for the crate's [`Error`] type.
 pub type Result<T> = std::result::Result<T, Error>;
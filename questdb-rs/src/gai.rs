@@ -27,52 +27,75 @@ use dns_lookup::{AddrInfo, AddrInfoHints, AddrInfoIter, LookupError};
 use socket2::SockAddr;
 
 #[cfg(unix)]
-use libc::{AF_INET, SOCK_STREAM};
+use libc::{c_int, AF_UNSPEC, SOCK_STREAM};
 
 #[cfg(windows)]
-use winapi::shared::ws2def::{AF_INET, SOCK_STREAM};
+use winapi::{
+    ctypes::c_int,
+    shared::ws2def::{AF_UNSPEC, SOCK_STREAM},
+};
 
 fn map_getaddrinfo_result(
     dest: &str,
     result: Result<AddrInfoIter, LookupError>,
-) -> crate::Result<SockAddr> {
-    match result {
-        Ok(mut addrs) => {
-            let addr: AddrInfo = addrs.next().unwrap().map_err(|io_err| {
-                error::fmt!(
-                    CouldNotResolveAddr,
-                    "Could not resolve {:?}: {}",
-                    dest,
-                    io_err
-                )
-            })?;
-            Ok(addr.sockaddr.into())
-        }
-        Err(lookup_err) => {
-            let io_err: std::io::Error = lookup_err.into();
-            Err(error::fmt!(
-                CouldNotResolveAddr,
-                "Could not resolve {:?}: {}",
-                dest,
-                io_err
-            ))
+) -> crate::Result<Vec<SockAddr>> {
+    let map_io_err = |io_err: std::io::Error| {
+        error::fmt!(
+            CouldNotResolveAddr,
+            "Could not resolve {:?}: {}",
+            dest,
+            io_err
+        )
+    };
+    let addrs = result.map_err(|lookup_err| map_io_err(lookup_err.into()))?;
+    let mut resolved: Vec<SockAddr> = Vec::new();
+    let mut last_err = None;
+    for addr in addrs {
+        match addr {
+            Ok(AddrInfo { sockaddr, .. }) => {
+                let sockaddr: SockAddr = sockaddr.into();
+                if !resolved.contains(&sockaddr) {
+                    resolved.push(sockaddr);
+                }
+            }
+            Err(io_err) => last_err = Some(io_err),
         }
     }
+    match (resolved.is_empty(), last_err) {
+        (true, Some(io_err)) => Err(map_io_err(io_err)),
+        (true, None) => Err(error::fmt!(
+            CouldNotResolveAddr,
+            "Could not resolve {:?}: No addresses found.",
+            dest
+        )),
+        (false, _) => Ok(resolved),
+    }
 }
 
-pub(super) fn resolve_host(host: &str) -> super::Result<SockAddr> {
+/// Resolve a local address to bind to, of the same `family` as the peer.
+pub(super) fn resolve_host(host: &str, family: c_int) -> super::Result<SockAddr> {
     let hints = AddrInfoHints {
         socktype: SOCK_STREAM,
-        address: AF_INET,
+        address: family,
         ..AddrInfoHints::default()
     };
     map_getaddrinfo_result(host, dns_lookup::getaddrinfo(Some(host), None, Some(hints)))
+        .map(|addrs| addrs.into_iter().next().unwrap())
+}
+
+/// Resolve all IPv4 and IPv6 candidates, for the caller to try in order.
+pub(super) fn resolve_host_port(host: &str, port: &str) -> super::Result<Vec<SockAddr>> {
+    resolve_host_port_family(host, port, AF_UNSPEC)
 }
 
-pub(super) fn resolve_host_port(host: &str, port: &str) -> super::Result<SockAddr> {
+pub(super) fn resolve_host_port_family(
+    host: &str,
+    port: &str,
+    family: c_int,
+) -> super::Result<Vec<SockAddr>> {
     let hints = AddrInfoHints {
         socktype: SOCK_STREAM,
-        address: AF_INET,
+        address: family,
         ..AddrInfoHints::default()
     };
     let host_port = format!("{}:{}", host, port);
@@ -0,0 +1,815 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use socket2::SockAddr;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio::time::Instant;
+use tokio_rustls::TlsConnector;
+
+use crate::error::{self, Error, Result};
+use crate::ingress::shutdown::WorkerState;
+use crate::ingress::{
+    check_challenge, configure_tls, map_io_to_socket_err, prepare_auth, sign_challenge, AuthParams,
+    AutoFlush, Buffer, BufferTemplate, Deadline, EcdsaAuthParams, OverflowPolicy, ProtocolVersion,
+    SenderBuilder, ShutdownReport,
+};
+
+trait AsyncStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncStream for T {}
+
+/// Sends ILP over TCP from within a [tokio](https://docs.rs/tokio) runtime.
+///
+/// This is the async counterpart of [`Sender`](crate::ingress::Sender) for
+/// the `tcp` and `tcps` protocols. Build it with
+/// [`SenderBuilder::build_async`] and populate the same [`Buffer`] type:
+/// Only connecting and flushing are async.
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{SenderBuilder, TimestampNanos};
+///
+/// # async fn run() -> Result<()> {
+/// let mut sender = SenderBuilder::from_conf("tcp::addr=localhost:9009;")?
+///     .build_async()
+///     .await?;
+/// let mut buffer = sender.new_buffer();
+/// buffer
+///     .table("sensors")?
+///     .symbol("id", "toronto1")?
+///     .column_f64("temperature", 20.0)?
+///     .at(TimestampNanos::now())?;
+/// sender.flush(&mut buffer).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The flushing methods are cancellation safe in the sense that dropping
+/// their future never corrupts the data on the wire silently: If it's dropped
+/// before all the bytes are written, the server may have received part of a
+/// row, so the sender is marked as [must close](AsyncSender::must_close) and
+/// every later flush fails.
+///
+/// Auto-flushing, the [`FlushObserver`](crate::ingress::FlushObserver) and
+/// the other features specific to [`Sender`](crate::ingress::Sender) are not
+/// available.
+pub struct AsyncSender {
+    descr: String,
+    stream: Box<dyn AsyncStream>,
+    connected: bool,
+    buffer_template: BufferTemplate,
+}
+
+impl Debug for AsyncSender {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.descr.as_str())
+    }
+}
+
+impl AsyncSender {
+    /// Send the buffer's contents to the database, then clear it.
+    ///
+    /// See [`Sender::flush`](crate::ingress::Sender::flush).
+    pub async fn flush(&mut self, buf: &mut Buffer) -> Result<()> {
+        self.flush_and_keep(buf).await?;
+        buf.clear();
+        Ok(())
+    }
+
+    /// Send the buffer's contents to the database, without clearing it.
+    ///
+    /// See [`Sender::flush_and_keep`](crate::ingress::Sender::flush_and_keep).
+    pub async fn flush_and_keep(&mut self, buf: &Buffer) -> Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        if !self.connected {
+            return Err(error::fmt!(
+                SocketError,
+                "Could not flush buffer: not connected to database."
+            ));
+        }
+        self.buffer_template.check_can_flush(buf)?;
+
+        // Stays unset if this future is dropped mid-write.
+        self.connected = false;
+        self.stream
+            .write_all(buf.as_bytes())
+            .await
+            .map_err(|io_err| map_io_to_socket_err("Could not flush buffer: ", io_err))?;
+        self.stream
+            .flush()
+            .await
+            .map_err(|io_err| map_io_to_socket_err("Could not flush buffer: ", io_err))?;
+        self.connected = true;
+        Ok(())
+    }
+
+    /// Tell whether the sender is no longer usable and must be dropped.
+    ///
+    /// This happens after a failed or cancelled flush.
+    pub fn must_close(&self) -> bool {
+        !self.connected
+    }
+
+    /// The ILP protocol version the sender is pinned to, see
+    /// [`SenderBuilder::protocol_version`].
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.buffer_template.protocol_version
+    }
+
+    /// Create a buffer that encodes rows for the sender's protocol version and
+    /// honours its maximum buffer size.
+    pub fn new_buffer(&self) -> Buffer {
+        self.buffer_template.new_buffer()
+    }
+}
+
+impl SenderBuilder {
+    /// Build an [`AsyncSender`], connecting over ILP/TCP.
+    ///
+    /// Like [`build`](SenderBuilder::build), this returns once the TLS
+    /// handshake and authentication, if any, have completed. Only the `tcp`
+    /// and `tcps` protocols are supported, with a single address and no
+    /// [spill directory](SenderBuilder::spill_dir).
+    ///
+    /// The connection is made on the runtime, so dropping the future abandons
+    /// the attempt. Only a custom [resolver](SenderBuilder::resolver), a
+    /// [`dns_ttl`](SenderBuilder::dns_ttl) or a non-numeric port resolve the
+    /// address on a blocking thread.
+    pub async fn build_async(&self) -> Result<AsyncSender> {
+        if !self.protocol.is_tcpx() {
+            return Err(error::fmt!(
+                ConfigError,
+                "The async sender can only be used with the TCP protocol."
+            ));
+        }
+        if self.unix_socket.is_some() {
+            return Err(error::fmt!(
+                ConfigError,
+                "The async sender doesn't support Unix domain sockets."
+            ));
+        }
+        if !self.failover_addrs.is_empty() {
+            return Err(error::fmt!(
+                ConfigError,
+                "The async sender doesn't support failover addresses."
+            ));
+        }
+        if self.spill_dir.is_some() {
+            return Err(error::fmt!(
+                ConfigError,
+                "The async sender doesn't support spilling to disk."
+            ));
+        }
+        let auth = self.build_auth()?;
+
+        let stream = self.connect_addrs_async().await?;
+
+        #[cfg(feature = "insecure-skip-verify")]
+        let tls_verify = *self.tls_verify;
+
+        #[cfg(not(feature = "insecure-skip-verify"))]
+        let tls_verify = true;
+
+        let mut stream: Box<dyn AsyncStream> = match configure_tls(
+            self.protocol.tls_enabled(),
+            tls_verify,
+            *self.tls_ca,
+            self.tls_roots.deref(),
+        )? {
+            Some(tls_config) => {
+                let server_name = self.tls_server_name()?;
+                let connect = TlsConnector::from(tls_config).connect(server_name, stream);
+                let tls_stream = with_timeout(*self.auth_timeout, connect, || {
+                    error::fmt!(
+                        TlsError,
+                        "Failed to complete TLS handshake: Timed out waiting for server response after {:?}.",
+                        *self.auth_timeout
+                    )
+                    .with_io_source(std::io::ErrorKind::TimedOut.into())
+                })
+                .await?
+                .map_err(|io_err| {
+                    error::fmt!(TlsError, "Failed to complete TLS handshake: {}", io_err)
+                        .with_io_source(io_err)
+                })?;
+                Box::new(tls_stream)
+            }
+            None => Box::new(stream),
+        };
+
+        if let Some(AuthParams::Ecdsa(auth)) = &auth {
+            with_timeout(*self.auth_timeout, authenticate(&mut stream, auth), || {
+                error::fmt!(
+                    AuthError,
+                    "Failed to authenticate: Timed out waiting for server response after {:?}.",
+                    *self.auth_timeout
+                )
+            })
+            .await??;
+        }
+
+        let descr = format!(
+            "AsyncSender[host={:?},port={:?},tls={},auth={}]",
+            self.host,
+            self.port,
+            if self.protocol.tls_enabled() {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            if auth.is_some() { "on" } else { "off" }
+        );
+        Ok(AsyncSender {
+            descr,
+            stream,
+            connected: true,
+            buffer_template: self.buffer_template(),
+        })
+    }
+
+    /// Try each resolved address in turn, as
+    /// [`connect_addrs`](SenderBuilder::connect_addrs) does, reporting the
+    /// last error if none connect.
+    async fn connect_addrs_async(&self) -> Result<TcpStream> {
+        let mut last_err = error::fmt!(
+            CouldNotResolveAddr,
+            "Could not resolve \"{}:{}\": No addresses found.",
+            self.host.deref(),
+            *self.port
+        );
+        for addr in self.resolve_addrs_async().await? {
+            match self.connect_socket_async(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn resolve_addrs_async(&self) -> Result<Vec<SocketAddr>> {
+        let numeric_port = self.port.parse::<u16>().ok();
+        let addrs = if self.socket_addr.is_some() {
+            self.resolve_addrs()?
+        } else if let Some(port) =
+            numeric_port.filter(|_| self.resolver.is_none() && self.dns_ttl.is_none())
+        {
+            return self.lookup_host(port).await;
+        } else {
+            // A custom resolver, the DNS cache and service names take the sync
+            // code path, off the runtime's worker threads.
+            let builder = self.clone();
+            tokio::task::spawn_blocking(move || builder.resolve_addrs())
+                .await
+                .map_err(|join_err| {
+                    error::fmt!(CouldNotResolveAddr, "Could not resolve: {}", join_err)
+                })??
+        };
+        Ok(addrs.iter().filter_map(SockAddr::as_socket).collect())
+    }
+
+    async fn lookup_host(&self, port: u16) -> Result<Vec<SocketAddr>> {
+        let host_port = format!("{}:{}", self.host.deref(), port);
+        let lookup = tokio::net::lookup_host((self.host.as_str(), port));
+        let addrs = match *self.resolve_timeout {
+            Some(timeout) => {
+                with_timeout(timeout, lookup, || {
+                    error::fmt!(
+                        CouldNotResolveAddr,
+                        "Could not resolve {:?}: Timed out after {:?}.",
+                        host_port,
+                        timeout
+                    )
+                })
+                .await?
+            }
+            None => lookup.await,
+        }
+        .map_err(|io_err| {
+            error::fmt!(
+                CouldNotResolveAddr,
+                "Could not resolve {:?}: {}",
+                host_port,
+                io_err
+            )
+        })?;
+        let mut resolved: Vec<SocketAddr> = Vec::new();
+        for addr in addrs {
+            if !resolved.contains(&addr) {
+                resolved.push(addr);
+            }
+        }
+        Ok(resolved)
+    }
+
+    async fn connect_socket_async(&self, addr: SocketAddr) -> Result<TcpStream> {
+        let sock = self.open_socket(&addr.into())?;
+        sock.set_nonblocking(true).map_err(|io_err| {
+            map_io_to_socket_err("Could not set socket to non-blocking: ", io_err)
+        })?;
+        TcpSocket::from_std_stream(sock.into())
+            .connect(addr)
+            .await
+            .map_err(|io_err| self.map_connect_err(io_err))
+    }
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // Rows are the bulk of the messages, so don't box them.
+enum Message {
+    Rows(Buffer),
+    Flush(oneshot::Sender<Result<()>>),
+    Close(oneshot::Sender<Result<()>>),
+    Shutdown,
+}
+
+/// How long the task keeps flushing once every handle is dropped.
+const DROP_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An [`AsyncSender`] that flushes from a task on the tokio runtime.
+///
+/// Producers send complete rows via cheap, cloneable handles: The buffers are
+/// queued on a bounded channel and the task appends them into its own buffer,
+/// which it flushes per the builder's auto-flush settings, see
+/// [`SenderBuilder::auto_flush_rows`]. Without auto-flush settings, each
+/// buffer is flushed as it arrives.
+///
+/// Should the connection be lost, the task connects again before the next
+/// flush. The rows of a failed flush are dropped: The error is published via
+/// [`health`](AsyncSenderWorker::health) right away and returned by the next
+/// call to [`flush`](AsyncSenderWorker::flush) or
+/// [`close`](AsyncSenderWorker::close).
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{AsyncSenderWorker, SenderBuilder, TimestampNanos};
+///
+/// # async fn run() -> Result<()> {
+/// let builder = SenderBuilder::from_conf("tcp::addr=localhost:9009;auto_flush_rows=1000;")?;
+/// let worker = AsyncSenderWorker::spawn(&builder, 64).await?;
+/// let mut buffer = worker.new_buffer();
+/// buffer.table("x")?.symbol("a", "b")?.at(TimestampNanos::now())?;
+/// worker.send(buffer).await?;
+/// worker.close().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Should every handle be dropped without a [`close`](AsyncSenderWorker::close)
+/// or [`shutdown`](AsyncSenderWorker::shutdown), the task logs a warning and
+/// keeps flushing the remaining rows for up to 10 seconds before it exits.
+#[derive(Debug, Clone)]
+pub struct AsyncSenderWorker {
+    tx: mpsc::Sender<Message>,
+    state: Arc<WorkerState>,
+    shutdown: Arc<Notify>,
+    health: watch::Receiver<Option<Error>>,
+    stopped: watch::Receiver<bool>,
+    overflow_policy: OverflowPolicy,
+    buffer_template: BufferTemplate,
+}
+
+impl AsyncSenderWorker {
+    /// Build an [`AsyncSender`] and start flushing its rows from a new task on
+    /// the current tokio runtime.
+    ///
+    /// This connects before returning, so connection errors are reported
+    /// here. Up to `queue_capacity` buffers may wait in the queue before
+    /// [`send`](AsyncSenderWorker::send) waits and
+    /// [`try_send`](AsyncSenderWorker::try_send) gives the buffer back.
+    pub async fn spawn(builder: &SenderBuilder, queue_capacity: usize) -> Result<Self> {
+        if queue_capacity == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "Worker queue capacity must be at least 1."
+            ));
+        }
+        let sender = builder.build_async().await?;
+        let (tx, rx) = mpsc::channel(queue_capacity);
+        let (health_tx, health) = watch::channel(None);
+        let (stopped_tx, stopped) = watch::channel(false);
+        let worker = AsyncSenderWorker {
+            tx,
+            state: Arc::default(),
+            shutdown: Arc::default(),
+            health,
+            stopped,
+            overflow_policy: OverflowPolicy::Block,
+            buffer_template: sender.buffer_template,
+        };
+        let auto_flush_on = *builder.auto_flush == AutoFlush::On;
+        let task = Task {
+            pending: sender.new_buffer(),
+            sender,
+            builder: builder.clone(),
+            auto_flush_rows: builder.auto_flush_rows.filter(|_| auto_flush_on),
+            auto_flush_bytes: builder.auto_flush_bytes.filter(|_| auto_flush_on),
+            auto_flush_interval: builder.auto_flush_interval.filter(|_| auto_flush_on),
+            first_row_at: None,
+            state: Arc::clone(&worker.state),
+            shutdown: Arc::clone(&worker.shutdown),
+            health: health_tx,
+            stopped: stopped_tx,
+            error: None,
+            close_replies: Vec::new(),
+        };
+        tokio::spawn(task.run(rx));
+        Ok(worker)
+    }
+
+    /// What [`send`](AsyncSenderWorker::send) does while the queue is full,
+    /// see [`OverflowPolicy`]. The default is to wait.
+    ///
+    /// The policy applies to this handle and the clones made from it
+    /// afterwards. Dropped rows are counted in the [`ShutdownReport`].
+    /// [`OverflowPolicy::DropOldest`] can't take buffers back from the queue,
+    /// so it's rejected here.
+    pub fn with_overflow_policy(mut self, value: OverflowPolicy) -> Result<Self> {
+        if value == OverflowPolicy::DropOldest {
+            return Err(error::fmt!(
+                ConfigError,
+                "An async sender worker's overflow policy can't be `DropOldest`."
+            ));
+        }
+        self.overflow_policy = value;
+        Ok(self)
+    }
+
+    /// Create a buffer that encodes rows for the worker's sender, see
+    /// [`AsyncSender::new_buffer`].
+    pub fn new_buffer(&self) -> Buffer {
+        self.buffer_template.new_buffer()
+    }
+
+    /// Queue the buffer's rows to be flushed.
+    ///
+    /// While the queue is full, this applies the [overflow
+    /// policy](AsyncSenderWorker::with_overflow_policy): By default, it waits.
+    ///
+    /// The buffer must end at a row boundary. This returns once the rows are
+    /// queued, not sent: Await [`flush`](AsyncSenderWorker::flush) to wait
+    /// until they are.
+    pub async fn send(&self, buf: Buffer) -> Result<()> {
+        if self.overflow_policy != OverflowPolicy::Block {
+            return self.try_send(buf).map(|_| ());
+        }
+        self.check(&buf)?;
+        let rows = buf.row_count();
+        self.state.queued(rows);
+        self.tx.send(Message::Rows(buf)).await.map_err(|_| {
+            self.state.unqueued(rows);
+            closed()
+        })
+    }
+
+    /// Queue the buffer's rows to be flushed, without waiting.
+    ///
+    /// If the queue is full and the [overflow
+    /// policy](AsyncSenderWorker::with_overflow_policy) is to block, this
+    /// gives the buffer back instead, so you can retry later or handle the
+    /// backpressure otherwise. The other policies apply as for
+    /// [`send`](AsyncSenderWorker::send).
+    pub fn try_send(&self, buf: Buffer) -> Result<Option<Buffer>> {
+        self.check(&buf)?;
+        let rows = buf.row_count();
+        self.state.queued(rows);
+        match self.tx.try_send(Message::Rows(buf)) {
+            Ok(()) => Ok(None),
+            Err(TrySendError::Full(Message::Rows(buf))) => match self.overflow_policy {
+                OverflowPolicy::DropNewest => {
+                    self.state.dropped(rows);
+                    Ok(None)
+                }
+                OverflowPolicy::Error => {
+                    self.state.unqueued(rows);
+                    Err(error::fmt!(
+                        QueueFull,
+                        "Could not queue {} rows: The sender worker's queue is full.",
+                        rows
+                    ))
+                }
+                _ => {
+                    self.state.unqueued(rows);
+                    Ok(Some(buf))
+                }
+            },
+            Err(_) => {
+                self.state.unqueued(rows);
+                Err(closed())
+            }
+        }
+    }
+
+    /// Flush all the rows queued so far, returning once they are sent.
+    ///
+    /// This returns the first error the task ran into since the last call,
+    /// if any, even if these rows were sent.
+    pub async fn flush(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.tx
+            .send(Message::Flush(reply_tx))
+            .await
+            .map_err(|_| closed())?;
+        reply_rx.await.map_err(|_| closed())?
+    }
+
+    /// Watch the outcome of the task's latest flush: `None` if it succeeded,
+    /// or the error it failed with.
+    pub fn health(&self) -> watch::Receiver<Option<Error>> {
+        self.health.clone()
+    }
+
+    /// Flush the queued rows and stop the task.
+    ///
+    /// This returns the first error not yet reported by
+    /// [`flush`](AsyncSenderWorker::flush), if any. Buffers sent via other
+    /// handles afterwards are rejected.
+    pub async fn close(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.tx
+            .send(Message::Close(reply_tx))
+            .await
+            .map_err(|_| closed())?;
+        reply_rx.await.map_err(|_| closed())?
+    }
+
+    /// Stop accepting rows, flush the queued ones and close the connection.
+    ///
+    /// Unlike [`close`](AsyncSenderWorker::close), this returns by the
+    /// deadline, even if the server stops reading: The rows not sent by then
+    /// are dropped. Shutting down via several handles at once is fine: Each
+    /// call returns a report, and the earliest deadline applies.
+    pub async fn shutdown(&self, deadline: Deadline) -> ShutdownReport {
+        self.state.close(Some(deadline));
+        // Bounds the flush in progress, if any.
+        self.shutdown.notify_waiters();
+        // Wakes the task if it's idle. If the queue is full instead, the task
+        // notices the shutdown as it takes the next buffer.
+        let _ = self.tx.try_send(Message::Shutdown);
+        let mut stopped = self.stopped.clone();
+        let _ = tokio::time::timeout_at(
+            Instant::from_std(deadline.instant()),
+            stopped.wait_for(|stopped| *stopped),
+        )
+        .await;
+        self.state.report()
+    }
+
+    fn check(&self, buf: &Buffer) -> Result<()> {
+        if self.state.is_closing() {
+            return Err(closed());
+        }
+        self.buffer_template.check_can_flush(buf)
+    }
+}
+
+fn closed() -> Error {
+    error::fmt!(InvalidApiCall, "The sender worker was closed.")
+}
+
+struct Task {
+    sender: AsyncSender,
+    pending: Buffer,
+    builder: SenderBuilder,
+    auto_flush_rows: Option<usize>,
+    auto_flush_bytes: Option<usize>,
+    auto_flush_interval: Option<Duration>,
+
+    /// When the oldest pending rows were received, on the runtime's clock.
+    first_row_at: Option<Instant>,
+
+    state: Arc<WorkerState>,
+    shutdown: Arc<Notify>,
+    health: watch::Sender<Option<Error>>,
+    stopped: watch::Sender<bool>,
+
+    /// The first error not yet reported.
+    error: Option<Error>,
+
+    /// The callers of [`AsyncSenderWorker::close`] awaiting the final flush.
+    close_replies: Vec<oneshot::Sender<Result<()>>>,
+}
+
+impl Task {
+    async fn run(mut self, mut rx: mpsc::Receiver<Message>) {
+        loop {
+            let message = if self.state.is_closing() {
+                match rx.try_recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                }
+            } else {
+                let message = match self.flush_deadline() {
+                    Some(deadline) => match tokio::time::timeout_at(deadline, rx.recv()).await {
+                        Ok(message) => message,
+                        Err(_elapsed) => {
+                            self.flush().await;
+                            continue;
+                        }
+                    },
+                    None => rx.recv().await,
+                };
+                let Some(message) = message else {
+                    ingress_log!(
+                        warn,
+                        "Async sender worker dropped without a shutdown: Flushing the queued rows for up to {:?}.",
+                        DROP_FLUSH_TIMEOUT
+                    );
+                    self.state.close(Some(Deadline::after(DROP_FLUSH_TIMEOUT)));
+                    break;
+                };
+                message
+            };
+            match message {
+                Message::Rows(buf) if self.state.past_deadline() => {
+                    self.state.dropped(buf.row_count());
+                }
+                Message::Rows(buf) => {
+                    if self.pending.len() + buf.len() > self.sender.buffer_template.max_buf_size {
+                        self.flush().await;
+                    }
+                    self.pending.append_rows(&buf);
+                    self.first_row_at.get_or_insert_with(Instant::now);
+                    if self.flush_due() {
+                        self.flush().await;
+                    }
+                }
+                Message::Flush(reply) => {
+                    self.flush().await;
+                    let _ = reply.send(self.take_error());
+                }
+                // The queue is drained before stopping.
+                Message::Close(reply) => {
+                    self.state.close(None);
+                    self.close_replies.push(reply);
+                }
+                Message::Shutdown => {}
+            }
+        }
+
+        if self.state.past_deadline() {
+            self.state.dropped(self.pending.row_count());
+        } else {
+            self.flush().await;
+        }
+        let result = self.take_error();
+        for reply in self.close_replies.drain(..) {
+            let _ = reply.send(result.clone());
+        }
+        // Close the connection before telling the handles.
+        drop(self.sender);
+        self.stopped.send_replace(true);
+    }
+
+    fn flush_due(&self) -> bool {
+        if self.auto_flush_rows.is_none()
+            && self.auto_flush_bytes.is_none()
+            && self.auto_flush_interval.is_none()
+        {
+            return true;
+        }
+        self.auto_flush_rows
+            .is_some_and(|rows| self.pending.row_count() >= rows)
+            || self
+                .auto_flush_bytes
+                .is_some_and(|bytes| self.pending.len() >= bytes)
+    }
+
+    /// When the pending rows are due for an interval auto-flush.
+    fn flush_deadline(&self) -> Option<Instant> {
+        self.auto_flush_interval
+            .zip(self.first_row_at)
+            .map(|(interval, first_row_at)| first_row_at + interval)
+    }
+
+    /// Flush the pending rows, connecting again first if the connection was
+    /// lost. Should that fail, the rows are dropped.
+    /// Once shutting down, the flush is abandoned at the deadline.
+    async fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.first_row_at = None;
+        let rows = self.pending.row_count();
+        let deadline_passed = deadline_passed(Arc::clone(&self.state), Arc::clone(&self.shutdown));
+        let result = tokio::select! {
+            biased;
+            result = self.try_flush() => result,
+            () = deadline_passed => Err(error::fmt!(
+                SocketError,
+                "Could not flush buffer: The shutdown deadline has passed."
+            )),
+        };
+        match result {
+            Ok(()) => {
+                self.state.flushed(rows);
+                self.health
+                    .send_if_modified(|health| health.take().is_some());
+            }
+            Err(err) => {
+                self.pending.clear();
+                self.state.failed(rows, &err);
+                self.health.send_replace(Some(err.clone()));
+                self.error.get_or_insert(err);
+            }
+        }
+    }
+
+    async fn try_flush(&mut self) -> Result<()> {
+        if self.sender.must_close() {
+            self.sender = self.builder.build_async().await?;
+        }
+        self.sender.flush(&mut self.pending).await
+    }
+
+    fn take_error(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Resolve once the shutdown deadline has passed, following
+/// [`AsyncSenderWorker::shutdown`] calls made in the meantime.
+async fn deadline_passed(state: Arc<WorkerState>, shutdown: Arc<Notify>) {
+    loop {
+        // Created before reading the deadline, so no call is missed.
+        let notified = shutdown.notified();
+        match state.deadline() {
+            Some(deadline) => {
+                let deadline = Instant::from_std(deadline.instant());
+                if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    return;
+                }
+            }
+            None => notified.await,
+        }
+    }
+}
+
+async fn with_timeout<F: Future>(
+    timeout: Duration,
+    future: F,
+    on_timeout: impl FnOnce() -> error::Error,
+) -> Result<F::Output> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_elapsed| on_timeout())
+}
+
+async fn authenticate(stream: &mut Box<dyn AsyncStream>, auth: &EcdsaAuthParams) -> Result<()> {
+    let key_pair = prepare_auth(auth)?;
+    stream
+        .write_all(format!("{}\n", auth.key_id).as_bytes())
+        .await
+        .map_err(|io_err| map_io_to_socket_err("Failed to send key_id: ", io_err))?;
+    let mut buf = Vec::new();
+    BufReader::new(&mut *stream)
+        .read_until(b'\n', &mut buf)
+        .await
+        .map_err(|io_err| {
+            map_io_to_socket_err("Failed to read authentication challenge: ", io_err)
+        })?;
+    let challenge = check_challenge(buf)?;
+    let encoded_sig = sign_challenge(&key_pair, &challenge)?;
+    stream
+        .write_all(encoded_sig.as_bytes())
+        .await
+        .map_err(|io_err| map_io_to_socket_err("Could not send signed challenge: ", io_err))?;
+    stream
+        .flush()
+        .await
+        .map_err(|io_err| map_io_to_socket_err("Could not send signed challenge: ", io_err))
+}
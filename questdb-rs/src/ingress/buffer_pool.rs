@@ -0,0 +1,217 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use crate::ingress::Buffer;
+
+/// A pool of cleared [`Buffer`] instances, to reuse their allocations across
+/// batches.
+///
+/// [`get`](BufferPool::get) hands out a [`PooledBuffer`], which dereferences to
+/// a `Buffer` and, when dropped, is cleared and returned to the pool. The pool
+/// keeps at most `max_pooled` idle buffers and creates new ones, with the
+/// given initial capacity, when it runs out.
+///
+/// New buffers are created with the default settings of [`Buffer::new`]. To
+/// match a sender's protocol version, maximum size and name checks instead,
+/// pass one of its buffers to [`with_template`](BufferPool::with_template).
+///
+/// So that one unusually large batch doesn't pin its memory for good, a buffer
+/// whose capacity grew past [`max_capacity`](BufferPool::with_max_capacity) is
+/// dropped rather than returned to the pool.
+///
+/// The pool is `Send + Sync` and can be shared across threads, for example
+/// via an `Arc`. A `PooledBuffer` doesn't borrow the pool, so it can be moved
+/// to another thread and returns to the pool from there.
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::BufferPool;
+///
+/// # fn main() -> Result<()> {
+/// let pool = BufferPool::new(4, 64 * 1024);
+/// let mut buffer = pool.get();
+/// buffer.table("x")?.symbol("a", "b")?.at_now()?;
+/// // sender.flush(&mut buffer)?;
+/// drop(buffer);
+/// assert_eq!(pool.idle_count(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BufferPool {
+    shared: Arc<PoolShared>,
+}
+
+/// The state shared by a pool and the buffers it handed out.
+#[derive(Debug)]
+struct PoolShared {
+    max_pooled: usize,
+    capacity: usize,
+    max_capacity: usize,
+    template: Buffer,
+    idle: Mutex<Vec<Buffer>>,
+}
+
+impl BufferPool {
+    /// Create an empty pool that keeps up to `max_pooled` idle buffers, each
+    /// created with room for `capacity` bytes.
+    ///
+    /// Buffers that grow past four times `capacity` aren't pooled, see
+    /// [`with_max_capacity`](BufferPool::with_max_capacity).
+    pub fn new(max_pooled: usize, capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(PoolShared {
+                max_pooled,
+                capacity,
+                max_capacity: capacity.saturating_mul(4),
+                template: Buffer::new(),
+                idle: Mutex::new(Vec::with_capacity(max_pooled)),
+            }),
+        }
+    }
+
+    /// Drop returned buffers whose capacity exceeds `max_capacity` bytes,
+    /// instead of keeping them in the pool.
+    pub fn with_max_capacity(mut self, max_capacity: usize) -> Self {
+        self.shared_mut().max_capacity = max_capacity;
+        self
+    }
+
+    /// Create new buffers with the settings of `template`: Its protocol
+    /// version, maximum size, maximum name length and name and value checks.
+    /// The template's contents are ignored.
+    #[cfg_attr(feature = "net", doc = "")]
+    #[cfg_attr(
+        feature = "net",
+        doc = " Pass a buffer from [`Sender::new_buffer`](crate::ingress::Sender::new_buffer) to match a sender's settings."
+    )]
+    ///
+    /// Idle buffers already in the pool are dropped.
+    pub fn with_template(mut self, mut template: Buffer) -> Self {
+        template.clear();
+        template.output = Vec::new();
+        let shared = self.shared_mut();
+        shared.template = template;
+        shared.idle.get_mut().unwrap().clear();
+        self
+    }
+
+    /// The number of idle buffers held by the pool.
+    pub fn idle_count(&self) -> usize {
+        self.shared.idle.lock().unwrap().len()
+    }
+
+    /// Take an idle buffer from the pool, or create a new one if there's none.
+    ///
+    /// The buffer is empty, and returns to the pool when dropped.
+    pub fn get(&self) -> PooledBuffer {
+        let buffer = self.shared.idle.lock().unwrap().pop();
+        PooledBuffer {
+            pool: Arc::clone(&self.shared),
+            buffer: Some(buffer.unwrap_or_else(|| self.shared.new_buffer())),
+        }
+    }
+
+    fn shared_mut(&mut self) -> &mut PoolShared {
+        if Arc::get_mut(&mut self.shared).is_none() {
+            // Buffers still handed out return to the pool as it was configured.
+            let shared = &self.shared;
+            self.shared = Arc::new(PoolShared {
+                max_pooled: shared.max_pooled,
+                capacity: shared.capacity,
+                max_capacity: shared.max_capacity,
+                template: shared.template.clone(),
+                idle: Mutex::new(Vec::with_capacity(shared.max_pooled)),
+            });
+        }
+        Arc::get_mut(&mut self.shared).unwrap()
+    }
+}
+
+impl PoolShared {
+    fn new_buffer(&self) -> Buffer {
+        let mut buffer = self.template.clone();
+        buffer.output.reserve(self.capacity);
+        buffer.initial_capacity = self.capacity;
+        buffer
+    }
+
+    fn release(&self, mut buffer: Buffer) {
+        if buffer.capacity() > self.max_capacity {
+            return;
+        }
+        buffer.clear();
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_pooled {
+            idle.push(buffer);
+        }
+    }
+}
+
+/// A [`Buffer`] taken from a [`BufferPool`].
+///
+/// Clears the buffer and returns it to the pool when dropped, on whichever
+/// thread that happens.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    pool: Arc<PoolShared>,
+    buffer: Option<Buffer>,
+}
+
+impl PooledBuffer {
+    /// Take the buffer out of the pool for good, to hand it to a call that
+    /// takes ownership of it.
+    #[cfg_attr(
+        feature = "worker",
+        doc = " [`WorkerHandle::send`](crate::ingress::WorkerHandle::send), for example, does."
+    )]
+    pub fn into_inner(mut self) -> Buffer {
+        self.buffer.take().unwrap()
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buffer.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.release(buffer);
+        }
+    }
+}
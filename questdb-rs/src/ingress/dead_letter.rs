@@ -0,0 +1,165 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::fmt::{Debug, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64ct::{Base64, Encoding};
+
+use crate::error::{self, Error, Result};
+
+/// Receives the rows the server permanently rejected, so they aren't lost.
+///
+/// Set it via
+/// [`SenderBuilder::dead_letter_sink`](crate::ingress::SenderBuilder::dead_letter_sink).
+///
+/// With ILP over HTTP, the server refuses a request it can't ingest, e.g.
+/// because of a schema conflict, with a 400, 413 or 422 status. Sending it
+/// again would fail the same way, so the sender hands the refused payload to
+/// the sink before returning the error. Any other failure, such as a network
+/// error, a 408 or 429 status, or a 5xx status, may be transient and never
+/// reaches the sink, even once the retries are exhausted.
+///
+/// The sink is invoked on the thread calling `flush`. A panic in it is caught
+/// and otherwise ignored, as for a [`FlushObserver`](crate::ingress::FlushObserver).
+pub trait DeadLetterSink: Send + Sync {
+    /// Called with the ILP of the refused request, exactly as sent, and the
+    /// error the flush returns.
+    fn reject(&self, ilp_bytes: &[u8], err: &Error);
+}
+
+/// A [`DeadLetterSink`] appending each refused payload to a file, as a line
+/// of JSON:
+///
+/// ```json
+/// {"timestamp":1700000000000000,"code":"ServerFlushError","message":"Could not flush buffer: ...","payload":"trades,symbol=ETH-USD price=2615.54\n"}
+/// ```
+///
+/// The `timestamp` is the time of the rejection in microseconds since the
+/// Unix epoch. The `message` holds the server's error, with its id, code and
+/// line when provided. A payload that isn't valid UTF-8, such as one holding
+/// binary-encoded floats, is written as `payload_base64` instead.
+pub struct DeadLetterFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl DeadLetterFile {
+    /// Open the file for appending, creating it if it doesn't exist.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|io_err| {
+                error::fmt!(
+                    ConfigError,
+                    "Could not open dead-letter file {:?}: {}",
+                    path,
+                    io_err
+                )
+                .with_io_source(io_err)
+            })?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Debug for DeadLetterFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeadLetterFile")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DeadLetterSink for DeadLetterFile {
+    fn reject(&self, ilp_bytes: &[u8], err: &Error) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64);
+        let mut envelope = serde_json::json!({
+            "timestamp": timestamp,
+            "code": format!("{:?}", err.code()),
+            "message": err.msg(),
+        });
+        match std::str::from_utf8(ilp_bytes) {
+            Ok(ilp) => envelope["payload"] = ilp.into(),
+            Err(_) => envelope["payload_base64"] = Base64::encode_string(ilp_bytes).into(),
+        }
+        let mut line = envelope.to_string();
+        line.push('\n');
+
+        // A single write, so concurrent writers don't interleave their lines.
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(io_err) = file.write_all(line.as_bytes()) {
+            ingress_log!(
+                warn,
+                "Could not write {} rejected bytes to dead-letter file {:?}: {}",
+                ilp_bytes.len(),
+                self.path,
+                io_err
+            );
+        }
+    }
+}
+
+/// Hand a refused payload to the sink, catching and discarding any panic.
+pub(crate) fn reject(sink: &Option<DeadLetterRef>, ilp_bytes: &[u8], err: &Error) {
+    if let Some(sink) = sink {
+        let _ = catch_unwind(AssertUnwindSafe(|| sink.0.reject(ilp_bytes, err)));
+    }
+}
+
+/// A `SenderBuilder` setting holding a dead-letter sink.
+#[derive(Clone)]
+pub(crate) struct DeadLetterRef(pub(crate) Arc<dyn DeadLetterSink>);
+
+impl Debug for DeadLetterRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DeadLetterSink")
+    }
+}
+
+impl PartialEq for DeadLetterRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
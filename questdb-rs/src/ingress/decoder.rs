@@ -0,0 +1,584 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Parse ILP text back into rows, e.g. to check what a
+//! [`Buffer`](crate::ingress::Buffer) holds in tests, or to inspect captured
+//! or rejected ILP.
+//!
+//! ```
+//! # use questdb::Result;
+//! use questdb::ingress::decoder::{parse_lines, ParsedValue};
+//!
+//! # fn main() -> Result<()> {
+//! let ilp = "trades,symbol=ETH-USD price=2615.54,note=\"a \\\"big\\\" one\" 1700000000000000000\n";
+//! for row in parse_lines(ilp) {
+//!     let row = row?;
+//!     assert_eq!(row.table(), "trades");
+//!     assert_eq!(row.symbol("symbol"), Some("ETH-USD"));
+//!     assert_eq!(row.column("price"), Some(&ParsedValue::F64(2615.54)));
+//!     assert_eq!(
+//!         row.column("note"),
+//!         Some(&ParsedValue::Str("a \"big\" one".to_owned()))
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! To only check that ILP is well-formed, call [`validate`], which doesn't
+//! allocate per row.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::error::{self, Result};
+use crate::ingress::{Timestamp, TimestampMicros, TimestampNanos};
+
+/// The value of a column, typed per its ILP encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsedValue {
+    /// A boolean, e.g. `t` or `false`.
+    Bool(bool),
+
+    /// An integer, e.g. `42i`.
+    I64(i64),
+
+    /// A float, e.g. `1.5`, `NaN` or `-Infinity`.
+    F64(f64),
+
+    /// A quoted string, unescaped.
+    Str(String),
+
+    /// A timestamp in microseconds, e.g. `12345t`, or in nanoseconds, e.g.
+    /// `12345n`.
+    Timestamp(Timestamp),
+}
+
+/// A row parsed from ILP, with its names and values unescaped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRow {
+    table: String,
+    symbols: Vec<(String, String)>,
+    columns: Vec<(String, ParsedValue)>,
+    timestamp: Option<TimestampNanos>,
+}
+
+impl ParsedRow {
+    /// The table name.
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// The symbols' names and values, in the order they appear in.
+    pub fn symbols(&self) -> &[(String, String)] {
+        &self.symbols
+    }
+
+    /// The value of the first symbol with the given name.
+    pub fn symbol(&self, name: &str) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|(symbol, _)| symbol == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The columns' names and values, in the order they appear in.
+    pub fn columns(&self) -> &[(String, ParsedValue)] {
+        &self.columns
+    }
+
+    /// The value of the first column with the given name.
+    pub fn column(&self, name: &str) -> Option<&ParsedValue> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The designated timestamp, or `None` if the row leaves it to the server.
+    pub fn timestamp(&self) -> Option<TimestampNanos> {
+        self.timestamp
+    }
+}
+
+/// Iterate over the rows in `ilp`, one per line.
+///
+/// Blank lines are skipped. A malformed line yields a
+/// [`MalformedIlp`](crate::ErrorCode::MalformedIlp) error stating the byte
+/// offset of the problem within the line, and of the line within `ilp`, then
+/// parsing resumes at the next line. Each line must end with a newline.
+pub fn parse_lines(ilp: &str) -> ParseLines<'_> {
+    ParseLines { ilp, pos: 0 }
+}
+
+/// An iterator over the rows in ILP text, returned by [`parse_lines`].
+#[derive(Debug, Clone)]
+pub struct ParseLines<'a> {
+    ilp: &'a str,
+    pos: usize,
+}
+
+impl Iterator for ParseLines<'_> {
+    type Item = Result<ParsedRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut lexer = Lexer::new(self.ilp, self.pos);
+        if !lexer.skip_blank_lines() {
+            self.pos = lexer.pos;
+            return None;
+        }
+        let mut row = RowBuilder::default();
+        let result = lexer.row(&mut row);
+        self.pos = match result {
+            Ok(()) => lexer.pos,
+            Err(_) => lexer.next_line(),
+        };
+        Some(result.map(|()| row.build()).map_err(|malformed| {
+            error::fmt!(
+                MalformedIlp,
+                "Malformed ILP at byte {} of the line starting at byte {}: {}.",
+                malformed.at - lexer.line_start,
+                lexer.line_start,
+                malformed.reason
+            )
+        }))
+    }
+}
+
+/// The rows counted by [`validate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    rows: usize,
+    tables: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// The number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of rows for the given table, which is zero for a table
+    /// without any.
+    pub fn rows_for(&self, table: &str) -> usize {
+        self.tables.get(table).copied().unwrap_or(0)
+    }
+
+    /// The tables and their number of rows, ordered by name.
+    pub fn tables(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tables
+            .iter()
+            .map(|(table, rows)| (table.as_str(), *rows))
+    }
+}
+
+/// Check that `ilp` is well-formed, as [`parse_lines`] would parse it without
+/// errors, and count its rows per table.
+///
+/// This reads the values without keeping them, so it only allocates once per
+/// table. The first problem found is returned as a
+/// [`MalformedIlp`](crate::ErrorCode::MalformedIlp) error stating its line and
+/// column, both counted from 1. Since names and strings escape their line
+/// breaks, a row may span several lines.
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::decoder::validate;
+///
+/// # fn main() -> Result<()> {
+/// let stats = validate("trades,symbol=ETH-USD price=2615.54\nquotes bid=1.5\ntrades price=1.0\n")?;
+/// assert_eq!(stats.rows(), 3);
+/// assert_eq!(stats.rows_for("trades"), 2);
+///
+/// let err = validate("trades price=2615.54\ntrades price 1.0\n").unwrap_err();
+/// assert_eq!(
+///     err.msg(),
+///     "Malformed ILP at line 2, column 13: expected `=` after the column name."
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn validate(ilp: &str) -> Result<Stats> {
+    let mut stats = Stats::default();
+    let mut lexer = Lexer::new(ilp, 0);
+    while lexer.skip_blank_lines() {
+        let mut row = RowCounter::default();
+        if let Err(malformed) = lexer.row(&mut row) {
+            let line = ilp.as_bytes()[..malformed.at]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count();
+            let line_start = ilp.as_bytes()[..malformed.at]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |pos| pos + 1);
+            // Count chars rather than bytes, skipping UTF-8 continuation bytes.
+            let column = ilp.as_bytes()[line_start..malformed.at]
+                .iter()
+                .filter(|&&b| b & 0xC0 != 0x80)
+                .count();
+            return Err(error::fmt!(
+                MalformedIlp,
+                "Malformed ILP at line {}, column {}: {}.",
+                line + 1,
+                column + 1,
+                malformed.reason
+            ));
+        }
+        stats.rows += 1;
+        match stats.tables.get_mut(row.table.as_ref()) {
+            Some(rows) => *rows += 1,
+            None => {
+                stats.tables.insert(row.table.into_owned(), 1);
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Receives the parts of a row as the [`Lexer`] reads them.
+trait RowVisitor<'a> {
+    fn table(&mut self, table: Escaped<'a>);
+    fn symbol(&mut self, name: Escaped<'a>, value: Escaped<'a>);
+    fn column(&mut self, name: Escaped<'a>, value: RawValue<'a>);
+    fn timestamp(&mut self, timestamp: TimestampNanos);
+}
+
+/// A name or value as written in the ILP, with its escaping backslashes.
+#[derive(Clone, Copy)]
+struct Escaped<'a>(&'a str);
+
+impl Escaped<'_> {
+    /// Drop the escaping backslashes, keeping the char after each.
+    fn unescape(self) -> String {
+        let mut unescaped = String::with_capacity(self.0.len());
+        let mut chars = self.0.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => unescaped.extend(chars.next()),
+                c => unescaped.push(c),
+            }
+        }
+        unescaped
+    }
+}
+
+/// A column value, before unescaping a string.
+enum RawValue<'a> {
+    Str(Escaped<'a>),
+    Other(ParsedValue),
+}
+
+#[derive(Default)]
+struct RowBuilder {
+    table: String,
+    symbols: Vec<(String, String)>,
+    columns: Vec<(String, ParsedValue)>,
+    timestamp: Option<TimestampNanos>,
+}
+
+impl RowBuilder {
+    fn build(self) -> ParsedRow {
+        ParsedRow {
+            table: self.table,
+            symbols: self.symbols,
+            columns: self.columns,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+impl<'a> RowVisitor<'a> for RowBuilder {
+    fn table(&mut self, table: Escaped<'a>) {
+        self.table = table.unescape();
+    }
+
+    fn symbol(&mut self, name: Escaped<'a>, value: Escaped<'a>) {
+        self.symbols.push((name.unescape(), value.unescape()));
+    }
+
+    fn column(&mut self, name: Escaped<'a>, value: RawValue<'a>) {
+        let value = match value {
+            RawValue::Str(value) => ParsedValue::Str(value.unescape()),
+            RawValue::Other(value) => value,
+        };
+        self.columns.push((name.unescape(), value));
+    }
+
+    fn timestamp(&mut self, timestamp: TimestampNanos) {
+        self.timestamp = Some(timestamp);
+    }
+}
+
+/// Keeps only the table name, unescaped only if it needs to be.
+#[derive(Default)]
+struct RowCounter<'a> {
+    table: Cow<'a, str>,
+}
+
+impl<'a> RowVisitor<'a> for RowCounter<'a> {
+    fn table(&mut self, table: Escaped<'a>) {
+        self.table = if table.0.contains('\\') {
+            Cow::Owned(table.unescape())
+        } else {
+            Cow::Borrowed(table.0)
+        };
+    }
+
+    fn symbol(&mut self, _name: Escaped<'a>, _value: Escaped<'a>) {}
+
+    fn column(&mut self, _name: Escaped<'a>, _value: RawValue<'a>) {}
+
+    fn timestamp(&mut self, _timestamp: TimestampNanos) {}
+}
+
+/// Where and why a line is malformed.
+struct Malformed {
+    at: usize,
+    reason: String,
+}
+
+type Lexed<T> = std::result::Result<T, Malformed>;
+
+/// Reads ILP rows, handing their parts to a [`RowVisitor`].
+struct Lexer<'a> {
+    ilp: &'a str,
+    line_start: usize,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(ilp: &'a str, pos: usize) -> Self {
+        Self {
+            ilp,
+            line_start: pos,
+            pos,
+        }
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        self.ilp.as_bytes()
+    }
+
+    /// Move to the start of the next non-blank line, if any.
+    fn skip_blank_lines(&mut self) -> bool {
+        while self.bytes().get(self.pos) == Some(&b'\n') {
+            self.pos += 1;
+        }
+        self.line_start = self.pos;
+        self.pos < self.ilp.len()
+    }
+
+    fn row<V: RowVisitor<'a>>(&mut self, visitor: &mut V) -> Lexed<()> {
+        visitor.table(self.unquoted("table name", b", \n", false)?);
+        let mut has_symbols = false;
+        while self.eat(b',') {
+            let name = self.unquoted("symbol name", b"=, \n", false)?;
+            self.expect(b'=', "`=` after the symbol name")?;
+            let value = self.unquoted("symbol value", b", \n", true)?;
+            visitor.symbol(name, value);
+            has_symbols = true;
+        }
+
+        if self.eat(b' ') {
+            // A row may go straight from its symbols to its timestamp.
+            if !has_symbols || !self.at_timestamp() {
+                loop {
+                    let name = self.unquoted("column name", b"=, \n", false)?;
+                    self.expect(b'=', "`=` after the column name")?;
+                    let value = self.value(name)?;
+                    visitor.column(name, value);
+                    if !self.eat(b',') {
+                        break;
+                    }
+                }
+                if self.eat(b' ') {
+                    visitor.timestamp(self.timestamp()?);
+                }
+            } else {
+                visitor.timestamp(self.timestamp()?);
+            }
+        } else if !has_symbols {
+            return Err(self.malformed(self.pos, "expected a symbol or a column"));
+        }
+
+        match self.bytes().get(self.pos) {
+            Some(b'\n') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.malformed(self.pos, "expected the end of the line")),
+            None => Err(self.malformed(self.pos, "the line doesn't end with a newline")),
+        }
+    }
+
+    /// Read a name or a symbol value up to one of the unescaped `stops`.
+    fn unquoted(&mut self, what: &str, stops: &[u8], allow_empty: bool) -> Lexed<Escaped<'a>> {
+        let start = self.pos;
+        while let Some(&b) = self.bytes().get(self.pos) {
+            if stops.contains(&b) {
+                break;
+            }
+            if b == b'\\' {
+                self.pos += 1;
+                if self.pos == self.ilp.len() {
+                    return Err(self.malformed(self.pos, "a backslash ends the input"));
+                }
+                // Skip the whole escaped char, so the token ends at a char
+                // boundary.
+                self.pos += utf8_len(self.bytes()[self.pos]);
+            } else {
+                self.pos += 1;
+            }
+        }
+        if self.pos == start && !allow_empty {
+            return Err(self.malformed(start, &format!("expected a {}", what)));
+        }
+        Ok(Escaped(&self.ilp[start..self.pos]))
+    }
+
+    /// Read a column value: a quoted string, or else a token up to the next
+    /// separator.
+    fn value(&mut self, column: Escaped<'a>) -> Lexed<RawValue<'a>> {
+        let start = self.pos;
+        if self.eat(b'"') {
+            loop {
+                match self.bytes().get(self.pos) {
+                    Some(b'"') => break,
+                    Some(b'\\') if self.pos + 1 < self.ilp.len() => {
+                        self.pos += 1 + utf8_len(self.bytes()[self.pos + 1]);
+                    }
+                    Some(&b) if b != b'\\' => self.pos += 1,
+                    _ => return Err(self.malformed(start, "the string isn't closed")),
+                }
+            }
+            self.pos += 1;
+            return Ok(RawValue::Str(Escaped(&self.ilp[start + 1..self.pos - 1])));
+        }
+
+        let token = self.token(b", \n");
+        let value = match token {
+            "t" | "T" | "true" | "True" | "TRUE" => Some(ParsedValue::Bool(true)),
+            "f" | "F" | "false" | "False" | "FALSE" => Some(ParsedValue::Bool(false)),
+            _ => match token.as_bytes().last() {
+                Some(b'i') => token[..token.len() - 1].parse().ok().map(ParsedValue::I64),
+                Some(b't') => token[..token.len() - 1]
+                    .parse()
+                    .ok()
+                    .map(|micros| ParsedValue::Timestamp(TimestampMicros::new(micros).into())),
+                Some(b'n') => token[..token.len() - 1]
+                    .parse()
+                    .ok()
+                    .map(|nanos| ParsedValue::Timestamp(TimestampNanos::new(nanos).into())),
+                Some(_) => token.parse().ok().map(ParsedValue::F64),
+                None => None,
+            },
+        };
+        value.map(RawValue::Other).ok_or_else(|| {
+            self.malformed(
+                start,
+                &format!("bad value {:?} for column {:?}", token, column.unescape()),
+            )
+        })
+    }
+
+    /// Tell whether the rest of the line is a single unescaped token, which
+    /// can only be the designated timestamp.
+    fn at_timestamp(&self) -> bool {
+        self.bytes()[self.pos..]
+            .iter()
+            .take_while(|&&b| b != b'\n')
+            .all(|&b| !matches!(b, b'=' | b',' | b' ' | b'\\'))
+    }
+
+    fn timestamp(&mut self) -> Lexed<TimestampNanos> {
+        let start = self.pos;
+        let token = self.token(b" \n");
+        token
+            .parse()
+            .map(TimestampNanos::new)
+            .map_err(|_| self.malformed(start, &format!("bad designated timestamp {:?}", token)))
+    }
+
+    /// Read up to one of the ASCII `stops`, without unescaping.
+    fn token(&mut self, stops: &[u8]) -> &'a str {
+        let start = self.pos;
+        while self
+            .bytes()
+            .get(self.pos)
+            .is_some_and(|b| !stops.contains(b))
+        {
+            self.pos += 1;
+        }
+        &self.ilp[start..self.pos]
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.bytes().get(self.pos) == Some(&b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8, what: &str) -> Lexed<()> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.malformed(self.pos, &format!("expected {}", what)))
+        }
+    }
+
+    /// Where the line after the current one starts, skipping any escaped
+    /// newline.
+    fn next_line(&self) -> usize {
+        let mut pos = self.pos;
+        while let Some(&b) = self.bytes().get(pos) {
+            match b {
+                b'\\' => pos += 2,
+                b'\n' => return pos + 1,
+                _ => pos += 1,
+            }
+        }
+        self.ilp.len()
+    }
+
+    fn malformed(&self, at: usize, reason: &str) -> Malformed {
+        Malformed {
+            at,
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+/// The length of the UTF-8 char starting with the given byte.
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xF0.. => 4,
+        0xE0.. => 3,
+        0xC0.. => 2,
+        _ => 1,
+    }
+}
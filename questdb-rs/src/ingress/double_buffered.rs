@@ -0,0 +1,319 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+use std::mem;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::{self, Error, Result};
+use crate::ingress::sender::auto_flush_due;
+use crate::ingress::{Buffer, BufferTemplate, Sender, SenderBuilder, Timestamp};
+
+/// A sender that keeps building rows while the previous batch is written out.
+///
+/// It owns two buffers and a background thread: Rows go into the active
+/// buffer while the thread flushes the other one. A [flush](Self::flush)
+/// hands the active buffer over and swaps in the other one, as soon as it's
+/// done flushing. Should both buffers be full, the flush blocks until the
+/// thread is done with the previous batch, so at most one batch is in flight
+/// and rows are sent in the order they were completed.
+///
+/// The flush thread can't report its errors as they happen: Each is returned
+/// by the next call instead, and the rows of the failed batch are dropped.
+/// After a failed write, the thread reconnects before flushing the next batch.
+/// Should that fail, the batch is dropped with the connection error, and the
+/// batch after it tries again. A sender that can't reconnect, such as one
+/// created by [`Sender::from_sink`], fails every batch after the first failed
+/// write: Build a new one instead.
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{DoubleBufferedSender, SenderBuilder, TimestampNanos};
+///
+/// # fn main() -> Result<()> {
+/// let builder = SenderBuilder::from_conf("tcp::addr=localhost:9009;")?
+///     .auto_flush_rows(10_000)?;
+/// let mut sender = DoubleBufferedSender::spawn(&builder)?;
+/// for value in 0..1_000_000 {
+///     sender.buffer().table("trades")?.column_i64("value", value)?;
+///     sender.at(TimestampNanos::now())?;
+/// }
+/// sender.shutdown()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DoubleBufferedSender {
+    active: Buffer,
+
+    /// The other buffer, unless the thread is flushing it.
+    spare: Option<Buffer>,
+    batches: Option<SyncSender<Buffer>>,
+    flushed: Receiver<(Buffer, Result<()>)>,
+    thread: Option<JoinHandle<()>>,
+    buffer_template: BufferTemplate,
+    auto_flush_rows: Option<usize>,
+    auto_flush_bytes: Option<usize>,
+    auto_flush_interval: Option<Duration>,
+
+    /// The first error of the flush thread not yet reported.
+    error: Option<Error>,
+}
+
+impl DoubleBufferedSender {
+    /// Build a sender and start flushing its rows on a new thread.
+    ///
+    /// This connects before returning, so connection errors are reported
+    /// here.
+    pub fn spawn(builder: &SenderBuilder) -> Result<Self> {
+        Self::from_sender(builder.build()?)
+    }
+
+    /// Start flushing the rows of an existing sender on a new thread.
+    ///
+    /// The sender's auto-flush thresholds decide when [`at`](Self::at) and
+    /// [`at_now`](Self::at_now) hand the active buffer over.
+    pub fn from_sender(sender: Sender) -> Result<Self> {
+        let (batches, batch_rx) = mpsc::sync_channel::<Buffer>(1);
+        let (flushed_tx, flushed) = mpsc::sync_channel(1);
+        let active = sender.new_buffer();
+        let spare = sender.new_buffer();
+        let buffer_template = sender.buffer_template;
+        let auto_flush_rows = sender.auto_flush_rows;
+        let auto_flush_bytes = sender.auto_flush_bytes;
+        let auto_flush_interval = sender.auto_flush_interval;
+        let mut sender = sender;
+        let thread = thread::Builder::new()
+            .name("questdb-double-buffered-sender".to_owned())
+            .spawn(move || {
+                for mut batch in batch_rx {
+                    // A failed write leaves the connection unusable.
+                    let result = if sender.must_close() {
+                        sender.reconnect()
+                    } else {
+                        Ok(())
+                    }
+                    .and_then(|()| sender.flush(&mut batch));
+                    if let Err(err) = &result {
+                        ingress_log!(
+                            warn,
+                            "Double-buffered sender dropped {} rows: {}",
+                            batch.row_count(),
+                            err
+                        );
+                        batch.clear();
+                    }
+                    if flushed_tx.send((batch, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|io_err| {
+                error::fmt!(SocketError, "Could not start flush thread: {}", io_err)
+            })?;
+        Ok(Self {
+            active,
+            spare: Some(spare),
+            batches: Some(batches),
+            flushed,
+            thread: Some(thread),
+            buffer_template,
+            auto_flush_rows,
+            auto_flush_bytes,
+            auto_flush_interval,
+            error: None,
+        })
+    }
+
+    /// The active buffer, to add rows to.
+    ///
+    /// Complete each row with [`at`](Self::at) or [`at_now`](Self::at_now)
+    /// rather than with the buffer's own methods, so that it's handed over
+    /// once it crosses an auto-flush threshold.
+    pub fn buffer(&mut self) -> &mut Buffer {
+        &mut self.active
+    }
+
+    /// Complete the current row with the designated timestamp, as
+    /// [`Buffer::at`] does, then [flush](Self::flush) the active buffer if it
+    /// crossed an auto-flush threshold.
+    ///
+    /// If a background flush failed since the last call, this returns its
+    /// error and leaves the row incomplete: Call it again to complete the row.
+    pub fn at<T>(&mut self, timestamp: T) -> Result<()>
+    where
+        T: TryInto<Timestamp>,
+        Error: From<T::Error>,
+    {
+        self.poll();
+        self.take_error()?;
+        self.active.at(timestamp)?;
+        self.auto_flush()
+    }
+
+    /// Complete the current row without a designated timestamp, as
+    /// [`Buffer::at_now`] does, then [flush](Self::flush) the active buffer if
+    /// it crossed an auto-flush threshold.
+    ///
+    /// See [`at`](Self::at).
+    pub fn at_now(&mut self) -> Result<()> {
+        self.poll();
+        self.take_error()?;
+        self.active.at_now()?;
+        self.auto_flush()
+    }
+
+    /// Hand the rows of the active buffer over to the flush thread and carry
+    /// on with the other buffer.
+    ///
+    /// This returns as soon as the rows are handed over, not sent, unless the
+    /// thread is still flushing the previous batch: Then it blocks until
+    /// that's done. If the previous batch or any before it failed, this
+    /// returns the error instead and the active buffer keeps its rows.
+    ///
+    /// Like [`Sender::flush`], this returns an error if the buffer ends
+    /// mid-row. Flushing an empty buffer is a no-op.
+    pub fn flush(&mut self) -> Result<()> {
+        self.poll();
+        self.take_error()?;
+        if self.active.is_empty() {
+            return Ok(());
+        }
+        self.buffer_template.check_can_flush(&self.active)?;
+        if self.spare.is_none() {
+            self.wait()?;
+            self.take_error()?;
+        }
+        let Some(spare) = self.spare.take() else {
+            return Err(stopped());
+        };
+        let batch = mem::replace(&mut self.active, spare);
+        match &self.batches {
+            Some(batches) => batches.send(batch).map_err(|_| stopped()),
+            None => Err(stopped()),
+        }
+    }
+
+    /// [Flush](Self::flush) the active buffer and block until all the rows
+    /// are sent.
+    ///
+    /// This returns the first error the flush thread ran into since the last
+    /// call, if any.
+    pub fn flush_sync(&mut self) -> Result<()> {
+        self.flush()?;
+        if self.spare.is_none() {
+            self.wait()?;
+        }
+        self.take_error()
+    }
+
+    /// Flush the remaining rows and stop the flush thread.
+    ///
+    /// This returns the first error not reported yet, if any.
+    pub fn shutdown(mut self) -> Result<()> {
+        let result = self.flush_sync();
+        self.stop();
+        result
+    }
+
+    /// Pick up the batch the thread is done with, if any, without blocking.
+    fn poll(&mut self) {
+        match self.flushed.try_recv() {
+            Ok(flushed) => self.finish(flushed),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.error.get_or_insert_with(stopped);
+            }
+        }
+    }
+
+    /// Block until the thread is done with the batch in flight.
+    fn wait(&mut self) -> Result<()> {
+        let flushed = self.flushed.recv().map_err(|_| stopped())?;
+        self.finish(flushed);
+        Ok(())
+    }
+
+    fn finish(&mut self, (batch, result): (Buffer, Result<()>)) {
+        self.spare = Some(batch);
+        if let Err(err) = result {
+            self.error.get_or_insert(err);
+        }
+    }
+
+    fn take_error(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn auto_flush(&mut self) -> Result<()> {
+        if auto_flush_due(
+            &self.active,
+            self.auto_flush_rows,
+            self.auto_flush_bytes,
+            self.auto_flush_interval,
+        ) {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the batch channel and wait for the thread to finish the batch in
+    /// flight.
+    fn stop(&mut self) {
+        self.batches = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DoubleBufferedSender {
+    fn drop(&mut self) {
+        if self.thread.is_none() {
+            return;
+        }
+        if !self.active.is_empty() {
+            ingress_log!(
+                warn,
+                "Double-buffered sender dropped without a shutdown: Flushing {} rows.",
+                self.active.row_count()
+            );
+        }
+        if let Err(err) = self.flush_sync() {
+            ingress_log!(warn, "Double-buffered sender failed to flush: {}", err);
+        }
+        self.stop();
+    }
+}
+
+fn stopped() -> Error {
+    error::fmt!(
+        InvalidApiCall,
+        "The double-buffered sender's flush thread has stopped."
+    )
+}
@@ -7,6 +7,7 @@ use std::thread::sleep;
 use std::time::Duration;
 
 use super::conf::ConfigSetting;
+use super::dead_letter::DeadLetterRef;
 
 #[derive(PartialEq, Debug, Clone)]
 pub(super) struct BasicAuthParams {
@@ -45,6 +46,7 @@ pub(super) struct HttpConfig {
     pub(super) user_agent: String,
     pub(super) retry_timeout: ConfigSetting<Duration>,
     pub(super) request_timeout: ConfigSetting<Duration>,
+    pub(super) dead_letter: ConfigSetting<Option<DeadLetterRef>>,
 }
 
 impl Default for HttpConfig {
@@ -54,6 +56,7 @@ impl Default for HttpConfig {
             user_agent: concat!("questdb/rust/", env!("CARGO_PKG_VERSION")).to_string(),
             retry_timeout: ConfigSetting::new_default(Duration::from_secs(10)),
             request_timeout: ConfigSetting::new_default(Duration::from_secs(10)),
+            dead_letter: ConfigSetting::new_default(None),
         }
     }
 }
@@ -163,26 +166,39 @@ pub(super) fn parse_http_error(http_status_code: u16, response: ureq::Response)
 }
 
 pub(super) fn is_retriable_error(err: &ureq::Error) -> bool {
-    use ureq::Error::*;
     match err {
-        Transport(_) => true,
+        ureq::Error::Transport(_) => true,
+        ureq::Error::Status(http_status_code, _) => is_retriable_status(*http_status_code),
+    }
+}
 
+pub(super) fn is_retriable_status(http_status_code: u16) -> bool {
+    matches!(
+        http_status_code,
         // Official HTTP codes
-        Status(500, _) |  // Internal Server Error
-        Status(503, _) |  // Service Unavailable
-        Status(504, _) |  // Gateway Timeout
+        500 | // Internal Server Error
+        503 | // Service Unavailable
+        504 | // Gateway Timeout
 
         // Unofficial extensions
-        Status(507, _) | // Insufficient Storage
-        Status(509, _) | // Bandwidth Limit Exceeded
-        Status(523, _) | // Origin is Unreachable
-        Status(524, _) | // A Timeout Occurred
-        Status(529, _) | // Site is overloaded
-        Status(599, _) => { // Network Connect Timeout Error
-            true
-        }
-        _ => false
-    }
+        507 | // Insufficient Storage
+        509 | // Bandwidth Limit Exceeded
+        523 | // Origin is Unreachable
+        524 | // A Timeout Occurred
+        529 | // Site is overloaded
+        599 // Network Connect Timeout Error
+    )
+}
+
+/// Whether the server refused the request for good, so that sending the same
+/// bytes again would fail the same way.
+pub(super) fn is_permanent_rejection_status(http_status_code: u16) -> bool {
+    matches!(
+        http_status_code,
+        400 | // Bad Request
+        413 | // Payload Too Large
+        422 // Unprocessable Entity
+    )
 }
 
 #[allow(clippy::result_large_err)] // `ureq::Error` is large enough to cause this warning.
@@ -233,3 +249,28 @@ pub(super) fn http_send_with_retries(
 
     retry_http_send(request, buf, retry_timeout, last_err)
 }
+
+/// Send `GET /ping` to the server, which QuestDB answers without touching any
+/// table.
+pub(super) fn http_ping(state: &HttpHandlerState) -> crate::Result<()> {
+    let base = state.url.strip_suffix("/write").unwrap_or(&state.url);
+    let request = state
+        .agent
+        .get(&format!("{base}/ping"))
+        .timeout(*state.config.request_timeout);
+    let request = match state.auth.as_ref() {
+        Some(auth) => request.set("Authorization", auth),
+        None => request,
+    };
+    match request.call() {
+        Ok(_response) => Ok(()),
+        Err(ureq::Error::Status(http_status_code, _response)) => Err(error::fmt!(
+            SocketError,
+            "Ping failed: Server responded with HTTP status {}.",
+            http_status_code
+        )),
+        Err(ureq::Error::Transport(transport)) => {
+            Err(error::fmt!(SocketError, "Ping failed: {}", transport))
+        }
+    }
+}
@@ -0,0 +1,78 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::time::Duration;
+
+use ::metrics::{counter, histogram, Counter, Histogram};
+
+/// Handles to the metrics a sender updates on each flush.
+///
+/// They are registered once, when the sender is created, with the recorder
+/// installed at the time. Updating them is then cheap, and a no-op if there's
+/// no recorder.
+#[derive(Clone)]
+pub(crate) struct FlushMetrics {
+    rows_sent: Counter,
+    bytes_sent: Counter,
+    flush_errors: Counter,
+    flush_duration: Histogram,
+}
+
+impl FlushMetrics {
+    pub(crate) fn new(label: Option<&str>) -> Self {
+        match label {
+            Some(label) => {
+                let label = label.to_owned();
+                Self {
+                    rows_sent: counter!("questdb_client_rows_sent_total", "sender" => label.clone()),
+                    bytes_sent: counter!("questdb_client_bytes_sent_total", "sender" => label.clone()),
+                    flush_errors: counter!("questdb_client_flush_errors_total", "sender" => label.clone()),
+                    flush_duration: histogram!("questdb_client_flush_duration_seconds", "sender" => label),
+                }
+            }
+            None => Self {
+                rows_sent: counter!("questdb_client_rows_sent_total"),
+                bytes_sent: counter!("questdb_client_bytes_sent_total"),
+                flush_errors: counter!("questdb_client_flush_errors_total"),
+                flush_duration: histogram!("questdb_client_flush_duration_seconds"),
+            },
+        }
+    }
+
+    pub(crate) fn on_flush(&self, rows: usize, bytes: usize, duration: Duration) {
+        self.rows_sent.increment(rows as u64);
+        self.bytes_sent.increment(bytes as u64);
+        self.flush_duration.record(duration.as_secs_f64());
+    }
+
+    pub(crate) fn on_error(&self) {
+        self.flush_errors.increment(1);
+    }
+}
+
+impl std::fmt::Debug for FlushMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FlushMetrics")
+    }
+}
@@ -22,29 +22,100 @@
  *
  ******************************************************************************/
 
-#![doc = include_str!("mod.md")]
+#![cfg_attr(feature = "net", doc = include_str!("mod.md"))]
+#![cfg_attr(
+    not(feature = "net"),
+    doc = "Serialize rows into a [`Buffer`] as InfluxDB Line Protocol (ILP). \
+           Without the `net` feature, sending the buffer's \
+           [bytes](Buffer::as_bytes) is up to you."
+)]
 
+#[cfg(feature = "async-tokio")]
+pub use self::async_tokio::*;
+pub use self::buffer_pool::*;
+#[cfg(feature = "ilp-over-http")]
+pub use self::dead_letter::*;
+#[cfg(feature = "worker")]
+pub use self::double_buffered::*;
+#[cfg(feature = "net")]
+pub use self::observer::*;
+#[cfg(feature = "net")]
+pub use self::pool::*;
+#[cfg(feature = "net")]
+pub use self::resolver::*;
+#[cfg(feature = "net")]
+pub use self::sender::*;
+#[cfg(feature = "net")]
+pub use self::shared::*;
+#[cfg(any(feature = "worker", feature = "async-tokio"))]
+pub use self::shutdown::*;
+#[cfg(feature = "net")]
+pub use self::spill::*;
+#[cfg(feature = "net")]
+pub use self::tee::*;
 pub use self::timestamp::*;
+#[cfg(feature = "worker")]
+pub use self::worker::*;
 
 use crate::error::{self, Error, Result};
-use crate::gai;
-use crate::ingress::conf::ConfigSetting;
-use core::time::Duration;
-use std::collections::HashMap;
 use std::convert::Infallible;
-use std::fmt::{Debug, Display, Formatter, Write};
-use std::io::{self, BufRead, BufReader, ErrorKind, Write as IoWrite};
-use std::ops::Deref;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::fmt::{Debug, Formatter};
+use std::io;
 use std::sync::Arc;
+#[cfg(feature = "net")]
+use std::time::Instant;
+
+/// Log under the `questdb::ingress` target if the `log` feature is enabled.
+///
+/// Usage: `ingress_log!(debug, "Connected to {}", addr)`.
+/// Keep this off the per-row and per-column paths.
+#[cfg(feature = "net")]
+macro_rules! ingress_log {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::$level!(target: "questdb::ingress", $($arg)+);
+        // Without logging, type-check the arguments but never evaluate them.
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+/// Create a span under the `questdb::ingress` target if the `tracing` feature
+/// is enabled, to pass to [`in_span`].
+///
+/// Usage: `ingress_span!("flush", bytes = buf.len())`.
+/// Keep this off the per-row and per-column paths.
+#[cfg(feature = "net")]
+macro_rules! ingress_span {
+    ($name:literal, $($fields:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = ::tracing::info_span!(target: "questdb::ingress", $name, $($fields)*);
+        #[cfg(not(feature = "tracing"))]
+        let span = NoSpan;
+        span
+    }};
+}
+
+/// Run `f` within the span, recording any error it returns as an event in
+/// the span.
+#[cfg(all(feature = "net", feature = "tracing"))]
+fn in_span<T>(span: tracing::Span, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let _guard = span.enter();
+    f().inspect_err(
+        |err| tracing::error!(target: "questdb::ingress", code = ?err.code(), "{}", err.msg()),
+    )
+}
 
-use base64ct::{Base64, Base64UrlUnpadded, Encoding};
-use ring::rand::SystemRandom;
-use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
-use rustls::{ClientConnection, RootCertStore, StreamOwned};
-use rustls_pki_types::ServerName;
-use socket2::{Domain, Protocol as SockProtocol, SockAddr, Socket, Type};
+/// Stands in for a span when the `tracing` feature is disabled.
+#[cfg(all(feature = "net", not(feature = "tracing")))]
+struct NoSpan;
+
+#[cfg(all(feature = "net", not(feature = "tracing")))]
+fn in_span<T>(_span: NoSpan, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    f()
+}
 
 #[derive(Debug, Copy, Clone)]
 enum Op {
@@ -67,8 +138,322 @@ impl Op {
     }
 }
 
+#[cfg(feature = "net")]
 fn map_io_to_socket_err(prefix: &str, io_err: io::Error) -> Error {
-    error::fmt!(SocketError, "{}{}", prefix, io_err)
+    error::fmt!(SocketError, "{}{}", prefix, io_err).with_io_source(io_err)
+}
+
+/// The characters, other than control characters, that neither table nor
+/// column names may contain.
+///
+/// Names can't contain any control character either. Column names can't
+/// contain a dot `.` or a hyphen `-`, and table names only allow single dots
+/// between other characters. See [`TableName`] and [`ColumnName`] for all the
+/// rules, and their `is_valid` functions to check a name against them.
+pub const RESERVED_NAME_CHARS: &[char] = &[
+    '?', ',', '\'', '"', '\\', '/', ':', ')', '(', '+', '*', '%', '~', '\u{feff}',
+];
+
+/// How strictly a [`Buffer`] checks the characters of the table and column
+/// names passed to it as plain strings.
+///
+/// Set it with [`Buffer::set_validation`]. It applies to names only: Symbol
+/// and column values are always escaped as needed. Names always have their
+/// length checked.
+///
+#[cfg_attr(
+    feature = "net",
+    doc = " A sender's buffers take it from [`SenderBuilder::validation`]."
+)]
+#[cfg_attr(feature = "net", doc = "")]
+/// [`TableName`] and [`ColumnName`] values built with their `new` functions
+/// were checked by the [`Default`](Validation::Default) rules already, and
+/// only [`Strict`](Validation::Strict) checks them further.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Validation {
+    /// Also reject names with non-ASCII characters, to catch names built
+    /// from the wrong data early.
+    Strict,
+
+    /// Reject names that break the rules described for [`TableName`] and
+    /// [`ColumnName`].
+    #[default]
+    Default,
+
+    /// Don't check the characters of names, e.g. to write to tables created
+    /// with quoted names over SQL. Names still can't be empty. The server
+    /// rejects names it doesn't accept, by closing the connection.
+    Off,
+}
+
+/// Whether the character is a Unicode control character: C0, DEL or C1.
+///
+/// This is [`char::is_control`], as a `const fn`.
+const fn is_control_char(c: char) -> bool {
+    matches!(c, '\0'..='\u{1f}' | '\u{7f}'..='\u{9f}')
+}
+
+const fn is_reserved_name_char(c: char) -> bool {
+    let mut index = 0;
+    while index < RESERVED_NAME_CHARS.len() {
+        if RESERVED_NAME_CHARS[index] == c {
+            return true;
+        }
+        index += 1;
+    }
+    false
+}
+
+/// Decode the character starting at byte `pos` of valid UTF-8, returning it
+/// and its length in bytes.
+const fn decode_char(bytes: &[u8], pos: usize) -> (char, usize) {
+    let first = bytes[pos];
+    let (len, mut code) = match first {
+        0x00..=0x7f => return (first as char, 1),
+        0xc0..=0xdf => (2, (first & 0x1f) as u32),
+        0xe0..=0xef => (3, (first & 0x0f) as u32),
+        _ => (4, (first & 0x07) as u32),
+    };
+    let mut index = 1;
+    while index < len {
+        code = (code << 6) | (bytes[pos + index] & 0x3f) as u32;
+        index += 1;
+    }
+    match char::from_u32(code) {
+        Some(c) => (c, len),
+        None => panic!("Invalid UTF-8."),
+    }
+}
+
+/// Decode the last character of non-empty valid UTF-8.
+const fn decode_last_char(bytes: &[u8]) -> char {
+    let mut pos = bytes.len() - 1;
+    while bytes[pos] & 0xc0 == 0x80 {
+        pos -= 1;
+    }
+    decode_char(bytes, pos).0
+}
+
+/// The first rule a table or column name breaks, found without formatting an
+/// error.
+#[derive(Debug, Clone, Copy)]
+enum NameFault {
+    Empty,
+
+    /// QuestDB would keep leading or trailing whitespace, but it's almost
+    /// always a mistake and makes the table or column hard to query.
+    StartsWithWhitespace,
+    EndsWithWhitespace,
+
+    Dot(usize),
+    Char(usize, char),
+    Bom(usize),
+    Control(usize, char),
+
+    /// A non-ASCII or control character, which only [`Validation::Strict`]
+    /// rejects.
+    NotStrict(usize, char),
+}
+
+impl NameFault {
+    /// Find the first fault in the name.
+    ///
+    /// This is a `const fn` so that [`table_name!`](crate::table_name) and
+    /// [`column_name!`](crate::column_name) can check names at compile time
+    /// by the same rules.
+    const fn find(name: &str, is_table: bool) -> Option<Self> {
+        if name.is_empty() {
+            return Some(NameFault::Empty);
+        }
+        let bytes = name.as_bytes();
+        if decode_char(bytes, 0).0.is_whitespace() {
+            return Some(NameFault::StartsWithWhitespace);
+        }
+        if decode_last_char(bytes).is_whitespace() {
+            return Some(NameFault::EndsWithWhitespace);
+        }
+
+        let mut prev = '\0';
+        let mut index = 0;
+        while index < bytes.len() {
+            let (c, len) = decode_char(bytes, index);
+            match c {
+                _ if c.is_ascii_alphanumeric() => {}
+                '.' if is_table && (index == 0 || index == bytes.len() - 1 || prev == '.') => {
+                    return Some(NameFault::Dot(index));
+                }
+                '.' if is_table => {}
+                '.' | '-' if !is_table => return Some(NameFault::Char(index, c)),
+                _ if is_control_char(c) => return Some(NameFault::Control(index, c)),
+                // The UTF-8 BOM, aka 'ZERO WIDTH NO-BREAK SPACE'.
+                '\u{feff}' => return Some(NameFault::Bom(index)),
+                _ if is_reserved_name_char(c) => return Some(NameFault::Char(index, c)),
+                _ => {}
+            }
+            prev = c;
+            index += len;
+        }
+        None
+    }
+
+    fn find_strict(name: &str) -> Option<Self> {
+        name.char_indices()
+            .find(|&(_, c)| !c.is_ascii() || c.is_ascii_control())
+            .map(|(index, c)| NameFault::NotStrict(index, c))
+    }
+
+    /// Fail compilation with the fault, when called in a const context.
+    const fn panic(self, is_table: bool) -> ! {
+        match (self, is_table) {
+            (NameFault::Empty, _) => panic!("Names must have a non-zero length."),
+            (NameFault::StartsWithWhitespace, _) => panic!("Names can't start with whitespace."),
+            (NameFault::EndsWithWhitespace, _) => panic!("Names can't end with whitespace."),
+            (NameFault::Dot(_), _) => panic!("Found invalid dot `.` in table name."),
+            (NameFault::Char(_, _), true) => {
+                panic!("Table names can't contain any of `?,'\"\\/:)(+*%~` or control characters.")
+            }
+            (NameFault::Char(_, _), false) => {
+                panic!(
+                    "Column names can't contain any of `?.,'\"\\/:)(+-*%~` or control characters."
+                )
+            }
+            (NameFault::Bom(_), _) => panic!("Names can't contain a UTF-8 BOM character."),
+            (NameFault::Control(_, _), _) => panic!("Names can't contain control characters."),
+            (NameFault::NotStrict(_, _), _) => {
+                panic!("Names can't contain non-ASCII or control characters.")
+            }
+        }
+    }
+
+    fn into_error(self, kind: &str, name: &str) -> Error {
+        match self {
+            NameFault::Empty => {
+                error::fmt!(InvalidName, "{} names must have a non-zero length.", kind)
+            }
+            NameFault::StartsWithWhitespace => error::fmt!(
+                InvalidName,
+                "Bad string {:?}: {} names can't start with whitespace.",
+                name,
+                kind
+            ),
+            NameFault::EndsWithWhitespace => error::fmt!(
+                InvalidName,
+                "Bad string {:?}: {} names can't end with whitespace.",
+                name,
+                kind
+            ),
+            NameFault::Dot(index) => error::fmt!(
+                InvalidName,
+                concat!(
+                    "Bad string {:?}: ",
+                    "Found invalid dot `.` at byte position {}."
+                ),
+                name,
+                index
+            ),
+            NameFault::Char(index, c) => error::fmt!(
+                InvalidName,
+                concat!(
+                    "Bad string {:?}: ",
+                    "{} names can't contain ",
+                    "a {:?} character, which was found at ",
+                    "byte position {}."
+                ),
+                name,
+                kind,
+                c,
+                index
+            ),
+            NameFault::Bom(index) => error::fmt!(
+                InvalidName,
+                concat!(
+                    "Bad string {:?}: ",
+                    "{} names can't contain ",
+                    "a UTF-8 BOM character, which was found at ",
+                    "byte position {}."
+                ),
+                name,
+                kind,
+                index
+            ),
+            NameFault::Control(index, c) => error::fmt!(
+                InvalidName,
+                concat!(
+                    "Bad string {:?}: ",
+                    "{} names can't contain ",
+                    "the control character U+{:04X}, which was found at ",
+                    "byte position {}."
+                ),
+                name,
+                kind,
+                c as u32,
+                index
+            ),
+            NameFault::NotStrict(index, c) => error::fmt!(
+                InvalidName,
+                concat!(
+                    "Bad string {:?}: ",
+                    "{} names can't contain ",
+                    "a {:?} character in strict validation mode, ",
+                    "which was found at byte position {}."
+                ),
+                name,
+                kind,
+                c,
+                index
+            ),
+        }
+    }
+}
+
+/// QuestDB's default maximum length of table and column names in bytes, see
+/// [`Buffer::with_max_name_len`] and [`SenderBuilder::max_name_len`].
+const DEFAULT_MAX_NAME_LEN: usize = 127;
+
+fn row_too_large(row_len: usize, buf_len: usize, max_buf_size: usize) -> Error {
+    error::fmt!(
+        BufferTooLarge,
+        "Could not add row of {} bytes: It would grow the buffer to {} bytes, exceeding the maximum of {} bytes. Flush the buffer first.",
+        row_len,
+        buf_len + row_len,
+        max_buf_size
+    )
+}
+
+/// The offsets just past the newline of each complete row of ILP.
+///
+/// A newline preceded by an escaping backslash is part of a value, not the end
+/// of a row.
+fn row_ends(ilp: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    let mut escaped = false;
+    ilp.iter().enumerate().filter_map(move |(index, &byte)| {
+        let was_escaped = escaped;
+        escaped = !was_escaped && byte == b'\\';
+        (!was_escaped && byte == b'\n').then_some(index + 1)
+    })
+}
+
+/// View a buffer's bytes as the text they were built from.
+///
+/// A buffer only ever appends `&str`s and ASCII bytes, and only cuts its
+/// contents where a previous append ended, so they're always valid UTF-8.
+fn bytes_as_str(bytes: &[u8]) -> &str {
+    debug_assert!(std::str::from_utf8(bytes).is_ok());
+    // SAFETY: See above.
+    unsafe { std::str::from_utf8_unchecked(bytes) }
+}
+
+fn check_name_len(name: &str, max_name_len: usize) -> Result<()> {
+    if name.len() > max_name_len {
+        return Err(error::fmt!(
+            InvalidName,
+            "Bad name: {:?}: Too long ({} bytes, max {} bytes)",
+            name,
+            name.len(),
+            max_name_len
+        ));
+    }
+    Ok(())
 }
 
 /// A validated table name.
@@ -77,82 +462,115 @@ fn map_io_to_socket_err(prefix: &str, io_err: io::Error) -> Error {
 ///
 /// When you pass a `TableName` instead of a plain string to a [`Buffer`] method,
 /// it doesn't have to validate it again. This saves CPU cycles.
+///
+/// A table name must not be empty, and must not:
+/// * start or end with whitespace,
+/// * start or end with a dot `.`, or contain two dots in a row,
+/// * contain any of `?,'"\/:)(+*%~`,
+/// * contain a control character: `\0` to `\x1f`, `\x7f`, or `\u{80}` to
+///   `\u{9f}`,
+/// * contain a UTF-8 BOM `\u{feff}`.
+///
+/// It must also be no longer than 127 bytes, QuestDB's default limit, or
+/// the limit passed to [`new_with_max_len`](TableName::new_with_max_len).
+///
+/// Names may start with a digit, but SQL queries then have to quote them.
+///
+/// To check a name without building an error, call
+/// [`is_valid`](TableName::is_valid).
 #[derive(Clone, Copy)]
 pub struct TableName<'a> {
     name: &'a str,
+
+    /// The name as escaped for ILP, if an [`OwnedTableName`] escaped it in
+    /// advance.
+    escaped: Option<&'a [u8]>,
+
+    /// Whether the name's characters were checked already, rather than left
+    /// to the buffer's [`Validation`] mode.
+    validated: bool,
 }
 
 impl<'a> TableName<'a> {
     /// Construct a validated table name.
     pub fn new(name: &'a str) -> Result<Self> {
-        if name.is_empty() {
-            return Err(error::fmt!(
-                InvalidName,
-                "Table names must have a non-zero length."
-            ));
-        }
+        Self::new_with_max_len(name, DEFAULT_MAX_NAME_LEN)
+    }
 
-        let mut prev = '\0';
-        for (index, c) in name.chars().enumerate() {
-            match c {
-                '.' => {
-                    if index == 0 || index == name.len() - 1 || prev == '.' {
-                        return Err(error::fmt!(
-                            InvalidName,
-                            concat!("Bad string {:?}: ", "Found invalid dot `.` at position {}."),
-                            name,
-                            index
-                        ));
-                    }
-                }
-                '?' | ',' | '\'' | '\"' | '\\' | '/' | ':' | ')' | '(' | '+' | '*' | '%' | '~'
-                | '\r' | '\n' | '\0' | '\u{0001}' | '\u{0002}' | '\u{0003}' | '\u{0004}'
-                | '\u{0005}' | '\u{0006}' | '\u{0007}' | '\u{0008}' | '\u{0009}' | '\u{000b}'
-                | '\u{000c}' | '\u{000e}' | '\u{000f}' | '\u{007f}' => {
-                    return Err(error::fmt!(
-                        InvalidName,
-                        concat!(
-                            "Bad string {:?}: ",
-                            "Table names can't contain ",
-                            "a {:?} character, which was found at ",
-                            "byte position {}."
-                        ),
-                        name,
-                        c,
-                        index
-                    ));
-                }
-                '\u{feff}' => {
-                    // Reject unicode char 'ZERO WIDTH NO-BREAK SPACE',
-                    // aka UTF-8 BOM if it appears anywhere in the string.
-                    return Err(error::fmt!(
-                        InvalidName,
-                        concat!(
-                            "Bad string {:?}: ",
-                            "Table names can't contain ",
-                            "a UTF-8 BOM character, which was found at ",
-                            "byte position {}."
-                        ),
-                        name,
-                        index
-                    ));
-                }
-                _ => (),
-            }
-            prev = c;
+    /// Construct a validated table name of at most `max_name_len` bytes.
+    ///
+    /// This should match the `cairo.max.file.name.length` setting of the
+    /// QuestDB instance you're connecting to.
+    pub fn new_with_max_len(name: &'a str, max_name_len: usize) -> Result<Self> {
+        let validated = Self::validate(name)?;
+        check_name_len(name, max_name_len)?;
+        Ok(validated)
+    }
+
+    /// Tell whether the name is valid, without building an error if it isn't.
+    ///
+    /// This checks the same rules as [`new`](TableName::new), including the
+    /// default length limit.
+    pub fn is_valid(name: &str) -> bool {
+        NameFault::find(name, true).is_none() && name.len() <= DEFAULT_MAX_NAME_LEN
+    }
+
+    /// Construct a validated table name in a const context, such as a
+    /// `const` item, where an invalid name fails compilation.
+    ///
+    /// This checks the same rules as [`new`](TableName::new), but panics
+    /// instead of returning an error. The [`table_name!`](crate::table_name)
+    /// macro calls it for you.
+    ///
+    /// ```
+    /// use questdb::ingress::TableName;
+    ///
+    /// const NAME: TableName = TableName::new_const("trades");
+    /// ```
+    pub const fn new_const(name: &'a str) -> Self {
+        if let Some(fault) = NameFault::find(name, true) {
+            fault.panic(true);
+        }
+        if name.len() > DEFAULT_MAX_NAME_LEN {
+            panic!("Names can't be longer than 127 bytes.");
+        }
+        Self {
+            name,
+            escaped: None,
+            validated: true,
         }
+    }
 
-        Ok(Self { name })
+    fn validate(name: &'a str) -> Result<Self> {
+        match NameFault::find(name, true) {
+            Some(fault) => Err(fault.into_error("Table", name)),
+            None => Ok(Self {
+                name,
+                escaped: None,
+                validated: true,
+            }),
+        }
     }
 
     /// Construct a table name without validating it.
     ///
     /// This breaks API encapsulation and is only intended for use
-    /// when the the string was already previously validated.
+    /// when the the string was already previously validated: Names validated
+    /// once at startup can then be passed to a [`Buffer`] on every row without
+    /// scanning them again. The buffer still checks their length.
     ///
-    /// The QuestDB server will reject an invalid table name.
+    /// The QuestDB server will reject an invalid table name. Debug builds
+    /// validate the name anyway, and panic if it's invalid.
     pub fn new_unchecked(name: &'a str) -> Self {
-        Self { name }
+        debug_assert!(
+            NameFault::find(name, true).is_none(),
+            "Invalid table name passed to `TableName::new_unchecked`: {name:?}"
+        );
+        Self {
+            name,
+            escaped: None,
+            validated: true,
+        }
     }
 }
 
@@ -162,251 +580,481 @@ impl<'a> TableName<'a> {
 ///
 /// When you pass a `ColumnName` instead of a plain string to a [`Buffer`] method,
 /// it doesn't have to validate it again. This saves CPU cycles.
+///
+/// A column name must not be empty, and must not:
+/// * start or end with whitespace,
+/// * contain any of `?.,'"\/:)(+-*%~`,
+/// * contain a control character: `\0` to `\x1f`, `\x7f`, or `\u{80}` to
+///   `\u{9f}`,
+/// * contain a UTF-8 BOM `\u{feff}`.
+///
+/// It must also be no longer than 127 bytes, QuestDB's default limit, or
+/// the limit passed to [`new_with_max_len`](ColumnName::new_with_max_len).
+///
+/// Names may start with a digit, but SQL queries then have to quote them.
+///
+/// To check a name without building an error, call
+/// [`is_valid`](ColumnName::is_valid).
 #[derive(Clone, Copy)]
 pub struct ColumnName<'a> {
     name: &'a str,
+
+    /// The name as escaped for ILP, if an [`OwnedColumnName`] escaped it in
+    /// advance.
+    escaped: Option<&'a [u8]>,
+
+    /// Whether the name's characters were checked already, rather than left
+    /// to the buffer's [`Validation`] mode.
+    validated: bool,
 }
 
 impl<'a> ColumnName<'a> {
-    /// Construct a validated table name.
+    /// Construct a validated column name.
     pub fn new(name: &'a str) -> Result<Self> {
-        if name.is_empty() {
-            return Err(error::fmt!(
-                InvalidName,
-                "Column names must have a non-zero length."
-            ));
-        }
+        Self::new_with_max_len(name, DEFAULT_MAX_NAME_LEN)
+    }
 
-        for (index, c) in name.chars().enumerate() {
-            match c {
-                '?' | '.' | ',' | '\'' | '\"' | '\\' | '/' | ':' | ')' | '(' | '+' | '-' | '*'
-                | '%' | '~' | '\r' | '\n' | '\0' | '\u{0001}' | '\u{0002}' | '\u{0003}'
-                | '\u{0004}' | '\u{0005}' | '\u{0006}' | '\u{0007}' | '\u{0008}' | '\u{0009}'
-                | '\u{000b}' | '\u{000c}' | '\u{000e}' | '\u{000f}' | '\u{007f}' => {
-                    return Err(error::fmt!(
-                        InvalidName,
-                        concat!(
-                            "Bad string {:?}: ",
-                            "Column names can't contain ",
-                            "a {:?} character, which was found at ",
-                            "byte position {}."
-                        ),
-                        name,
-                        c,
-                        index
-                    ));
-                }
-                '\u{FEFF}' => {
-                    // Reject unicode char 'ZERO WIDTH NO-BREAK SPACE',
-                    // aka UTF-8 BOM if it appears anywhere in the string.
-                    return Err(error::fmt!(
-                        InvalidName,
-                        concat!(
-                            "Bad string {:?}: ",
-                            "Column names can't contain ",
-                            "a UTF-8 BOM character, which was found at ",
-                            "byte position {}."
-                        ),
-                        name,
-                        index
-                    ));
-                }
-                _ => (),
-            }
+    /// Construct a validated column name of at most `max_name_len` bytes.
+    ///
+    /// This should match the `cairo.max.file.name.length` setting of the
+    /// QuestDB instance you're connecting to.
+    pub fn new_with_max_len(name: &'a str, max_name_len: usize) -> Result<Self> {
+        let validated = Self::validate(name)?;
+        check_name_len(name, max_name_len)?;
+        Ok(validated)
+    }
+
+    /// Tell whether the name is valid, without building an error if it isn't.
+    ///
+    /// This checks the same rules as [`new`](ColumnName::new), including the
+    /// default length limit.
+    pub fn is_valid(name: &str) -> bool {
+        NameFault::find(name, false).is_none() && name.len() <= DEFAULT_MAX_NAME_LEN
+    }
+
+    /// Construct a validated column name in a const context, such as a
+    /// `const` item, where an invalid name fails compilation.
+    ///
+    /// This checks the same rules as [`new`](ColumnName::new), but panics
+    /// instead of returning an error. The [`column_name!`](crate::column_name)
+    /// macro calls it for you.
+    ///
+    /// ```
+    /// use questdb::ingress::ColumnName;
+    ///
+    /// const NAME: ColumnName = ColumnName::new_const("price");
+    /// ```
+    pub const fn new_const(name: &'a str) -> Self {
+        if let Some(fault) = NameFault::find(name, false) {
+            fault.panic(false);
         }
+        if name.len() > DEFAULT_MAX_NAME_LEN {
+            panic!("Names can't be longer than 127 bytes.");
+        }
+        Self {
+            name,
+            escaped: None,
+            validated: true,
+        }
+    }
 
-        Ok(Self { name })
+    fn validate(name: &'a str) -> Result<Self> {
+        match NameFault::find(name, false) {
+            Some(fault) => Err(fault.into_error("Column", name)),
+            None => Ok(Self {
+                name,
+                escaped: None,
+                validated: true,
+            }),
+        }
     }
 
     /// Construct a column name without validating it.
     ///
     /// This breaks API encapsulation and is only intended for use
-    /// when the the string was already previously validated.
+    /// when the the string was already previously validated: Names validated
+    /// once at startup can then be passed to a [`Buffer`] on every row without
+    /// scanning them again. The buffer still checks their length.
     ///
-    /// The QuestDB server will reject an invalid column name.
+    /// The QuestDB server will reject an invalid column name. Debug builds
+    /// validate the name anyway, and panic if it's invalid.
     pub fn new_unchecked(name: &'a str) -> Self {
-        Self { name }
+        debug_assert!(
+            NameFault::find(name, false).is_none(),
+            "Invalid column name passed to `ColumnName::new_unchecked`: {name:?}"
+        );
+        Self {
+            name,
+            escaped: None,
+            validated: true,
+        }
     }
 }
 
-impl<'a> TryFrom<&'a str> for TableName<'a> {
-    type Error = self::Error;
-
-    fn try_from(name: &'a str) -> Result<Self> {
-        Self::new(name)
-    }
+/// Construct a [`TableName`](crate::ingress::TableName) from a string
+/// literal, validated at compile time.
+///
+/// An invalid name fails compilation rather than every row at runtime. Call
+/// [`TableName::new`] for names only known at runtime.
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::{column_name, table_name};
+/// use questdb::ingress::{Buffer, TimestampNanos};
+///
+/// # fn main() -> Result<()> {
+/// let mut buffer = Buffer::new();
+/// buffer
+///     .table(table_name!("trades"))?
+///     .column_f64(column_name!("price"), 2615.54)?
+///     .at(TimestampNanos::now())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// let name = questdb::table_name!("trades..2024");
+/// ```
+#[macro_export]
+macro_rules! table_name {
+    ($name:literal) => {{
+        const NAME: $crate::ingress::TableName<'static> =
+            $crate::ingress::TableName::new_const($name);
+        NAME
+    }};
 }
 
-impl<'a> TryFrom<&'a str> for ColumnName<'a> {
-    type Error = self::Error;
+/// Construct a [`ColumnName`](crate::ingress::ColumnName) from a string
+/// literal, validated at compile time.
+///
+/// See [`table_name!`](crate::table_name).
+///
+/// ```compile_fail
+/// let name = questdb::column_name!("best-bid");
+/// ```
+#[macro_export]
+macro_rules! column_name {
+    ($name:literal) => {{
+        const NAME: $crate::ingress::ColumnName<'static> =
+            $crate::ingress::ColumnName::new_const($name);
+        NAME
+    }};
+}
 
-    fn try_from(name: &'a str) -> Result<Self> {
-        Self::new(name)
-    }
+/// An owned table name, validated and escaped once when it's constructed.
+///
+/// Build these once, e.g. in a struct holding your schema's names, and pass
+/// references to them to [`Buffer::table`] for every row: The buffer then
+/// neither validates nor escapes the name again. It still checks the name's
+/// length against its own limit.
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::{Buffer, OwnedColumnName, OwnedTableName};
+///
+/// # fn main() -> Result<()> {
+/// struct Schema {
+///     table: OwnedTableName,
+///     price: OwnedColumnName,
+/// }
+///
+/// let schema = Schema {
+///     table: OwnedTableName::new("trades")?,
+///     price: OwnedColumnName::new("price usd")?,
+/// };
+/// let mut buffer = Buffer::new();
+/// buffer.table(&schema.table)?.column_f64(&schema.price, 2615.54)?.at_now()?;
+/// assert_eq!(buffer.as_str(), "trades price\\ usd=2615.54\n");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OwnedTableName {
+    name: String,
+    escaped: Vec<u8>,
 }
 
-impl From<Infallible> for Error {
-    fn from(_: Infallible) -> Self {
-        unreachable!()
+impl OwnedTableName {
+    /// Construct a table name, validated as by [`TableName::new`].
+    pub fn new<S: Into<String>>(name: S) -> Result<Self> {
+        Self::new_with_max_len(name, DEFAULT_MAX_NAME_LEN)
     }
-}
 
-fn write_escaped_impl<Q, C>(check_escape_fn: C, quoting_fn: Q, output: &mut String, s: &str)
-where
-    C: Fn(u8) -> bool,
-    Q: Fn(&mut Vec<u8>),
-{
-    let output_vec = unsafe { output.as_mut_vec() };
-    let mut to_escape = 0usize;
-    for b in s.bytes() {
-        if check_escape_fn(b) {
-            to_escape += 1;
-        }
+    /// Construct a table name of at most `max_name_len` bytes, validated
+    /// as by [`TableName::new_with_max_len`].
+    pub fn new_with_max_len<S: Into<String>>(name: S, max_name_len: usize) -> Result<Self> {
+        let name = name.into();
+        TableName::new_with_max_len(&name, max_name_len)?;
+        let escaped = escape_name(&name);
+        Ok(Self { name, escaped })
     }
 
-    quoting_fn(output_vec);
-
-    if to_escape == 0 {
-        // output.push_str(s);
-        output_vec.extend_from_slice(s.as_bytes());
-    } else {
-        let additional = s.len() + to_escape;
-        output_vec.reserve(additional);
-        let mut index = output_vec.len();
-        unsafe { output_vec.set_len(index + additional) };
-        for b in s.bytes() {
-            if check_escape_fn(b) {
-                unsafe {
-                    *output_vec.get_unchecked_mut(index) = b'\\';
-                }
-                index += 1;
-            }
+    /// The name, unescaped.
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
 
-            unsafe {
-                *output_vec.get_unchecked_mut(index) = b;
-            }
-            index += 1;
+    /// Borrow the name as a [`TableName`], to pass to a [`Buffer`].
+    pub fn as_name(&self) -> TableName<'_> {
+        TableName {
+            name: &self.name,
+            escaped: Some(&self.escaped),
+            validated: true,
         }
     }
-
-    quoting_fn(output_vec);
 }
 
-fn must_escape_unquoted(c: u8) -> bool {
-    matches!(c, b' ' | b',' | b'=' | b'\n' | b'\r' | b'\\')
-}
-
-fn must_escape_quoted(c: u8) -> bool {
-    matches!(c, b'\n' | b'\r' | b'"' | b'\\')
+impl<'a> From<&'a OwnedTableName> for TableName<'a> {
+    fn from(name: &'a OwnedTableName) -> Self {
+        name.as_name()
+    }
 }
 
-fn write_escaped_unquoted(output: &mut String, s: &str) {
-    write_escaped_impl(must_escape_unquoted, |_output| (), output, s);
+/// An owned column name, validated and escaped once when it's constructed.
+///
+/// Pass references to it wherever a [`Buffer`] takes a column or symbol name.
+/// See [`OwnedTableName`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OwnedColumnName {
+    name: String,
+    escaped: Vec<u8>,
 }
 
-fn write_escaped_quoted(output: &mut String, s: &str) {
-    write_escaped_impl(must_escape_quoted, |output| output.push(b'"'), output, s)
-}
+impl OwnedColumnName {
+    /// Construct a column name, validated as by [`ColumnName::new`].
+    pub fn new<S: Into<String>>(name: S) -> Result<Self> {
+        Self::new_with_max_len(name, DEFAULT_MAX_NAME_LEN)
+    }
 
-enum Connection {
-    Direct(Socket),
-    Tls(Box<StreamOwned<ClientConnection, Socket>>),
-}
+    /// Construct a column name of at most `max_name_len` bytes, validated
+    /// as by [`ColumnName::new_with_max_len`].
+    pub fn new_with_max_len<S: Into<String>>(name: S, max_name_len: usize) -> Result<Self> {
+        let name = name.into();
+        ColumnName::new_with_max_len(&name, max_name_len)?;
+        let escaped = escape_name(&name);
+        Ok(Self { name, escaped })
+    }
 
-impl Connection {
-    fn send_key_id(&mut self, key_id: &str) -> Result<()> {
-        writeln!(self, "{}", key_id)
-            .map_err(|io_err| map_io_to_socket_err("Failed to send key_id: ", io_err))?;
-        Ok(())
+    /// The name, unescaped.
+    pub fn as_str(&self) -> &str {
+        &self.name
     }
 
-    fn read_challenge(&mut self) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        let mut reader = BufReader::new(self);
-        reader.read_until(b'\n', &mut buf).map_err(|io_err| {
-            map_io_to_socket_err(
-                "Failed to read authentication challenge (timed out?): ",
-                io_err,
-            )
-        })?;
-        if buf.last().copied().unwrap_or(b'\0') != b'\n' {
-            return Err(if buf.is_empty() {
-                error::fmt!(
-                    AuthError,
-                    concat!(
-                        "Did not receive auth challenge. ",
-                        "Is the database configured to require ",
-                        "authentication?"
-                    )
-                )
-            } else {
-                error::fmt!(AuthError, "Received incomplete auth challenge: {:?}", buf)
-            });
+    /// Borrow the name as a [`ColumnName`], to pass to a [`Buffer`].
+    pub fn as_name(&self) -> ColumnName<'_> {
+        ColumnName {
+            name: &self.name,
+            escaped: Some(&self.escaped),
+            validated: true,
         }
-        buf.pop(); // b'\n'
-        Ok(buf)
     }
+}
 
-    fn authenticate(&mut self, auth: &EcdsaAuthParams) -> Result<()> {
-        if auth.key_id.contains('\n') {
-            return Err(error::fmt!(
-                AuthError,
-                "Bad key id {:?}: Should not contain new-line char.",
-                auth.key_id
-            ));
-        }
-        let key_pair = parse_key_pair(auth)?;
-        self.send_key_id(auth.key_id.as_str())?;
-        let challenge = self.read_challenge()?;
-        let rng = SystemRandom::new();
-        let signature = key_pair
-            .sign(&rng, &challenge[..])
-            .map_err(|unspecified_err| {
-                error::fmt!(AuthError, "Failed to sign challenge: {}", unspecified_err)
-            })?;
-        let mut encoded_sig = Base64::encode_string(signature.as_ref());
-        encoded_sig.push('\n');
-        let buf = encoded_sig.as_bytes();
-        if let Err(io_err) = self.write_all(buf) {
-            return Err(map_io_to_socket_err(
-                "Could not send signed challenge: ",
-                io_err,
-            ));
-        }
-        Ok(())
+impl<'a> From<&'a OwnedColumnName> for ColumnName<'a> {
+    fn from(name: &'a OwnedColumnName) -> Self {
+        name.as_name()
     }
 }
 
-enum ProtocolHandler {
-    Socket(Connection),
+fn escape_name(name: &str) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(name.len());
+    write_escaped_unquoted(&mut escaped, name);
+    escaped
+}
 
-    #[cfg(feature = "ilp-over-http")]
-    Http(HttpHandlerState),
+/// Write a validated name, escaping it unless that was done in advance.
+fn write_name(output: &mut Vec<u8>, name: &str, escaped: Option<&[u8]>) {
+    match escaped {
+        Some(escaped) => output.extend_from_slice(escaped),
+        None => write_escaped_unquoted(output, name),
+    }
 }
 
-impl io::Read for Connection {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self {
-            Self::Direct(sock) => sock.read(buf),
-            Self::Tls(stream) => stream.read(buf),
-        }
+/// Validates the name, except for its length: The [`Buffer`] checks that against
+/// its own limit.
+impl<'a> TryFrom<&'a str> for TableName<'a> {
+    type Error = self::Error;
+
+    fn try_from(name: &'a str) -> Result<Self> {
+        Self::validate(name)
     }
 }
 
-impl io::Write for Connection {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self {
-            Self::Direct(sock) => sock.write(buf),
-            Self::Tls(stream) => stream.write(buf),
-        }
+/// Validates the name, except for its length: The [`Buffer`] checks that against
+/// its own limit.
+impl<'a> TryFrom<&'a str> for ColumnName<'a> {
+    type Error = self::Error;
+
+    fn try_from(name: &'a str) -> Result<Self> {
+        Self::validate(name)
     }
+}
 
-    fn flush(&mut self) -> io::Result<()> {
-        match self {
-            Self::Direct(sock) => sock.flush(),
-            Self::Tls(stream) => stream.flush(),
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A table name to pass to a [`Buffer`]: A [`TableName`], a reference to an
+/// [`OwnedTableName`], or a `&str` for the buffer to validate.
+///
+/// This trait is sealed, and can't be implemented outside this crate.
+pub trait IntoTableName<'a>: sealed::Sealed {
+    #[doc(hidden)]
+    fn into_table_name(self) -> Result<TableName<'a>>;
+}
+
+/// A column or symbol name to pass to a [`Buffer`]: A [`ColumnName`], a
+/// reference to an [`OwnedColumnName`], or a `&str` for the buffer to
+/// validate.
+///
+/// This trait is sealed, and can't be implemented outside this crate.
+pub trait IntoColumnName<'a>: sealed::Sealed {
+    #[doc(hidden)]
+    fn into_column_name(self) -> Result<ColumnName<'a>>;
+}
+
+impl sealed::Sealed for &str {}
+impl sealed::Sealed for TableName<'_> {}
+impl sealed::Sealed for ColumnName<'_> {}
+impl sealed::Sealed for &OwnedTableName {}
+impl sealed::Sealed for &OwnedColumnName {}
+
+/// Leaves validating the name to the [`Buffer`], which checks it according
+/// to its [`Validation`] mode and against its own length limit.
+impl<'a> IntoTableName<'a> for &'a str {
+    fn into_table_name(self) -> Result<TableName<'a>> {
+        Ok(TableName {
+            name: self,
+            escaped: None,
+            validated: false,
+        })
+    }
+}
+
+impl<'a> IntoTableName<'a> for TableName<'a> {
+    fn into_table_name(self) -> Result<TableName<'a>> {
+        Ok(self)
+    }
+}
+
+impl<'a> IntoTableName<'a> for &'a OwnedTableName {
+    fn into_table_name(self) -> Result<TableName<'a>> {
+        Ok(self.as_name())
+    }
+}
+
+/// Leaves validating the name to the [`Buffer`], which checks it according
+/// to its [`Validation`] mode and against its own length limit.
+impl<'a> IntoColumnName<'a> for &'a str {
+    fn into_column_name(self) -> Result<ColumnName<'a>> {
+        Ok(ColumnName {
+            name: self,
+            escaped: None,
+            validated: false,
+        })
+    }
+}
+
+impl<'a> IntoColumnName<'a> for ColumnName<'a> {
+    fn into_column_name(self) -> Result<ColumnName<'a>> {
+        Ok(self)
+    }
+}
+
+impl<'a> IntoColumnName<'a> for &'a OwnedColumnName {
+    fn into_column_name(self) -> Result<ColumnName<'a>> {
+        Ok(self.as_name())
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(_: Infallible) -> Self {
+        unreachable!()
+    }
+}
+
+/// The index of the first byte to escape, if any.
+///
+/// Most strings need no escaping at all, so this tests 16 bytes at a time
+/// without branching on each, and only then looks for the exact index.
+fn find_escape(bytes: &[u8], must_escape: &[bool; 256]) -> Option<usize> {
+    let mut chunks = bytes.chunks_exact(16);
+    let mut offset = 0;
+    for chunk in &mut chunks {
+        if chunk
+            .iter()
+            .fold(false, |found, &b| found | must_escape[b as usize])
+        {
+            break;
         }
+        offset += 16;
+    }
+    bytes[offset..]
+        .iter()
+        .position(|&b| must_escape[b as usize])
+        .map(|index| offset + index)
+}
+
+/// Write `s` between the quotes, if any, putting a backslash before each byte
+/// to escape.
+///
+/// Unescaped spans are copied whole, so a clean string costs one scan and one
+/// copy.
+fn write_escaped_impl<Q>(must_escape: &[bool; 256], quoting_fn: Q, output: &mut Vec<u8>, s: &str)
+where
+    Q: Fn(&mut Vec<u8>),
+{
+    // Most strings need no escaping: Reserve for those and the quotes.
+    output.reserve(s.len() + 2);
+    quoting_fn(output);
+    let mut rest = s.as_bytes();
+    while let Some(index) = find_escape(rest, must_escape) {
+        // The bytes to escape are ASCII, so the output stays valid UTF-8.
+        output.extend_from_slice(&rest[..index]);
+        output.push(b'\\');
+        output.push(rest[index]);
+        rest = &rest[index + 1..];
+    }
+    output.extend_from_slice(rest);
+    quoting_fn(output);
+}
+
+// Outside quotes, tabs are escaped along with the ILP separators. Inside
+// quotes, only the quote, the backslash and line breaks are special: `=`, `,`,
+// spaces and tabs are kept as they are.
+pub(crate) const fn must_escape_unquoted(c: u8) -> bool {
+    matches!(c, b' ' | b',' | b'=' | b'\n' | b'\r' | b'\t' | b'\\')
+}
+
+pub(crate) const fn must_escape_quoted(c: u8) -> bool {
+    matches!(c, b'\n' | b'\r' | b'"' | b'\\')
+}
+
+/// Which bytes to escape, as a table: Looking a byte up is cheaper than
+/// matching it against each byte to escape.
+const fn escape_table(quoted: bool) -> [bool; 256] {
+    let mut table = [false; 256];
+    let mut c = 0;
+    while c < 256 {
+        table[c] = if quoted {
+            must_escape_quoted(c as u8)
+        } else {
+            must_escape_unquoted(c as u8)
+        };
+        c += 1;
     }
+    table
+}
+
+static MUST_ESCAPE_UNQUOTED: [bool; 256] = escape_table(false);
+static MUST_ESCAPE_QUOTED: [bool; 256] = escape_table(true);
+
+pub(crate) fn write_escaped_unquoted(output: &mut Vec<u8>, s: &str) {
+    write_escaped_impl(&MUST_ESCAPE_UNQUOTED, |_output| (), output, s);
+}
+
+pub(crate) fn write_escaped_quoted(output: &mut Vec<u8>, s: &str) {
+    write_escaped_impl(&MUST_ESCAPE_QUOTED, |output| output.push(b'"'), output, s)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -434,8 +1082,25 @@ impl OpCase {
 struct BufferState {
     op_case: OpCase,
     row_count: usize,
-    first_table: Option<String>,
+
+    /// The table of the first row, empty if there's none. Kept as a `String`
+    /// that's cleared rather than dropped, so that its allocation is reused.
+    first_table: String,
     transactional: bool,
+
+    /// The oldest protocol version that understands the buffer's contents.
+    required_version: ProtocolVersion,
+
+    /// When the first row since the buffer was last cleared was completed.
+    ///
+    /// Only senders read it, to auto-flush. Without them, the buffer doesn't
+    /// read the clock, which isn't available on all targets, such as
+    /// `wasm32-unknown-unknown`.
+    #[cfg(feature = "net")]
+    first_row_at: Option<Instant>,
+
+    /// Where the last row, complete or not, starts.
+    row_start: RowStart,
 }
 
 impl BufferState {
@@ -443,19 +1108,170 @@ impl BufferState {
         Self {
             op_case: OpCase::Init,
             row_count: 0,
-            first_table: None,
+            first_table: String::new(),
             transactional: true,
+            required_version: ProtocolVersion::V1,
+            #[cfg(feature = "net")]
+            first_row_at: None,
+            row_start: RowStart::new(),
         }
     }
 
     fn clear(&mut self) {
         self.op_case = OpCase::Init;
         self.row_count = 0;
-        self.first_table = None;
+        self.first_table.clear();
         self.transactional = true;
+        self.required_version = ProtocolVersion::V1;
+        #[cfg(feature = "net")]
+        {
+            self.first_row_at = None;
+        }
+        self.row_start = RowStart::new();
+    }
+}
+
+/// What to restore if the row being written is rejected.
+#[derive(Debug, Clone, Copy)]
+struct RowStart {
+    len: usize,
+
+    /// Where the row before starts.
+    prev_len: usize,
+    transactional: bool,
+    required_version: ProtocolVersion,
+}
+
+impl RowStart {
+    fn new() -> Self {
+        Self {
+            len: 0,
+            prev_len: 0,
+            transactional: true,
+            required_version: ProtocolVersion::V1,
+        }
+    }
+}
+
+/// A column value of any type, to record columns known only at runtime with
+/// [`Buffer::column`] or [`Buffer::columns`].
+///
+/// Values convert from `bool`, the integer types that fit in an `i64`, `f32`,
+/// `f64`, strings and timestamps.
+#[derive(Debug, Clone, Copy)]
+pub enum ColumnValue<'a> {
+    /// See [`Buffer::column_bool`].
+    Bool(bool),
+
+    /// See [`Buffer::column_i64`].
+    I64(i64),
+
+    /// See [`Buffer::column_f64`].
+    F64(f64),
+
+    /// See [`Buffer::column_str`].
+    Str(&'a str),
+
+    /// See [`Buffer::column_ts`].
+    Ts(Timestamp),
+}
+
+impl From<bool> for ColumnValue<'_> {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+/// Implement `From` for the types that convert to a variant's value without
+/// loss, as [`Buffer::column_int`] does.
+macro_rules! column_value_from {
+    ($variant:ident($target:ty): $($source:ty),*) => {
+        $(
+            impl From<$source> for ColumnValue<'_> {
+                fn from(value: $source) -> Self {
+                    Self::$variant(<$target>::from(value))
+                }
+            }
+        )*
+    };
+}
+
+column_value_from!(I64(i64): i8, i16, i32, i64, u8, u16, u32);
+column_value_from!(F64(f64): f32, f64);
+
+impl<'a> From<&'a str> for ColumnValue<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl<'a> From<&'a String> for ColumnValue<'a> {
+    fn from(value: &'a String) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl From<Timestamp> for ColumnValue<'_> {
+    fn from(value: Timestamp) -> Self {
+        Self::Ts(value)
+    }
+}
+
+impl From<TimestampMicros> for ColumnValue<'_> {
+    fn from(value: TimestampMicros) -> Self {
+        Self::Ts(value.into())
+    }
+}
+
+impl From<TimestampNanos> for ColumnValue<'_> {
+    fn from(value: TimestampNanos) -> Self {
+        Self::Ts(value.into())
+    }
+}
+
+impl<'a> From<&ColumnValue<'a>> for ColumnValue<'a> {
+    fn from(value: &ColumnValue<'a>) -> Self {
+        *value
+    }
+}
+
+/// The function [`NumericSymbols::Warn`] calls with the column name and the
+/// value of a numeric symbol.
+pub type NumericSymbolWarning = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// What [`Buffer::symbol`] does with values that parse as a number, see
+/// [`Buffer::set_numeric_symbols`].
+#[derive(Clone, Default)]
+pub enum NumericSymbols {
+    /// Record them like any other symbol value.
+    #[default]
+    Allow,
+
+    /// Call the function with the column name and the value, then record the
+    /// symbol anyway.
+    Warn(NumericSymbolWarning),
+
+    /// Fail with [`InvalidApiCall`](crate::ErrorCode::InvalidApiCall).
+    Reject,
+}
+
+impl Debug for NumericSymbols {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NumericSymbols::Allow => f.write_str("Allow"),
+            NumericSymbols::Warn(_) => f.write_str("Warn"),
+            NumericSymbols::Reject => f.write_str("Reject"),
+        }
     }
 }
 
+/// Whether a symbol value looks like it was meant for a numeric column, such
+/// as `"123.4"` or `"-5"`.
+fn is_numeric(value: &str) -> bool {
+    // Rules out "inf" and "NaN", which `f64` parses too.
+    value.bytes().any(|b| b.is_ascii_digit()) && value.parse::<f64>().is_ok()
+}
+
 /// A reusable buffer to prepare a batch of ILP messages.
 ///
 /// # Example
@@ -487,7 +1303,14 @@ impl BufferState {
 /// # }
 /// ```
 ///
-/// Send the buffer to QuestDB using [`sender.flush(&mut buffer)`](Sender::flush).
+/// Each method that adds to a row returns `Result<&mut Buffer>`, so the
+/// calls chain with `?`. [`at`](Buffer::at) and [`at_now`](Buffer::at_now)
+/// complete the row and return `Result<()>`.
+///
+#[cfg_attr(
+    feature = "net",
+    doc = " Send the buffer to QuestDB using [`sender.flush(&mut buffer)`](Sender::flush)."
+)]
 ///
 /// # Sequential Coupling
 /// The Buffer API is sequentially coupled:
@@ -541,26 +1364,193 @@ impl BufferState {
 /// [`buffer.rewind_to_marker()`](Buffer::rewind_to_marker) to go back to the
 /// marked last known good state.
 ///
-#[derive(Debug, Clone)]
+/// # Concatenating chunks
+///
+/// Each row completed with [`at`](Buffer::at) or [`at_now`](Buffer::at_now)
+/// ends with exactly one `\n`, and line breaks in names and values are
+/// escaped, so no row ever spans a chunk boundary. Flushing sends the buffer's
+/// contents as they are, without adding or removing newlines, so the chunks of
+/// successive flushes can be concatenated, e.g. for archival, into valid ILP.
+/// Check that a buffer is safe to split off there with
+/// [`ends_with_complete_row`](Buffer::ends_with_complete_row).
+///
+/// # Sharing across threads
+///
+/// A buffer is plain data: It's `Send` and `Sync`, and holds no reference to a
+/// sender or its connection. You can build buffers on worker threads and hand
+/// them over to the thread that flushes them, or merge them with
+/// [`append`](Buffer::append). To send the same rows repeatedly, e.g. a
+/// heartbeat, build them once and flush a clone each time, since flushing
+/// clears the buffer. Clones keep the original's capacity.
+///
+/// # Allocations
+///
+/// Once a buffer has grown to fit a batch, recording the next batch of that
+/// size with [`table`](Buffer::table), [`symbol`](Buffer::symbol), the
+/// `column_*` methods, [`at`](Buffer::at) and [`at_now`](Buffer::at_now)
+/// performs no heap allocations: Clearing the buffer keeps its capacity.
+#[cfg_attr(
+    feature = "net",
+    doc = " Neither does flushing it over ILP/TCP with [`Sender::flush`]."
+)]
+///
+/// These do allocate:
+///   * Growing the buffer past its [`capacity`](Buffer::capacity), and
+///     recording a first table name longer than any before.
+///   * Errors, which format their message.
+///   * [`set_marker`](Buffer::set_marker), cloning, and the methods that
+///     split a buffer, such as [`split_completed`](Buffer::split_completed).
+#[cfg_attr(
+    feature = "net",
+    doc = "   * Flushing over ILP/HTTP, and shrinking the buffer on flush per [`buffer_shrink_threshold`](SenderBuilder::buffer_shrink_threshold)."
+)]
+///
+#[derive(Debug)]
 pub struct Buffer {
-    output: String,
+    output: Vec<u8>,
     state: BufferState,
     marker: Option<(usize, BufferState)>,
     max_name_len: usize,
+    max_buf_size: usize,
+    protocol_version: ProtocolVersion,
+    reject_empty_symbols: bool,
+    reject_control_chars_in_values: bool,
+    numeric_symbols: NumericSymbols,
+    validation: Validation,
+    initial_capacity: usize,
+}
+
+/// The settings a sender gives the buffers it creates, taken from its
+/// builder once and shared by every sender type's `new_buffer`.
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BufferTemplate {
+    pub(crate) protocol_version: ProtocolVersion,
+    pub(crate) max_buf_size: usize,
+    pub(crate) max_name_len: usize,
+    pub(crate) validation: Validation,
+    pub(crate) reject_control_chars_in_values: bool,
+}
+
+#[cfg(feature = "net")]
+impl BufferTemplate {
+    pub(crate) fn new_buffer(&self) -> Buffer {
+        let mut buf = Buffer::with_protocol_version(self.protocol_version);
+        buf.max_buf_size = self.max_buf_size;
+        buf.max_name_len = self.max_name_len;
+        buf.validation = self.validation;
+        buf.reject_control_chars_in_values = self.reject_control_chars_in_values;
+        buf
+    }
+
+    /// Check that a sender with these settings can flush `buf`.
+    pub(crate) fn check_can_flush(&self, buf: &Buffer) -> Result<()> {
+        buf.check_can_flush(self.protocol_version, self.max_buf_size)
+    }
+}
+
+/// The settings of a sender that wasn't built from a [`SenderBuilder`], such
+/// as one from [`Sender::from_sink`]: Those of the builder's defaults.
+#[cfg(feature = "net")]
+impl Default for BufferTemplate {
+    fn default() -> Self {
+        Self {
+            protocol_version: ProtocolVersion::V2,
+            max_buf_size: 100 * 1024 * 1024,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            validation: Validation::Default,
+            reject_control_chars_in_values: false,
+        }
+    }
+}
+
+impl Clone for Buffer {
+    fn clone(&self) -> Self {
+        // A derived clone would allocate just enough for the contents, so
+        // adding rows to it would grow it again from there.
+        let mut output = Vec::with_capacity(self.output.capacity());
+        output.extend_from_slice(&self.output);
+        Self {
+            output,
+            state: self.state.clone(),
+            marker: self.marker.clone(),
+            max_name_len: self.max_name_len,
+            max_buf_size: self.max_buf_size,
+            protocol_version: self.protocol_version,
+            reject_empty_symbols: self.reject_empty_symbols,
+            reject_control_chars_in_values: self.reject_control_chars_in_values,
+            numeric_symbols: self.numeric_symbols.clone(),
+            validation: self.validation,
+            initial_capacity: self.initial_capacity,
+        }
+    }
 }
 
 impl Buffer {
     /// Construct a `Buffer` with a `max_name_len` of `127`, which is the same as the
     /// QuestDB server default.
+    ///
+    /// The buffer encodes rows with [`ProtocolVersion::V1`], which all
+    /// QuestDB servers understand. Call [`Buffer::with_protocol_version`] to
+    /// use a newer version.
+    ///
+    /// The buffer's size isn't capped: A sender only rejects it on flush if it
+    /// exceeds the sender's maximum buffer size. Call
+    /// [`Buffer::with_max_buf_size`] to reject rows past a maximum size as
+    /// they're completed instead.
+    ///
+    #[cfg_attr(
+        feature = "net",
+        doc = " [`Sender::new_buffer`] creates a buffer with the sender's protocol version and [`max_buf_size`](SenderBuilder::max_buf_size)."
+    )]
     pub fn new() -> Self {
         Self {
-            output: String::new(),
+            output: Vec::new(),
             state: BufferState::new(),
             marker: None,
-            max_name_len: 127,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            max_buf_size: usize::MAX,
+            protocol_version: ProtocolVersion::default(),
+            reject_empty_symbols: false,
+            reject_control_chars_in_values: false,
+            numeric_symbols: NumericSymbols::Allow,
+            validation: Validation::Default,
+            initial_capacity: 0,
         }
     }
 
+    /// Construct a `Buffer` with room for at least `capacity` bytes before it
+    /// needs to resize.
+    ///
+    /// See also [`reserve`](Buffer::reserve).
+    #[cfg_attr(
+        feature = "net",
+        doc = " A sender's [`buffer_shrink_threshold`](SenderBuilder::buffer_shrink_threshold) shrinks the buffer back to this capacity."
+    )]
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut buf = Self::new();
+        buf.output.reserve(capacity);
+        buf.initial_capacity = capacity;
+        buf
+    }
+
+    /// Construct a `Buffer` that encodes rows for the given [`ProtocolVersion`].
+    ///
+    #[cfg_attr(
+        feature = "net",
+        doc = " See also [`Sender::new_buffer`], which matches the sender's version."
+    )]
+    pub fn with_protocol_version(protocol_version: ProtocolVersion) -> Self {
+        let mut buf = Self::new();
+        buf.protocol_version = protocol_version;
+        buf
+    }
+
+    /// The protocol version the buffer encodes rows for.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
     /// Construct a `Buffer` with a custom maximum length for table and column names.
     ///
     /// This should match the `cairo.max.file.name.length` setting of the
@@ -568,12 +1558,146 @@ impl Buffer {
     ///
     /// If the server does not configure it, the default is `127` and you can simply
     /// call [`new`](Buffer::new).
+    ///
+    #[cfg_attr(
+        feature = "net",
+        doc = " See also [`Sender::new_buffer`], which matches the sender's [`max_name_len`](SenderBuilder::max_name_len)."
+    )]
     pub fn with_max_name_len(max_name_len: usize) -> Self {
         let mut buf = Self::new();
         buf.max_name_len = max_name_len;
         buf
     }
 
+    /// The maximum length in bytes of the table and column names the buffer
+    /// accepts.
+    pub fn max_name_len(&self) -> usize {
+        self.max_name_len
+    }
+
+    /// Construct a `Buffer` that holds at most `max_buf_size` bytes.
+    ///
+    /// Completing a row that would grow the buffer past this size fails with
+    /// [`BufferTooLarge`](crate::ErrorCode::BufferTooLarge), and the row is
+    /// discarded, leaving the buffer as it was before the row's
+    /// [`table`](Buffer::table) call.
+    ///
+    #[cfg_attr(
+        feature = "net",
+        doc = " See also [`Sender::new_buffer`], which matches the sender's [`max_buf_size`](SenderBuilder::max_buf_size)."
+    )]
+    pub fn with_max_buf_size(max_buf_size: usize) -> Self {
+        let mut buf = Self::new();
+        buf.max_buf_size = max_buf_size;
+        buf
+    }
+
+    /// The maximum number of bytes the buffer may hold, `usize::MAX` if it's
+    /// uncapped.
+    pub fn max_buf_size(&self) -> usize {
+        self.max_buf_size
+    }
+
+    /// Make [`symbol`](Buffer::symbol) fail with
+    /// [`ConfigError`](crate::ErrorCode::ConfigError) when passed an empty
+    /// value.
+    ///
+    /// To record a null symbol, skip the call instead. Empty string columns
+    /// are unaffected, since an empty string is a legitimate value there.
+    ///
+    /// Off by default, and kept across [`clear`](Buffer::clear).
+    pub fn set_reject_empty_symbols(&mut self, reject: bool) {
+        self.reject_empty_symbols = reject;
+    }
+
+    /// Whether [`symbol`](Buffer::symbol) rejects empty values.
+    ///
+    /// See [`set_reject_empty_symbols`](Buffer::set_reject_empty_symbols).
+    pub fn rejects_empty_symbols(&self) -> bool {
+        self.reject_empty_symbols
+    }
+
+    /// Make [`symbol`](Buffer::symbol) and [`column_str`](Buffer::column_str)
+    /// fail with [`InvalidValue`](crate::ErrorCode::InvalidValue) when passed
+    /// a value containing a control character other than `\n` or `\r`.
+    ///
+    /// Such values are escaped and sent as they are, but are usually the sign
+    /// of corrupted input. Off by default, and kept across
+    /// [`clear`](Buffer::clear).
+    #[cfg_attr(
+        feature = "net",
+        doc = " See also [`Sender::new_buffer`], which matches the sender's [`reject_control_chars_in_values`](SenderBuilder::reject_control_chars_in_values)."
+    )]
+    pub fn set_reject_control_chars_in_values(&mut self, reject: bool) {
+        self.reject_control_chars_in_values = reject;
+    }
+
+    /// Whether [`symbol`](Buffer::symbol) and
+    /// [`column_str`](Buffer::column_str) reject values with control
+    /// characters.
+    ///
+    /// See
+    /// [`set_reject_control_chars_in_values`](Buffer::set_reject_control_chars_in_values).
+    pub fn rejects_control_chars_in_values(&self) -> bool {
+        self.reject_control_chars_in_values
+    }
+
+    /// Set what [`symbol`](Buffer::symbol) does with values that parse as a
+    /// number, such as `"123.4"`.
+    ///
+    /// These were usually meant for a numeric column. As symbols, each
+    /// distinct value adds an entry to the column's symbol table, so a
+    /// high-cardinality series of numbers degrades the server's performance.
+    /// Numeric-looking categories, such as zip codes, are legitimate too,
+    /// though: Hence checking is opt-in.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// use std::sync::Arc;
+    /// use questdb::ingress::{Buffer, NumericSymbols};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut buffer = Buffer::new();
+    /// buffer.set_numeric_symbols(NumericSymbols::Warn(Arc::new(|name, value| {
+    ///     eprintln!("Symbol {name:?} has a numeric value {value:?}.");
+    /// })));
+    /// buffer.table("trades")?.symbol("price", "123.4")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Off by default, and kept across [`clear`](Buffer::clear).
+    pub fn set_numeric_symbols(&mut self, check: NumericSymbols) {
+        self.numeric_symbols = check;
+    }
+
+    /// What [`symbol`](Buffer::symbol) does with values that parse as a number.
+    ///
+    /// See [`set_numeric_symbols`](Buffer::set_numeric_symbols).
+    pub fn numeric_symbols(&self) -> &NumericSymbols {
+        &self.numeric_symbols
+    }
+
+    /// Set how strictly the buffer checks the characters of table and column
+    /// names, see [`Validation`].
+    ///
+    /// [`Validation::Default`] unless set, and kept across
+    /// [`clear`](Buffer::clear).
+    #[cfg_attr(
+        feature = "net",
+        doc = " See also [`Sender::new_buffer`], which matches the sender's [`validation`](SenderBuilder::validation)."
+    )]
+    pub fn set_validation(&mut self, validation: Validation) {
+        self.validation = validation;
+    }
+
+    /// How strictly the buffer checks the characters of names.
+    ///
+    /// See [`set_validation`](Buffer::set_validation).
+    pub fn validation(&self) -> Validation {
+        self.validation
+    }
+
     /// Pre-allocate to ensure the buffer has enough capacity for at least the
     /// specified additional byte count. This may be rounded up.
     /// This does not allocate if such additional capacity is already satisfied.
@@ -587,7 +1711,25 @@ impl Buffer {
         self.output.len()
     }
 
-    /// The number of rows accumulated in the buffer.
+    /// The number of bytes of complete rows in the buffer, excluding the row
+    /// being written, if any.
+    ///
+    /// Unlike [`len`](Buffer::len), this doesn't count a row until it's
+    /// complete, so prefer it when deciding whether to flush.
+    pub fn committed_size(&self) -> usize {
+        if self.ends_at_row_boundary() {
+            self.output.len()
+        } else {
+            self.state.row_start.len
+        }
+    }
+
+    /// The number of complete rows accumulated in the buffer.
+    ///
+    /// A row counts once [`at`](Buffer::at) or [`at_now`](Buffer::at_now)
+    /// completes it. Clearing the buffer resets the count, and
+    /// [`rewind_to_marker`](Buffer::rewind_to_marker) restores the count at the
+    /// marker.
     pub fn row_count(&self) -> usize {
         self.state.row_count
     }
@@ -610,27 +1752,89 @@ impl Buffer {
 
     /// A string representation of the buffer's contents. Useful for debugging.
     pub fn as_str(&self) -> &str {
+        bytes_as_str(&self.output)
+    }
+
+    /// The buffer's contents, as the bytes a flush sends.
+    pub fn as_bytes(&self) -> &[u8] {
         &self.output
     }
 
-    /// Mark a rewind point.
-    /// This allows undoing accumulated changes to the buffer for one or more
-    /// rows by calling [`rewind_to_marker`](Buffer::rewind_to_marker).
-    /// Any previous marker will be discarded.
-    /// Once the marker is no longer needed, call
-    /// [`clear_marker`](Buffer::clear_marker).
-    pub fn set_marker(&mut self) -> Result<()> {
-        if (self.state.op_case as isize & Op::Table as isize) == 0 {
-            return Err(error::fmt!(
-                InvalidApiCall,
-                concat!(
-                    "Can't set the marker whilst constructing a line. ",
-                    "A marker may only be set on an empty buffer or after ",
-                    "`at` or `at_now` is called."
-                )
-            ));
-        }
-        self.marker = Some((self.output.len(), self.state.clone()));
+    /// The ILP text of the last row: The row being written, if any, or else the
+    /// last complete row, including its trailing newline.
+    ///
+    /// Returns an empty string if the buffer holds no rows. Useful to find out
+    /// what the client sent when the server rejects a row.
+    pub fn peek_last_row(&self) -> &str {
+        bytes_as_str(&self.output[self.state.row_start.len..])
+    }
+
+    /// The ILP text of each complete row, without its trailing newline.
+    ///
+    /// A row still being written is left out, as it would be from a flush.
+    /// Newlines within string values are escaped, and don't split their row.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::{Buffer, TimestampNanos};
+    /// # fn main() -> Result<()> {
+    /// let mut buffer = Buffer::new();
+    /// buffer.table("t")?.column_i64("i", 0)?.at(TimestampNanos::new(0))?;
+    /// buffer.table("t")?.column_str("s", "a\nb")?.at(TimestampNanos::new(1))?;
+    /// buffer.table("t")?.column_i64("i", 2)?;
+    /// let lines: Vec<&str> = buffer.lines().collect();
+    /// assert_eq!(lines, ["t i=0i 0", "t s=\"a\\\nb\" 1"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lines(&self) -> impl Iterator<Item = &str> + '_ {
+        let committed = &self.output[..self.committed_size()];
+        let mut start = 0;
+        row_ends(committed).map(move |end| {
+            let line = bytes_as_str(&committed[start..end - 1]);
+            start = end;
+            line
+        })
+    }
+
+    /// Tell whether the buffer ends at a row boundary, i.e. it's empty or its
+    /// last row was completed with [`at`](Buffer::at) or
+    /// [`at_now`](Buffer::at_now).
+    ///
+    /// Only a buffer that ends at a row boundary can be flushed.
+    pub fn ends_at_row_boundary(&self) -> bool {
+        matches!(self.state.op_case, OpCase::Init | OpCase::MayFlushOrTable)
+    }
+
+    /// Tell whether the buffer ends with a complete row, i.e. it holds at
+    /// least one row and isn't in the middle of another.
+    ///
+    /// The contents then end with the `\n` of the last row, and can be
+    /// concatenated with other such chunks. Unlike
+    /// [`ends_at_row_boundary`](Buffer::ends_at_row_boundary), this is false
+    /// for an empty buffer.
+    pub fn ends_with_complete_row(&self) -> bool {
+        self.state.op_case == OpCase::MayFlushOrTable
+    }
+
+    /// Mark a rewind point.
+    /// This allows undoing accumulated changes to the buffer for one or more
+    /// rows by calling [`rewind_to_marker`](Buffer::rewind_to_marker).
+    /// Any previous marker will be discarded.
+    /// Once the marker is no longer needed, call
+    /// [`clear_marker`](Buffer::clear_marker).
+    pub fn set_marker(&mut self) -> Result<()> {
+        if (self.state.op_case as isize & Op::Table as isize) == 0 {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                concat!(
+                    "Can't set the marker whilst constructing a line. ",
+                    "A marker may only be set on an empty buffer or after ",
+                    "`at` or `at_now` is called."
+                )
+            ));
+        }
+        self.marker = Some((self.output.len(), self.state.clone()));
         Ok(())
     }
 
@@ -667,6 +1871,197 @@ impl Buffer {
         self.marker = None;
     }
 
+    /// Remove the complete rows from the buffer and return their ILP, leaving
+    /// behind the row being written, if any.
+    ///
+    /// This lets one thread keep recording rows while another sends the
+    /// returned chunk. The buffer's row count drops to zero and any marker is
+    /// cleared.
+    #[cfg_attr(feature = "net", doc = "")]
+    #[cfg_attr(feature = "net", doc = " Send the chunk with [`Sender::send_raw`].")]
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::{Buffer, TimestampNanos};
+    /// # fn main() -> Result<()> {
+    /// let mut buffer = Buffer::new();
+    /// buffer.table("trades")?.symbol("symbol", "ETH-USD")?.at(TimestampNanos::new(1))?;
+    /// buffer.table("trades")?.symbol("symbol", "BTC-USD")?;
+    /// let chunk = buffer.split_completed();
+    /// assert_eq!(chunk, "trades,symbol=ETH-USD 1\n");
+    /// assert_eq!(buffer.as_str(), "trades,symbol=BTC-USD");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split_completed(&mut self) -> String {
+        let rest = self.output.split_off(self.committed_size());
+        let completed = std::mem::replace(&mut self.output, rest);
+        // SAFETY: The completed rows end with a newline, so they're cut at a
+        // char boundary of the buffer's UTF-8 contents.
+        let completed = unsafe { String::from_utf8_unchecked(completed) };
+        debug_assert!(completed.is_empty() || completed.ends_with('\n'));
+        self.marker = None;
+        if self.ends_at_row_boundary() {
+            self.state.clear();
+        } else {
+            // The row being written may target another table than
+            // `first_table`, so the buffer conservatively keeps its
+            // transactional flag and protocol version.
+            self.state.row_count = 0;
+            #[cfg(feature = "net")]
+            {
+                self.state.first_row_at = None;
+            }
+            self.state.row_start = RowStart::new();
+        }
+        completed
+    }
+
+    /// Detach the longest prefix of complete rows that fits in `max_bytes` and
+    /// return it as a new buffer, leaving the remaining rows, and the row being
+    /// written if any, in place.
+    ///
+    /// Use this to fan a large buffer out over several connections. The
+    /// returned buffer has the same settings as this one and can be flushed on
+    /// its own. Rows are never cut in half: Should the first row alone exceed
+    /// `max_bytes`, the returned buffer holds just that row. It's empty only if
+    /// this buffer holds no complete rows.
+    ///
+    /// Any marker is cleared.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::{Buffer, TimestampNanos};
+    /// # fn main() -> Result<()> {
+    /// let mut buffer = Buffer::new();
+    /// for i in 0..3 {
+    ///     buffer.table("t")?.column_i64("i", i)?.at(TimestampNanos::new(i))?;
+    /// }
+    /// let head = buffer.split_off_rows(20);
+    /// assert_eq!(head.as_str(), "t i=0i 0\nt i=1i 1\n");
+    /// assert_eq!(head.row_count(), 2);
+    /// assert_eq!(buffer.as_str(), "t i=2i 2\n");
+    /// assert_eq!(buffer.row_count(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split_off_rows(&mut self, max_bytes: usize) -> Buffer {
+        let mut head = Buffer {
+            output: Vec::new(),
+            state: BufferState::new(),
+            marker: None,
+            max_name_len: self.max_name_len,
+            max_buf_size: self.max_buf_size,
+            protocol_version: self.protocol_version,
+            reject_empty_symbols: self.reject_empty_symbols,
+            reject_control_chars_in_values: self.reject_control_chars_in_values,
+            numeric_symbols: self.numeric_symbols.clone(),
+            validation: self.validation,
+            initial_capacity: self.initial_capacity,
+        };
+
+        // Find where the rows to detach end, and where the last two of them start.
+        let mut rows = 0;
+        let mut starts = (0, 0);
+        let mut cut = 0;
+        for end in row_ends(&self.output[..self.committed_size()]) {
+            if end > max_bytes && rows > 0 {
+                break;
+            }
+            rows += 1;
+            starts = (starts.1, cut);
+            cut = end;
+        }
+        if rows == 0 {
+            return head;
+        }
+
+        let rest = self.output.split_off(cut);
+        head.output = std::mem::replace(&mut self.output, rest);
+        // Without tracking each row's table, the detached rows conservatively
+        // inherit the buffer's table, transactional flag and protocol version.
+        head.state = BufferState {
+            op_case: OpCase::MayFlushOrTable,
+            row_count: rows,
+            first_table: self.state.first_table.clone(),
+            transactional: self.state.transactional,
+            required_version: self.state.required_version,
+            #[cfg(feature = "net")]
+            first_row_at: self.state.first_row_at,
+            row_start: RowStart {
+                len: starts.1,
+                prev_len: starts.0,
+                transactional: self.state.transactional,
+                required_version: self.state.required_version,
+            },
+        };
+
+        self.marker = None;
+        self.state.row_count -= rows;
+        if self.output.is_empty() {
+            self.state.clear();
+        } else {
+            #[cfg(feature = "net")]
+            if self.state.row_count == 0 {
+                self.state.first_row_at = None;
+            }
+            self.state.row_start.len -= cut;
+            self.state.row_start.prev_len = self.state.row_start.prev_len.saturating_sub(cut);
+        }
+        head
+    }
+
+    /// Write the buffer's contents to `writer` instead of sending them to the
+    /// database, then clear the buffer.
+    ///
+    /// The bytes written are exactly those a sender's flush would send, so
+    /// this is useful to capture ILP to a file for later replay, or to compare
+    /// against a golden file in tests. As with a flush, the buffer must not end
+    /// in an incomplete row, an empty buffer writes nothing, and on error the
+    /// buffer keeps its contents. Write errors are reported as
+    /// [`ErrorCode::IoError`](crate::ErrorCode::IoError).
+    ///
+    /// This doesn't call [`flush`](io::Write::flush) on the writer.
+    pub fn flush_to<W: io::Write>(&mut self, writer: &mut W) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check_op(Op::Flush)?;
+        writer.write_all(&self.output).map_err(|io_err| {
+            error::fmt!(IoError, "Could not flush buffer: {}", io_err).with_io_source(io_err)
+        })?;
+        self.clear();
+        Ok(())
+    }
+
+    /// Check that a sender pinned to `protocol_version` and capped at
+    /// `max_buf_size` bytes may send the buffer.
+    #[cfg(feature = "net")]
+    fn check_can_flush(
+        &self,
+        protocol_version: ProtocolVersion,
+        max_buf_size: usize,
+    ) -> Result<()> {
+        self.check_op(Op::Flush)?;
+        if self.state.required_version > protocol_version {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                "Could not flush buffer: It requires ILP protocol version {}, but the sender is pinned to version {}. Create the buffer with Sender::new_buffer.",
+                self.state.required_version as u8,
+                protocol_version as u8
+            ));
+        }
+        if self.len() > max_buf_size {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                "Could not flush buffer: Buffer size of {} exceeds maximum configured allowed size of {} bytes.",
+                self.len(),
+                max_buf_size
+            ));
+        }
+        Ok(())
+    }
+
     /// Check if the next API operation is allowed as per the OP case state machine.
     #[inline(always)]
     fn check_op(&self, op: Op) -> Result<()> {
@@ -682,17 +2077,52 @@ impl Buffer {
         }
     }
 
-    #[inline(always)]
-    fn validate_max_name_len(&self, name: &str) -> Result<()> {
-        if name.len() > self.max_name_len {
-            return Err(error::fmt!(
-                InvalidName,
-                "Bad name: {:?}: Too long (max {} characters)",
+    fn check_value_chars(&self, kind: &str, name: &str, value: &str) -> Result<()> {
+        if !self.reject_control_chars_in_values {
+            return Ok(());
+        }
+        match value
+            .char_indices()
+            .find(|&(_, c)| c.is_control() && c != '\n' && c != '\r')
+        {
+            None => Ok(()),
+            Some((index, c)) => Err(error::fmt!(
+                InvalidValue,
+                "Bad value for {} {:?}: Found the control character U+{:04X} at byte position {}.",
+                kind,
                 name,
-                self.max_name_len
-            ));
+                c as u32,
+                index
+            )),
         }
-        Ok(())
+    }
+
+    fn validate_table_name(&self, name: &TableName) -> Result<()> {
+        self.validate_name(name.name, name.validated, true)
+    }
+
+    fn validate_column_name(&self, name: &ColumnName) -> Result<()> {
+        self.validate_name(name.name, name.validated, false)
+    }
+
+    /// Check the name's characters per the buffer's [`Validation`] mode,
+    /// unless they were checked already, and its length.
+    #[inline(always)]
+    fn validate_name(&self, name: &str, validated: bool, is_table: bool) -> Result<()> {
+        let fault = match self.validation {
+            Validation::Off => name.is_empty().then_some(NameFault::Empty),
+            Validation::Default if validated => None,
+            Validation::Default => NameFault::find(name, is_table),
+            Validation::Strict if validated => NameFault::find_strict(name),
+            Validation::Strict => {
+                NameFault::find(name, is_table).or_else(|| NameFault::find_strict(name))
+            }
+        };
+        if let Some(fault) = fault {
+            let kind = if is_table { "Table" } else { "Column" };
+            return Err(fault.into_error(kind, name));
+        }
+        check_name_len(name, self.max_name_len)
     }
 
     /// Begin recording a new row for the given table.
@@ -721,31 +2151,116 @@ impl Buffer {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn table<'a, N>(&mut self, name: N) -> Result<&mut Self>
-    where
-        N: TryInto<TableName<'a>>,
-        Error: From<N::Error>,
-    {
-        let name: TableName<'a> = name.try_into()?;
-        self.validate_max_name_len(name.name)?;
+    pub fn table<'a>(&mut self, name: impl IntoTableName<'a>) -> Result<&mut Self> {
+        let name = name.into_table_name()?;
+        self.validate_table_name(&name)?;
+        self.start_row(name)?;
+        Ok(self)
+    }
+
+    /// Begin a row, as [`table`](Buffer::table) does, with a name validated
+    /// already. Kept apart from the generic method, so that
+    /// [`table_rows`](Buffer::table_rows) can call it.
+    fn start_row(&mut self, name: TableName) -> Result<()> {
         self.check_op(Op::Table)?;
-        write_escaped_unquoted(&mut self.output, name.name);
+        self.state.row_start = RowStart {
+            len: self.output.len(),
+            prev_len: self.state.row_start.len,
+            transactional: self.state.transactional,
+            required_version: self.state.required_version,
+        };
+        write_name(&mut self.output, name.name, name.escaped);
         self.state.op_case = OpCase::TableWritten;
 
         // A buffer stops being transactional if it targets multiple tables.
-        if let Some(first_table) = &self.state.first_table {
-            if first_table != name.name {
-                self.state.transactional = false;
+        if self.state.first_table.is_empty() {
+            self.state.first_table.push_str(name.name);
+        } else if self.state.first_table != name.name {
+            self.state.transactional = false;
+        }
+        Ok(())
+    }
+
+    /// Record a group of rows for the same table, calling `write_row` for
+    /// each of `rows` to add its symbols and columns and complete it with
+    /// [`at`](Buffer::at) or [`at_now`](Buffer::at_now).
+    ///
+    /// The table name is validated once for the whole group. Call this again
+    /// to add a group for another table: A buffer can hold rows for any number
+    /// of tables, interleaved as you like.
+    ///
+    /// Should `write_row` fail, or return without completing its row, the
+    /// incomplete row is discarded and this returns the error. The rows
+    /// completed before it, for this table or others, stay in the buffer.
+    ///
+    /// Returns the number of rows recorded.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// use questdb::ingress::{Buffer, TimestampNanos};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let trades = [("ETH-USD", 2615.54), ("BTC-USD", 39269.98)];
+    /// let mut buffer = Buffer::new();
+    /// buffer.table_rows("trades", trades, |buffer, (symbol, price)| {
+    ///     buffer
+    ///         .symbol("symbol", symbol)?
+    ///         .column_f64("price", price)?
+    ///         .at(TimestampNanos::now())
+    /// })?;
+    /// buffer.table_rows("quotes", [1.5, 2.5], |buffer, bid| {
+    ///     buffer.column_f64("bid", bid)?.at(TimestampNanos::now())
+    /// })?;
+    /// assert_eq!(buffer.row_count(), 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn table_rows<'a, I, F>(
+        &mut self,
+        table: impl IntoTableName<'a>,
+        rows: I,
+        mut write_row: F,
+    ) -> Result<usize>
+    where
+        I: IntoIterator,
+        F: FnMut(&mut Buffer, I::Item) -> Result<()>,
+    {
+        let table = table.into_table_name()?;
+        self.validate_table_name(&table)?;
+        let mut written = 0;
+        for row in rows {
+            self.start_row(table)?;
+            let result = write_row(self, row).and_then(|()| {
+                if self.ends_with_complete_row() {
+                    Ok(())
+                } else {
+                    Err(error::fmt!(
+                        InvalidApiCall,
+                        "Row {} for table {:?} was not completed: Call `at` or `at_now` at the end of each row.",
+                        written,
+                        table.name
+                    ))
+                }
+            });
+            if let Err(err) = result {
+                if !self.ends_at_row_boundary() {
+                    self.discard_row();
+                }
+                return Err(err);
             }
-        } else {
-            self.state.first_table = Some(name.name.to_owned());
+            written += 1;
         }
-        Ok(self)
+        Ok(written)
     }
 
     /// Record a symbol for the given column.
     /// Make sure you record all symbol columns before any other column type.
     ///
+    /// An empty `value` is accepted unless the buffer was configured with
+    /// [`set_reject_empty_symbols`](Buffer::set_reject_empty_symbols). To catch
+    /// numeric values, which likely belong in a numeric column, see
+    /// [`set_numeric_symbols`](Buffer::set_numeric_symbols).
+    ///
     /// ```
     /// # use questdb::Result;
     /// # use questdb::ingress::Buffer;
@@ -787,39 +2302,57 @@ impl Buffer {
     /// # }
     /// ```
     ///
-    pub fn symbol<'a, N, S>(&mut self, name: N, value: S) -> Result<&mut Self>
+    pub fn symbol<'a, S>(&mut self, name: impl IntoColumnName<'a>, value: S) -> Result<&mut Self>
     where
-        N: TryInto<ColumnName<'a>>,
         S: AsRef<str>,
-        Error: From<N::Error>,
     {
-        let name: ColumnName<'a> = name.try_into()?;
-        self.validate_max_name_len(name.name)?;
+        let name = name.into_column_name()?;
+        self.validate_column_name(&name)?;
         self.check_op(Op::Symbol)?;
-        self.output.push(',');
-        write_escaped_unquoted(&mut self.output, name.name);
-        self.output.push('=');
-        write_escaped_unquoted(&mut self.output, value.as_ref());
+        let value = value.as_ref();
+        self.check_value_chars("symbol", name.name, value)?;
+        if self.reject_empty_symbols && value.is_empty() {
+            return Err(error::fmt!(
+                ConfigError,
+                "Bad value for symbol {:?}: Symbol values must not be empty. \
+                 Skip the symbol to record a null instead.",
+                name.name
+            ));
+        }
+        match &self.numeric_symbols {
+            NumericSymbols::Allow => {}
+            NumericSymbols::Warn(warn) if is_numeric(value) => warn(name.name, value),
+            NumericSymbols::Reject if is_numeric(value) => {
+                return Err(error::fmt!(
+                    InvalidApiCall,
+                    "Bad value for symbol {:?}: {:?} is a number. \
+                     Record it with a numeric column method instead, such as `column_f64`.",
+                    name.name,
+                    value
+                ));
+            }
+            NumericSymbols::Warn(_) | NumericSymbols::Reject => {}
+        }
+        self.output.push(b',');
+        write_name(&mut self.output, name.name, name.escaped);
+        self.output.push(b'=');
+        write_escaped_unquoted(&mut self.output, value);
         self.state.op_case = OpCase::SymbolWritten;
         Ok(self)
     }
 
-    fn write_column_key<'a, N>(&mut self, name: N) -> Result<&mut Self>
-    where
-        N: TryInto<ColumnName<'a>>,
-        Error: From<N::Error>,
-    {
-        let name: ColumnName<'a> = name.try_into()?;
-        self.validate_max_name_len(name.name)?;
+    fn write_column_key<'a>(&mut self, name: impl IntoColumnName<'a>) -> Result<&mut Self> {
+        let name = name.into_column_name()?;
+        self.validate_column_name(&name)?;
         self.check_op(Op::Column)?;
         self.output
             .push(if (self.state.op_case as isize & Op::Symbol as isize) > 0 {
-                ' '
+                b' '
             } else {
-                ','
+                b','
             });
-        write_escaped_unquoted(&mut self.output, name.name);
-        self.output.push('=');
+        write_name(&mut self.output, name.name, name.escaped);
+        self.output.push(b'=');
         self.state.op_case = OpCase::ColumnWritten;
         Ok(self)
     }
@@ -852,13 +2385,13 @@ impl Buffer {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn column_bool<'a, N>(&mut self, name: N, value: bool) -> Result<&mut Self>
-    where
-        N: TryInto<ColumnName<'a>>,
-        Error: From<N::Error>,
-    {
+    pub fn column_bool<'a>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: bool,
+    ) -> Result<&mut Self> {
         self.write_column_key(name)?;
-        self.output.push(if value { 't' } else { 'f' });
+        self.output.push(if value { b't' } else { b'f' });
         Ok(self)
     }
 
@@ -890,19 +2423,195 @@ impl Buffer {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn column_i64<'a, N>(&mut self, name: N, value: i64) -> Result<&mut Self>
-    where
-        N: TryInto<ColumnName<'a>>,
-        Error: From<N::Error>,
-    {
+    pub fn column_i64<'a>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: i64,
+    ) -> Result<&mut Self> {
         self.write_column_key(name)?;
         let mut buf = itoa::Buffer::new();
         let printed = buf.format(value);
-        self.output.push_str(printed);
-        self.output.push('i');
+        self.output.extend_from_slice(printed.as_bytes());
+        self.output.push(b'i');
+        Ok(self)
+    }
+
+    /// Record an integer value for the given column, from any integer type
+    /// that converts losslessly to `i64`, such as `i32`, `u8` or `u32`.
+    ///
+    /// This is the same as calling [`column_i64`](Buffer::column_i64) with the
+    /// value cast to `i64`. A `u64`, `usize` or `i128` value may be out of
+    /// range: convert it with `i64::try_from` first.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?;
+    /// let count: u32 = 42;
+    /// buffer.column_int("col_name", count)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column_int<'a, I>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: I,
+    ) -> Result<&mut Self>
+    where
+        I: Into<i64>,
+    {
+        self.column_i64(name, value.into())
+    }
+
+    /// Record a symbol for each of the `(name, value)` pairs, in order.
+    ///
+    /// This is useful when the symbols are only known at runtime, e.g. when
+    /// they are held in a map. It's the same as calling
+    /// [`symbol`](Buffer::symbol) for each pair: On error, the pairs before the
+    /// failing one have been recorded.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// let tags = HashMap::from([("city".to_owned(), "London".to_owned())]);
+    /// buffer.table("x")?.symbols(&tags)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn symbols<I, N, S>(&mut self, iter: I) -> Result<&mut Self>
+    where
+        I: IntoIterator<Item = (N, S)>,
+        N: AsRef<str>,
+        S: AsRef<str>,
+    {
+        for (name, value) in iter {
+            self.symbol(name.as_ref(), value)?;
+        }
+        Ok(self)
+    }
+
+    /// Record a column for each of the `(name, value)` pairs, in order, with
+    /// the method matching each [`ColumnValue`].
+    ///
+    /// This is useful when the columns are only known at runtime. As with
+    /// [`symbols`](Buffer::symbols), on error the pairs before the failing one
+    /// have been recorded.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use questdb::ingress::ColumnValue;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// let fields = vec![
+    ///     ("temperature", ColumnValue::F64(20.5)),
+    ///     ("status", ColumnValue::Str("ok")),
+    /// ];
+    /// buffer.table("x")?.columns(fields)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn columns<'v, I, N, V>(&mut self, iter: I) -> Result<&mut Self>
+    where
+        I: IntoIterator<Item = (N, V)>,
+        N: AsRef<str>,
+        V: Into<ColumnValue<'v>>,
+    {
+        for (name, value) in iter {
+            self.column(name.as_ref(), value)?;
+        }
         Ok(self)
     }
 
+    /// Record a column with the method matching the [`ColumnValue`]'s type.
+    ///
+    /// This is useful when the column's type is only known at runtime.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use questdb::ingress::ColumnValue;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?;
+    /// let value = ColumnValue::F64(20.5);
+    /// buffer.column("temperature", value)?.column("count", 3u32)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column<'a, 'v, V>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: V,
+    ) -> Result<&mut Self>
+    where
+        V: Into<ColumnValue<'v>>,
+    {
+        self.column_value(name.into_column_name()?, value.into())
+    }
+
+    /// Record a column if `value` is `Some`, or skip it if `None`.
+    ///
+    /// Skipping leaves the row as it was, so the server records a null for
+    /// the column. A row whose columns are all skipped can still be completed
+    /// with [`at`](Buffer::at) or [`at_now`](Buffer::at_now) as long as it has
+    /// at least one symbol.
+    ///
+    /// The name and the call order are validated even when the value is
+    /// skipped.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// let humidity: Option<f64> = None;
+    /// buffer
+    ///     .table("x")?
+    ///     .symbol("sensor", "a1")?
+    ///     .column_opt("temperature", Some(20.5))?
+    ///     .column_opt("humidity", humidity)?
+    ///     .at_now()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column_opt<'a, 'v, V>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: Option<V>,
+    ) -> Result<&mut Self>
+    where
+        V: Into<ColumnValue<'v>>,
+    {
+        let name = name.into_column_name()?;
+        match value {
+            Some(value) => self.column_value(name, value.into()),
+            None => {
+                self.validate_column_name(&name)?;
+                self.check_op(Op::Column)?;
+                Ok(self)
+            }
+        }
+    }
+
+    fn column_value(&mut self, name: ColumnName, value: ColumnValue) -> Result<&mut Self> {
+        match value {
+            ColumnValue::Bool(value) => self.column_bool(name, value),
+            ColumnValue::I64(value) => self.column_i64(name, value),
+            ColumnValue::F64(value) => self.column_f64(name, value),
+            ColumnValue::Str(value) => self.column_str(name, value),
+            ColumnValue::Ts(value) => self.column_ts(name, value),
+        }
+    }
+
     /// Record a floating point value for the given column.
     ///
     /// ```
@@ -931,19 +2640,22 @@ impl Buffer {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn column_f64<'a, N>(&mut self, name: N, value: f64) -> Result<&mut Self>
-    where
-        N: TryInto<ColumnName<'a>>,
-        Error: From<N::Error>,
-    {
+    pub fn column_f64<'a>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: f64,
+    ) -> Result<&mut Self> {
         self.write_column_key(name)?;
         let mut ser = F64Serializer::new(value);
-        self.output.push_str(ser.as_str());
+        self.output.extend_from_slice(ser.as_str().as_bytes());
         Ok(self)
     }
 
     /// Record a string value for the given column.
     ///
+    /// Empty strings are always accepted, even if the buffer
+    /// [rejects empty symbols](Buffer::set_reject_empty_symbols).
+    ///
     /// ```
     /// # use questdb::Result;
     /// # use questdb::ingress::Buffer;
@@ -984,14 +2696,19 @@ impl Buffer {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn column_str<'a, N, S>(&mut self, name: N, value: S) -> Result<&mut Self>
+    pub fn column_str<'a, S>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: S,
+    ) -> Result<&mut Self>
     where
-        N: TryInto<ColumnName<'a>>,
         S: AsRef<str>,
-        Error: From<N::Error>,
     {
+        let name = name.into_column_name()?;
+        let value = value.as_ref();
+        self.check_value_chars("column", name.name, value)?;
         self.write_column_key(name)?;
-        write_escaped_quoted(&mut self.output, value.as_ref());
+        write_escaped_quoted(&mut self.output, value);
         Ok(self)
     }
 
@@ -1041,40 +2758,166 @@ impl Buffer {
     /// # }
     /// ```
     ///
-    /// or you can also pass in a `TimestampNanos`.
+    /// or you can also pass in a `TimestampNanos`. With
+    /// [`ProtocolVersion::V1`], nanosecond timestamps are truncated to
+    /// microseconds.
     ///
     /// Note that both `TimestampMicros` and `TimestampNanos` can be constructed
     /// easily from either `chrono::DateTime` and `std::time::SystemTime`.
     ///
-    /// This last option requires the `chrono_timestamp` feature.
-    pub fn column_ts<'a, N, T>(&mut self, name: N, value: T) -> Result<&mut Self>
+    /// This last option requires the `chrono_timestamp` feature. To pass a
+    /// `chrono::DateTime` or a `time::OffsetDateTime` directly, see
+    /// `column_ts_datetime` and `column_ts_offset_datetime`.
+    pub fn column_ts<'a, T>(&mut self, name: impl IntoColumnName<'a>, value: T) -> Result<&mut Self>
     where
-        N: TryInto<ColumnName<'a>>,
         T: TryInto<Timestamp>,
-        Error: From<N::Error>,
         Error: From<T::Error>,
     {
         self.write_column_key(name)?;
         let timestamp: Timestamp = value.try_into()?;
-        let timestamp: TimestampMicros = timestamp.try_into()?;
+        let (epoch, suffix) = match timestamp {
+            Timestamp::Nanos(nanos) if self.protocol_version >= ProtocolVersion::V2 => {
+                self.state.required_version = ProtocolVersion::V2;
+                (nanos.as_i64(), b'n')
+            }
+            timestamp => {
+                let timestamp = TimestampMicros::try_from(timestamp)?;
+                (timestamp.as_i64(), b't')
+            }
+        };
         let mut buf = itoa::Buffer::new();
-        let printed = buf.format(timestamp.as_i64());
-        self.output.push_str(printed);
-        self.output.push('t');
+        let printed = buf.format(epoch);
+        self.output.extend_from_slice(printed.as_bytes());
+        self.output.push(suffix);
         Ok(self)
     }
 
-    /// Complete the current row with the designated timestamp. After this call, you can
-    /// start recording the next row by calling [Buffer::table] again, or  you can send
-    /// the accumulated batch by calling [Sender::flush] or one of its variants.
+    /// Record a timestamp column value given as a `chrono::DateTime`, at
+    /// nanosecond precision.
+    ///
+    /// This is [`column_ts`](Buffer::column_ts) with
+    /// [`TimestampNanos::from_datetime`]. Returns an
+    /// [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp) error if the
+    /// date-time is out of the range of nanoseconds since the Unix epoch, i.e.
+    /// before 1677 or after 2262. Unlike the designated timestamp, a column may
+    /// hold a timestamp before the epoch.
     ///
     /// ```
     /// # use questdb::Result;
     /// # use questdb::ingress::Buffer;
-    /// use questdb::ingress::TimestampNanos;
+    /// use chrono::Utc;
+    ///
     /// # fn main() -> Result<()> {
     /// # let mut buffer = Buffer::new();
-    /// # buffer.table("x")?.symbol("a", "b")?;
+    /// # buffer.table("x")?;
+    /// buffer.column_ts_datetime("col_name", Utc::now())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This requires the `chrono_timestamp` feature.
+    #[cfg(feature = "chrono_timestamp")]
+    pub fn column_ts_datetime<'a, T>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: chrono::DateTime<T>,
+    ) -> Result<&mut Self>
+    where
+        T: chrono::TimeZone,
+    {
+        self.check_op(Op::Column)?;
+        let name = name.into_column_name()?;
+        self.column_ts_nanos(name, TimestampNanos::from_datetime(value)?)
+    }
+
+    /// Record a timestamp column value given as a `time::OffsetDateTime`, at
+    /// nanosecond precision.
+    ///
+    /// This is [`column_ts`](Buffer::column_ts) with
+    /// [`TimestampNanos::from_offset_datetime`]. As with
+    /// `column_ts_datetime`, the date-time must
+    /// be within the range of nanoseconds since the Unix epoch, but may be
+    /// before the epoch.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use time::OffsetDateTime;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?;
+    /// buffer.column_ts_offset_datetime("col_name", OffsetDateTime::now_utc())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This requires the `time_timestamp` feature.
+    #[cfg(feature = "time_timestamp")]
+    pub fn column_ts_offset_datetime<'a>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        value: time::OffsetDateTime,
+    ) -> Result<&mut Self> {
+        self.check_op(Op::Column)?;
+        let name = name.into_column_name()?;
+        self.column_ts_nanos(name, TimestampNanos::from_offset_datetime(value)?)
+    }
+
+    // Outside of the `where` clauses above, which would otherwise trip up the
+    // inference of `column_ts`'s bounds.
+    // https://github.com/rust-lang/rust/issues/115880
+    #[cfg(any(feature = "chrono_timestamp", feature = "time_timestamp"))]
+    fn column_ts_nanos(&mut self, name: ColumnName, value: TimestampNanos) -> Result<&mut Self> {
+        self.column_ts(name, value)
+    }
+
+    /// Record a column value that is already encoded as ILP, writing it
+    /// verbatim after the `=` sign.
+    ///
+    /// The column name is validated and escaped as usual. This skips escaping
+    /// and validating the value, e.g. for rows templated from cached fragments.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?;
+    /// // SAFETY: `42i` is a valid ILP integer.
+    /// unsafe { buffer.column_raw("col_name", "42i")? };
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The value must be a single, complete ILP field value, such as `42i`,
+    /// `1.5`, `t` or a quoted and escaped string like `"a \"b\""`. It must not
+    /// contain unescaped spaces, commas or line breaks outside of quotes. An
+    /// invalid value isn't memory-unsafe, but corrupts the whole batch: The
+    /// server may reject it, or misread this and the following rows.
+    pub unsafe fn column_raw<'a>(
+        &mut self,
+        name: impl IntoColumnName<'a>,
+        raw_value: &str,
+    ) -> Result<&mut Self> {
+        self.write_column_key(name)?;
+        self.output.extend_from_slice(raw_value.as_bytes());
+        Ok(self)
+    }
+
+    /// Complete the current row with the designated timestamp. After this call, you can
+    /// start recording the next row by calling [Buffer::table] again, or  you can send
+    /// the accumulated batch by calling `Sender::flush` or one of its variants.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use questdb::ingress::TimestampNanos;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?.symbol("a", "b")?;
     /// buffer.at(TimestampNanos::now())?;
     /// # Ok(())
     /// # }
@@ -1099,8 +2942,128 @@ impl Buffer {
     ///
     /// Note that both `TimestampMicros` and `TimestampNanos` can be constructed
     /// easily from either `chrono::DateTime` and `std::time::SystemTime`.
+    /// For a plain number in another unit, call [`at_micros`](Buffer::at_micros)
+    /// or [`at_millis`](Buffer::at_millis).
+    ///
+    /// If the row would grow the buffer past its
+    /// [`max_buf_size`](Buffer::max_buf_size), it is discarded and this returns
+    /// a [`BufferTooLarge`](crate::ErrorCode::BufferTooLarge) error.
     ///
     pub fn at<T>(&mut self, timestamp: T) -> Result<()>
+    where
+        T: TryInto<Timestamp>,
+        Error: From<T::Error>,
+    {
+        self.at_capped(timestamp, self.max_buf_size)
+    }
+
+    /// Complete the current row with the designated timestamp, given in
+    /// microseconds since the Unix epoch (UTC).
+    ///
+    /// The timestamp is sent in nanoseconds, like that of [`at`](Buffer::at).
+    /// Returns an [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp)
+    /// error if it is out of the range of nanoseconds, and keeps the row
+    /// incomplete.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?.symbol("a", "b")?;
+    /// buffer.at_micros(1659548315647406)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    #[cfg_attr(
+        feature = "net",
+        doc = " To complete the row through a [`Sender`], which may auto-flush, pass [`TimestampNanos::from_micros`] to [`Sender::at`] instead."
+    )]
+    pub fn at_micros(&mut self, micros: i64) -> Result<()> {
+        self.check_op(Op::At)?;
+        self.at(TimestampNanos::from_micros(micros)?)
+    }
+
+    /// Complete the current row with the designated timestamp, given in
+    /// milliseconds since the Unix epoch (UTC).
+    ///
+    /// See [`at_micros`](Buffer::at_micros).
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?.symbol("a", "b")?;
+    /// buffer.at_millis(1659548315647)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn at_millis(&mut self, millis: i64) -> Result<()> {
+        self.check_op(Op::At)?;
+        self.at(TimestampNanos::from_millis(millis)?)
+    }
+
+    /// Complete the current row with the designated timestamp given as a
+    /// `chrono::DateTime`, at nanosecond precision.
+    ///
+    /// Returns an [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp)
+    /// error if the date-time is before the Unix epoch or after 2262, beyond
+    /// the range of nanoseconds, and keeps the row incomplete.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use chrono::Utc;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?.symbol("a", "b")?;
+    /// buffer.at_datetime(Utc::now())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This requires the `chrono_timestamp` feature.
+    #[cfg(feature = "chrono_timestamp")]
+    pub fn at_datetime<T: chrono::TimeZone>(
+        &mut self,
+        timestamp: chrono::DateTime<T>,
+    ) -> Result<()> {
+        self.check_op(Op::At)?;
+        self.at(TimestampNanos::from_datetime(timestamp)?)
+    }
+
+    /// Complete the current row with the designated timestamp given as a
+    /// `time::OffsetDateTime`, at nanosecond precision.
+    ///
+    /// As with `at_datetime`, this returns an
+    /// [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp) error if the
+    /// date-time is before the Unix epoch or after 2262, and keeps the row
+    /// incomplete.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// use time::OffsetDateTime;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?.symbol("a", "b")?;
+    /// buffer.at_offset_datetime(OffsetDateTime::now_utc())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This requires the `time_timestamp` feature.
+    #[cfg(feature = "time_timestamp")]
+    pub fn at_offset_datetime(&mut self, timestamp: time::OffsetDateTime) -> Result<()> {
+        self.check_op(Op::At)?;
+        self.at(TimestampNanos::from_offset_datetime(timestamp)?)
+    }
+
+    fn at_capped<T>(&mut self, timestamp: T, max_buf_size: usize) -> Result<()>
     where
         T: TryInto<Timestamp>,
         Error: From<T::Error>,
@@ -1122,17 +3085,20 @@ impl Buffer {
         }
         let mut buf = itoa::Buffer::new();
         let printed = buf.format(epoch_nanos);
-        self.output.push(' ');
-        self.output.push_str(printed);
-        self.output.push('\n');
-        self.state.op_case = OpCase::MayFlushOrTable;
-        self.state.row_count += 1;
-        Ok(())
+        self.output.push(b' ');
+        self.output.extend_from_slice(printed.as_bytes());
+        self.output.push(b'\n');
+        self.complete_row(max_buf_size)
     }
 
     /// Complete the current row without providing a timestamp. The QuestDB instance
     /// will insert its own timestamp.
     ///
+    /// The "now" is the server's clock when it receives the row, not the client's
+    /// clock when the row is recorded: No timestamp is written to the buffer at all.
+    /// [`at_server_time`](Buffer::at_server_time) is the same call, under a name
+    /// that says so.
+    ///
     /// Letting the server assign the timestamp can be faster since it reliably avoids
     /// out-of-order operations in the database for maximum ingestion throughput. However,
     /// it removes the ability to deduplicate rows.
@@ -1145,7 +3111,7 @@ impl Buffer {
     /// In almost all cases, you should prefer the [Buffer::at] function.
     ///
     /// After this call, you can start recording the next row by calling [Buffer::table]
-    /// again, or you can send the accumulated batch by calling [Sender::flush] or one of
+    /// again, or you can send the accumulated batch by calling `Sender::flush` or one of
     /// its variants.
     ///
     /// ```
@@ -1159,12 +3125,182 @@ impl Buffer {
     /// # }
     /// ```
     pub fn at_now(&mut self) -> Result<()> {
+        self.at_now_capped(self.max_buf_size)
+    }
+
+    /// Complete the current row without a timestamp, so that the QuestDB server
+    /// stamps it with its own clock once it receives the row.
+    ///
+    /// This is an alias of [`at_now`](Buffer::at_now). To stamp the row with the
+    /// client's current time instead, call [`at`](Buffer::at) with
+    /// [`TimestampNanos::now()`].
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// # let mut buffer = Buffer::new();
+    /// # buffer.table("x")?.symbol("a", "b")?;
+    /// buffer.at_server_time()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn at_server_time(&mut self) -> Result<()> {
+        self.at_now()
+    }
+
+    fn at_now_capped(&mut self, max_buf_size: usize) -> Result<()> {
         self.check_op(Op::At)?;
-        self.output.push('\n');
+        self.output.push(b'\n');
+        self.complete_row(max_buf_size)
+    }
+
+    fn complete_row(&mut self, max_buf_size: usize) -> Result<()> {
+        if self.output.len() > max_buf_size {
+            let row_len = self.output.len() - self.state.row_start.len;
+            self.discard_row();
+            return Err(row_too_large(row_len, self.output.len(), max_buf_size));
+        }
         self.state.op_case = OpCase::MayFlushOrTable;
         self.state.row_count += 1;
+        #[cfg(feature = "net")]
+        self.state.first_row_at.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+
+    /// Move the complete rows of `other` to the end of this buffer, leaving
+    /// `other` empty.
+    ///
+    /// Use this to merge rows recorded on several threads before flushing them
+    /// over a single connection. The rows are copied as they are, without
+    /// validating them again.
+    ///
+    /// Returns an [`InvalidApiCall`](crate::ErrorCode::InvalidApiCall) error if
+    /// either buffer is in the middle of a row, and a
+    /// [`BufferTooLarge`](crate::ErrorCode::BufferTooLarge) error if the rows
+    /// would grow this buffer past its [`max_buf_size`](Buffer::max_buf_size).
+    /// Both buffers are then left unchanged.
+    ///
+    /// This buffer keeps its marker, if any, so that rewinding to it also drops
+    /// the appended rows. The marker of `other` is cleared.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// # use questdb::ingress::Buffer;
+    /// # fn main() -> Result<()> {
+    /// let mut buffer = Buffer::new();
+    /// buffer.table("x")?.symbol("a", "b")?.at_now()?;
+    /// let mut other = Buffer::new();
+    /// other.table("x")?.symbol("a", "c")?.at_now()?;
+    /// buffer.append(&mut other)?;
+    /// assert_eq!(buffer.as_str(), "x,a=b\nx,a=c\n");
+    /// assert_eq!(buffer.row_count(), 2);
+    /// assert!(other.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append(&mut self, other: &mut Buffer) -> Result<()> {
+        if !self.ends_at_row_boundary() || !other.ends_at_row_boundary() {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                concat!(
+                    "Can't append a buffer whilst constructing a line. ",
+                    "Both buffers must be empty or end with a row completed ",
+                    "by `at` or `at_now`."
+                )
+            ));
+        }
+        let len = self.output.len() + other.output.len();
+        if len > self.max_buf_size {
+            return Err(error::fmt!(
+                BufferTooLarge,
+                "Could not append {} bytes: It would grow the buffer to {} bytes, exceeding the maximum of {} bytes. Flush the buffer first.",
+                other.output.len(),
+                len,
+                self.max_buf_size
+            ));
+        }
+        self.append_rows(other);
+        other.clear();
         Ok(())
     }
+
+    /// Append the complete rows of another buffer.
+    fn append_rows(&mut self, other: &Buffer) {
+        if other.is_empty() {
+            return;
+        }
+        let len = self.output.len();
+        self.output.extend_from_slice(&other.output);
+        self.state.transactional = if self.state.first_table.is_empty() {
+            other.state.transactional
+        } else if other.state.first_table.is_empty() {
+            self.state.transactional
+        } else {
+            self.state.transactional
+                && other.state.transactional
+                && self.state.first_table == other.state.first_table
+        };
+        if self.state.first_table.is_empty() {
+            self.state.first_table.push_str(&other.state.first_table);
+        }
+        self.state.row_count += other.state.row_count;
+        self.state.required_version = self
+            .state
+            .required_version
+            .max(other.state.required_version);
+        #[cfg(feature = "net")]
+        {
+            self.state.first_row_at = self.state.first_row_at.or(other.state.first_row_at);
+        }
+        self.state.op_case = OpCase::MayFlushOrTable;
+        self.state.row_start = RowStart {
+            len: len + other.state.row_start.len,
+            prev_len: len + other.state.row_start.prev_len,
+            transactional: self.state.transactional,
+            required_version: self.state.required_version,
+        };
+    }
+
+    /// Remove the last complete row, returning its text and the buffer's state
+    /// with it, to [restore](Buffer::restore_row) it once the rows before it
+    /// have been flushed.
+    #[cfg(feature = "net")]
+    fn take_last_row(&mut self) -> (String, BufferState) {
+        let state = self.state.clone();
+        let row = self.peek_last_row().to_owned();
+        self.state.row_count -= 1;
+        self.discard_row();
+        (row, state)
+    }
+
+    /// Add back a row removed by [`take_last_row`](Buffer::take_last_row) to the
+    /// now empty buffer.
+    #[cfg(feature = "net")]
+    fn restore_row(&mut self, row: &str, mut state: BufferState) {
+        self.output.extend_from_slice(row.as_bytes());
+        state.row_count = 1;
+        state.first_row_at = Some(Instant::now());
+        state.row_start = RowStart::new();
+        // If the flushed rows targeted several tables, `first_table` may not be
+        // this row's table, so the buffer conservatively stays non-transactional.
+        self.state = state;
+    }
+
+    /// Undo the row being written, back to its `table` call.
+    fn discard_row(&mut self) {
+        let row_start = self.state.row_start;
+        self.output.truncate(row_start.len);
+        self.state.transactional = row_start.transactional;
+        self.state.required_version = row_start.required_version;
+        self.state.row_start.len = row_start.prev_len;
+        if self.state.row_count == 0 {
+            self.state.op_case = OpCase::Init;
+            self.state.first_table.clear();
+        } else {
+            self.state.op_case = OpCase::MayFlushOrTable;
+        }
+    }
 }
 
 impl Default for Buffer {
@@ -1173,1512 +3309,133 @@ impl Default for Buffer {
     }
 }
 
-/// Connects to a QuestDB instance and inserts data via the ILP protocol.
+/// Version of the InfluxDB Line Protocol (ILP) encoding.
 ///
-/// * To construct an instance, use [`Sender::from_conf`] or the [`SenderBuilder`].
-/// * To prepare messages, use [`Buffer`] objects.
-/// * To send messages, call the [`flush`](Sender::flush) method.
-pub struct Sender {
-    descr: String,
-    handler: ProtocolHandler,
-    connected: bool,
-    max_buf_size: usize,
-}
-
-impl std::fmt::Debug for Sender {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.write_str(self.descr.as_str())
-    }
+/// Newer versions can represent more values exactly, but older QuestDB
+/// servers reject them.
+#[cfg_attr(
+    feature = "net",
+    doc = " Pin the version your servers understand with [`SenderBuilder::protocol_version`] and create buffers with [`Sender::new_buffer`] to encode rows accordingly."
+)]
+///
+/// Note that the defaults differ: A sender defaults to the latest version,
+/// but a standalone [`Buffer::new`] to `V1`, so that any sender can flush it.
+/// The same nanosecond timestamp column is thus sent as `1000n` from a
+/// sender's buffer and as `1t` from a standalone one.
+///
+/// Integers carry the `i` suffix, e.g. `42i`, in every version: All QuestDB
+/// servers require it to tell an integer from a float.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
+pub enum ProtocolVersion {
+    /// The original encoding: Timestamp columns are sent in microseconds, with
+    /// the `t` suffix. Nanosecond timestamps lose their sub-microsecond part.
+    ///
+    /// This is the default, as used by [`Buffer::new`].
+    #[default]
+    V1 = 1,
+
+    /// Timestamp columns given in nanoseconds are sent at full precision, with
+    /// the `n` suffix.
+    V2 = 2,
 }
 
-#[derive(PartialEq, Debug, Clone)]
-struct EcdsaAuthParams {
-    key_id: String,
-    priv_key: String,
-    pub_key_x: String,
-    pub_key_y: String,
+pub(crate) struct F64Serializer {
+    buf: ryu::Buffer,
+    n: f64,
 }
 
-#[derive(PartialEq, Debug, Clone)]
-enum AuthParams {
-    Ecdsa(EcdsaAuthParams),
+impl F64Serializer {
+    pub(crate) fn new(n: f64) -> Self {
+        F64Serializer {
+            buf: ryu::Buffer::new(),
+            n,
+        }
+    }
 
-    #[cfg(feature = "ilp-over-http")]
-    Basic(BasicAuthParams),
+    // This function was taken and customized from the ryu crate.
+    #[cold]
+    #[cfg_attr(feature = "no-panic", inline)]
+    fn format_nonfinite(&self) -> &'static str {
+        const MANTISSA_MASK: u64 = 0x000fffffffffffff;
+        const SIGN_MASK: u64 = 0x8000000000000000;
+        let bits = self.n.to_bits();
+        if bits & MANTISSA_MASK != 0 {
+            "NaN"
+        } else if bits & SIGN_MASK != 0 {
+            "-Infinity"
+        } else {
+            "Infinity"
+        }
+    }
 
-    #[cfg(feature = "ilp-over-http")]
-    Token(TokenAuthParams),
+    pub(crate) fn as_str(&mut self) -> &str {
+        if self.n.is_finite() {
+            self.buf.format_finite(self.n)
+        } else {
+            self.format_nonfinite()
+        }
+    }
 }
 
-/// Possible sources of the root certificates used to validate the server's TLS
-/// certificate.
-#[derive(PartialEq, Debug, Clone, Copy)]
-pub enum CertificateAuthority {
-    /// Use the root certificates provided by the
-    /// [`webpki-roots`](https://crates.io/crates/webpki-roots) crate.
-    #[cfg(feature = "tls-webpki-certs")]
-    WebpkiRoots,
+#[cfg(feature = "async-tokio")]
+mod async_tokio;
 
-    /// Use the root certificates provided by the OS
-    #[cfg(feature = "tls-native-certs")]
-    OsRoots,
+mod buffer_pool;
 
-    /// Combine the root certificates provided by the OS and the `webpki-roots` crate.
-    #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
-    WebpkiAndOsRoots,
+#[cfg(feature = "net")]
+mod conf;
 
-    /// Use the root certificates provided in a PEM-encoded file.
-    PemFile,
-}
+#[cfg(feature = "ilp-over-http")]
+mod dead_letter;
 
-/// A `u16` port number or `String` port service name as is registered with
-/// `/etc/services` or equivalent.
-///
-/// ```
-/// use questdb::ingress::Port;
-/// use std::convert::Into;
-///
-/// let service: Port = 9009.into();
-/// ```
-///
-/// or
-///
-/// ```
-/// use questdb::ingress::Port;
-/// use std::convert::Into;
-///
-/// // Assuming the service name is registered.
-/// let service: Port = "qdb_ilp".into();  // or with a String too.
-/// ```
-pub struct Port(String);
+pub mod decoder;
 
-impl From<String> for Port {
-    fn from(s: String) -> Self {
-        Port(s)
-    }
-}
+#[cfg(feature = "net")]
+mod observer;
 
-impl From<&str> for Port {
-    fn from(s: &str) -> Self {
-        Port(s.to_owned())
-    }
-}
+#[cfg(feature = "metrics")]
+mod metrics;
 
-impl From<u16> for Port {
-    fn from(p: u16) -> Self {
-        Port(p.to_string())
-    }
-}
+#[cfg(feature = "metrics")]
+use self::metrics::FlushMetrics;
 
-#[cfg(feature = "insecure-skip-verify")]
-mod danger {
-    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
-    use rustls::{DigitallySignedStruct, Error, SignatureScheme};
-    use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+#[cfg(feature = "net")]
+mod pool;
 
-    #[derive(Debug)]
-    pub struct NoCertificateVerification {}
+#[cfg(feature = "net")]
+mod resolver;
 
-    impl ServerCertVerifier for NoCertificateVerification {
-        fn verify_server_cert(
-            &self,
-            _end_entity: &CertificateDer<'_>,
-            _intermediates: &[CertificateDer<'_>],
-            _server_name: &ServerName<'_>,
-            _ocsp_response: &[u8],
-            _now: UnixTime,
-        ) -> Result<ServerCertVerified, Error> {
-            Ok(ServerCertVerified::assertion())
-        }
+#[cfg(feature = "net")]
+mod sender;
 
-        fn verify_tls12_signature(
-            &self,
-            _message: &[u8],
-            _cert: &CertificateDer<'_>,
-            _dss: &DigitallySignedStruct,
-        ) -> Result<HandshakeSignatureValid, Error> {
-            Ok(HandshakeSignatureValid::assertion())
-        }
+#[cfg(feature = "net")]
+mod shared;
 
-        fn verify_tls13_signature(
-            &self,
-            _message: &[u8],
-            _cert: &CertificateDer<'_>,
-            _dss: &DigitallySignedStruct,
-        ) -> Result<HandshakeSignatureValid, Error> {
-            Ok(HandshakeSignatureValid::assertion())
-        }
+#[cfg(any(feature = "worker", feature = "async-tokio"))]
+mod shutdown;
 
-        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
-            rustls::crypto::ring::default_provider()
-                .signature_verification_algorithms
-                .supported_schemes()
-        }
-    }
-}
+#[cfg(feature = "net")]
+mod spill;
 
-#[cfg(feature = "tls-webpki-certs")]
-fn add_webpki_roots(root_store: &mut RootCertStore) {
-    root_store
-        .roots
-        .extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned())
-}
+#[cfg(feature = "net")]
+use self::spill::{Replayed, SpillWriter};
 
-#[cfg(feature = "tls-native-certs")]
-fn add_os_roots(root_store: &mut RootCertStore) -> Result<()> {
-    let os_certs = rustls_native_certs::load_native_certs().map_err(|io_err| {
-        error::fmt!(
-            TlsError,
-            "Could not load OS native TLS certificates: {}",
-            io_err
-        )
-    })?;
+#[cfg(feature = "net")]
+mod tee;
 
-    let (valid_count, invalid_count) = root_store.add_parsable_certificates(os_certs);
-    if valid_count == 0 && invalid_count > 0 {
-        return Err(error::fmt!(
-            TlsError,
-            "No valid certificates found in native root store ({} found but were invalid)",
-            invalid_count
-        ));
-    }
-    Ok(())
-}
+#[cfg(feature = "net")]
+pub mod testing;
 
-fn configure_tls(
-    tls_enabled: bool,
-    tls_verify: bool,
-    tls_ca: CertificateAuthority,
-    tls_roots: &Option<PathBuf>,
-) -> Result<Option<Arc<rustls::ClientConfig>>> {
-    if !tls_enabled {
-        return Ok(None);
-    }
+mod timestamp;
 
-    let mut root_store = RootCertStore::empty();
-    if tls_verify {
-        match (tls_ca, tls_roots) {
-            #[cfg(feature = "tls-webpki-certs")]
-            (CertificateAuthority::WebpkiRoots, None) => {
-                add_webpki_roots(&mut root_store);
-            }
+#[cfg(feature = "worker")]
+mod double_buffered;
 
-            #[cfg(feature = "tls-webpki-certs")]
-            (CertificateAuthority::WebpkiRoots, Some(_)) => {
-                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" must be unset when \"tls_ca\" is set to \"webpki_roots\"."));
-            }
-
-            #[cfg(feature = "tls-native-certs")]
-            (CertificateAuthority::OsRoots, None) => {
-                add_os_roots(&mut root_store)?;
-            }
-
-            #[cfg(feature = "tls-native-certs")]
-            (CertificateAuthority::OsRoots, Some(_)) => {
-                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" must be unset when \"tls_ca\" is set to \"os_roots\"."));
-            }
-
-            #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
-            (CertificateAuthority::WebpkiAndOsRoots, None) => {
-                add_webpki_roots(&mut root_store);
-                add_os_roots(&mut root_store)?;
-            }
-
-            #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
-            (CertificateAuthority::WebpkiAndOsRoots, Some(_)) => {
-                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" must be unset when \"tls_ca\" is set to \"webpki_and_os_roots\"."));
-            }
-
-            (CertificateAuthority::PemFile, Some(ca_file)) => {
-                let certfile = std::fs::File::open(ca_file).map_err(|io_err| {
-                    error::fmt!(
-                        TlsError,
-                        concat!(
-                            "Could not open tls_roots certificate authority ",
-                            "file from path {:?}: {}"
-                        ),
-                        ca_file,
-                        io_err
-                    )
-                })?;
-                let mut reader = BufReader::new(certfile);
-                let der_certs = rustls_pemfile::certs(&mut reader)
-                    .collect::<std::result::Result<Vec<_>, _>>()
-                    .map_err(|io_err| {
-                        error::fmt!(
-                            TlsError,
-                            concat!(
-                                "Could not read certificate authority ",
-                                "file from path {:?}: {}"
-                            ),
-                            ca_file,
-                            io_err
-                        )
-                    })?;
-                root_store.add_parsable_certificates(der_certs);
-            }
-
-            (CertificateAuthority::PemFile, None) => {
-                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" is required when \"tls_ca\" is set to \"pem_file\"."));
-            }
-        }
-    }
-
-    let mut config = rustls::ClientConfig::builder()
-        .with_root_certificates(root_store)
-        .with_no_client_auth();
-
-    // TLS log file for debugging.
-    // Set the SSLKEYLOGFILE env variable to a writable location.
-    config.key_log = Arc::new(rustls::KeyLogFile::new());
-
-    #[cfg(feature = "insecure-skip-verify")]
-    if !tls_verify {
-        config
-            .dangerous()
-            .set_certificate_verifier(Arc::new(danger::NoCertificateVerification {}));
-    }
-
-    Ok(Some(Arc::new(config)))
-}
-
-fn validate_auto_flush_params(params: &HashMap<String, String>) -> Result<()> {
-    if let Some(auto_flush) = params.get("auto_flush") {
-        if auto_flush.as_str() != "off" {
-            return Err(error::fmt!(
-                ConfigError,
-                "Invalid auto_flush value '{auto_flush}'. This client does not \
-                support auto-flush, so the only accepted value is 'off'"
-            ));
-        }
-    }
-
-    for &param in ["auto_flush_rows", "auto_flush_bytes"].iter() {
-        if params.contains_key(param) {
-            return Err(error::fmt!(
-                ConfigError,
-                "Invalid configuration parameter {:?}. This client does not support auto-flush",
-                param
-            ));
-        }
-    }
-    Ok(())
-}
-
-/// Protocol used to communicate with the QuestDB server.
-#[derive(PartialEq, Debug, Clone, Copy)]
-pub enum Protocol {
-    /// ILP over TCP (streaming).
-    Tcp,
-
-    /// TCP + TLS
-    Tcps,
-
-    #[cfg(feature = "ilp-over-http")]
-    /// ILP over HTTP (request-response, InfluxDB-compatible).
-    Http,
-
-    #[cfg(feature = "ilp-over-http")]
-    /// HTTP + TLS
-    Https,
-}
-
-impl Display for Protocol {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.write_str(self.schema())
-    }
-}
-
-impl Protocol {
-    fn default_port(&self) -> &str {
-        match self {
-            Protocol::Tcp | Protocol::Tcps => "9009",
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Http | Protocol::Https => "9000",
-        }
-    }
-
-    fn tls_enabled(&self) -> bool {
-        match self {
-            Protocol::Tcp => false,
-            Protocol::Tcps => true,
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Http => false,
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Https => true,
-        }
-    }
-
-    fn is_tcpx(&self) -> bool {
-        match self {
-            Protocol::Tcp => true,
-            Protocol::Tcps => true,
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Http => false,
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Https => false,
-        }
-    }
-
-    #[cfg(feature = "ilp-over-http")]
-    fn is_httpx(&self) -> bool {
-        match self {
-            Protocol::Tcp => false,
-            Protocol::Tcps => false,
-            Protocol::Http => true,
-            Protocol::Https => true,
-        }
-    }
-
-    fn schema(&self) -> &str {
-        match self {
-            Protocol::Tcp => "tcp",
-            Protocol::Tcps => "tcps",
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Http => "http",
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Https => "https",
-        }
-    }
-
-    fn from_schema(schema: &str) -> Result<Self> {
-        match schema {
-            "tcp" => Ok(Protocol::Tcp),
-            "tcps" => Ok(Protocol::Tcps),
-            #[cfg(feature = "ilp-over-http")]
-            "http" => Ok(Protocol::Http),
-            #[cfg(feature = "ilp-over-http")]
-            "https" => Ok(Protocol::Https),
-            _ => Err(error::fmt!(ConfigError, "Unsupported protocol: {}", schema)),
-        }
-    }
-}
-
-/// Accumulates parameters for a new `Sender` instance.
-///
-/// You can also create the builder from a config string or the `QDB_CLIENT_CONF`
-/// environment variable.
-///
-#[cfg_attr(
-    feature = "ilp-over-http",
-    doc = r##"
-```no_run
-# use questdb::Result;
-use questdb::ingress::{Protocol, SenderBuilder};
-# fn main() -> Result<()> {
-let mut sender = SenderBuilder::new(Protocol::Http, "localhost", 9009).build()?;
-# Ok(())
-# }
-```
-"##
-)]
-///
-/// ```no_run
-/// # use questdb::Result;
-/// use questdb::ingress::{Protocol, SenderBuilder};
-///
-/// # fn main() -> Result<()> {
-/// let mut sender = SenderBuilder::new(Protocol::Tcp, "localhost", 9009).build()?;
-/// # Ok(())
-/// # }
-/// ```
-///
-/// ```no_run
-/// # use questdb::Result;
-/// use questdb::ingress::SenderBuilder;
-///
-/// # fn main() -> Result<()> {
-/// let mut sender = SenderBuilder::from_conf("https::addr=localhost:9000;")?.build()?;
-/// # Ok(())
-/// # }
-/// ```
-///
-/// ```no_run
-/// # use questdb::Result;
-/// use questdb::ingress::SenderBuilder;
-///
-/// # fn main() -> Result<()> {
-/// // export QDB_CLIENT_CONF="https::addr=localhost:9000;"
-/// let mut sender = SenderBuilder::from_env()?.build()?;
-/// # Ok(())
-/// # }
-/// ```
-///
-#[derive(Debug, Clone)]
-pub struct SenderBuilder {
-    protocol: Protocol,
-    host: ConfigSetting<String>,
-    port: ConfigSetting<String>,
-    net_interface: ConfigSetting<Option<String>>,
-    max_buf_size: ConfigSetting<usize>,
-    auth_timeout: ConfigSetting<Duration>,
-    username: ConfigSetting<Option<String>>,
-    password: ConfigSetting<Option<String>>,
-    token: ConfigSetting<Option<String>>,
-    token_x: ConfigSetting<Option<String>>,
-    token_y: ConfigSetting<Option<String>>,
-
-    #[cfg(feature = "insecure-skip-verify")]
-    tls_verify: ConfigSetting<bool>,
-
-    tls_ca: ConfigSetting<CertificateAuthority>,
-    tls_roots: ConfigSetting<Option<PathBuf>>,
-
-    #[cfg(feature = "ilp-over-http")]
-    http: Option<HttpConfig>,
-}
-
-impl SenderBuilder {
-    /// Create a new `SenderBuilder` instance from the configuration string.
-    ///
-    /// The format of the string is: `"http::addr=host:port;key=value;...;"`.
-    ///
-    /// Instead of `"http"`, you can also specify `"https"`, `"tcp"`, and `"tcps"`.
-    ///
-    /// We recommend HTTP for most cases because it provides more features, like
-    /// reporting errors to the client and supporting transaction control. TCP can
-    /// sometimes be faster in higher-latency networks, but misses a number of
-    /// features.
-    ///
-    /// The accepted keys match one-for-one with the methods on `SenderBuilder`.
-    /// For example, this is a valid configuration string:
-    ///
-    /// "https::addr=host:port;username=alice;password=secret;"
-    ///
-    /// and there are matching methods [SenderBuilder::username] and
-    /// [SenderBuilder::password]. The value of `addr=` is supplied directly to the
-    /// `SenderBuilder` constructor, so there's no matching method for that.
-    ///
-    /// You can also load the configuration from an environment variable. See
-    /// [`SenderBuilder::from_env`].
-    ///
-    /// Once you have a `SenderBuilder` instance, you can further customize it
-    /// before calling [`SenderBuilder::build`], but you can't change any settings
-    /// that are already set in the config string.
-    pub fn from_conf<T: AsRef<str>>(conf: T) -> Result<Self> {
-        let conf = conf.as_ref();
-        let conf = questdb_confstr::parse_conf_str(conf)
-            .map_err(|e| error::fmt!(ConfigError, "Config parse error: {}", e))?;
-        let service = conf.service();
-        let params = conf.params();
-
-        let protocol = Protocol::from_schema(service)?;
-
-        let Some(addr) = params.get("addr") else {
-            return Err(error::fmt!(
-                ConfigError,
-                "Missing \"addr\" parameter in config string"
-            ));
-        };
-        let (host, port) = match addr.split_once(':') {
-            Some((h, p)) => (h, p),
-            None => (addr.as_str(), protocol.default_port()),
-        };
-        let mut builder = SenderBuilder::new(protocol, host, port);
-
-        validate_auto_flush_params(params)?;
-
-        for (key, val) in params.iter().map(|(k, v)| (k.as_str(), v.as_str())) {
-            builder = match key {
-                "username" => builder.username(val)?,
-                "password" => builder.password(val)?,
-                "token" => builder.token(val)?,
-                "token_x" => builder.token_x(val)?,
-                "token_y" => builder.token_y(val)?,
-                "bind_interface" => builder.bind_interface(val)?,
-
-                "init_buf_size" => {
-                    return Err(error::fmt!(
-                        ConfigError,
-                        "\"init_buf_size\" is not supported in config string"
-                    ))
-                }
-
-                "max_buf_size" => builder.max_buf_size(parse_conf_value(key, val)?)?,
-
-                "auth_timeout" => {
-                    builder.auth_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
-                }
-
-                "tls_verify" => {
-                    let verify = match val {
-                        "on" => true,
-                        "unsafe_off" => false,
-                        _ => {
-                            return Err(error::fmt!(
-                                ConfigError,
-                                r##"Config parameter "tls_verify" must be either "on" or "unsafe_off".'"##,
-                            ))
-                        }
-                    };
-
-                    #[cfg(not(feature = "insecure-skip-verify"))]
-                    {
-                        if !verify {
-                            return Err(error::fmt!(
-                                ConfigError,
-                                r##"The "insecure-skip-verify" feature is not enabled, so "tls_verify=unsafe_off" is not supported"##,
-                            ));
-                        }
-                        builder
-                    }
-
-                    #[cfg(feature = "insecure-skip-verify")]
-                    builder.tls_verify(verify)?
-                }
-
-                "tls_ca" => {
-                    let ca = match val {
-                        #[cfg(feature = "tls-webpki-certs")]
-                        "webpki_roots" => CertificateAuthority::WebpkiRoots,
-
-                        #[cfg(not(feature = "tls-webpki-certs"))]
-                        "webpki_roots" => return Err(error::fmt!(ConfigError, "Config parameter \"tls_ca=webpki_roots\" requires the \"tls-webpki-certs\" feature")),
-
-                        #[cfg(feature = "tls-native-certs")]
-                        "os_roots" => CertificateAuthority::OsRoots,
-
-                        #[cfg(not(feature = "tls-native-certs"))]
-                        "os_roots" => return Err(error::fmt!(ConfigError, "Config parameter \"tls_ca=os_roots\" requires the \"tls-native-certs\" feature")),
-
-                        #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
-                        "webpki_and_os_roots" => CertificateAuthority::WebpkiAndOsRoots,
-
-                        #[cfg(not(all(feature = "tls-webpki-certs", feature = "tls-native-certs")))]
-                        "webpki_and_os_roots" => return Err(error::fmt!(ConfigError, "Config parameter \"tls_ca=webpki_and_os_roots\" requires both the \"tls-webpki-certs\" and \"tls-native-certs\" features")),
-
-                        _ => return Err(error::fmt!(ConfigError, "Invalid value {val:?} for \"tls_ca\"")),
-                    };
-                    builder.tls_ca(ca)?
-                }
-
-                "tls_roots" => {
-                    let path = PathBuf::from_str(val).map_err(|e| {
-                        error::fmt!(
-                            ConfigError,
-                            "Invalid path {:?} for \"tls_roots\": {}",
-                            val,
-                            e
-                        )
-                    })?;
-                    builder.tls_roots(path)?
-                }
-
-                "tls_roots_password" => {
-                    return Err(error::fmt!(
-                        ConfigError,
-                        "\"tls_roots_password\" is not supported."
-                    ))
-                }
-
-                #[cfg(feature = "ilp-over-http")]
-                "request_min_throughput" => {
-                    builder.request_min_throughput(parse_conf_value(key, val)?)?
-                }
-
-                #[cfg(feature = "ilp-over-http")]
-                "request_timeout" => {
-                    builder.request_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
-                }
-
-                #[cfg(feature = "ilp-over-http")]
-                "retry_timeout" => {
-                    builder.retry_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
-                }
-                // Ignore other parameters.
-                // We don't want to fail on unknown keys as this would require releasing different
-                // library implementations in lock step as soon as a new parameter is added to any of them,
-                // even if it's not used.
-                _ => builder,
-            };
-        }
-
-        Ok(builder)
-    }
-
-    /// Create a new `SenderBuilder` instance from the configuration from the
-    /// configuration stored in the `QDB_CLIENT_CONF` environment variable.
-    ///
-    /// The format of the string is the same as for [`SenderBuilder::from_conf`].
-    pub fn from_env() -> Result<Self> {
-        let conf = std::env::var("QDB_CLIENT_CONF").map_err(|_| {
-            error::fmt!(ConfigError, "Environment variable QDB_CLIENT_CONF not set.")
-        })?;
-        Self::from_conf(conf)
-    }
-
-    /// Create a new `SenderBuilder` instance with the provided QuestDB
-    /// server and port, using ILP over the specified protocol.
-    ///
-    /// ```no_run
-    /// # use questdb::Result;
-    /// use questdb::ingress::{Protocol, SenderBuilder};
-    ///
-    /// # fn main() -> Result<()> {
-    /// let mut sender = SenderBuilder::new(
-    ///     Protocol::Tcp, "localhost", 9009).build()?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn new<H: Into<String>, P: Into<Port>>(protocol: Protocol, host: H, port: P) -> Self {
-        let host = host.into();
-        let port: Port = port.into();
-        let port = port.0;
-
-        #[cfg(feature = "tls-webpki-certs")]
-        let tls_ca = CertificateAuthority::WebpkiRoots;
-
-        #[cfg(all(not(feature = "tls-webpki-certs"), feature = "tls-native-certs"))]
-        let tls_ca = CertificateAuthority::OsRoots;
-
-        #[cfg(not(any(feature = "tls-webpki-certs", feature = "tls-native-certs")))]
-        let tls_ca = CertificateAuthority::PemFile;
-
-        Self {
-            protocol,
-            host: ConfigSetting::new_specified(host),
-            port: ConfigSetting::new_specified(port),
-            net_interface: ConfigSetting::new_default(None),
-            max_buf_size: ConfigSetting::new_default(100 * 1024 * 1024),
-            auth_timeout: ConfigSetting::new_default(Duration::from_secs(15)),
-            username: ConfigSetting::new_default(None),
-            password: ConfigSetting::new_default(None),
-            token: ConfigSetting::new_default(None),
-            token_x: ConfigSetting::new_default(None),
-            token_y: ConfigSetting::new_default(None),
-
-            #[cfg(feature = "insecure-skip-verify")]
-            tls_verify: ConfigSetting::new_default(true),
-
-            tls_ca: ConfigSetting::new_default(tls_ca),
-            tls_roots: ConfigSetting::new_default(None),
-
-            #[cfg(feature = "ilp-over-http")]
-            http: if protocol.is_httpx() {
-                Some(HttpConfig::default())
-            } else {
-                None
-            },
-        }
-    }
-
-    /// Select local outbound interface.
-    ///
-    /// This may be relevant if your machine has multiple network interfaces.
-    ///
-    /// The default is `"0.0.0.0"`.
-    pub fn bind_interface<I: Into<String>>(mut self, addr: I) -> Result<Self> {
-        self.ensure_is_tcpx("bind_interface")?;
-        self.net_interface
-            .set_specified("bind_interface", Some(validate_value(addr.into())?))?;
-        Ok(self)
-    }
-
-    /// Set the username for authentication.
-    ///
-    /// For TCP, this is the `kid` part of the ECDSA key set.
-    /// The other fields are [`token`](SenderBuilder::token), [`token_x`](SenderBuilder::token_x),
-    /// and [`token_y`](SenderBuilder::token_y).
-    ///
-    /// For HTTP, this is a part of basic authentication.
-    /// See also: [`password`](SenderBuilder::password).
-    pub fn username(mut self, username: &str) -> Result<Self> {
-        self.username
-            .set_specified("username", Some(validate_value(username.to_string())?))?;
-        Ok(self)
-    }
-
-    /// Set the password for basic HTTP authentication.
-    /// See also: [`username`](SenderBuilder::username).
-    pub fn password(mut self, password: &str) -> Result<Self> {
-        self.password
-            .set_specified("password", Some(validate_value(password.to_string())?))?;
-        Ok(self)
-    }
-
-    /// Set the Token (Bearer) Authentication parameter for HTTP,
-    /// or the ECDSA private key for TCP authentication.
-    pub fn token(mut self, token: &str) -> Result<Self> {
-        self.token
-            .set_specified("token", Some(validate_value(token.to_string())?))?;
-        Ok(self)
-    }
-
-    /// Set the ECDSA public key X for TCP authentication.
-    pub fn token_x(mut self, token_x: &str) -> Result<Self> {
-        self.token_x
-            .set_specified("token_x", Some(validate_value(token_x.to_string())?))?;
-        Ok(self)
-    }
-
-    /// Set the ECDSA public key Y for TCP authentication.
-    pub fn token_y(mut self, token_y: &str) -> Result<Self> {
-        self.token_y
-            .set_specified("token_y", Some(validate_value(token_y.to_string())?))?;
-        Ok(self)
-    }
-
-    /// Configure how long to wait for messages from the QuestDB server during
-    /// the TLS handshake and authentication process.
-    /// The default is 15 seconds.
-    pub fn auth_timeout(mut self, value: Duration) -> Result<Self> {
-        self.auth_timeout.set_specified("auth_timeout", value)?;
-        Ok(self)
-    }
-
-    /// Ensure that TLS is enabled for the protocol.
-    pub fn ensure_tls_enabled(&self, property: &str) -> Result<()> {
-        if !self.protocol.tls_enabled() {
-            return Err(error::fmt!(
-                ConfigError,
-                "Cannot set {property:?}: TLS is not supported for protocol {}",
-                self.protocol
-            ));
-        }
-        Ok(())
-    }
-
-    /// Set to `false` to disable TLS certificate verification.
-    /// This should only be used for debugging purposes as it reduces security.
-    ///
-    /// For testing, consider specifying a path to a `.pem` file instead via
-    /// the [`tls_roots`](SenderBuilder::tls_roots) method.
-    #[cfg(feature = "insecure-skip-verify")]
-    pub fn tls_verify(mut self, verify: bool) -> Result<Self> {
-        self.ensure_tls_enabled("tls_verify")?;
-        self.tls_verify.set_specified("tls_verify", verify)?;
-        Ok(self)
-    }
-
-    /// Specify where to find the root certificate used to validate the
-    /// server's TLS certificate.
-    pub fn tls_ca(mut self, ca: CertificateAuthority) -> Result<Self> {
-        self.ensure_tls_enabled("tls_ca")?;
-        self.tls_ca.set_specified("tls_ca", ca)?;
-        Ok(self)
-    }
-
-    /// Set the path to a custom root certificate `.pem` file.
-    /// This is used to validate the server's certificate during the TLS handshake.
-    ///
-    /// See notes on how to test with [self-signed
-    /// certificates](https://github.com/questdb/c-questdb-client/tree/main/tls_certs).
-    pub fn tls_roots<P: Into<PathBuf>>(self, path: P) -> Result<Self> {
-        let mut builder = self.tls_ca(CertificateAuthority::PemFile)?;
-        let path = path.into();
-        // Attempt to read the file here to catch any issues early.
-        let _file = std::fs::File::open(&path).map_err(|io_err| {
-            error::fmt!(
-                ConfigError,
-                "Could not open root certificate file from path {:?}: {}",
-                path,
-                io_err
-            )
-        })?;
-        builder.tls_roots.set_specified("tls_roots", Some(path))?;
-        Ok(builder)
-    }
-
-    /// The maximum buffer size in bytes that the client will flush to the server.
-    /// The default is 100 MiB.
-    pub fn max_buf_size(mut self, value: usize) -> Result<Self> {
-        let min = 1024;
-        if value < min {
-            return Err(error::fmt!(
-                ConfigError,
-                "max_buf_size\" must be at least {min} bytes."
-            ));
-        }
-        self.max_buf_size.set_specified("max_buf_size", value)?;
-        Ok(self)
-    }
-
-    #[cfg(feature = "ilp-over-http")]
-    /// Set the cumulative duration spent in retries.
-    /// The value is in milliseconds, and the default is 10 seconds.
-    pub fn retry_timeout(mut self, value: Duration) -> Result<Self> {
-        if let Some(http) = &mut self.http {
-            http.retry_timeout.set_specified("retry_timeout", value)?;
-        } else {
-            return Err(error::fmt!(
-                ConfigError,
-                "retry_timeout is supported only in ILP over HTTP."
-            ));
-        }
-        Ok(self)
-    }
-
-    #[cfg(feature = "ilp-over-http")]
-    /// Set the minimum acceptable throughput while sending a buffer to the server.
-    /// The sender will divide the payload size by this number to determine for how
-    /// long to keep sending the payload before timing out.
-    /// The value is in bytes per second, and the default is 100 KiB/s.
-    /// The timeout calculated from minimum throughput is adedd to the value of
-    /// [`request_timeout`](SenderBuilder::request_timeout) to get the total timeout
-    /// value.
-    pub fn request_min_throughput(mut self, value: u64) -> Result<Self> {
-        if let Some(http) = &mut self.http {
-            http.request_min_throughput
-                .set_specified("request_min_throughput", value)?;
-        } else {
-            return Err(error::fmt!(
-                ConfigError,
-                "\"request_min_throughput\" is supported only in ILP over HTTP."
-            ));
-        }
-        Ok(self)
-    }
-
-    #[cfg(feature = "ilp-over-http")]
-    /// Additional time to wait on top of that calculated from the minimum throughput.
-    /// This accounts for the fixed latency of the HTTP request-response roundtrip.
-    /// The value is in milliseconds, and the default is 10 seconds.
-    /// See also: [`request_min_throughput`](SenderBuilder::request_min_throughput).
-    pub fn request_timeout(mut self, value: Duration) -> Result<Self> {
-        if let Some(http) = &mut self.http {
-            http.request_timeout
-                .set_specified("request_timeout", value)?;
-        } else {
-            return Err(error::fmt!(
-                ConfigError,
-                "\"request_timeout\" is supported only in ILP over HTTP."
-            ));
-        }
-        Ok(self)
-    }
-
-    #[cfg(feature = "ilp-over-http")]
-    /// Internal API, do not use.
-    /// This is exposed exclusively for the Python client.
-    /// We (QuestDB) use this to help us debug which client is being used if we encounter issues.
-    #[doc(hidden)]
-    pub fn user_agent(mut self, value: &str) -> Result<Self> {
-        let value = validate_value(value)?;
-        if let Some(http) = &mut self.http {
-            http.user_agent = value.to_string();
-        }
-        Ok(self)
-    }
-
-    fn connect_tcp(&self, auth: &Option<AuthParams>) -> Result<ProtocolHandler> {
-        let addr: SockAddr = gai::resolve_host_port(self.host.as_str(), self.port.as_str())?;
-        let mut sock = Socket::new(Domain::IPV4, Type::STREAM, Some(SockProtocol::TCP))
-            .map_err(|io_err| map_io_to_socket_err("Could not open TCP socket: ", io_err))?;
-
-        // See: https://idea.popcount.org/2014-04-03-bind-before-connect/
-        // We set `SO_REUSEADDR` on the outbound socket to avoid issues where a client may exhaust
-        // their interface's ports. See: https://github.com/questdb/py-questdb-client/issues/21
-        sock.set_reuse_address(true)
-            .map_err(|io_err| map_io_to_socket_err("Could not set SO_REUSEADDR: ", io_err))?;
-
-        sock.set_linger(Some(Duration::from_secs(120)))
-            .map_err(|io_err| map_io_to_socket_err("Could not set socket linger: ", io_err))?;
-        sock.set_keepalive(true)
-            .map_err(|io_err| map_io_to_socket_err("Could not set SO_KEEPALIVE: ", io_err))?;
-        sock.set_nodelay(true)
-            .map_err(|io_err| map_io_to_socket_err("Could not set TCP_NODELAY: ", io_err))?;
-        if let Some(ref host) = self.net_interface.deref() {
-            let bind_addr = gai::resolve_host(host.as_str())?;
-            sock.bind(&bind_addr).map_err(|io_err| {
-                map_io_to_socket_err(
-                    &format!("Could not bind to interface address {:?}: ", host),
-                    io_err,
-                )
-            })?;
-        }
-        sock.connect(&addr).map_err(|io_err| {
-            let host_port = format!("{}:{}", self.host.deref(), *self.port);
-            let prefix = format!("Could not connect to {:?}: ", host_port);
-            map_io_to_socket_err(&prefix, io_err)
-        })?;
-
-        // We read during both TLS handshake and authentication.
-        // We set up a read timeout to prevent the client from "hanging"
-        // should we be connecting to a server configured in a different way
-        // from the client.
-        sock.set_read_timeout(Some(*self.auth_timeout))
-            .map_err(|io_err| {
-                map_io_to_socket_err("Failed to set read timeout on socket: ", io_err)
-            })?;
-
-        #[cfg(feature = "insecure-skip-verify")]
-        let tls_verify = *self.tls_verify;
-
-        #[cfg(not(feature = "insecure-skip-verify"))]
-        let tls_verify = true;
-
-        let mut conn = match configure_tls(
-            self.protocol.tls_enabled(),
-            tls_verify,
-            *self.tls_ca,
-            self.tls_roots.deref(),
-        )? {
-            Some(tls_config) => {
-                let server_name: ServerName = ServerName::try_from(self.host.as_str())
-                    .map_err(|inv_dns_err| error::fmt!(TlsError, "Bad host: {}", inv_dns_err))?
-                    .to_owned();
-                let mut tls_conn =
-                    ClientConnection::new(tls_config, server_name).map_err(|rustls_err| {
-                        error::fmt!(TlsError, "Could not create TLS client: {}", rustls_err)
-                    })?;
-                while tls_conn.wants_write() || tls_conn.is_handshaking() {
-                    tls_conn.complete_io(&mut sock).map_err(|io_err| {
-                        if (io_err.kind() == ErrorKind::TimedOut)
-                            || (io_err.kind() == ErrorKind::WouldBlock)
-                        {
-                            error::fmt!(
-                                TlsError,
-                                concat!(
-                                    "Failed to complete TLS handshake:",
-                                    " Timed out waiting for server ",
-                                    "response after {:?}."
-                                ),
-                                *self.auth_timeout
-                            )
-                        } else {
-                            error::fmt!(TlsError, "Failed to complete TLS handshake: {}", io_err)
-                        }
-                    })?;
-                }
-                Connection::Tls(StreamOwned::new(tls_conn, sock).into())
-            }
-            None => Connection::Direct(sock),
-        };
-
-        if let Some(AuthParams::Ecdsa(auth)) = auth {
-            conn.authenticate(auth)?;
-        }
-
-        Ok(ProtocolHandler::Socket(conn))
-    }
-
-    fn build_auth(&self) -> Result<Option<AuthParams>> {
-        match (
-            self.protocol,
-            self.username.deref(),
-            self.password.deref(),
-            self.token.deref(),
-            self.token_x.deref(),
-            self.token_y.deref(),
-        ) {
-            (_, None, None, None, None, None) => Ok(None),
-            (
-                protocol,
-                Some(username),
-                None,
-                Some(token),
-                Some(token_x),
-                Some(token_y),
-            ) if protocol.is_tcpx() => Ok(Some(AuthParams::Ecdsa(EcdsaAuthParams {
-                key_id: username.to_string(),
-                priv_key: token.to_string(),
-                pub_key_x: token_x.to_string(),
-                pub_key_y: token_y.to_string(),
-            }))),
-            (protocol, Some(_username), Some(_password), None, None, None)
-                if protocol.is_tcpx() => {
-                Err(error::fmt!(ConfigError,
-                    r##"The "basic_auth" setting can only be used with the ILP/HTTP protocol."##,
-                ))
-            }
-            (protocol, None, None, Some(_token), None, None)
-                if protocol.is_tcpx() => {
-                Err(error::fmt!(ConfigError, "Token authentication only be used with the ILP/HTTP protocol."))
-            }
-            (protocol, _username, None, _token, _token_x, _token_y)
-                if protocol.is_tcpx() => {
-                Err(error::fmt!(ConfigError,
-                    r##"Incomplete ECDSA authentication parameters. Specify either all or none of: "username", "token", "token_x", "token_y"."##,
-                ))
-            }
-            #[cfg(feature = "ilp-over-http")]
-            (protocol, Some(username), Some(password), None, None, None)
-                if protocol.is_httpx() => {
-                Ok(Some(AuthParams::Basic(BasicAuthParams {
-                    username: username.to_string(),
-                    password: password.to_string(),
-                })))
-            }
-            #[cfg(feature = "ilp-over-http")]
-            (protocol, Some(_username), None, None, None, None)
-                if protocol.is_httpx() => {
-                Err(error::fmt!(ConfigError,
-                    r##"Basic authentication parameter "username" is present, but "password" is missing."##,
-                ))
-            }
-            #[cfg(feature = "ilp-over-http")]
-            (protocol, None, Some(_password), None, None, None)
-                if protocol.is_httpx() => {
-                Err(error::fmt!(ConfigError,
-                    r##"Basic authentication parameter "password" is present, but "username" is missing."##,
-                ))
-            }
-            #[cfg(feature = "ilp-over-http")]
-            (protocol, None, None, Some(token), None, None)
-                if protocol.is_httpx() => {
-                Ok(Some(AuthParams::Token(TokenAuthParams {
-                    token: token.to_string(),
-                })))
-            }
-            #[cfg(feature = "ilp-over-http")]
-            (
-                protocol,
-                Some(_username),
-                None,
-                Some(_token),
-                Some(_token_x),
-                Some(_token_y),
-            ) if protocol.is_httpx() => {
-                Err(error::fmt!(ConfigError, "ECDSA authentication is only available with ILP/TCP and not available with ILP/HTTP."))
-            }
-            #[cfg(feature = "ilp-over-http")]
-            (protocol, _username, _password, _token, None, None)
-                if protocol.is_httpx() => {
-                Err(error::fmt!(ConfigError,
-                    r##"Inconsistent HTTP authentication parameters. Specify either "username" and "password", or just "token"."##,
-                ))
-            }
-            _ => {
-                Err(error::fmt!(ConfigError,
-                    r##"Incomplete authentication parameters. Check "username", "password", "token", "token_x" and "token_y" parameters are set correctly."##,
-                ))
-            }
-        }
-    }
-
-    /// Build the sender.
-    ///
-    /// In the case of TCP, this synchronously establishes the TCP connection, and
-    /// returns once the connection is fully established. If the connection
-    /// requires authentication or TLS, these will also be completed before
-    /// returning.
-    pub fn build(&self) -> Result<Sender> {
-        let mut descr = format!("Sender[host={:?},port={:?},", self.host, self.port);
-
-        if self.protocol.tls_enabled() {
-            write!(descr, "tls=enabled,").unwrap();
-        } else {
-            write!(descr, "tls=disabled,").unwrap();
-        }
-
-        let auth = self.build_auth()?;
-
-        let handler = match self.protocol {
-            Protocol::Tcp | Protocol::Tcps => self.connect_tcp(&auth)?,
-            #[cfg(feature = "ilp-over-http")]
-            Protocol::Http | Protocol::Https => {
-                if self.net_interface.is_some() {
-                    // See: https://github.com/algesten/ureq/issues/692
-                    return Err(error::fmt!(
-                        InvalidApiCall,
-                        "net_interface is not supported for ILP over HTTP."
-                    ));
-                }
-
-                let user_agent = self.http.as_ref().unwrap().user_agent.as_str();
-                let agent_builder = ureq::AgentBuilder::new()
-                    .user_agent(user_agent)
-                    .no_delay(true);
-
-                #[cfg(feature = "insecure-skip-verify")]
-                let tls_verify = *self.tls_verify;
-
-                #[cfg(not(feature = "insecure-skip-verify"))]
-                let tls_verify = true;
-
-                let agent_builder = match configure_tls(
-                    self.protocol.tls_enabled(),
-                    tls_verify,
-                    *self.tls_ca,
-                    self.tls_roots.deref(),
-                )? {
-                    Some(tls_config) => agent_builder.tls_config(tls_config),
-                    None => agent_builder,
-                };
-                let auth = match auth {
-                    Some(AuthParams::Basic(ref auth)) => Some(auth.to_header_string()),
-                    Some(AuthParams::Token(ref auth)) => Some(auth.to_header_string()?),
-                    Some(AuthParams::Ecdsa(_)) => {
-                        return Err(error::fmt!(
-                            AuthError,
-                            "ECDSA authentication is not supported for ILP over HTTP. \
-                            Please use basic or token authentication instead."
-                        ));
-                    }
-                    None => None,
-                };
-                let agent = agent_builder.build();
-                let proto = self.protocol.schema();
-                let url = format!(
-                    "{}://{}:{}/write",
-                    proto,
-                    self.host.deref(),
-                    self.port.deref()
-                );
-                ProtocolHandler::Http(HttpHandlerState {
-                    agent,
-                    url,
-                    auth,
-
-                    config: self.http.as_ref().unwrap().clone(),
-                })
-            }
-        };
-
-        if auth.is_some() {
-            descr.push_str("auth=on]");
-        } else {
-            descr.push_str("auth=off]");
-        }
-
-        let sender = Sender {
-            descr,
-            handler,
-            connected: true,
-            max_buf_size: *self.max_buf_size,
-        };
-
-        Ok(sender)
-    }
-
-    fn ensure_is_tcpx(&mut self, param_name: &str) -> Result<()> {
-        if self.protocol.is_tcpx() {
-            Ok(())
-        } else {
-            Err(error::fmt!(
-                ConfigError,
-                "The {param_name:?} setting can only be used with the TCP protocol."
-            ))
-        }
-    }
-}
-
-/// When parsing from config, we exclude certain characters.
-/// Here we repeat the same validation logic for consistency.
-fn validate_value<T: AsRef<str>>(value: T) -> Result<T> {
-    let str_ref = value.as_ref();
-    for (p, c) in str_ref.chars().enumerate() {
-        if matches!(c, '\u{0}'..='\u{1f}' | '\u{7f}'..='\u{9f}') {
-            return Err(error::fmt!(
-                ConfigError,
-                "Invalid character {c:?} at position {p}"
-            ));
-        }
-    }
-    Ok(value)
-}
-
-fn parse_conf_value<T>(param_name: &str, str_value: &str) -> Result<T>
-where
-    T: FromStr,
-    T::Err: std::fmt::Debug,
-{
-    str_value.parse().map_err(|e| {
-        error::fmt!(
-            ConfigError,
-            "Could not parse {param_name:?} to number: {e:?}"
-        )
-    })
-}
-
-fn b64_decode(descr: &'static str, buf: &str) -> Result<Vec<u8>> {
-    Base64UrlUnpadded::decode_vec(buf).map_err(|b64_err| {
-        error::fmt!(
-            AuthError,
-            "Misconfigured ILP authentication keys. Could not decode {}: {}. \
-            Hint: Check the keys for a possible typo.",
-            descr,
-            b64_err
-        )
-    })
-}
-
-fn parse_public_key(pub_key_x: &str, pub_key_y: &str) -> Result<Vec<u8>> {
-    let mut pub_key_x = b64_decode("public key x", pub_key_x)?;
-    let mut pub_key_y = b64_decode("public key y", pub_key_y)?;
-
-    // SEC 1 Uncompressed Octet-String-to-Elliptic-Curve-Point Encoding
-    let mut encoded = Vec::new();
-    encoded.push(4u8); // 0x04 magic byte that identifies this as uncompressed.
-    let pub_key_x_ken = pub_key_x.len();
-    if pub_key_x_ken > 32 {
-        return Err(error::fmt!(
-            AuthError,
-            "Misconfigured ILP authentication keys. Public key x is too long. \
-            Hint: Check the keys for a possible typo."
-        ));
-    }
-    let pub_key_y_len = pub_key_y.len();
-    if pub_key_y_len > 32 {
-        return Err(error::fmt!(
-            AuthError,
-            "Misconfigured ILP authentication keys. Public key y is too long. \
-            Hint: Check the keys for a possible typo."
-        ));
-    }
-    encoded.resize((32 - pub_key_x_ken) + 1, 0u8);
-    encoded.append(&mut pub_key_x);
-    encoded.resize((32 - pub_key_y_len) + 1 + 32, 0u8);
-    encoded.append(&mut pub_key_y);
-    Ok(encoded)
-}
-
-fn parse_key_pair(auth: &EcdsaAuthParams) -> Result<EcdsaKeyPair> {
-    let private_key = b64_decode("private authentication key", auth.priv_key.as_str())?;
-    let public_key = parse_public_key(auth.pub_key_x.as_str(), auth.pub_key_y.as_str())?;
-    let system_random = SystemRandom::new();
-    EcdsaKeyPair::from_private_key_and_public_key(
-        &ECDSA_P256_SHA256_FIXED_SIGNING,
-        &private_key[..],
-        &public_key[..],
-        &system_random,
-    )
-    .map_err(|key_rejected| {
-        error::fmt!(
-            AuthError,
-            "Misconfigured ILP authentication keys: {}. Hint: Check the keys for a possible typo.",
-            key_rejected
-        )
-    })
-}
-
-pub(crate) struct F64Serializer {
-    buf: ryu::Buffer,
-    n: f64,
-}
-
-impl F64Serializer {
-    pub(crate) fn new(n: f64) -> Self {
-        F64Serializer {
-            buf: ryu::Buffer::new(),
-            n,
-        }
-    }
-
-    // This function was taken and customized from the ryu crate.
-    #[cold]
-    #[cfg_attr(feature = "no-panic", inline)]
-    fn format_nonfinite(&self) -> &'static str {
-        const MANTISSA_MASK: u64 = 0x000fffffffffffff;
-        const SIGN_MASK: u64 = 0x8000000000000000;
-        let bits = self.n.to_bits();
-        if bits & MANTISSA_MASK != 0 {
-            "NaN"
-        } else if bits & SIGN_MASK != 0 {
-            "-Infinity"
-        } else {
-            "Infinity"
-        }
-    }
-
-    pub(crate) fn as_str(&mut self) -> &str {
-        if self.n.is_finite() {
-            self.buf.format_finite(self.n)
-        } else {
-            self.format_nonfinite()
-        }
-    }
-}
-
-impl Sender {
-    /// Create a new `Sender` instance from the given configuration string.
-    ///
-    /// The format of the string is: `"http::addr=host:port;key=value;...;"`.
-    ///
-    /// Instead of `"http"`, you can also specify `"https"`, `"tcp"`, and `"tcps"`.
-    ///
-    /// We recommend HTTP for most cases because it provides more features, like
-    /// reporting errors to the client and supporting transaction control. TCP can
-    /// sometimes be faster in higher-latency networks, but misses a number of
-    /// features.
-    ///
-    /// Keys in the config string correspond to same-named methods on `SenderBuilder`.
-    ///
-    /// For the full list of keys and values, see the docs on [`SenderBuilder`].
-    ///
-    /// You can also load the configuration from an environment variable.
-    /// See [`Sender::from_env`].
-    ///
-    /// In the case of TCP, this synchronously establishes the TCP connection, and
-    /// returns once the connection is fully established. If the connection
-    /// requires authentication or TLS, these will also be completed before
-    /// returning.
-    pub fn from_conf<T: AsRef<str>>(conf: T) -> Result<Self> {
-        SenderBuilder::from_conf(conf)?.build()
-    }
-
-    /// Create a new `Sender` from the configuration stored in the `QDB_CLIENT_CONF`
-    /// environment variable. The format is the same as that accepted by
-    /// [`Sender::from_conf`].
-    ///
-    /// In the case of TCP, this synchronously establishes the TCP connection, and
-    /// returns once the connection is fully established. If the connection
-    /// requires authentication or TLS, these will also be completed before
-    /// returning.
-    pub fn from_env() -> Result<Self> {
-        SenderBuilder::from_env()?.build()
-    }
-
-    #[allow(unused_variables)]
-    fn flush_impl(&mut self, buf: &Buffer, transactional: bool) -> Result<()> {
-        if !self.connected {
-            return Err(error::fmt!(
-                SocketError,
-                "Could not flush buffer: not connected to database."
-            ));
-        }
-        buf.check_op(Op::Flush)?;
-
-        if buf.len() > self.max_buf_size {
-            return Err(error::fmt!(
-                InvalidApiCall,
-                "Could not flush buffer: Buffer size of {} exceeds maximum configured allowed size of {} bytes.",
-                buf.len(),
-                self.max_buf_size
-            ));
-        }
-
-        let bytes = buf.as_str().as_bytes();
-        if bytes.is_empty() {
-            return Ok(());
-        }
-        match self.handler {
-            ProtocolHandler::Socket(ref mut conn) => {
-                if transactional {
-                    return Err(error::fmt!(
-                        InvalidApiCall,
-                        "Transactional flushes are not supported for ILP over TCP."
-                    ));
-                }
-                conn.write_all(bytes).map_err(|io_err| {
-                    self.connected = false;
-                    map_io_to_socket_err("Could not flush buffer: ", io_err)
-                })?;
-            }
-            #[cfg(feature = "ilp-over-http")]
-            ProtocolHandler::Http(ref state) => {
-                if transactional && !buf.transactional() {
-                    return Err(error::fmt!(
-                        InvalidApiCall,
-                        "Buffer contains lines for multiple tables. \
-                        Transactional flushes are only supported for buffers containing lines for a single table."
-                    ));
-                }
-                let request_min_throughput = *state.config.request_min_throughput;
-                let extra_time = if request_min_throughput > 0 {
-                    (bytes.len() as f64) / (request_min_throughput as f64)
-                } else {
-                    0.0f64
-                };
-                let timeout = *state.config.request_timeout + Duration::from_secs_f64(extra_time);
-                let request = state
-                    .agent
-                    .post(&state.url)
-                    .query_pairs([("precision", "n")])
-                    .timeout(timeout)
-                    .set("Content-Type", "text/plain; charset=utf-8");
-                let request = match state.auth.as_ref() {
-                    Some(auth) => request.set("Authorization", auth),
-                    None => request,
-                };
-                let response_or_err =
-                    http_send_with_retries(request, bytes, *state.config.retry_timeout);
-                match response_or_err {
-                    Ok(_response) => {
-                        // on success, there's no information in the response.
-                    }
-                    Err(ureq::Error::Status(http_status_code, response)) => {
-                        return Err(parse_http_error(http_status_code, response));
-                    }
-                    Err(ureq::Error::Transport(transport)) => {
-                        return Err(error::fmt!(
-                            SocketError,
-                            "Could not flush buffer: {}",
-                            transport
-                        ));
-                    }
-                }
-            }
-        }
-        Ok(())
-    }
-
-    /// Send the batch of rows in the buffer to the QuestDB server, and, if the
-    /// `transactional` parameter is true, ensure the flush will be transactional.
-    ///
-    /// A flush is transactional iff all the rows belong to the same table. This allows
-    /// QuestDB to treat the flush as a single database transaction, because it doesn't
-    /// support transactions spanning multiple tables. Additionally, only ILP-over-HTTP
-    /// supports transactional flushes.
-    ///
-    /// If the flush wouldn't be transactional, this function returns an error and
-    /// doesn't flush any data.
-    ///
-    /// The function sends an HTTP request and waits for the response. If the server
-    /// responds with an error, it returns a descriptive error. In the case of a network
-    /// error, it retries until it has exhausted the retry time budget.
-    ///
-    /// All the data stays in the buffer. Clear the buffer before starting a new batch.
-    #[cfg(feature = "ilp-over-http")]
-    pub fn flush_and_keep_with_flags(&mut self, buf: &Buffer, transactional: bool) -> Result<()> {
-        self.flush_impl(buf, transactional)
-    }
-
-    /// Send the given buffer of rows to the QuestDB server.
-    ///
-    /// All the data stays in the buffer. Clear the buffer before starting a new batch.
-    ///
-    /// To send and clear in one step, call [Sender::flush] instead.
-    pub fn flush_and_keep(&mut self, buf: &Buffer) -> Result<()> {
-        self.flush_impl(buf, false)
-    }
-
-    /// Send the given buffer of rows to the QuestDB server, clearing the buffer.
-    ///
-    /// After this function returns, the buffer is empty and ready for the next batch.
-    /// If you want to preserve the buffer contents, call [Sender::flush_and_keep]. If
-    /// you want to ensure the flush is transactional, call
-    /// [Sender::flush_and_keep_with_flags].
-    ///
-    /// With ILP-over-HTTP, this function sends an HTTP request and waits for the
-    /// response. If the server responds with an error, it returns a descriptive error.
-    /// In the case of a network error, it retries until it has exhausted the retry time
-    /// budget.
-    ///
-    /// With ILP-over-TCP, the function blocks only until the buffer is flushed to the
-    /// underlying OS-level network socket, without waiting to actually send it to the
-    /// server. In the case of an error, the server will quietly disconnect: consult the
-    /// server logs for error messages.
-    ///
-    /// HTTP should be the first choice, but use TCP if you need to continuously send
-    /// data to the server at a high rate.
-    ///
-    /// To improve the HTTP performance, send larger buffers (with more rows), and
-    /// consider parallelizing writes using multiple senders from multiple threads.
-    pub fn flush(&mut self, buf: &mut Buffer) -> Result<()> {
-        self.flush_impl(buf, false)?;
-        buf.clear();
-        Ok(())
-    }
-
-    /// Tell whether the sender is no longer usable and must be dropped.
-    ///
-    /// This happens when there was an earlier failure.
-    ///
-    /// This method is specific to ILP-over-TCP and is not relevant for ILP-over-HTTP.
-    pub fn must_close(&self) -> bool {
-        !self.connected
-    }
-}
-
-mod conf;
-mod timestamp;
+#[cfg(feature = "worker")]
+mod worker;
 
 #[cfg(feature = "ilp-over-http")]
 mod http;
 
 #[cfg(feature = "ilp-over-http")]
 use http::*;
-
-#[cfg(test)]
-mod tests;
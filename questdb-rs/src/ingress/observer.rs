@@ -0,0 +1,121 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::fmt::{Debug, Formatter};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Error, ErrorCode};
+
+/// Receives callbacks as a sender completes rows and flushes them, for example
+/// to update metrics or to log.
+///
+/// Set it via
+/// [`SenderBuilder::observer`](crate::ingress::SenderBuilder::observer).
+/// All methods do nothing by default, so implement only the ones you need.
+///
+/// Each flush of a non-empty buffer invokes, in order:
+/// * [`on_flush_start`](FlushObserver::on_flush_start),
+/// * either [`on_flush`](FlushObserver::on_flush) or
+///   [`on_error`](FlushObserver::on_error),
+/// * [`on_flush_end`](FlushObserver::on_flush_end).
+///
+/// Flushing an empty buffer is a no-op and doesn't invoke any callback.
+///
+/// The callbacks are invoked on the thread calling `flush`, so keep them cheap.
+/// They only get a shared reference to the observer and can't affect the
+/// sender. A panic in a callback is caught and otherwise ignored, so a buggy
+/// observer can't interrupt ingestion. The panic hook still runs, and if
+/// panics abort the process, so does a panicking callback.
+pub trait FlushObserver: Send + Sync {
+    /// Called before sending a buffer holding `rows` complete rows in `bytes`
+    /// bytes.
+    fn on_flush_start(&self, rows: usize, bytes: usize) {
+        let _ = (rows, bytes);
+    }
+
+    /// Called after the rows in a buffer were sent successfully.
+    ///
+    /// `duration` is the time spent sending, including any HTTP retries.
+    fn on_flush(&self, rows: usize, bytes: usize, duration: Duration) {
+        let _ = (rows, bytes, duration);
+    }
+
+    /// Called when a flush fails, with the code of the returned error.
+    fn on_error(&self, code: ErrorCode) {
+        let _ = code;
+    }
+
+    /// Called once a flush has finished, whether it succeeded or not.
+    ///
+    /// `duration` is the time spent sending, as for
+    /// [`on_flush`](FlushObserver::on_flush).
+    fn on_flush_end(&self, result: Result<(), &Error>, duration: Duration) {
+        let _ = (result, duration);
+    }
+
+    /// Called when [`Sender::at`](crate::ingress::Sender::at) or
+    /// [`Sender::at_now`](crate::ingress::Sender::at_now) completes a row of
+    /// `bytes` bytes.
+    ///
+    /// Rows completed directly on the [`Buffer`](crate::ingress::Buffer) aren't
+    /// reported, as the buffer doesn't know about the sender.
+    fn on_row_completed(&self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// Called when `rows` rows are dropped, per the
+    /// [`OverflowPolicy`](crate::ingress::OverflowPolicy) or because a
+    /// background worker couldn't flush them.
+    fn on_rows_dropped(&self, rows: usize) {
+        let _ = rows;
+    }
+}
+
+/// Invoke an observer callback, catching and discarding any panic.
+pub(crate) fn notify<F>(observer: &Option<Arc<dyn FlushObserver>>, callback: F)
+where
+    F: FnOnce(&dyn FlushObserver),
+{
+    if let Some(observer) = observer.as_deref() {
+        let _ = catch_unwind(AssertUnwindSafe(|| callback(observer)));
+    }
+}
+
+/// A `SenderBuilder` setting holding a flush observer.
+#[derive(Clone)]
+pub(crate) struct ObserverRef(pub(crate) Arc<dyn FlushObserver>);
+
+impl Debug for ObserverRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FlushObserver")
+    }
+}
+
+impl PartialEq for ObserverRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -0,0 +1,227 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+
+use crate::error::{self, Result};
+use crate::ingress::conf::ConfigSetting;
+use crate::ingress::{Sender, SenderBuilder};
+
+#[derive(Debug)]
+struct PoolState {
+    /// Senders that are currently not in use, by slot.
+    idle: Vec<(usize, Sender)>,
+
+    /// Slots whose sender was lost and must be rebuilt before use.
+    broken: Vec<usize>,
+}
+
+/// A fixed-size pool of connected [`Sender`] instances.
+///
+/// Opening a new connection for each batch of rows is slow. Instead, a
+/// `SenderPool` keeps a set of open senders and hands them out via
+/// [`acquire`](SenderPool::acquire). The returned [`PooledSender`] dereferences
+/// to a `Sender` and returns it to the pool when dropped.
+///
+/// Before a sender is handed out again, the pool checks whether it
+/// [must be closed](Sender::must_close) following an earlier error, or whether
+/// the server closed its connection while it sat idle, by peeking at the
+/// socket without blocking. If so, the pool replaces it with a newly built one.
+///
+/// With a [spill directory](SenderBuilder::spill_dir) set, each sender spills
+/// to a subdirectory of its own, named after its slot: `0`, `1`, and so on.
+/// Rows spilled directly to the directory, e.g. by a sender built outside the
+/// pool, aren't replayed by the pool's senders.
+///
+/// The pool is `Send + Sync` and can be shared across threads, for example
+/// via an `Arc`.
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{Buffer, SenderBuilder, SenderPool};
+///
+/// # fn main() -> Result<()> {
+/// let pool = SenderPool::new(SenderBuilder::from_conf("tcp::addr=localhost:9009;")?, 4)?;
+/// let mut buffer = Buffer::new();
+/// buffer.table("x")?.symbol("a", "b")?.at_now()?;
+/// pool.acquire()?.flush(&mut buffer)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SenderPool {
+    /// The builder of each slot's sender.
+    builders: Vec<SenderBuilder>,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+impl SenderPool {
+    /// Create a pool of `size` senders, all built from `builder`.
+    ///
+    /// This establishes all the connections before returning.
+    pub fn new(builder: SenderBuilder, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "Sender pool size must be at least 1."
+            ));
+        }
+        let builders = (0..size)
+            .map(|slot| slot_builder(&builder, slot))
+            .collect::<Vec<_>>();
+        let idle = builders
+            .iter()
+            .enumerate()
+            .map(|(slot, builder)| Ok((slot, builder.build()?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            builders,
+            state: Mutex::new(PoolState {
+                idle,
+                broken: Vec::new(),
+            }),
+            available: Condvar::new(),
+        })
+    }
+
+    /// The number of senders managed by the pool.
+    pub fn size(&self) -> usize {
+        self.builders.len()
+    }
+
+    /// The number of senders that are currently not in use.
+    pub fn idle_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.idle.len() + state.broken.len()
+    }
+
+    /// Take a sender from the pool, blocking until one is available.
+    ///
+    /// If the sender must be closed due to an earlier error, or the server
+    /// closed its connection, it is replaced with a newly built one. Should
+    /// rebuilding it fail, this returns the error and the pool will try again
+    /// on the next call.
+    pub fn acquire(&self) -> Result<PooledSender<'_>> {
+        let mut state = self.state.lock().unwrap();
+        while state.idle.is_empty() && state.broken.is_empty() {
+            state = self.available.wait(state).unwrap();
+        }
+        let sender = self.take(state);
+        self.check_out(sender)
+    }
+
+    /// Take a sender from the pool if one is available, without blocking.
+    ///
+    /// See [`acquire`](SenderPool::acquire).
+    pub fn try_acquire(&self) -> Result<Option<PooledSender<'_>>> {
+        let state = self.state.lock().unwrap();
+        if state.idle.is_empty() && state.broken.is_empty() {
+            return Ok(None);
+        }
+        let sender = self.take(state);
+        self.check_out(sender).map(Some)
+    }
+
+    /// Take an idle sender, or claim a broken slot (with no sender).
+    fn take(&self, mut state: std::sync::MutexGuard<'_, PoolState>) -> (usize, Option<Sender>) {
+        match state.idle.pop() {
+            // Also catch a connection the server closed while the sender sat
+            // idle: The first write to it would still succeed.
+            Some((slot, mut sender)) => (slot, sender.check_connection().is_ok().then_some(sender)),
+            None => (state.broken.pop().unwrap(), None),
+        }
+    }
+
+    /// Wrap the sender, rebuilding it first if the slot was broken.
+    /// Connecting happens outside the lock, so other threads aren't held up.
+    fn check_out(&self, (slot, sender): (usize, Option<Sender>)) -> Result<PooledSender<'_>> {
+        let sender = match sender {
+            Some(sender) => sender,
+            None => match self.builders[slot].build() {
+                Ok(sender) => sender,
+                Err(err) => {
+                    self.state.lock().unwrap().broken.push(slot);
+                    self.available.notify_one();
+                    return Err(err);
+                }
+            },
+        };
+        Ok(PooledSender {
+            pool: self,
+            slot,
+            sender: Some(sender),
+        })
+    }
+
+    fn release(&self, slot: usize, sender: Sender) {
+        self.state.lock().unwrap().idle.push((slot, sender));
+        self.available.notify_one();
+    }
+}
+
+/// The builder of a slot's sender: Senders replay every spill file they can
+/// lock, so one spilling to a shared directory would replay rows out of order
+/// with those of the others.
+fn slot_builder(builder: &SenderBuilder, slot: usize) -> SenderBuilder {
+    let mut builder = builder.clone();
+    if let Some(dir) = builder.spill_dir.as_ref() {
+        let dir = dir.join(slot.to_string());
+        builder.spill_dir = ConfigSetting::new_specified(Some(dir));
+    }
+    builder
+}
+
+/// A [`Sender`] borrowed from a [`SenderPool`].
+///
+/// Returns the sender to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledSender<'a> {
+    pool: &'a SenderPool,
+    slot: usize,
+    sender: Option<Sender>,
+}
+
+impl Deref for PooledSender<'_> {
+    type Target = Sender;
+
+    fn deref(&self) -> &Self::Target {
+        self.sender.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledSender<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.sender.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledSender<'_> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            self.pool.release(self.slot, sender);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use socket2::SockAddr;
+
+use crate::error::{self, Result};
+use crate::gai;
+
+/// Resolves a host name and port to the socket addresses to connect to.
+///
+/// The default implementation, [`GaiResolver`], calls the operating system's
+/// `getaddrinfo`. Supply a custom implementation via
+/// [`SenderBuilder::resolver`](crate::ingress::SenderBuilder::resolver), for
+/// example to cache resolutions or override DNS in tests.
+///
+/// A sender connecting over TCP tries the returned addresses in order until
+/// one of them accepts the connection.
+pub trait Resolver: Send + Sync {
+    /// Resolve the host and port to one or more socket addresses.
+    ///
+    /// Return a [`CouldNotResolveAddr`](crate::ErrorCode::CouldNotResolveAddr)
+    /// error if the host can't be resolved.
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>>;
+}
+
+/// The default [`Resolver`], which calls the operating system's `getaddrinfo`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GaiResolver;
+
+impl Resolver for GaiResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        gai::resolve_host_port(host, &port.to_string())?
+            .into_iter()
+            .map(|addr| {
+                addr.as_socket().ok_or_else(|| {
+                    error::fmt!(
+                        CouldNotResolveAddr,
+                        "Could not resolve \"{}:{}\": Not an IP address.",
+                        host,
+                        port
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// Resolve the host and port to all candidate socket addresses via the
+/// operating system's `getaddrinfo`, in the order it returned them.
+///
+/// This is the list a sender connecting over TCP tries in turn, unless a custom
+/// [`Resolver`] is set.
+///
+/// ```
+/// use questdb::ingress::resolve_all;
+///
+/// let addrs = resolve_all("127.0.0.1", 9009).unwrap();
+/// assert_eq!(addrs, vec!["127.0.0.1:9009".parse().unwrap()]);
+/// ```
+pub fn resolve_all(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    GaiResolver.resolve(host, port)
+}
+
+/// A [`Resolver`] that maps host names to fixed IP addresses, without
+/// performing any DNS lookups.
+///
+/// Mostly useful in tests.
+///
+/// ```
+/// use questdb::ingress::{Resolver, StaticResolver};
+/// use std::net::{IpAddr, Ipv4Addr};
+///
+/// let resolver = StaticResolver::new()
+///     .with_host("questdb.internal", [IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+/// let addrs = resolver.resolve("questdb.internal", 9009).unwrap();
+/// assert_eq!(addrs, vec!["127.0.0.1:9009".parse().unwrap()]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    /// Create a resolver with no known hosts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map the host name to the given addresses, replacing any earlier mapping.
+    pub fn with_host<H, I>(mut self, host: H, addrs: I) -> Self
+    where
+        H: Into<String>,
+        I: IntoIterator<Item = IpAddr>,
+    {
+        self.hosts.insert(host.into(), addrs.into_iter().collect());
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        match self.hosts.get(host) {
+            Some(addrs) if !addrs.is_empty() => {
+                Ok(addrs.iter().map(|&ip| SocketAddr::new(ip, port)).collect())
+            }
+            _ => Err(error::fmt!(
+                CouldNotResolveAddr,
+                "Could not resolve {:?}: Unknown host.",
+                host
+            )),
+        }
+    }
+}
+
+/// A `SenderBuilder` setting holding a custom resolver.
+#[derive(Clone)]
+pub(crate) struct ResolverRef(pub(crate) Arc<dyn Resolver>);
+
+impl Debug for ResolverRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Resolver")
+    }
+}
+
+impl PartialEq for ResolverRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The `"host:port"` resolved, when, and what to.
+type DnsCacheEntry = (String, Instant, Vec<SockAddr>);
+
+/// Addresses resolved by a `SenderBuilder`, kept for reuse up to its `dns_ttl`.
+///
+/// Clones share the same cache.
+#[derive(Clone, Default)]
+pub(crate) struct DnsCache(Arc<Mutex<Option<DnsCacheEntry>>>);
+
+impl DnsCache {
+    pub(crate) fn get_or_resolve(
+        &self,
+        host_port: String,
+        ttl: Duration,
+        resolve: impl FnOnce() -> Result<Vec<SockAddr>>,
+    ) -> Result<Vec<SockAddr>> {
+        if let Some((cached, resolved_at, addrs)) = self.0.lock().unwrap().as_ref() {
+            if *cached == host_port && resolved_at.elapsed() < ttl {
+                return Ok(addrs.clone());
+            }
+        }
+
+        // Resolve without holding the lock: It may take a while.
+        let addrs = resolve()?;
+        *self.0.lock().unwrap() = Some((host_port, Instant::now(), addrs.clone()));
+        Ok(addrs)
+    }
+}
+
+impl Debug for DnsCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DnsCache")
+    }
+}
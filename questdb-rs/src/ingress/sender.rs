@@ -0,0 +1,3994 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use super::*;
+
+use crate::error::ErrorCode;
+use crate::gai;
+use crate::ingress::conf::ConfigSetting;
+use core::time::Duration;
+use std::fmt::{Display, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Write as IoWrite};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use base64ct::{Base64, Base64UrlUnpadded, Encoding};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use rustls::{ClientConnection, RootCertStore, StreamOwned};
+use rustls_pki_types::ServerName;
+use socket2::{Domain, Protocol as SockProtocol, SockAddr, Socket, TcpKeepalive, Type};
+
+/// The default for [`SenderBuilder::write_chunk_size`].
+const DEFAULT_WRITE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Like `write_all`, but writes at most `chunk_size` bytes per call and, on
+/// error, also returns how many bytes were written.
+fn write_all_counted<W: io::Write>(
+    writer: &mut W,
+    bytes: &[u8],
+    chunk_size: usize,
+) -> std::result::Result<(), (usize, io::Error)> {
+    let mut sent = 0;
+    while sent < bytes.len() {
+        let chunk_end = bytes.len().min(sent.saturating_add(chunk_size));
+        match writer.write(&bytes[sent..chunk_end]) {
+            Ok(0) => {
+                let io_err = io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer");
+                return Err((sent, io_err));
+            }
+            Ok(n) => sent += n,
+            Err(io_err) if io_err.kind() == ErrorKind::Interrupted => {}
+            Err(io_err) => return Err((sent, io_err)),
+        }
+    }
+    Ok(())
+}
+
+/// A destination for serialized ILP messages sent over a stream.
+///
+/// This abstracts the transport underneath a [`Sender`], allowing, for example,
+/// an in-memory sink to stand in for a TCP socket in tests.
+///
+/// Any `io::Write + Send` type implements this trait.
+pub trait IlpSink: io::Write + Send {}
+
+impl<T: io::Write + Send> IlpSink for T {}
+
+enum Connection {
+    Direct(Socket),
+    Tls(Box<StreamOwned<ClientConnection, Socket>>),
+    Sink(Box<dyn IlpSink>),
+}
+
+impl Connection {
+    fn send_key_id(&mut self, key_id: &str) -> Result<()> {
+        writeln!(self, "{}", key_id)
+            .map_err(|io_err| map_io_to_socket_err("Failed to send key_id: ", io_err))?;
+        Ok(())
+    }
+
+    fn read_challenge(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let mut reader = BufReader::new(self);
+        reader.read_until(b'\n', &mut buf).map_err(|io_err| {
+            map_io_to_socket_err(
+                "Failed to read authentication challenge (timed out?): ",
+                io_err,
+            )
+        })?;
+        check_challenge(buf)
+    }
+
+    fn authenticate(&mut self, auth: &EcdsaAuthParams) -> Result<()> {
+        let key_pair = prepare_auth(auth)?;
+        self.send_key_id(auth.key_id.as_str())?;
+        let challenge = self.read_challenge()?;
+        let encoded_sig = sign_challenge(&key_pair, &challenge)?;
+        let buf = encoded_sig.as_bytes();
+        if let Err(io_err) = self.write_all(buf) {
+            return Err(map_io_to_socket_err(
+                "Could not send signed challenge: ",
+                io_err,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Validate the auth parameters before starting the challenge-response
+/// authentication.
+pub(super) fn prepare_auth(auth: &EcdsaAuthParams) -> Result<EcdsaKeyPair> {
+    if auth.key_id.contains('\n') {
+        return Err(error::fmt!(
+            AuthError,
+            "Bad key id {:?}: Should not contain new-line char.",
+            auth.key_id
+        ));
+    }
+    parse_key_pair(auth)
+}
+
+/// Strip the trailing newline off the challenge read from the server.
+pub(super) fn check_challenge(mut buf: Vec<u8>) -> Result<Vec<u8>> {
+    if buf.last().copied().unwrap_or(b'\0') != b'\n' {
+        return Err(if buf.is_empty() {
+            error::fmt!(
+                AuthError,
+                concat!(
+                    "Did not receive auth challenge. ",
+                    "Is the database configured to require ",
+                    "authentication?"
+                )
+            )
+        } else {
+            error::fmt!(AuthError, "Received incomplete auth challenge: {:?}", buf)
+        });
+    }
+    buf.pop(); // b'\n'
+    Ok(buf)
+}
+
+/// Sign the challenge, returning the base64-encoded, newline-terminated
+/// signature to send back to the server.
+pub(super) fn sign_challenge(key_pair: &EcdsaKeyPair, challenge: &[u8]) -> Result<String> {
+    let rng = SystemRandom::new();
+    let signature = key_pair.sign(&rng, challenge).map_err(|unspecified_err| {
+        error::fmt!(AuthError, "Failed to sign challenge: {}", unspecified_err)
+    })?;
+    let mut encoded_sig = Base64::encode_string(signature.as_ref());
+    encoded_sig.push('\n');
+    Ok(encoded_sig)
+}
+
+enum ProtocolHandler {
+    Socket(Connection),
+
+    #[cfg(feature = "ilp-over-http")]
+    Http(HttpHandlerState),
+}
+
+impl io::Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Direct(sock) => sock.read(buf),
+            Self::Tls(stream) => stream.read(buf),
+            Self::Sink(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "ILP sinks are write-only",
+            )),
+        }
+    }
+}
+
+impl io::Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Direct(sock) => sock.write(buf),
+            Self::Tls(stream) => stream.write(buf),
+            Self::Sink(sink) => sink.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Direct(sock) => sock.flush(),
+            Self::Tls(stream) => stream.flush(),
+            Self::Sink(sink) => sink.flush(),
+        }
+    }
+}
+
+/// Connects to a QuestDB instance and inserts data via the ILP protocol.
+///
+/// * To construct an instance, use [`Sender::from_conf`] or the [`SenderBuilder`].
+/// * To prepare messages, use [`Buffer`] objects.
+/// * To send messages, call the [`flush`](Sender::flush) method.
+pub struct Sender {
+    descr: String,
+    handler: ProtocolHandler,
+    connected: bool,
+    pub(super) buffer_template: BufferTemplate,
+
+    /// The builder this sender was built from, used to reconnect.
+    builder: Option<Box<SenderBuilder>>,
+
+    /// Index of the connected address among the builder's addresses.
+    host_index: usize,
+
+    /// Consecutive failures to reconnect to the address at `host_index`.
+    host_failures: u32,
+
+    check_connection_on_flush: bool,
+    write_chunk_size: usize,
+    pub(super) observer: Option<Arc<dyn FlushObserver>>,
+
+    #[cfg(feature = "metrics")]
+    metrics: FlushMetrics,
+
+    /// Thresholds for [`Sender::at`] to flush at, `None` if disabled.
+    pub(super) auto_flush_rows: Option<usize>,
+    pub(super) auto_flush_bytes: Option<usize>,
+    pub(super) auto_flush_interval: Option<Duration>,
+
+    buffer_shrink_threshold: Option<usize>,
+    overflow_policy: OverflowPolicy,
+
+    /// Where to spill the rows of failed flushes, if anywhere.
+    spill: Option<SpillWriter>,
+
+    /// How many bytes of the last flush were written before it failed.
+    failed_flush_sent: usize,
+
+    stats: SenderStats,
+
+    /// How long connecting took, `None` if the sender didn't connect itself.
+    connect_timings: Option<ConnectTimings>,
+}
+
+impl std::fmt::Debug for Sender {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.write_str(self.descr.as_str())
+    }
+}
+
+/// Cumulative counters of a [`Sender`]'s flushes, see [`Sender::stats`].
+///
+/// Flushing an empty buffer is a no-op and isn't counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderStats {
+    /// Rows sent by successful flushes.
+    pub rows_sent: u64,
+
+    /// Bytes sent by successful flushes.
+    pub bytes_sent: u64,
+
+    /// Successful flushes.
+    pub flushes: u64,
+
+    /// Flushes that returned an error.
+    pub failed_flushes: u64,
+
+    /// Rows dropped per the [`OverflowPolicy`].
+    pub rows_dropped: u64,
+
+    /// Rows of failed flushes written to the
+    /// [spill directory](SenderBuilder::spill_dir).
+    pub rows_spilled: u64,
+
+    /// When the last successful flush completed.
+    pub last_flush_at: Option<Instant>,
+}
+
+/// How long each phase of establishing a [`Sender`]'s ILP/TCP connection took,
+/// see [`Sender::connect_timings`].
+///
+/// A phase that didn't happen, e.g. the TLS handshake of a plain TCP
+/// connection, takes zero time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectTimings {
+    /// Resolving the server's host name. Negligible for addresses served from
+    /// the [DNS cache](SenderBuilder::dns_ttl) or given as a
+    /// [socket address](SenderBuilder::from_socket_addr), and zero for a
+    /// [Unix domain socket](SenderBuilder::unix_socket).
+    pub resolve: Duration,
+
+    /// Opening the socket and connecting it, including attempts on addresses
+    /// that failed to connect.
+    pub connect: Duration,
+
+    /// The TLS handshake.
+    pub tls_handshake: Duration,
+
+    /// Authenticating with the server.
+    pub auth: Duration,
+
+    /// The whole of [`build`](SenderBuilder::build) or
+    /// [`reconnect`](Sender::reconnect), including setting up the sender.
+    pub total: Duration,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub(super) struct EcdsaAuthParams {
+    pub(super) key_id: String,
+    priv_key: String,
+    pub_key_x: String,
+    pub_key_y: String,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub(super) enum AuthParams {
+    Ecdsa(EcdsaAuthParams),
+
+    #[cfg(feature = "ilp-over-http")]
+    Basic(BasicAuthParams),
+
+    #[cfg(feature = "ilp-over-http")]
+    Token(TokenAuthParams),
+}
+
+/// Possible sources of the root certificates used to validate the server's TLS
+/// certificate.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CertificateAuthority {
+    /// Use the root certificates provided by the
+    /// [`webpki-roots`](https://crates.io/crates/webpki-roots) crate.
+    #[cfg(feature = "tls-webpki-certs")]
+    WebpkiRoots,
+
+    /// Use the root certificates provided by the OS
+    #[cfg(feature = "tls-native-certs")]
+    OsRoots,
+
+    /// Combine the root certificates provided by the OS and the `webpki-roots` crate.
+    #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
+    WebpkiAndOsRoots,
+
+    /// Use the root certificates provided in a PEM-encoded file.
+    PemFile,
+}
+
+/// A `u16` port number or `String` port service name as is registered with
+/// `/etc/services` or equivalent.
+///
+/// ```
+/// use questdb::ingress::Port;
+/// use std::convert::Into;
+///
+/// let service: Port = 9009.into();
+/// ```
+///
+/// or
+///
+/// ```
+/// use questdb::ingress::Port;
+/// use std::convert::Into;
+///
+/// // Assuming the service name is registered.
+/// let service: Port = "qdb_ilp".into();  // or with a String too.
+/// ```
+pub struct Port(String);
+
+impl From<String> for Port {
+    fn from(s: String) -> Self {
+        Port(s)
+    }
+}
+
+impl From<&str> for Port {
+    fn from(s: &str) -> Self {
+        Port(s.to_owned())
+    }
+}
+
+impl From<u16> for Port {
+    fn from(p: u16) -> Self {
+        Port(p.to_string())
+    }
+}
+
+/// Split an address into its host and optional port.
+///
+/// IPv6 literal hosts must be enclosed in brackets, e.g. `"[::1]:9009"`.
+fn parse_host_port(addr: &str) -> Result<(&str, Option<&str>)> {
+    let invalid = |reason: &str| error::fmt!(ConfigError, "Invalid address {:?}: {}", addr, reason);
+    let (host, port) = if let Some(bracketed) = addr.strip_prefix('[') {
+        let Some((host, rest)) = bracketed.split_once(']') else {
+            return Err(invalid("Missing closing `]` after IPv6 host."));
+        };
+        let port = match rest {
+            "" => None,
+            _ => match rest.strip_prefix(':') {
+                Some(port) => Some(port),
+                None => {
+                    return Err(invalid(&format!(
+                        "Unexpected {:?} after IPv6 host, expected `:`.",
+                        rest
+                    )))
+                }
+            },
+        };
+        (host, port)
+    } else {
+        match addr.rsplit_once(':') {
+            Some((host, _)) if host.contains(':') => {
+                return Err(invalid(
+                    "IPv6 hosts must be enclosed in brackets, e.g. \"[::1]:9009\".",
+                ))
+            }
+            Some((host, port)) => (host, Some(port)),
+            None => (addr, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(invalid("Missing host."));
+    }
+    if port == Some("") {
+        return Err(invalid("Missing port after `:`."));
+    }
+    Ok((host, port))
+}
+
+/// The address of a QuestDB server: A host and a [`Port`].
+///
+/// A `Service` can be constructed from a `(host, port)` tuple, where the port
+/// is either a `u16` or a service name, or parsed from a `"host:port"` string.
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::Service;
+///
+/// # fn main() -> Result<()> {
+/// let service: Service = ("localhost", 9009).into();
+/// let service: Service = ("localhost", "qdb_ilp").into();
+/// let service: Service = "localhost:9009".parse()?;
+/// let service: Service = "[::1]:9009".parse()?;
+/// assert_eq!(service.host(), "::1");
+/// assert_eq!(service.port(), "9009");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    host: String,
+    port: String,
+}
+
+impl Service {
+    /// Create a `Service` from a host and port.
+    pub fn new<H: Into<String>, P: Into<Port>>(host: H, port: P) -> Self {
+        Self {
+            host: host.into(),
+            port: port.into().0,
+        }
+    }
+
+    /// The host name or IP address. IPv6 addresses are not bracketed.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The port number or service name.
+    pub fn port(&self) -> &str {
+        &self.port
+    }
+}
+
+impl<H: Into<String>, P: Into<Port>> From<(H, P)> for Service {
+    fn from((host, port): (H, P)) -> Self {
+        Self::new(host, port)
+    }
+}
+
+impl FromStr for Service {
+    type Err = Error;
+
+    fn from_str(addr: &str) -> Result<Self> {
+        match parse_host_port(addr)? {
+            (host, Some(port)) => Ok(Self::new(host, port)),
+            (_, None) => Err(error::fmt!(
+                ConfigError,
+                "Invalid address {:?}: Missing port, expected \"host:port\".",
+                addr
+            )),
+        }
+    }
+}
+
+impl TryFrom<&str> for Service {
+    type Error = self::Error;
+
+    fn try_from(addr: &str) -> Result<Self> {
+        addr.parse()
+    }
+}
+
+impl TryFrom<String> for Service {
+    type Error = self::Error;
+
+    fn try_from(addr: String) -> Result<Self> {
+        addr.parse()
+    }
+}
+
+#[cfg(feature = "insecure-skip-verify")]
+mod danger {
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::{DigitallySignedStruct, Error, SignatureScheme};
+    use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+
+    #[derive(Debug)]
+    pub struct NoCertificateVerification {}
+
+    impl ServerCertVerifier for NoCertificateVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+}
+
+#[cfg(feature = "tls-webpki-certs")]
+fn add_webpki_roots(root_store: &mut RootCertStore) {
+    root_store
+        .roots
+        .extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned())
+}
+
+#[cfg(feature = "tls-native-certs")]
+fn add_os_roots(root_store: &mut RootCertStore) -> Result<()> {
+    let os_certs = rustls_native_certs::load_native_certs().map_err(|io_err| {
+        error::fmt!(
+            TlsError,
+            "Could not load OS native TLS certificates: {}",
+            io_err
+        )
+    })?;
+
+    let (valid_count, invalid_count) = root_store.add_parsable_certificates(os_certs);
+    if valid_count == 0 && invalid_count > 0 {
+        return Err(error::fmt!(
+            TlsError,
+            "No valid certificates found in native root store ({} found but were invalid)",
+            invalid_count
+        ));
+    }
+    Ok(())
+}
+
+pub(super) fn configure_tls(
+    tls_enabled: bool,
+    tls_verify: bool,
+    tls_ca: CertificateAuthority,
+    tls_roots: &Option<PathBuf>,
+) -> Result<Option<Arc<rustls::ClientConfig>>> {
+    if !tls_enabled {
+        return Ok(None);
+    }
+
+    let mut root_store = RootCertStore::empty();
+    if tls_verify {
+        match (tls_ca, tls_roots) {
+            #[cfg(feature = "tls-webpki-certs")]
+            (CertificateAuthority::WebpkiRoots, None) => {
+                add_webpki_roots(&mut root_store);
+            }
+
+            #[cfg(feature = "tls-webpki-certs")]
+            (CertificateAuthority::WebpkiRoots, Some(_)) => {
+                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" must be unset when \"tls_ca\" is set to \"webpki_roots\"."));
+            }
+
+            #[cfg(feature = "tls-native-certs")]
+            (CertificateAuthority::OsRoots, None) => {
+                add_os_roots(&mut root_store)?;
+            }
+
+            #[cfg(feature = "tls-native-certs")]
+            (CertificateAuthority::OsRoots, Some(_)) => {
+                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" must be unset when \"tls_ca\" is set to \"os_roots\"."));
+            }
+
+            #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
+            (CertificateAuthority::WebpkiAndOsRoots, None) => {
+                add_webpki_roots(&mut root_store);
+                add_os_roots(&mut root_store)?;
+            }
+
+            #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
+            (CertificateAuthority::WebpkiAndOsRoots, Some(_)) => {
+                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" must be unset when \"tls_ca\" is set to \"webpki_and_os_roots\"."));
+            }
+
+            (CertificateAuthority::PemFile, Some(ca_file)) => {
+                let certfile = std::fs::File::open(ca_file).map_err(|io_err| {
+                    error::fmt!(
+                        TlsError,
+                        concat!(
+                            "Could not open tls_roots certificate authority ",
+                            "file from path {:?}: {}"
+                        ),
+                        ca_file,
+                        io_err
+                    )
+                })?;
+                let mut reader = BufReader::new(certfile);
+                let der_certs = rustls_pemfile::certs(&mut reader)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|io_err| {
+                        error::fmt!(
+                            TlsError,
+                            concat!(
+                                "Could not read certificate authority ",
+                                "file from path {:?}: {}"
+                            ),
+                            ca_file,
+                            io_err
+                        )
+                    })?;
+                root_store.add_parsable_certificates(der_certs);
+            }
+
+            (CertificateAuthority::PemFile, None) => {
+                return Err(error::fmt!(ConfigError, "Config parameter \"tls_roots\" is required when \"tls_ca\" is set to \"pem_file\"."));
+            }
+        }
+    }
+
+    let mut config = rustls::ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    // TLS log file for debugging.
+    // Set the SSLKEYLOGFILE env variable to a writable location.
+    config.key_log = Arc::new(rustls::KeyLogFile::new());
+
+    #[cfg(feature = "insecure-skip-verify")]
+    if !tls_verify {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(danger::NoCertificateVerification {}));
+    }
+
+    Ok(Some(Arc::new(config)))
+}
+
+/// Whether [`Sender::at`] and [`Sender::at_now`] flush the buffer once it
+/// reaches the [`auto_flush_rows`](SenderBuilder::auto_flush_rows),
+/// [`auto_flush_bytes`](SenderBuilder::auto_flush_bytes) or
+/// [`auto_flush_interval`](SenderBuilder::auto_flush_interval) threshold.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum AutoFlush {
+    /// Flush when a threshold is crossed.
+    #[default]
+    On,
+
+    /// Never flush automatically, regardless of the thresholds.
+    Off,
+}
+
+/// What to do with rows that don't fit, when ingestion can't keep up.
+///
+/// A [`Sender`] applies it to rows that would grow the buffer past its
+/// [`max_buf_size`](SenderBuilder::max_buf_size), see
+/// [`SenderBuilder::overflow_policy`]: Only [`Error`](OverflowPolicy::Error)
+/// and [`DropNewest`](OverflowPolicy::DropNewest) apply there. A background
+/// worker applies it to buffers sent while its queue is full.
+///
+/// Dropped rows are counted, and reported to the
+/// [`FlushObserver::on_rows_dropped`] callback.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OverflowPolicy {
+    /// Wait until there's room.
+    Block,
+
+    /// Drop the incoming rows.
+    DropNewest,
+
+    /// Drop the oldest queued rows to make room for the incoming ones.
+    DropOldest,
+
+    /// Reject the incoming rows with an error: A
+    /// [`BufferTooLarge`](ErrorCode::BufferTooLarge) one for a full buffer,
+    /// or a [`QueueFull`](ErrorCode::QueueFull) one for a worker's full queue.
+    Error,
+}
+
+/// Protocol used to communicate with the QuestDB server.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Protocol {
+    /// ILP over TCP (streaming).
+    Tcp,
+
+    /// TCP + TLS
+    Tcps,
+
+    #[cfg(feature = "ilp-over-http")]
+    /// ILP over HTTP (request-response, InfluxDB-compatible).
+    Http,
+
+    #[cfg(feature = "ilp-over-http")]
+    /// HTTP + TLS
+    Https,
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.write_str(self.schema())
+    }
+}
+
+impl Protocol {
+    fn default_port(&self) -> &str {
+        match self {
+            Protocol::Tcp | Protocol::Tcps => "9009",
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Http | Protocol::Https => "9000",
+        }
+    }
+
+    pub(super) fn tls_enabled(&self) -> bool {
+        match self {
+            Protocol::Tcp => false,
+            Protocol::Tcps => true,
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Http => false,
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Https => true,
+        }
+    }
+
+    pub(super) fn is_tcpx(&self) -> bool {
+        match self {
+            Protocol::Tcp => true,
+            Protocol::Tcps => true,
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Http => false,
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Https => false,
+        }
+    }
+
+    #[cfg(feature = "ilp-over-http")]
+    fn is_httpx(&self) -> bool {
+        match self {
+            Protocol::Tcp => false,
+            Protocol::Tcps => false,
+            Protocol::Http => true,
+            Protocol::Https => true,
+        }
+    }
+
+    fn schema(&self) -> &str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Tcps => "tcps",
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Http => "http",
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Https => "https",
+        }
+    }
+
+    fn from_schema(schema: &str) -> Result<Self> {
+        match schema {
+            "tcp" => Ok(Protocol::Tcp),
+            "tcps" => Ok(Protocol::Tcps),
+            #[cfg(feature = "ilp-over-http")]
+            "http" => Ok(Protocol::Http),
+            #[cfg(feature = "ilp-over-http")]
+            "https" => Ok(Protocol::Https),
+            _ => Err(error::fmt!(ConfigError, "Unsupported protocol: {}", schema)),
+        }
+    }
+}
+
+/// Accumulates parameters for a new `Sender` instance.
+///
+/// You can also create the builder from a config string or the `QDB_CLIENT_CONF`
+/// environment variable.
+///
+#[cfg_attr(
+    feature = "ilp-over-http",
+    doc = r##"
+```no_run
+# use questdb::Result;
+use questdb::ingress::{Protocol, SenderBuilder};
+# fn main() -> Result<()> {
+let mut sender = SenderBuilder::new(Protocol::Http, "localhost", 9009).build()?;
+# Ok(())
+# }
+```
+"##
+)]
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{Protocol, SenderBuilder};
+///
+/// # fn main() -> Result<()> {
+/// let mut sender = SenderBuilder::new(Protocol::Tcp, "localhost", 9009).build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::SenderBuilder;
+///
+/// # fn main() -> Result<()> {
+/// let mut sender = SenderBuilder::from_conf("https::addr=localhost:9000;")?.build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::SenderBuilder;
+///
+/// # fn main() -> Result<()> {
+/// // export QDB_CLIENT_CONF="https::addr=localhost:9000;"
+/// let mut sender = SenderBuilder::from_env()?.build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct SenderBuilder {
+    pub(super) protocol: Protocol,
+    pub(super) host: ConfigSetting<String>,
+    pub(super) port: ConfigSetting<String>,
+    net_interface: ConfigSetting<Option<String>>,
+    net_port: ConfigSetting<Option<u16>>,
+    ip_tos: ConfigSetting<Option<u8>>,
+    pub(super) resolver: ConfigSetting<Option<ResolverRef>>,
+    pub(super) socket_addr: ConfigSetting<Option<std::net::SocketAddr>>,
+    pub(super) resolve_timeout: ConfigSetting<Option<Duration>>,
+    pub(super) dns_ttl: ConfigSetting<Option<Duration>>,
+    dns_cache: DnsCache,
+    pub(super) unix_socket: ConfigSetting<Option<PathBuf>>,
+    send_buffer_size: ConfigSetting<Option<usize>>,
+    recv_buffer_size: ConfigSetting<Option<usize>>,
+    pub(super) failover_addrs: Vec<Service>,
+    failover_after: ConfigSetting<u32>,
+    nodelay: ConfigSetting<bool>,
+    keepalive_idle: ConfigSetting<Option<Duration>>,
+    keepalive_interval: ConfigSetting<Option<Duration>>,
+    keepalive_retries: ConfigSetting<Option<u32>>,
+    check_connection_on_flush: ConfigSetting<bool>,
+    write_chunk_size: ConfigSetting<usize>,
+    pub(super) protocol_version: ConfigSetting<ProtocolVersion>,
+    observer: ConfigSetting<Option<ObserverRef>>,
+
+    #[cfg(feature = "metrics")]
+    metrics_label: ConfigSetting<Option<String>>,
+
+    pub(super) auto_flush: ConfigSetting<AutoFlush>,
+    pub(super) auto_flush_rows: ConfigSetting<Option<usize>>,
+    pub(super) auto_flush_bytes: ConfigSetting<Option<usize>>,
+    pub(super) auto_flush_interval: ConfigSetting<Option<Duration>>,
+    buffer_shrink_threshold: ConfigSetting<Option<usize>>,
+    overflow_policy: ConfigSetting<OverflowPolicy>,
+    pub(super) spill_dir: ConfigSetting<Option<PathBuf>>,
+    spill_max_bytes: ConfigSetting<u64>,
+    pub(super) max_buf_size: ConfigSetting<usize>,
+    pub(super) max_name_len: ConfigSetting<usize>,
+    pub(super) validation: ConfigSetting<Validation>,
+    pub(super) reject_control_chars_in_values: ConfigSetting<bool>,
+    pub(super) auth_timeout: ConfigSetting<Duration>,
+    username: ConfigSetting<Option<String>>,
+    password: ConfigSetting<Option<String>>,
+    token: ConfigSetting<Option<String>>,
+    token_x: ConfigSetting<Option<String>>,
+    token_y: ConfigSetting<Option<String>>,
+
+    #[cfg(feature = "insecure-skip-verify")]
+    pub(super) tls_verify: ConfigSetting<bool>,
+
+    pub(super) tls_ca: ConfigSetting<CertificateAuthority>,
+    pub(super) tls_roots: ConfigSetting<Option<PathBuf>>,
+
+    #[cfg(feature = "ilp-over-http")]
+    http: Option<HttpConfig>,
+}
+
+impl SenderBuilder {
+    /// Create a new `SenderBuilder` instance from the configuration string.
+    ///
+    /// The format of the string is: `"http::addr=host:port;key=value;...;"`.
+    ///
+    /// Instead of `"http"`, you can also specify `"https"`, `"tcp"`, and `"tcps"`.
+    ///
+    /// We recommend HTTP for most cases because it provides more features, like
+    /// reporting errors to the client and supporting transaction control. TCP can
+    /// sometimes be faster in higher-latency networks, but misses a number of
+    /// features.
+    ///
+    /// The accepted keys match one-for-one with the methods on `SenderBuilder`.
+    /// For example, this is a valid configuration string:
+    ///
+    /// "https::addr=host:port;username=alice;password=secret;"
+    ///
+    /// and there are matching methods [SenderBuilder::username] and
+    /// [SenderBuilder::password]. The value of `addr=` is supplied directly to the
+    /// `SenderBuilder` constructor, so there's no matching method for that.
+    ///
+    /// You can also load the configuration from an environment variable. See
+    /// [`SenderBuilder::from_env`].
+    ///
+    /// Once you have a `SenderBuilder` instance, you can further customize it
+    /// before calling [`SenderBuilder::build`], but you can't change any settings
+    /// that are already set in the config string.
+    pub fn from_conf<T: AsRef<str>>(conf: T) -> Result<Self> {
+        let conf = conf.as_ref();
+        let conf = questdb_confstr::parse_conf_str(conf)
+            .map_err(|e| error::fmt!(ConfigError, "Config parse error: {}", e))?;
+        let service = conf.service();
+        let params = conf.params();
+
+        let protocol = Protocol::from_schema(service)?;
+
+        // With a Unix domain socket, the host only serves as the TLS server name.
+        let addr = match (params.get("addr"), params.get("unix_socket")) {
+            (Some(addr), _) => addr.as_str(),
+            (None, Some(_)) => "localhost",
+            (None, None) => {
+                return Err(error::fmt!(
+                    ConfigError,
+                    "Missing \"addr\" parameter in config string"
+                ))
+            }
+        };
+        let mut addrs = addr.split(',');
+        let (host, port) = parse_host_port(addrs.next().unwrap_or_default())?;
+        let port = port.unwrap_or(protocol.default_port());
+        let mut builder = SenderBuilder::new(protocol, host, port);
+        for addr in addrs {
+            let (host, port) = parse_host_port(addr)?;
+            let port = port.unwrap_or(protocol.default_port());
+            builder = builder.addr((host, port))?;
+        }
+
+        for (key, val) in params.iter().map(|(k, v)| (k.as_str(), v.as_str())) {
+            builder = match key {
+                "username" => builder.username(val)?,
+                "password" => builder.password(val)?,
+                "token" => builder.token(val)?,
+                "token_x" => builder.token_x(val)?,
+                "token_y" => builder.token_y(val)?,
+                "bind_interface" => builder.bind_interface(val)?,
+                "bind_port" => builder.bind_port(parse_conf_value(key, val)?)?,
+                "ip_tos" => builder.ip_tos(parse_conf_value(key, val)?)?,
+                "unix_socket" => builder.unix_socket(val)?,
+                "auto_flush" => {
+                    let auto_flush = match val {
+                        "on" => AutoFlush::On,
+                        "off" => AutoFlush::Off,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "auto_flush" must be either "on" or "off"."##,
+                            ))
+                        }
+                    };
+                    builder.auto_flush(auto_flush)?
+                }
+                "auto_flush_rows" => builder.auto_flush_rows(parse_conf_value(key, val)?)?,
+                "auto_flush_bytes" => builder.auto_flush_bytes(parse_conf_value(key, val)?)?,
+                "auto_flush_interval" => builder
+                    .auto_flush_interval(Duration::from_millis(parse_conf_value(key, val)?))?,
+                "buffer_shrink_threshold" => {
+                    builder.buffer_shrink_threshold(parse_conf_value(key, val)?)?
+                }
+                "overflow_policy" => {
+                    let overflow_policy = match val {
+                        "error" => OverflowPolicy::Error,
+                        "drop_newest" => OverflowPolicy::DropNewest,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "overflow_policy" must be either "error" or "drop_newest"."##,
+                            ))
+                        }
+                    };
+                    builder.overflow_policy(overflow_policy)?
+                }
+                "nodelay" => {
+                    let nodelay = match val {
+                        "on" => true,
+                        "off" => false,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "nodelay" must be either "on" or "off"."##,
+                            ))
+                        }
+                    };
+                    builder.nodelay(nodelay)?
+                }
+                "keepalive_idle" => {
+                    builder.keepalive_idle(Duration::from_millis(parse_conf_value(key, val)?))?
+                }
+                "keepalive_interval" => builder
+                    .keepalive_interval(Duration::from_millis(parse_conf_value(key, val)?))?,
+                "keepalive_retries" => builder.keepalive_retries(parse_conf_value(key, val)?)?,
+                "protocol_version" => {
+                    let version = match val {
+                        "1" => ProtocolVersion::V1,
+                        "2" => ProtocolVersion::V2,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "protocol_version" must be either "1" or "2"."##,
+                            ))
+                        }
+                    };
+                    builder.protocol_version(version)?
+                }
+                "check_connection_on_flush" => {
+                    let check = match val {
+                        "on" => true,
+                        "off" => false,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "check_connection_on_flush" must be either "on" or "off"."##,
+                            ))
+                        }
+                    };
+                    builder.check_connection_on_flush(check)?
+                }
+                "write_chunk_size" => builder.write_chunk_size(parse_conf_value(key, val)?)?,
+                "failover_after" => builder.failover_after(parse_conf_value(key, val)?)?,
+                "send_buffer_size" => builder.send_buffer_size(parse_conf_value(key, val)?)?,
+                "recv_buffer_size" => builder.recv_buffer_size(parse_conf_value(key, val)?)?,
+
+                "init_buf_size" => {
+                    return Err(error::fmt!(
+                        ConfigError,
+                        "\"init_buf_size\" is not supported in config string"
+                    ))
+                }
+
+                "max_buf_size" => builder.max_buf_size(parse_conf_value(key, val)?)?,
+                "max_name_len" => builder.max_name_len(parse_conf_value(key, val)?)?,
+                "validation" => {
+                    let validation = match val {
+                        "strict" => Validation::Strict,
+                        "default" => Validation::Default,
+                        "off" => Validation::Off,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "validation" must be either "strict", "default" or "off"."##,
+                            ))
+                        }
+                    };
+                    builder.validation(validation)?
+                }
+                "reject_control_chars_in_values" => {
+                    let reject = match val {
+                        "on" => true,
+                        "off" => false,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "reject_control_chars_in_values" must be either "on" or "off"."##,
+                            ))
+                        }
+                    };
+                    builder.reject_control_chars_in_values(reject)?
+                }
+
+                "auth_timeout" => {
+                    builder.auth_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
+                }
+
+                "resolve_timeout" => {
+                    builder.resolve_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
+                }
+
+                "dns_ttl" => builder.dns_ttl(Duration::from_millis(parse_conf_value(key, val)?))?,
+
+                "tls_verify" => {
+                    let verify = match val {
+                        "on" => true,
+                        "unsafe_off" => false,
+                        _ => {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"Config parameter "tls_verify" must be either "on" or "unsafe_off".'"##,
+                            ))
+                        }
+                    };
+
+                    #[cfg(not(feature = "insecure-skip-verify"))]
+                    {
+                        if !verify {
+                            return Err(error::fmt!(
+                                ConfigError,
+                                r##"The "insecure-skip-verify" feature is not enabled, so "tls_verify=unsafe_off" is not supported"##,
+                            ));
+                        }
+                        builder
+                    }
+
+                    #[cfg(feature = "insecure-skip-verify")]
+                    builder.tls_verify(verify)?
+                }
+
+                "tls_ca" => {
+                    let ca = match val {
+                        #[cfg(feature = "tls-webpki-certs")]
+                        "webpki_roots" => CertificateAuthority::WebpkiRoots,
+
+                        #[cfg(not(feature = "tls-webpki-certs"))]
+                        "webpki_roots" => return Err(error::fmt!(ConfigError, "Config parameter \"tls_ca=webpki_roots\" requires the \"tls-webpki-certs\" feature")),
+
+                        #[cfg(feature = "tls-native-certs")]
+                        "os_roots" => CertificateAuthority::OsRoots,
+
+                        #[cfg(not(feature = "tls-native-certs"))]
+                        "os_roots" => return Err(error::fmt!(ConfigError, "Config parameter \"tls_ca=os_roots\" requires the \"tls-native-certs\" feature")),
+
+                        #[cfg(all(feature = "tls-webpki-certs", feature = "tls-native-certs"))]
+                        "webpki_and_os_roots" => CertificateAuthority::WebpkiAndOsRoots,
+
+                        #[cfg(not(all(feature = "tls-webpki-certs", feature = "tls-native-certs")))]
+                        "webpki_and_os_roots" => return Err(error::fmt!(ConfigError, "Config parameter \"tls_ca=webpki_and_os_roots\" requires both the \"tls-webpki-certs\" and \"tls-native-certs\" features")),
+
+                        _ => return Err(error::fmt!(ConfigError, "Invalid value {val:?} for \"tls_ca\"")),
+                    };
+                    builder.tls_ca(ca)?
+                }
+
+                "spill_dir" => {
+                    let path = PathBuf::from_str(val).map_err(|e| {
+                        error::fmt!(
+                            ConfigError,
+                            "Invalid path {:?} for \"spill_dir\": {}",
+                            val,
+                            e
+                        )
+                    })?;
+                    builder.spill_dir(path)?
+                }
+                "spill_max_bytes" => builder.spill_max_bytes(parse_conf_value(key, val)?)?,
+
+                "tls_roots" => {
+                    let path = PathBuf::from_str(val).map_err(|e| {
+                        error::fmt!(
+                            ConfigError,
+                            "Invalid path {:?} for \"tls_roots\": {}",
+                            val,
+                            e
+                        )
+                    })?;
+                    builder.tls_roots(path)?
+                }
+
+                "tls_roots_password" => {
+                    return Err(error::fmt!(
+                        ConfigError,
+                        "\"tls_roots_password\" is not supported."
+                    ))
+                }
+
+                #[cfg(feature = "ilp-over-http")]
+                "request_min_throughput" => {
+                    builder.request_min_throughput(parse_conf_value(key, val)?)?
+                }
+
+                #[cfg(feature = "ilp-over-http")]
+                "request_timeout" => {
+                    builder.request_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
+                }
+
+                #[cfg(feature = "ilp-over-http")]
+                "retry_timeout" => {
+                    builder.retry_timeout(Duration::from_millis(parse_conf_value(key, val)?))?
+                }
+                // Ignore other parameters.
+                // We don't want to fail on unknown keys as this would require releasing different
+                // library implementations in lock step as soon as a new parameter is added to any of them,
+                // even if it's not used.
+                _ => builder,
+            };
+        }
+
+        Ok(builder)
+    }
+
+    /// Create a new `SenderBuilder` instance from the configuration from the
+    /// configuration stored in the `QDB_CLIENT_CONF` environment variable.
+    ///
+    /// The format of the string is the same as for [`SenderBuilder::from_conf`].
+    pub fn from_env() -> Result<Self> {
+        let conf = std::env::var("QDB_CLIENT_CONF").map_err(|_| {
+            error::fmt!(ConfigError, "Environment variable QDB_CLIENT_CONF not set.")
+        })?;
+        Self::from_conf(conf)
+    }
+
+    /// Create a new `SenderBuilder` instance with the provided QuestDB
+    /// server and port, using ILP over the specified protocol.
+    ///
+    /// ```no_run
+    /// # use questdb::Result;
+    /// use questdb::ingress::{Protocol, SenderBuilder};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut sender = SenderBuilder::new(
+    ///     Protocol::Tcp, "localhost", 9009).build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new<H: Into<String>, P: Into<Port>>(protocol: Protocol, host: H, port: P) -> Self {
+        let host = host.into();
+        let port: Port = port.into();
+        let port = port.0;
+
+        #[cfg(feature = "tls-webpki-certs")]
+        let tls_ca = CertificateAuthority::WebpkiRoots;
+
+        #[cfg(all(not(feature = "tls-webpki-certs"), feature = "tls-native-certs"))]
+        let tls_ca = CertificateAuthority::OsRoots;
+
+        #[cfg(not(any(feature = "tls-webpki-certs", feature = "tls-native-certs")))]
+        let tls_ca = CertificateAuthority::PemFile;
+
+        Self {
+            protocol,
+            host: ConfigSetting::new_specified(host),
+            port: ConfigSetting::new_specified(port),
+            net_interface: ConfigSetting::new_default(None),
+            net_port: ConfigSetting::new_default(None),
+            ip_tos: ConfigSetting::new_default(None),
+            resolver: ConfigSetting::new_default(None),
+            socket_addr: ConfigSetting::new_default(None),
+            resolve_timeout: ConfigSetting::new_default(None),
+            dns_ttl: ConfigSetting::new_default(None),
+            dns_cache: DnsCache::default(),
+            unix_socket: ConfigSetting::new_default(None),
+            send_buffer_size: ConfigSetting::new_default(None),
+            recv_buffer_size: ConfigSetting::new_default(None),
+            failover_addrs: Vec::new(),
+            failover_after: ConfigSetting::new_default(1),
+            nodelay: ConfigSetting::new_default(true),
+            keepalive_idle: ConfigSetting::new_default(None),
+            keepalive_interval: ConfigSetting::new_default(None),
+            keepalive_retries: ConfigSetting::new_default(None),
+            check_connection_on_flush: ConfigSetting::new_default(false),
+            write_chunk_size: ConfigSetting::new_default(DEFAULT_WRITE_CHUNK_SIZE),
+            protocol_version: ConfigSetting::new_default(ProtocolVersion::V2),
+            observer: ConfigSetting::new_default(None),
+
+            #[cfg(feature = "metrics")]
+            metrics_label: ConfigSetting::new_default(None),
+
+            auto_flush: ConfigSetting::new_default(AutoFlush::On),
+            auto_flush_rows: ConfigSetting::new_default(None),
+            auto_flush_bytes: ConfigSetting::new_default(None),
+            auto_flush_interval: ConfigSetting::new_default(None),
+            buffer_shrink_threshold: ConfigSetting::new_default(None),
+            overflow_policy: ConfigSetting::new_default(OverflowPolicy::Error),
+            spill_dir: ConfigSetting::new_default(None),
+            spill_max_bytes: ConfigSetting::new_default(1024 * 1024 * 1024),
+            max_buf_size: ConfigSetting::new_default(100 * 1024 * 1024),
+            max_name_len: ConfigSetting::new_default(DEFAULT_MAX_NAME_LEN),
+            validation: ConfigSetting::new_default(Validation::Default),
+            reject_control_chars_in_values: ConfigSetting::new_default(false),
+            auth_timeout: ConfigSetting::new_default(Duration::from_secs(15)),
+            username: ConfigSetting::new_default(None),
+            password: ConfigSetting::new_default(None),
+            token: ConfigSetting::new_default(None),
+            token_x: ConfigSetting::new_default(None),
+            token_y: ConfigSetting::new_default(None),
+
+            #[cfg(feature = "insecure-skip-verify")]
+            tls_verify: ConfigSetting::new_default(true),
+
+            tls_ca: ConfigSetting::new_default(tls_ca),
+            tls_roots: ConfigSetting::new_default(None),
+
+            #[cfg(feature = "ilp-over-http")]
+            http: if protocol.is_httpx() {
+                Some(HttpConfig::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Create a new `SenderBuilder` instance for the QuestDB server at the given
+    /// address, using ILP over the specified protocol.
+    ///
+    /// The address is either a `"host:port"` string or anything else that
+    /// converts into a [`Service`], such as a `(host, port)` tuple.
+    ///
+    /// ```no_run
+    /// # use questdb::Result;
+    /// use questdb::ingress::{Protocol, SenderBuilder};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut sender = SenderBuilder::from_addr(Protocol::Tcp, "localhost:9009")?.build()?;
+    /// let mut sender = SenderBuilder::from_addr(Protocol::Tcp, "[::1]:9009")?.build()?;
+    /// let mut sender = SenderBuilder::from_addr(Protocol::Tcp, ("localhost", 9009))?.build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_addr<S>(protocol: Protocol, addr: S) -> Result<Self>
+    where
+        S: TryInto<Service>,
+        Error: From<S::Error>,
+    {
+        let service: Service = addr.try_into()?;
+        Ok(Self::new(protocol, service.host, service.port))
+    }
+
+    /// Create a new `SenderBuilder` instance for the QuestDB server at an
+    /// already resolved socket address, using ILP over the specified protocol.
+    ///
+    /// The sender connects to exactly this address, bypassing `getaddrinfo`,
+    /// any custom [`resolver`](SenderBuilder::resolver) and the DNS cache. All
+    /// the other connection settings, such as
+    /// [`nodelay`](SenderBuilder::nodelay), apply as usual. Use this with your
+    /// own service discovery or load balancing.
+    ///
+    /// The IP address stands in for the host name: With TLS, the server's
+    /// certificate must then be issued for that IP address.
+    ///
+    /// ```no_run
+    /// # use questdb::Result;
+    /// use questdb::ingress::{Protocol, SenderBuilder};
+    /// use std::net::SocketAddr;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let addr: SocketAddr = "10.0.0.7:9009".parse().unwrap();
+    /// let mut sender = SenderBuilder::from_socket_addr(Protocol::Tcp, addr).build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_socket_addr(protocol: Protocol, addr: std::net::SocketAddr) -> Self {
+        let mut builder = Self::new(protocol, addr.ip().to_string(), addr.port());
+        builder.socket_addr = ConfigSetting::new_specified(Some(addr));
+        builder
+    }
+
+    /// Add a failover address, to connect to should the addresses before it be
+    /// unreachable.
+    ///
+    /// [`build`](SenderBuilder::build) tries the address the builder was
+    /// created with first, then each failover address in the order they were
+    /// added. [`Sender::reconnect`] keeps to the address it was connected to,
+    /// until it fails to reconnect to it as many consecutive times as set by
+    /// [`failover_after`](SenderBuilder::failover_after).
+    ///
+    /// In the config string, list the addresses separated by commas:
+    /// `"tcp::addr=primary:9009,standby:9009;"`.
+    ///
+    /// ```no_run
+    /// # use questdb::Result;
+    /// use questdb::ingress::{Protocol, SenderBuilder};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut sender = SenderBuilder::from_addr(Protocol::Tcp, "primary:9009")?
+    ///     .addr("standby:9009")?
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn addr<S>(mut self, addr: S) -> Result<Self>
+    where
+        S: TryInto<Service>,
+        Error: From<S::Error>,
+    {
+        self.ensure_is_tcpx("addr")?;
+        self.failover_addrs.push(addr.try_into()?);
+        Ok(self)
+    }
+
+    /// Set after how many consecutive failures to reconnect to the current
+    /// address [`Sender::reconnect`] moves on to the next one.
+    ///
+    /// Only relevant with failover addresses, see [`addr`](SenderBuilder::addr).
+    ///
+    /// The default is `1`: Move on after the first failure.
+    pub fn failover_after(mut self, value: u32) -> Result<Self> {
+        self.ensure_is_tcpx("failover_after")?;
+        if value == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"failover_after\" must be at least 1."
+            ));
+        }
+        self.failover_after.set_specified("failover_after", value)?;
+        Ok(self)
+    }
+
+    /// Select local outbound interface.
+    ///
+    /// This may be relevant if your machine has multiple network interfaces.
+    ///
+    /// The default is the unspecified address of the server's address family,
+    /// i.e. `"0.0.0.0"` for IPv4 or `"::"` for IPv6.
+    pub fn bind_interface<I: Into<String>>(mut self, addr: I) -> Result<Self> {
+        self.ensure_is_tcpx("bind_interface")?;
+        self.net_interface
+            .set_specified("bind_interface", Some(validate_value(addr.into())?))?;
+        Ok(self)
+    }
+
+    /// Select the local outbound port.
+    ///
+    /// This may be relevant if firewall rules require the connection to
+    /// originate from a fixed source port.
+    ///
+    /// If no [`bind_interface`](SenderBuilder::bind_interface) is set, this binds
+    /// to the unspecified address of the server's address family on the given
+    /// port: `"0.0.0.0"` for IPv4, or `"::"` for IPv6.
+    ///
+    /// The default is to let the OS pick an ephemeral port.
+    pub fn bind_port(mut self, port: u16) -> Result<Self> {
+        self.ensure_is_tcpx("bind_port")?;
+        self.net_port.set_specified("bind_port", Some(port))?;
+        Ok(self)
+    }
+
+    /// Set the IP type-of-service byte (`IP_TOS`) on outgoing packets.
+    ///
+    /// This allows network equipment to prioritize ILP traffic.
+    /// The upper six bits are the DSCP class and the lower two bits are ECN,
+    /// so to mark traffic as DSCP class `AF21` (`18`), pass `18 << 2`.
+    ///
+    /// Over IPv6, this sets the traffic class (`IPV6_TCLASS`) instead, which
+    /// has the same layout. Where the platform doesn't support that, e.g. on
+    /// Windows, connecting to an IPv6 address fails with a
+    /// [`ConfigError`](crate::ErrorCode::ConfigError).
+    ///
+    /// The default is to leave the OS default unchanged.
+    pub fn ip_tos(mut self, tos: u8) -> Result<Self> {
+        self.ensure_is_tcpx("ip_tos")?;
+        if cfg!(any(
+            target_os = "fuchsia",
+            target_os = "redox",
+            target_os = "solaris",
+            target_os = "illumos"
+        )) {
+            return Err(error::fmt!(
+                ConfigError,
+                "The \"ip_tos\" setting is not supported on this platform."
+            ));
+        }
+        self.ip_tos.set_specified("ip_tos", Some(tos))?;
+        Ok(self)
+    }
+
+    /// Use a custom [`Resolver`] to look up the server's addresses, instead of
+    /// the operating system's `getaddrinfo`.
+    ///
+    /// A custom resolver requires the port to be numeric, not a service name.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolver>) -> Result<Self> {
+        self.ensure_is_tcpx("resolver")?;
+        self.resolver
+            .set_specified("resolver", Some(ResolverRef(resolver)))?;
+        Ok(self)
+    }
+
+    /// Bound how long resolving the server's host name may take.
+    ///
+    /// Should the resolution time out, [`build`](SenderBuilder::build) returns a
+    /// [`CouldNotResolveAddr`](crate::ErrorCode::CouldNotResolveAddr) error.
+    ///
+    /// Resolution is blocking, so with a timeout set it runs on a helper thread.
+    /// On timeout, that thread is abandoned rather than cancelled: It keeps
+    /// running in the background until the underlying resolution call returns.
+    ///
+    /// This only bounds resolution: Connecting to the resolved address is not
+    /// subject to this timeout.
+    ///
+    /// The default is no timeout.
+    pub fn resolve_timeout(mut self, value: Duration) -> Result<Self> {
+        self.ensure_is_tcpx("resolve_timeout")?;
+        self.resolve_timeout
+            .set_specified("resolve_timeout", Some(value))?;
+        Ok(self)
+    }
+
+    /// Reuse the addresses the server's host name resolved to for up to this
+    /// long.
+    ///
+    /// Each call to [`build`](SenderBuilder::build) resolves the host name anew,
+    /// so a sender rebuilt after a disconnect follows DNS changes, such as
+    /// during a failover. Set a TTL to avoid hitting DNS on every attempt when
+    /// reconnecting in rapid succession. The cache is shared among clones of
+    /// this builder, such as those held by a [`SenderPool`].
+    ///
+    /// The default is not to cache resolutions.
+    pub fn dns_ttl(mut self, value: Duration) -> Result<Self> {
+        self.ensure_is_tcpx("dns_ttl")?;
+        self.dns_ttl.set_specified("dns_ttl", Some(value))?;
+        Ok(self)
+    }
+
+    /// Set whether to disable Nagle's algorithm (`TCP_NODELAY`) on the socket.
+    ///
+    /// With `true`, each flush goes out on the wire immediately, minimizing
+    /// latency when sending a few rows at a time. With `false`, the operating
+    /// system may hold back small writes to coalesce them into fewer, fuller
+    /// packets: This can improve throughput for bulk loads that flush large
+    /// buffers, at the cost of added latency.
+    ///
+    /// The default is `true`.
+    pub fn nodelay(mut self, value: bool) -> Result<Self> {
+        self.ensure_is_tcpx("nodelay")?;
+        self.nodelay.set_specified("nodelay", value)?;
+        Ok(self)
+    }
+
+    /// Pin the ILP protocol version to send, for servers that don't understand
+    /// the latest one.
+    ///
+    /// Buffers created by [`Sender::new_buffer`] encode rows for this version,
+    /// and flushing a buffer that requires a newer version fails.
+    ///
+    /// The default is the latest version, [`ProtocolVersion::V2`]. Note that a
+    /// standalone [`Buffer::new`] instead defaults to `V1`, which every sender
+    /// can flush.
+    pub fn protocol_version(mut self, value: ProtocolVersion) -> Result<Self> {
+        self.protocol_version
+            .set_specified("protocol_version", value)?;
+        Ok(self)
+    }
+
+    /// Call the given [`FlushObserver`] after each flush, with the number of
+    /// rows and bytes sent and how long that took, or with the error's code.
+    ///
+    /// Use this to feed metrics without wrapping each call to `flush`.
+    pub fn observer(mut self, observer: Arc<dyn FlushObserver>) -> Result<Self> {
+        self.observer
+            .set_specified("observer", Some(ObserverRef(observer)))?;
+        Ok(self)
+    }
+
+    /// Set the value of the `sender` label on the sender's metrics, to tell
+    /// apart several senders in the same process.
+    ///
+    /// By default, the metrics carry no labels. See the
+    /// [module docs](crate::ingress#metrics) for the metrics emitted.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_label(mut self, label: &str) -> Result<Self> {
+        self.metrics_label
+            .set_specified("metrics_label", Some(label.to_owned()))?;
+        Ok(self)
+    }
+
+    /// Set whether [`Sender::at`] and [`Sender::at_now`] flush automatically
+    /// once the buffer crosses the
+    /// [`auto_flush_rows`](SenderBuilder::auto_flush_rows),
+    /// [`auto_flush_bytes`](SenderBuilder::auto_flush_bytes) or
+    /// [`auto_flush_interval`](SenderBuilder::auto_flush_interval) threshold.
+    ///
+    /// The thresholds are only checked there: Rows completed with the buffer's
+    /// own [`Buffer::at`] or [`Buffer::at_now`] count towards them, but never
+    /// trigger a flush themselves, and are sent with the next row completed
+    /// through the sender or the next call to [`Sender::flush`].
+    ///
+    /// The default is [`AutoFlush::On`], but no threshold is set, so nothing is
+    /// flushed automatically until you set one.
+    pub fn auto_flush(mut self, value: AutoFlush) -> Result<Self> {
+        self.auto_flush.set_specified("auto_flush", value)?;
+        Ok(self)
+    }
+
+    /// Flush from [`Sender::at`] and [`Sender::at_now`] once the buffer holds
+    /// this many rows.
+    pub fn auto_flush_rows(mut self, value: usize) -> Result<Self> {
+        if value == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"auto_flush_rows\" must be greater than 0."
+            ));
+        }
+        self.auto_flush_rows
+            .set_specified("auto_flush_rows", Some(value))?;
+        Ok(self)
+    }
+
+    /// Flush from [`Sender::at`] and [`Sender::at_now`] once the buffer holds
+    /// at least this many bytes.
+    pub fn auto_flush_bytes(mut self, value: usize) -> Result<Self> {
+        if value == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"auto_flush_bytes\" must be greater than 0."
+            ));
+        }
+        self.auto_flush_bytes
+            .set_specified("auto_flush_bytes", Some(value))?;
+        Ok(self)
+    }
+
+    /// Flush from [`Sender::at`] and [`Sender::at_now`] once the oldest row in
+    /// the buffer was completed at least this long ago.
+    ///
+    /// There's no background timer: The age is only checked when a row is
+    /// completed, so a buffer that stops receiving rows isn't flushed until you
+    /// call [`Sender::flush`].
+    pub fn auto_flush_interval(mut self, value: Duration) -> Result<Self> {
+        if value.is_zero() {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"auto_flush_interval\" must be greater than 0."
+            ));
+        }
+        self.auto_flush_interval
+            .set_specified("auto_flush_interval", Some(value))?;
+        Ok(self)
+    }
+
+    /// Set whether each flush first checks that the server hasn't closed the
+    /// connection, see [`Sender::check_connection`].
+    ///
+    /// With ILP over TCP, the server closes the connection when it rejects the
+    /// data, yet writes to a closed socket may keep succeeding for a while.
+    /// Checking before each flush surfaces the disconnection sooner, at the cost
+    /// of a system call per flush.
+    ///
+    /// The default is `false`.
+    pub fn check_connection_on_flush(mut self, value: bool) -> Result<Self> {
+        self.ensure_is_tcpx("check_connection_on_flush")?;
+        self.check_connection_on_flush
+            .set_specified("check_connection_on_flush", value)?;
+        Ok(self)
+    }
+
+    /// Set the most bytes a flush writes to the socket in a single call.
+    ///
+    /// A flush writes a large buffer in chunks of this size, rather than all at
+    /// once, so that each write completes in bounded time and an error reports
+    /// how many bytes were sent. The chunks are sent back to back: The server
+    /// receives the same bytes either way.
+    ///
+    /// The default is 4 MiB.
+    pub fn write_chunk_size(mut self, value: usize) -> Result<Self> {
+        self.ensure_is_tcpx("write_chunk_size")?;
+        if value == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"write_chunk_size\" must be greater than 0."
+            ));
+        }
+        self.write_chunk_size
+            .set_specified("write_chunk_size", value)?;
+        Ok(self)
+    }
+
+    /// Set how long the connection must be idle before TCP keepalive probes
+    /// are sent.
+    ///
+    /// Keepalive probes let an idle sender detect a peer that vanished, e.g.
+    /// behind a NAT or firewall that dropped the connection: The next flush
+    /// then fails promptly and the sender can [reconnect](Sender::reconnect).
+    /// A peer is deemed dead after `idle + interval * retries`.
+    ///
+    /// Some platforms only support whole seconds. The default is the operating
+    /// system's, typically two hours.
+    pub fn keepalive_idle(mut self, value: Duration) -> Result<Self> {
+        self.ensure_is_tcpx("keepalive_idle")?;
+        self.keepalive_idle
+            .set_specified("keepalive_idle", Some(value))?;
+        Ok(self)
+    }
+
+    /// Set the interval between TCP keepalive probes.
+    ///
+    /// See [`keepalive_idle`](SenderBuilder::keepalive_idle).
+    /// The default is the operating system's.
+    pub fn keepalive_interval(mut self, value: Duration) -> Result<Self> {
+        self.ensure_is_tcpx("keepalive_interval")?;
+        if !cfg!(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "windows"
+        )) {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"keepalive_interval\" is not supported on this platform."
+            ));
+        }
+        self.keepalive_interval
+            .set_specified("keepalive_interval", Some(value))?;
+        Ok(self)
+    }
+
+    /// Set how many unanswered TCP keepalive probes to send before deeming the
+    /// peer dead.
+    ///
+    /// See [`keepalive_idle`](SenderBuilder::keepalive_idle).
+    /// The default is the operating system's. Not supported on Windows.
+    pub fn keepalive_retries(mut self, value: u32) -> Result<Self> {
+        self.ensure_is_tcpx("keepalive_retries")?;
+        if !cfg!(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd"
+        )) {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"keepalive_retries\" is not supported on this platform."
+            ));
+        }
+        self.keepalive_retries
+            .set_specified("keepalive_retries", Some(value))?;
+        Ok(self)
+    }
+
+    /// Set the size of the socket's send buffer (`SO_SNDBUF`), in bytes.
+    ///
+    /// A larger buffer helps sustain throughput for bulk loads over
+    /// high-latency links. The operating system may adjust the requested size:
+    /// Call [`Sender::send_buffer_size`] to get the size actually applied.
+    ///
+    /// The default is the operating system's.
+    pub fn send_buffer_size(mut self, value: usize) -> Result<Self> {
+        self.ensure_is_tcpx("send_buffer_size")?;
+        self.send_buffer_size
+            .set_specified("send_buffer_size", Some(value))?;
+        Ok(self)
+    }
+
+    /// Set the size of the socket's receive buffer (`SO_RCVBUF`), in bytes.
+    ///
+    /// The sender only reads from the socket during the TLS handshake and
+    /// authentication. The operating system may adjust the requested size:
+    /// Call [`Sender::recv_buffer_size`] to get the size actually applied.
+    ///
+    /// The default is the operating system's.
+    pub fn recv_buffer_size(mut self, value: usize) -> Result<Self> {
+        self.ensure_is_tcpx("recv_buffer_size")?;
+        self.recv_buffer_size
+            .set_specified("recv_buffer_size", Some(value))?;
+        Ok(self)
+    }
+
+    /// Connect over the Unix domain socket at the given path rather than over
+    /// TCP/IP.
+    ///
+    /// This avoids the overhead of the TCP stack when QuestDB runs on the same
+    /// host. The sender still speaks ILP over TCP, so the server must expose the
+    /// ILP/TCP endpoint on that socket, e.g. through a local proxy.
+    ///
+    /// The host name and port are then not used to connect: With TLS, the
+    /// host name is still checked against the server's certificate.
+    /// This setting can't be combined with
+    /// [`bind_interface`](SenderBuilder::bind_interface),
+    /// [`bind_port`](SenderBuilder::bind_port), nor with the TCP/IP socket
+    /// options [`nodelay`](SenderBuilder::nodelay),
+    /// [`keepalive_idle`](SenderBuilder::keepalive_idle),
+    /// [`keepalive_interval`](SenderBuilder::keepalive_interval),
+    /// [`keepalive_retries`](SenderBuilder::keepalive_retries) and
+    /// [`ip_tos`](SenderBuilder::ip_tos): Building the sender fails with a
+    /// [`ConfigError`](crate::ErrorCode::ConfigError).
+    pub fn unix_socket<P: Into<PathBuf>>(mut self, path: P) -> Result<Self> {
+        self.ensure_is_tcpx("unix_socket")?;
+        self.unix_socket
+            .set_specified("unix_socket", Some(path.into()))?;
+        Ok(self)
+    }
+
+    /// Set the username for authentication.
+    ///
+    /// For TCP, this is the `kid` part of the ECDSA key set.
+    /// The other fields are [`token`](SenderBuilder::token), [`token_x`](SenderBuilder::token_x),
+    /// and [`token_y`](SenderBuilder::token_y).
+    ///
+    /// For HTTP, this is a part of basic authentication.
+    /// See also: [`password`](SenderBuilder::password).
+    pub fn username(mut self, username: &str) -> Result<Self> {
+        self.username
+            .set_specified("username", Some(validate_value(username.to_string())?))?;
+        Ok(self)
+    }
+
+    /// Set the password for basic HTTP authentication.
+    /// See also: [`username`](SenderBuilder::username).
+    pub fn password(mut self, password: &str) -> Result<Self> {
+        self.password
+            .set_specified("password", Some(validate_value(password.to_string())?))?;
+        Ok(self)
+    }
+
+    /// Set the Token (Bearer) Authentication parameter for HTTP,
+    /// or the ECDSA private key for TCP authentication.
+    pub fn token(mut self, token: &str) -> Result<Self> {
+        self.token
+            .set_specified("token", Some(validate_value(token.to_string())?))?;
+        Ok(self)
+    }
+
+    /// Set the ECDSA public key X for TCP authentication.
+    pub fn token_x(mut self, token_x: &str) -> Result<Self> {
+        self.token_x
+            .set_specified("token_x", Some(validate_value(token_x.to_string())?))?;
+        Ok(self)
+    }
+
+    /// Set the ECDSA public key Y for TCP authentication.
+    pub fn token_y(mut self, token_y: &str) -> Result<Self> {
+        self.token_y
+            .set_specified("token_y", Some(validate_value(token_y.to_string())?))?;
+        Ok(self)
+    }
+
+    /// Configure how long to wait for messages from the QuestDB server during
+    /// the TLS handshake and authentication process.
+    /// The default is 15 seconds.
+    pub fn auth_timeout(mut self, value: Duration) -> Result<Self> {
+        self.auth_timeout.set_specified("auth_timeout", value)?;
+        Ok(self)
+    }
+
+    /// Ensure that TLS is enabled for the protocol.
+    pub fn ensure_tls_enabled(&self, property: &str) -> Result<()> {
+        if !self.protocol.tls_enabled() {
+            return Err(error::fmt!(
+                ConfigError,
+                "Cannot set {property:?}: TLS is not supported for protocol {}",
+                self.protocol
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set to `false` to disable TLS certificate verification.
+    /// This should only be used for debugging purposes as it reduces security.
+    ///
+    /// For testing, consider specifying a path to a `.pem` file instead via
+    /// the [`tls_roots`](SenderBuilder::tls_roots) method.
+    #[cfg(feature = "insecure-skip-verify")]
+    pub fn tls_verify(mut self, verify: bool) -> Result<Self> {
+        self.ensure_tls_enabled("tls_verify")?;
+        self.tls_verify.set_specified("tls_verify", verify)?;
+        Ok(self)
+    }
+
+    /// Specify where to find the root certificate used to validate the
+    /// server's TLS certificate.
+    pub fn tls_ca(mut self, ca: CertificateAuthority) -> Result<Self> {
+        self.ensure_tls_enabled("tls_ca")?;
+        self.tls_ca.set_specified("tls_ca", ca)?;
+        Ok(self)
+    }
+
+    /// Set the path to a custom root certificate `.pem` file.
+    /// This is used to validate the server's certificate during the TLS handshake.
+    ///
+    /// See notes on how to test with [self-signed
+    /// certificates](https://github.com/questdb/c-questdb-client/tree/main/tls_certs).
+    pub fn tls_roots<P: Into<PathBuf>>(self, path: P) -> Result<Self> {
+        let mut builder = self.tls_ca(CertificateAuthority::PemFile)?;
+        let path = path.into();
+        // Attempt to read the file here to catch any issues early.
+        let _file = std::fs::File::open(&path).map_err(|io_err| {
+            error::fmt!(
+                ConfigError,
+                "Could not open root certificate file from path {:?}: {}",
+                path,
+                io_err
+            )
+        })?;
+        builder.tls_roots.set_specified("tls_roots", Some(path))?;
+        Ok(builder)
+    }
+
+    /// Shrink a buffer back to its initial capacity after [`Sender::flush`]
+    /// sends it, if its capacity exceeds this many bytes.
+    ///
+    /// The initial capacity is the one given to [`Buffer::with_capacity`], or
+    /// none for other buffers.
+    ///
+    /// A buffer only ever grows, so a single large batch would otherwise pin its
+    /// memory for as long as the buffer is reused. By default, buffers keep their
+    /// capacity, which suits a steady, high rate of large batches.
+    pub fn buffer_shrink_threshold(mut self, value: usize) -> Result<Self> {
+        self.buffer_shrink_threshold
+            .set_specified("buffer_shrink_threshold", Some(value))?;
+        Ok(self)
+    }
+
+    /// What [`Sender::at`] and [`Sender::at_now`] do with a row that would
+    /// grow the buffer past the [`max_buf_size`](SenderBuilder::max_buf_size)
+    /// and can't be made room for by auto-flushing.
+    ///
+    /// The default, [`OverflowPolicy::Error`], returns a
+    /// [`BufferTooLarge`](ErrorCode::BufferTooLarge) error. With
+    /// [`OverflowPolicy::DropNewest`], the row is dropped and counted in
+    /// [`SenderStats::rows_dropped`] instead. The other policies need a queue,
+    /// so they're rejected here.
+    pub fn overflow_policy(mut self, value: OverflowPolicy) -> Result<Self> {
+        if !matches!(value, OverflowPolicy::Error | OverflowPolicy::DropNewest) {
+            return Err(error::fmt!(
+                ConfigError,
+                "A sender's overflow policy must be either `Error` or `DropNewest`, not `{:?}`.",
+                value
+            ));
+        }
+        self.overflow_policy
+            .set_specified("overflow_policy", value)?;
+        Ok(self)
+    }
+
+    /// Write the rows of flushes that fail to connect or send to spill files in
+    /// this directory, rather than keep them in the buffer.
+    ///
+    /// On such a failure, [`Sender::flush`] appends the buffer's rows to a
+    /// spill file, clears the buffer and returns the error as usual: The
+    /// sender still [must be closed](Sender::must_close) or
+    /// [reconnected](Sender::reconnect). Flushes that the server rejects
+    /// aren't spilled, and neither are those that fail partway through a
+    /// write: Some of the rows reached the server, so the buffer keeps them
+    /// all, as described for [`Sender::flush`]. Nor are those of
+    /// [`Sender::flush_and_keep`], which leaves the rows to the caller. Only
+    /// complete rows are spilled.
+    ///
+    /// While the sender is disconnected, [`Sender::at`] and [`Sender::at_now`]
+    /// also spill the buffer's rows once a row would grow it past the
+    /// [`max_buf_size`](SenderBuilder::max_buf_size), rather than fail, so the
+    /// buffer doesn't grow until it's full.
+    ///
+    /// Each sender appends to a file of its own, created by its first spill,
+    /// see [`spill_files`]. Should spilling fail, e.g. because of the
+    /// [`spill_max_bytes`](SenderBuilder::spill_max_bytes) limit, the buffer
+    /// keeps its rows as it would without a spill directory.
+    ///
+    /// Once connected, a sender replays the spilled rows, including those left
+    /// by earlier senders, before the rows of its next flush, so that the
+    /// server receives them in order. See [`Sender::replay_now`] and
+    /// [`Sender::pending_spill`]. A sender skips the files that another one
+    /// is still appending to or replaying, so concurrent senders may share a
+    /// directory, though each then replays whichever files are free, in no
+    /// particular order across senders. For the rows to be replayed in the
+    /// order they were spilled, give each concurrent sender a directory of its
+    /// own, as a [`SenderPool`] does.
+    ///
+    /// Spilled rows the server refuses for good, e.g. with an HTTP 400, or
+    /// that need a newer [protocol version](SenderBuilder::protocol_version)
+    /// than the sender's, don't hold up the replay: They're moved to a
+    /// `.rejected` spill file next to theirs and no longer replayed.
+    pub fn spill_dir<P: Into<PathBuf>>(mut self, path: P) -> Result<Self> {
+        let path = path.into();
+        if path.as_os_str().is_empty() {
+            return Err(error::fmt!(ConfigError, "\"spill_dir\" must not be empty."));
+        }
+        self.spill_dir.set_specified("spill_dir", Some(path))?;
+        Ok(self)
+    }
+
+    /// The most bytes the files in the [spill directory](SenderBuilder::spill_dir)
+    /// may add up to, counting those left by earlier senders.
+    /// The default is 1 GiB.
+    ///
+    /// Rows that would grow the files past this size aren't spilled.
+    pub fn spill_max_bytes(mut self, value: u64) -> Result<Self> {
+        self.spill_max_bytes
+            .set_specified("spill_max_bytes", value)?;
+        Ok(self)
+    }
+
+    /// The maximum buffer size in bytes that the client will flush to the server.
+    /// The default is 100 MiB.
+    ///
+    /// Buffers created by [`Sender::new_buffer`] reject rows past this size with
+    /// [`ErrorCode::BufferTooLarge`], as does completing a row with
+    /// [`Sender::at`] or [`Sender::at_now`], unless auto-flushing is enabled:
+    /// These then flush the rows before it first to make room.
+    pub fn max_buf_size(mut self, value: usize) -> Result<Self> {
+        let min = 1024;
+        if value < min {
+            return Err(error::fmt!(
+                ConfigError,
+                "max_buf_size\" must be at least {min} bytes."
+            ));
+        }
+        self.max_buf_size.set_specified("max_buf_size", value)?;
+        Ok(self)
+    }
+
+    /// The maximum length in bytes of table and column names.
+    /// The default is 127, QuestDB's default.
+    ///
+    /// This should match the `cairo.max.file.name.length` setting of the
+    /// server: It rejects longer names by closing the connection. Buffers
+    /// created by [`Sender::new_buffer`] reject them up front with
+    /// [`ErrorCode::InvalidName`], see [`Buffer::with_max_name_len`].
+    pub fn max_name_len(mut self, value: usize) -> Result<Self> {
+        if value == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"max_name_len\" must be at least 1 byte."
+            ));
+        }
+        self.max_name_len.set_specified("max_name_len", value)?;
+        Ok(self)
+    }
+
+    /// How strictly buffers created by [`Sender::new_buffer`] check the
+    /// characters of table and column names, see [`Validation`].
+    /// The default is [`Validation::Default`].
+    pub fn validation(mut self, value: Validation) -> Result<Self> {
+        self.validation.set_specified("validation", value)?;
+        Ok(self)
+    }
+
+    /// Make buffers created by [`Sender::new_buffer`] reject symbol and string
+    /// values containing control characters other than `\n` or `\r`, see
+    /// [`Buffer::set_reject_control_chars_in_values`].
+    /// The default is off.
+    pub fn reject_control_chars_in_values(mut self, value: bool) -> Result<Self> {
+        self.reject_control_chars_in_values
+            .set_specified("reject_control_chars_in_values", value)?;
+        Ok(self)
+    }
+
+    #[cfg(feature = "ilp-over-http")]
+    /// Set the cumulative duration spent in retries.
+    /// The value is in milliseconds, and the default is 10 seconds.
+    pub fn retry_timeout(mut self, value: Duration) -> Result<Self> {
+        if let Some(http) = &mut self.http {
+            http.retry_timeout.set_specified("retry_timeout", value)?;
+        } else {
+            return Err(error::fmt!(
+                ConfigError,
+                "retry_timeout is supported only in ILP over HTTP."
+            ));
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature = "ilp-over-http")]
+    /// Hand the payloads the server permanently rejects to the given
+    /// [`DeadLetterSink`], such as a [`DeadLetterFile`], rather than only
+    /// returning the error.
+    ///
+    /// The sink only receives requests refused with a 400, 413 or 422 status,
+    /// never those that failed because of the network or with a status that
+    /// may be transient, such as 408, 429 or any 5xx. The flush still returns
+    /// the error.
+    pub fn dead_letter_sink(mut self, sink: Arc<dyn DeadLetterSink>) -> Result<Self> {
+        if let Some(http) = &mut self.http {
+            http.dead_letter
+                .set_specified("dead_letter_sink", Some(DeadLetterRef(sink)))?;
+        } else {
+            return Err(error::fmt!(
+                ConfigError,
+                "dead_letter_sink is supported only in ILP over HTTP."
+            ));
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature = "ilp-over-http")]
+    /// Set the minimum acceptable throughput while sending a buffer to the server.
+    /// The sender will divide the payload size by this number to determine for how
+    /// long to keep sending the payload before timing out.
+    /// The value is in bytes per second, and the default is 100 KiB/s.
+    /// The timeout calculated from minimum throughput is adedd to the value of
+    /// [`request_timeout`](SenderBuilder::request_timeout) to get the total timeout
+    /// value.
+    pub fn request_min_throughput(mut self, value: u64) -> Result<Self> {
+        if let Some(http) = &mut self.http {
+            http.request_min_throughput
+                .set_specified("request_min_throughput", value)?;
+        } else {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"request_min_throughput\" is supported only in ILP over HTTP."
+            ));
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature = "ilp-over-http")]
+    /// Additional time to wait on top of that calculated from the minimum throughput.
+    /// This accounts for the fixed latency of the HTTP request-response roundtrip.
+    /// The value is in milliseconds, and the default is 10 seconds.
+    /// See also: [`request_min_throughput`](SenderBuilder::request_min_throughput).
+    pub fn request_timeout(mut self, value: Duration) -> Result<Self> {
+        if let Some(http) = &mut self.http {
+            http.request_timeout
+                .set_specified("request_timeout", value)?;
+        } else {
+            return Err(error::fmt!(
+                ConfigError,
+                "\"request_timeout\" is supported only in ILP over HTTP."
+            ));
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature = "ilp-over-http")]
+    /// Internal API, do not use.
+    /// This is exposed exclusively for the Python client.
+    /// We (QuestDB) use this to help us debug which client is being used if we encounter issues.
+    #[doc(hidden)]
+    pub fn user_agent(mut self, value: &str) -> Result<Self> {
+        let value = validate_value(value)?;
+        if let Some(http) = &mut self.http {
+            http.user_agent = value.to_string();
+        }
+        Ok(self)
+    }
+
+    /// The settings of the buffers the built senders create.
+    pub(super) fn buffer_template(&self) -> BufferTemplate {
+        BufferTemplate {
+            protocol_version: *self.protocol_version,
+            max_buf_size: *self.max_buf_size,
+            max_name_len: *self.max_name_len,
+            validation: *self.validation,
+            reject_control_chars_in_values: *self.reject_control_chars_in_values,
+        }
+    }
+
+    pub(super) fn resolve_addrs(&self) -> Result<Vec<SockAddr>> {
+        if let Some(addr) = *self.socket_addr {
+            return Ok(vec![addr.into()]);
+        }
+        match *self.dns_ttl {
+            Some(ttl) => self.dns_cache.get_or_resolve(
+                format!("{}:{}", self.host.deref(), *self.port),
+                ttl,
+                || self.resolve_addrs_uncached(),
+            ),
+            None => self.resolve_addrs_uncached(),
+        }
+    }
+
+    fn resolve_addrs_uncached(&self) -> Result<Vec<SockAddr>> {
+        let resolver = self.resolver.as_ref().map(|resolver| resolver.0.clone());
+        let Some(timeout) = *self.resolve_timeout else {
+            return resolve_host_port(resolver.as_deref(), &self.host, &self.port);
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let host = self.host.deref().clone();
+        let port = self.port.deref().clone();
+        let started = Instant::now();
+        std::thread::Builder::new()
+            .name("questdb-resolve".to_owned())
+            .spawn(move || {
+                // The receiver is gone if we timed out: Ignore the error.
+                let _ = tx.send(resolve_host_port(resolver.as_deref(), &host, &port));
+            })
+            .map_err(|io_err| {
+                error::fmt!(
+                    CouldNotResolveAddr,
+                    "Could not spawn resolver thread: {}",
+                    io_err
+                )
+            })?;
+        rx.recv_timeout(timeout).unwrap_or_else(|_| {
+            Err(error::fmt!(
+                CouldNotResolveAddr,
+                "Could not resolve \"{}:{}\": Timed out after {:?}.",
+                self.host.deref(),
+                self.port.deref(),
+                started.elapsed()
+            ))
+        })
+    }
+
+    pub(super) fn connect_addrs(&self, timings: &mut ConnectTimings) -> Result<Socket> {
+        // Try each resolved address in turn, reporting the last error if none connect.
+        let mut last_err = error::fmt!(
+            CouldNotResolveAddr,
+            "Could not resolve \"{}:{}\": No addresses found.",
+            self.host.deref(),
+            *self.port
+        );
+        let started = Instant::now();
+        let addrs = self.resolve_addrs()?;
+        timings.resolve = started.elapsed();
+        let started = Instant::now();
+        ingress_log!(
+            debug,
+            "Resolved {}:{} to {:?}",
+            self.host.deref(),
+            *self.port,
+            addrs
+                .iter()
+                .filter_map(SockAddr::as_socket)
+                .collect::<Vec<_>>()
+        );
+        for addr in addrs.iter() {
+            match self.connect_socket(addr) {
+                Ok(sock) => {
+                    timings.connect = started.elapsed();
+                    ingress_log!(debug, "Connected to {:?}", addr.as_socket());
+                    return Ok(sock);
+                }
+                Err(err) => {
+                    ingress_log!(
+                        debug,
+                        "Could not connect to {:?}: {}",
+                        addr.as_socket(),
+                        err
+                    );
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    fn connect_socket(&self, addr: &SockAddr) -> Result<Socket> {
+        let sock = self.open_socket(addr)?;
+        sock.connect(addr)
+            .map_err(|io_err| self.map_connect_err(io_err))?;
+        Ok(sock)
+    }
+
+    /// Open a socket to connect to the address, with the socket options and
+    /// local address applied.
+    pub(super) fn open_socket(&self, addr: &SockAddr) -> Result<Socket> {
+        let sock = Socket::new(addr.domain(), Type::STREAM, Some(SockProtocol::TCP))
+            .map_err(|io_err| map_io_to_socket_err("Could not open TCP socket: ", io_err))?;
+
+        // See: https://idea.popcount.org/2014-04-03-bind-before-connect/
+        // We set `SO_REUSEADDR` on the outbound socket to avoid issues where a client may exhaust
+        // their interface's ports. See: https://github.com/questdb/py-questdb-client/issues/21
+        sock.set_reuse_address(true)
+            .map_err(|io_err| map_io_to_socket_err("Could not set SO_REUSEADDR: ", io_err))?;
+
+        sock.set_linger(Some(Duration::from_secs(120)))
+            .map_err(|io_err| map_io_to_socket_err("Could not set socket linger: ", io_err))?;
+        sock.set_keepalive(true)
+            .map_err(|io_err| map_io_to_socket_err("Could not set SO_KEEPALIVE: ", io_err))?;
+        self.set_keepalive_params(&sock)?;
+        sock.set_nodelay(*self.nodelay)
+            .map_err(|io_err| map_io_to_socket_err("Could not set TCP_NODELAY: ", io_err))?;
+        self.set_buffer_sizes(&sock)?;
+
+        if let Some(tos) = *self.ip_tos {
+            set_ip_tos(&sock, addr, tos)?;
+        }
+        match (self.net_interface.deref(), self.net_port.deref()) {
+            (None, None) => {}
+            (Some(host), None) => {
+                let bind_addr = gai::resolve_host(host.as_str(), addr.family().into())?;
+                sock.bind(&bind_addr).map_err(|io_err| {
+                    map_io_to_socket_err(
+                        &format!("Could not bind to interface address {:?}: ", host),
+                        io_err,
+                    )
+                })?;
+            }
+            (host, Some(port)) => {
+                let (bind_addr, descr) = match host {
+                    Some(host) => (
+                        gai::resolve_host_port_family(
+                            host,
+                            &port.to_string(),
+                            addr.family().into(),
+                        )?
+                        .remove(0),
+                        format!("{}:{}", host, port),
+                    ),
+                    None => {
+                        // The unspecified address of the peer's family.
+                        let ip = if addr.is_ipv6() {
+                            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                        } else {
+                            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+                        };
+                        let bind_addr = std::net::SocketAddr::new(ip, *port);
+                        (bind_addr.into(), bind_addr.to_string())
+                    }
+                };
+                sock.bind(&bind_addr).map_err(|io_err| {
+                    map_io_to_socket_err(
+                        &format!("Could not bind to local address {:?}: ", descr),
+                        io_err,
+                    )
+                })?;
+            }
+        }
+        Ok(sock)
+    }
+
+    pub(super) fn map_connect_err(&self, io_err: io::Error) -> Error {
+        let host_port = format!("{}:{}", self.host.deref(), *self.port);
+        let prefix = format!("Could not connect to {:?}: ", host_port);
+        map_io_to_socket_err(&prefix, io_err)
+    }
+
+    fn set_keepalive_params(&self, sock: &Socket) -> Result<()> {
+        let mut keepalive = TcpKeepalive::new();
+        let mut customized = false;
+        if let Some(idle) = *self.keepalive_idle {
+            keepalive = keepalive.with_time(idle);
+            customized = true;
+        }
+
+        // The setters reject these settings on platforms that don't support them.
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "windows"
+        ))]
+        if let Some(interval) = *self.keepalive_interval {
+            keepalive = keepalive.with_interval(interval);
+            customized = true;
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd"
+        ))]
+        if let Some(retries) = *self.keepalive_retries {
+            keepalive = keepalive.with_retries(retries);
+            customized = true;
+        }
+
+        if customized {
+            sock.set_tcp_keepalive(&keepalive).map_err(|io_err| {
+                map_io_to_socket_err("Could not set TCP keepalive parameters: ", io_err)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn set_buffer_sizes(&self, sock: &Socket) -> Result<()> {
+        if let Some(size) = *self.send_buffer_size {
+            sock.set_send_buffer_size(size)
+                .map_err(|io_err| map_io_to_socket_err("Could not set SO_SNDBUF: ", io_err))?;
+        }
+        if let Some(size) = *self.recv_buffer_size {
+            sock.set_recv_buffer_size(size)
+                .map_err(|io_err| map_io_to_socket_err("Could not set SO_RCVBUF: ", io_err))?;
+        }
+        Ok(())
+    }
+
+    fn connect_unix(&self, path: &Path) -> Result<Socket> {
+        if self.net_interface.is_some() || self.net_port.is_some() {
+            return Err(error::fmt!(
+                ConfigError,
+                "Cannot bind to a local address when connecting over a Unix domain socket."
+            ));
+        }
+        for (setting_name, is_set) in [
+            (
+                "nodelay",
+                matches!(self.nodelay, ConfigSetting::Specified(_)),
+            ),
+            ("keepalive_idle", self.keepalive_idle.is_some()),
+            ("keepalive_interval", self.keepalive_interval.is_some()),
+            ("keepalive_retries", self.keepalive_retries.is_some()),
+            ("ip_tos", self.ip_tos.is_some()),
+        ] {
+            if is_set {
+                return Err(error::fmt!(
+                    ConfigError,
+                    "The {setting_name:?} setting can't be used when connecting over a Unix domain socket."
+                ));
+            }
+        }
+        let addr = SockAddr::unix(path).map_err(|io_err| {
+            error::fmt!(
+                ConfigError,
+                "Bad Unix domain socket path {:?}: {}",
+                path,
+                io_err
+            )
+        })?;
+        let sock = Socket::new(Domain::UNIX, Type::STREAM, None).map_err(|io_err| {
+            map_io_to_socket_err("Could not open Unix domain socket: ", io_err)
+        })?;
+        self.set_buffer_sizes(&sock)?;
+        sock.connect(&addr).map_err(|io_err| {
+            map_io_to_socket_err(&format!("Could not connect to {:?}: ", path), io_err)
+        })?;
+        Ok(sock)
+    }
+
+    fn connect_tcp(
+        &self,
+        auth: &Option<AuthParams>,
+        timings: &mut ConnectTimings,
+    ) -> Result<ProtocolHandler> {
+        let mut sock = match self.unix_socket.deref() {
+            Some(path) => {
+                let started = Instant::now();
+                let sock = self.connect_unix(path)?;
+                timings.connect = started.elapsed();
+                sock
+            }
+            None => self.connect_addrs(timings)?,
+        };
+
+        // We read during both TLS handshake and authentication.
+        // We set up a read timeout to prevent the client from "hanging"
+        // should we be connecting to a server configured in a different way
+        // from the client.
+        sock.set_read_timeout(Some(*self.auth_timeout))
+            .map_err(|io_err| {
+                map_io_to_socket_err("Failed to set read timeout on socket: ", io_err)
+            })?;
+
+        #[cfg(feature = "insecure-skip-verify")]
+        let tls_verify = *self.tls_verify;
+
+        #[cfg(not(feature = "insecure-skip-verify"))]
+        let tls_verify = true;
+
+        let mut conn = match configure_tls(
+            self.protocol.tls_enabled(),
+            tls_verify,
+            *self.tls_ca,
+            self.tls_roots.deref(),
+        )? {
+            Some(tls_config) => {
+                let started = Instant::now();
+                let server_name = self.tls_server_name()?;
+                let mut tls_conn =
+                    ClientConnection::new(tls_config, server_name).map_err(|rustls_err| {
+                        error::fmt!(TlsError, "Could not create TLS client: {}", rustls_err)
+                    })?;
+                while tls_conn.wants_write() || tls_conn.is_handshaking() {
+                    tls_conn.complete_io(&mut sock).map_err(|io_err| {
+                        if (io_err.kind() == ErrorKind::TimedOut)
+                            || (io_err.kind() == ErrorKind::WouldBlock)
+                        {
+                            error::fmt!(
+                                TlsError,
+                                concat!(
+                                    "Failed to complete TLS handshake:",
+                                    " Timed out waiting for server ",
+                                    "response after {:?}."
+                                ),
+                                *self.auth_timeout
+                            )
+                            // A read timeout shows up as `WouldBlock` on Unix.
+                            .with_io_source(std::io::Error::new(ErrorKind::TimedOut, io_err))
+                        } else {
+                            error::fmt!(TlsError, "Failed to complete TLS handshake: {}", io_err)
+                                .with_io_source(io_err)
+                        }
+                    })?;
+                }
+                timings.tls_handshake = started.elapsed();
+                Connection::Tls(StreamOwned::new(tls_conn, sock).into())
+            }
+            None => Connection::Direct(sock),
+        };
+
+        if let Some(AuthParams::Ecdsa(auth)) = auth {
+            let started = Instant::now();
+            conn.authenticate(auth)?;
+            timings.auth = started.elapsed();
+        }
+
+        Ok(ProtocolHandler::Socket(conn))
+    }
+
+    pub(super) fn tls_server_name(&self) -> Result<ServerName<'static>> {
+        Ok(ServerName::try_from(self.host.as_str())
+            .map_err(|inv_dns_err| error::fmt!(TlsError, "Bad host: {}", inv_dns_err))?
+            .to_owned())
+    }
+
+    pub(super) fn build_auth(&self) -> Result<Option<AuthParams>> {
+        match (
+            self.protocol,
+            self.username.deref(),
+            self.password.deref(),
+            self.token.deref(),
+            self.token_x.deref(),
+            self.token_y.deref(),
+        ) {
+            (_, None, None, None, None, None) => Ok(None),
+            (
+                protocol,
+                Some(username),
+                None,
+                Some(token),
+                Some(token_x),
+                Some(token_y),
+            ) if protocol.is_tcpx() => Ok(Some(AuthParams::Ecdsa(EcdsaAuthParams {
+                key_id: username.to_string(),
+                priv_key: token.to_string(),
+                pub_key_x: token_x.to_string(),
+                pub_key_y: token_y.to_string(),
+            }))),
+            (protocol, Some(_username), Some(_password), None, None, None)
+                if protocol.is_tcpx() => {
+                Err(error::fmt!(ConfigError,
+                    r##"The "basic_auth" setting can only be used with the ILP/HTTP protocol."##,
+                ))
+            }
+            (protocol, None, None, Some(_token), None, None)
+                if protocol.is_tcpx() => {
+                Err(error::fmt!(ConfigError, "Token authentication only be used with the ILP/HTTP protocol."))
+            }
+            (protocol, _username, None, _token, _token_x, _token_y)
+                if protocol.is_tcpx() => {
+                Err(error::fmt!(ConfigError,
+                    r##"Incomplete ECDSA authentication parameters. Specify either all or none of: "username", "token", "token_x", "token_y"."##,
+                ))
+            }
+            #[cfg(feature = "ilp-over-http")]
+            (protocol, Some(username), Some(password), None, None, None)
+                if protocol.is_httpx() => {
+                Ok(Some(AuthParams::Basic(BasicAuthParams {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                })))
+            }
+            #[cfg(feature = "ilp-over-http")]
+            (protocol, Some(_username), None, None, None, None)
+                if protocol.is_httpx() => {
+                Err(error::fmt!(ConfigError,
+                    r##"Basic authentication parameter "username" is present, but "password" is missing."##,
+                ))
+            }
+            #[cfg(feature = "ilp-over-http")]
+            (protocol, None, Some(_password), None, None, None)
+                if protocol.is_httpx() => {
+                Err(error::fmt!(ConfigError,
+                    r##"Basic authentication parameter "password" is present, but "username" is missing."##,
+                ))
+            }
+            #[cfg(feature = "ilp-over-http")]
+            (protocol, None, None, Some(token), None, None)
+                if protocol.is_httpx() => {
+                Ok(Some(AuthParams::Token(TokenAuthParams {
+                    token: token.to_string(),
+                })))
+            }
+            #[cfg(feature = "ilp-over-http")]
+            (
+                protocol,
+                Some(_username),
+                None,
+                Some(_token),
+                Some(_token_x),
+                Some(_token_y),
+            ) if protocol.is_httpx() => {
+                Err(error::fmt!(ConfigError, "ECDSA authentication is only available with ILP/TCP and not available with ILP/HTTP."))
+            }
+            #[cfg(feature = "ilp-over-http")]
+            (protocol, _username, _password, _token, None, None)
+                if protocol.is_httpx() => {
+                Err(error::fmt!(ConfigError,
+                    r##"Inconsistent HTTP authentication parameters. Specify either "username" and "password", or just "token"."##,
+                ))
+            }
+            _ => {
+                Err(error::fmt!(ConfigError,
+                    r##"Incomplete authentication parameters. Check "username", "password", "token", "token_x" and "token_y" parameters are set correctly."##,
+                ))
+            }
+        }
+    }
+
+    /// Build the sender.
+    ///
+    /// In the case of TCP, this synchronously establishes the TCP connection, and
+    /// returns once the connection is fully established. If the connection
+    /// requires authentication or TLS, these will also be completed before
+    /// returning.
+    ///
+    /// With failover addresses, see [`addr`](SenderBuilder::addr), this connects
+    /// to the first address that's reachable. Should none be, the error lists
+    /// each address's failure.
+    pub fn build(&self) -> Result<Sender> {
+        self.build_from(0)
+    }
+
+    /// All the addresses to connect to, in order of preference.
+    fn services(&self) -> Vec<Service> {
+        let mut services = vec![Service::new(self.host.as_str(), self.port.as_str())];
+        services.extend(self.failover_addrs.iter().cloned());
+        services
+    }
+
+    /// Connect to the first reachable address, starting at the given index
+    /// and wrapping around.
+    fn build_from(&self, start: usize) -> Result<Sender> {
+        let services = self.services();
+        if services.len() == 1 {
+            return self.build_at(0);
+        }
+
+        let mut failures = Vec::with_capacity(services.len());
+        let mut last_code = ErrorCode::SocketError;
+        for offset in 0..services.len() {
+            let index = (start + offset) % services.len();
+            match self.build_at(index) {
+                Ok(sender) => return Ok(sender),
+                Err(err) => {
+                    let service = &services[index];
+                    failures.push(format!(
+                        "{:?}: {}",
+                        format!("{}:{}", service.host(), service.port()),
+                        err.msg()
+                    ));
+                    last_code = err.code();
+                }
+            }
+        }
+        Err(Error::new(
+            last_code,
+            format!(
+                "Could not connect to any of the {} addresses: {}",
+                services.len(),
+                failures.join("; ")
+            ),
+        ))
+    }
+
+    /// Connect to the address at the given index of [`services`](Self::services).
+    fn build_at(&self, index: usize) -> Result<Sender> {
+        let mut sender = if index == 0 {
+            self.build_single()?
+        } else {
+            let service = &self.failover_addrs[index - 1];
+            let mut builder = self.clone();
+            builder.host = ConfigSetting::new_specified(service.host().to_owned());
+            builder.port = ConfigSetting::new_specified(service.port().to_owned());
+            builder.socket_addr = ConfigSetting::new_default(None);
+            builder.build_single()?
+        };
+        sender.builder = Some(Box::new(self.clone()));
+        sender.host_index = index;
+        Ok(sender)
+    }
+
+    fn build_single(&self) -> Result<Sender> {
+        let span = ingress_span!(
+            "connect",
+            host = %self.host.deref(),
+            port = %self.port.deref(),
+            protocol = self.protocol.schema()
+        );
+        in_span(span, || self.connect())
+    }
+
+    fn connect(&self) -> Result<Sender> {
+        let started = Instant::now();
+        let mut descr = match self.unix_socket.deref() {
+            Some(path) => format!("Sender[unix_socket={:?},", path),
+            None => format!("Sender[host={:?},port={:?},", self.host, self.port),
+        };
+
+        if self.protocol.tls_enabled() {
+            write!(descr, "tls=enabled,").unwrap();
+        } else {
+            write!(descr, "tls=disabled,").unwrap();
+        }
+
+        let auth = self.build_auth()?;
+
+        let mut connect_timings = None;
+        let handler = match self.protocol {
+            Protocol::Tcp | Protocol::Tcps => {
+                let timings = connect_timings.insert(ConnectTimings::default());
+                self.connect_tcp(&auth, timings)?
+            }
+            #[cfg(feature = "ilp-over-http")]
+            Protocol::Http | Protocol::Https => {
+                if self.net_interface.is_some() {
+                    // See: https://github.com/algesten/ureq/issues/692
+                    return Err(error::fmt!(
+                        InvalidApiCall,
+                        "net_interface is not supported for ILP over HTTP."
+                    ));
+                }
+
+                let user_agent = self.http.as_ref().unwrap().user_agent.as_str();
+                let agent_builder = ureq::AgentBuilder::new()
+                    .user_agent(user_agent)
+                    .no_delay(true);
+
+                #[cfg(feature = "insecure-skip-verify")]
+                let tls_verify = *self.tls_verify;
+
+                #[cfg(not(feature = "insecure-skip-verify"))]
+                let tls_verify = true;
+
+                let agent_builder = match configure_tls(
+                    self.protocol.tls_enabled(),
+                    tls_verify,
+                    *self.tls_ca,
+                    self.tls_roots.deref(),
+                )? {
+                    Some(tls_config) => agent_builder.tls_config(tls_config),
+                    None => agent_builder,
+                };
+                let auth = match auth {
+                    Some(AuthParams::Basic(ref auth)) => Some(auth.to_header_string()),
+                    Some(AuthParams::Token(ref auth)) => Some(auth.to_header_string()?),
+                    Some(AuthParams::Ecdsa(_)) => {
+                        return Err(error::fmt!(
+                            AuthError,
+                            "ECDSA authentication is not supported for ILP over HTTP. \
+                            Please use basic or token authentication instead."
+                        ));
+                    }
+                    None => None,
+                };
+                let agent = agent_builder.build();
+                let proto = self.protocol.schema();
+                let host = self.host.deref();
+                let url = if host.contains(':') {
+                    // An IPv6 address, which must be bracketed in a URL.
+                    format!("{}://[{}]:{}/write", proto, host, self.port.deref())
+                } else {
+                    format!("{}://{}:{}/write", proto, host, self.port.deref())
+                };
+                ProtocolHandler::Http(HttpHandlerState {
+                    agent,
+                    url,
+                    auth,
+
+                    config: self.http.as_ref().unwrap().clone(),
+                })
+            }
+        };
+
+        if auth.is_some() {
+            descr.push_str("auth=on]");
+        } else {
+            descr.push_str("auth=off]");
+        }
+
+        let mut sender = Sender {
+            descr,
+            handler,
+            connected: true,
+            buffer_template: self.buffer_template(),
+            builder: None,
+            host_index: 0,
+            host_failures: 0,
+            check_connection_on_flush: *self.check_connection_on_flush,
+            write_chunk_size: *self.write_chunk_size,
+            observer: self.observer.as_ref().map(|observer| observer.0.clone()),
+            #[cfg(feature = "metrics")]
+            metrics: FlushMetrics::new(self.metrics_label.as_deref()),
+            auto_flush_rows: self
+                .auto_flush_rows
+                .filter(|_| *self.auto_flush == AutoFlush::On),
+            auto_flush_bytes: self
+                .auto_flush_bytes
+                .filter(|_| *self.auto_flush == AutoFlush::On),
+            auto_flush_interval: self
+                .auto_flush_interval
+                .filter(|_| *self.auto_flush == AutoFlush::On),
+            buffer_shrink_threshold: *self.buffer_shrink_threshold,
+            overflow_policy: *self.overflow_policy,
+            spill: self
+                .spill_dir
+                .as_ref()
+                .map(|dir| SpillWriter::open(dir.clone(), *self.spill_max_bytes))
+                .transpose()?,
+            failed_flush_sent: 0,
+            stats: SenderStats::default(),
+            connect_timings,
+        };
+        if let Some(timings) = &mut sender.connect_timings {
+            timings.total = started.elapsed();
+        }
+        ingress_log!(
+            debug,
+            "Created {}, protocol_version={}",
+            sender.descr,
+            sender.buffer_template.protocol_version as u8
+        );
+
+        Ok(sender)
+    }
+
+    fn ensure_is_tcpx(&mut self, param_name: &str) -> Result<()> {
+        if self.protocol.is_tcpx() {
+            Ok(())
+        } else {
+            Err(error::fmt!(
+                ConfigError,
+                "The {param_name:?} setting can only be used with the TCP protocol."
+            ))
+        }
+    }
+}
+
+fn resolve_host_port(
+    resolver: Option<&dyn Resolver>,
+    host: &str,
+    port: &str,
+) -> Result<Vec<SockAddr>> {
+    match resolver {
+        Some(resolver) => {
+            let port: u16 = port.parse().map_err(|_| {
+                error::fmt!(
+                    ConfigError,
+                    "A custom resolver requires a numeric port, not {:?}.",
+                    port
+                )
+            })?;
+            let addrs = resolver.resolve(host, port)?;
+            Ok(addrs.into_iter().map(SockAddr::from).collect())
+        }
+        None => gai::resolve_host_port(host, port),
+    }
+}
+
+/// Tell whether the buffer crossed any of the given auto-flush thresholds.
+pub(crate) fn auto_flush_due(
+    buf: &Buffer,
+    rows: Option<usize>,
+    bytes: Option<usize>,
+    interval: Option<Duration>,
+) -> bool {
+    let rows_reached = rows.is_some_and(|rows| buf.row_count() >= rows);
+    let bytes_reached = bytes.is_some_and(|bytes| buf.len() >= bytes);
+    let interval_reached = interval
+        .zip(buf.state.first_row_at)
+        .is_some_and(|(interval, first_row_at)| first_row_at.elapsed() >= interval);
+    rows_reached || bytes_reached || interval_reached
+}
+
+/// Set the `IP_TOS` byte, or the `IPV6_TCLASS` one for an IPv6 peer.
+fn set_ip_tos(sock: &Socket, addr: &SockAddr, tos: u8) -> Result<()> {
+    if addr.is_ipv6() {
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        return sock
+            .set_tclass_v6(tos as u32)
+            .map_err(|io_err| map_io_to_socket_err("Could not set IPV6_TCLASS: ", io_err));
+
+        #[cfg(not(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        )))]
+        return Err(error::fmt!(
+            ConfigError,
+            "The \"ip_tos\" setting is not supported for IPv6 connections on this platform."
+        ));
+    }
+
+    // `ip_tos` rejects the platforms without `IP_TOS`.
+    #[cfg(not(any(
+        target_os = "fuchsia",
+        target_os = "redox",
+        target_os = "solaris",
+        target_os = "illumos"
+    )))]
+    sock.set_tos(tos as u32)
+        .map_err(|io_err| map_io_to_socket_err("Could not set IP_TOS: ", io_err))?;
+    Ok(())
+}
+
+/// When parsing from config, we exclude certain characters.
+/// Here we repeat the same validation logic for consistency.
+fn validate_value<T: AsRef<str>>(value: T) -> Result<T> {
+    let str_ref = value.as_ref();
+    for (p, c) in str_ref.chars().enumerate() {
+        if matches!(c, '\u{0}'..='\u{1f}' | '\u{7f}'..='\u{9f}') {
+            return Err(error::fmt!(
+                ConfigError,
+                "Invalid character {c:?} at position {p}"
+            ));
+        }
+    }
+    Ok(value)
+}
+
+fn parse_conf_value<T>(param_name: &str, str_value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Debug,
+{
+    str_value.parse().map_err(|e| {
+        error::fmt!(
+            ConfigError,
+            "Could not parse {param_name:?} to number: {e:?}"
+        )
+    })
+}
+
+fn b64_decode(descr: &'static str, buf: &str) -> Result<Vec<u8>> {
+    Base64UrlUnpadded::decode_vec(buf).map_err(|b64_err| {
+        error::fmt!(
+            AuthError,
+            "Misconfigured ILP authentication keys. Could not decode {}: {}. \
+            Hint: Check the keys for a possible typo.",
+            descr,
+            b64_err
+        )
+    })
+}
+
+fn parse_public_key(pub_key_x: &str, pub_key_y: &str) -> Result<Vec<u8>> {
+    let mut pub_key_x = b64_decode("public key x", pub_key_x)?;
+    let mut pub_key_y = b64_decode("public key y", pub_key_y)?;
+
+    // SEC 1 Uncompressed Octet-String-to-Elliptic-Curve-Point Encoding
+    let mut encoded = Vec::new();
+    encoded.push(4u8); // 0x04 magic byte that identifies this as uncompressed.
+    let pub_key_x_ken = pub_key_x.len();
+    if pub_key_x_ken > 32 {
+        return Err(error::fmt!(
+            AuthError,
+            "Misconfigured ILP authentication keys. Public key x is too long. \
+            Hint: Check the keys for a possible typo."
+        ));
+    }
+    let pub_key_y_len = pub_key_y.len();
+    if pub_key_y_len > 32 {
+        return Err(error::fmt!(
+            AuthError,
+            "Misconfigured ILP authentication keys. Public key y is too long. \
+            Hint: Check the keys for a possible typo."
+        ));
+    }
+    encoded.resize((32 - pub_key_x_ken) + 1, 0u8);
+    encoded.append(&mut pub_key_x);
+    encoded.resize((32 - pub_key_y_len) + 1 + 32, 0u8);
+    encoded.append(&mut pub_key_y);
+    Ok(encoded)
+}
+
+fn parse_key_pair(auth: &EcdsaAuthParams) -> Result<EcdsaKeyPair> {
+    let private_key = b64_decode("private authentication key", auth.priv_key.as_str())?;
+    let public_key = parse_public_key(auth.pub_key_x.as_str(), auth.pub_key_y.as_str())?;
+    let system_random = SystemRandom::new();
+    EcdsaKeyPair::from_private_key_and_public_key(
+        &ECDSA_P256_SHA256_FIXED_SIGNING,
+        &private_key[..],
+        &public_key[..],
+        &system_random,
+    )
+    .map_err(|key_rejected| {
+        error::fmt!(
+            AuthError,
+            "Misconfigured ILP authentication keys: {}. Hint: Check the keys for a possible typo.",
+            key_rejected
+        )
+    })
+}
+
+impl Sender {
+    /// Create a new `Sender` instance from the given configuration string.
+    ///
+    /// The format of the string is: `"http::addr=host:port;key=value;...;"`.
+    ///
+    /// Instead of `"http"`, you can also specify `"https"`, `"tcp"`, and `"tcps"`.
+    ///
+    /// We recommend HTTP for most cases because it provides more features, like
+    /// reporting errors to the client and supporting transaction control. TCP can
+    /// sometimes be faster in higher-latency networks, but misses a number of
+    /// features.
+    ///
+    /// Keys in the config string correspond to same-named methods on `SenderBuilder`.
+    ///
+    /// For the full list of keys and values, see the docs on [`SenderBuilder`].
+    ///
+    /// You can also load the configuration from an environment variable.
+    /// See [`Sender::from_env`].
+    ///
+    /// In the case of TCP, this synchronously establishes the TCP connection, and
+    /// returns once the connection is fully established. If the connection
+    /// requires authentication or TLS, these will also be completed before
+    /// returning.
+    pub fn from_conf<T: AsRef<str>>(conf: T) -> Result<Self> {
+        SenderBuilder::from_conf(conf)?.build()
+    }
+
+    /// Create a new `Sender` from the configuration stored in the `QDB_CLIENT_CONF`
+    /// environment variable. The format is the same as that accepted by
+    /// [`Sender::from_conf`].
+    ///
+    /// In the case of TCP, this synchronously establishes the TCP connection, and
+    /// returns once the connection is fully established. If the connection
+    /// requires authentication or TLS, these will also be completed before
+    /// returning.
+    pub fn from_env() -> Result<Self> {
+        SenderBuilder::from_env()?.build()
+    }
+
+    /// Create a `Sender` that writes ILP messages to the given sink,
+    /// bypassing address resolution and connection setup.
+    ///
+    /// The sender behaves as an ILP-over-TCP sender: [`flush`](Sender::flush)
+    /// writes the buffer to the sink and, should writing fail, the sender
+    /// must be closed.
+    ///
+    /// This is useful to test code that uses a `Sender` without a QuestDB
+    /// instance, see [`testing::InMemorySink`], or to send data over a custom
+    /// transport, such as a file or a compressing writer. The sink is flushed
+    /// after each write, and dropped along with the sender.
+    ///
+    /// ```
+    /// # use questdb::Result;
+    /// use questdb::ingress::{Buffer, Sender};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut sender = Sender::from_sink(Vec::new());
+    /// let mut buffer = Buffer::new();
+    /// buffer.table("x")?.symbol("a", "b")?.at_now()?;
+    /// sender.flush(&mut buffer)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_sink<S: IlpSink + 'static>(sink: S) -> Self {
+        Sender {
+            descr: "Sender[sink]".to_owned(),
+            handler: ProtocolHandler::Socket(Connection::Sink(Box::new(sink))),
+            connected: true,
+            buffer_template: BufferTemplate::default(),
+            builder: None,
+            host_index: 0,
+            host_failures: 0,
+            check_connection_on_flush: false,
+            write_chunk_size: DEFAULT_WRITE_CHUNK_SIZE,
+            observer: None,
+            #[cfg(feature = "metrics")]
+            metrics: FlushMetrics::new(None),
+            auto_flush_rows: None,
+            auto_flush_bytes: None,
+            auto_flush_interval: None,
+            buffer_shrink_threshold: None,
+            overflow_policy: OverflowPolicy::Error,
+            spill: None,
+            failed_flush_sent: 0,
+            stats: SenderStats::default(),
+            connect_timings: None,
+        }
+    }
+
+    /// Create a `Sender` over an already-established TCP connection, bypassing
+    /// address resolution and connection setup.
+    ///
+    /// This is useful when the connection is handed over by a connection pool or
+    /// set up through a tunnel. The stream must already be authenticated, if the
+    /// server requires it.
+    ///
+    /// Unlike [`Sender::from_sink`], the sender retains access to the underlying
+    /// socket.
+    ///
+    /// ```no_run
+    /// # use questdb::Result;
+    /// use questdb::ingress::Sender;
+    /// use std::net::TcpStream;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let stream = TcpStream::connect("localhost:9009").unwrap();
+    /// let mut sender = Sender::from_stream(stream);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_stream(stream: std::net::TcpStream) -> Self {
+        let descr = match stream.peer_addr() {
+            Ok(addr) => format!("Sender[stream,peer={}]", addr),
+            Err(_) => "Sender[stream]".to_owned(),
+        };
+        Sender {
+            descr,
+            handler: ProtocolHandler::Socket(Connection::Direct(Socket::from(stream))),
+            connected: true,
+            buffer_template: BufferTemplate::default(),
+            builder: None,
+            host_index: 0,
+            host_failures: 0,
+            check_connection_on_flush: false,
+            write_chunk_size: DEFAULT_WRITE_CHUNK_SIZE,
+            observer: None,
+            #[cfg(feature = "metrics")]
+            metrics: FlushMetrics::new(None),
+            auto_flush_rows: None,
+            auto_flush_bytes: None,
+            auto_flush_interval: None,
+            buffer_shrink_threshold: None,
+            overflow_policy: OverflowPolicy::Error,
+            spill: None,
+            failed_flush_sent: 0,
+            stats: SenderStats::default(),
+            connect_timings: None,
+        }
+    }
+
+    fn flush_impl(&mut self, buf: &Buffer, transactional: bool) -> Result<()> {
+        // Flushing an empty buffer is a no-op: It doesn't touch the socket,
+        // issue a request or change the sender's state.
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.failed_flush_sent = 0;
+
+        notify(&self.observer, |observer| {
+            observer.on_flush_start(buf.row_count(), buf.len())
+        });
+        let start = Instant::now();
+        let span = ingress_span!(
+            "flush",
+            bytes = buf.len(),
+            rows = buf.row_count(),
+            transactional
+        );
+        let result = in_span(span, || {
+            self.replay_spill()
+                .and_then(|()| self.send(buf, transactional))
+        });
+        let duration = start.elapsed();
+        if result.is_ok() {
+            self.stats.rows_sent += buf.row_count() as u64;
+            self.stats.bytes_sent += buf.len() as u64;
+            self.stats.flushes += 1;
+            self.stats.last_flush_at = Some(Instant::now());
+            #[cfg(feature = "metrics")]
+            self.metrics.on_flush(buf.row_count(), buf.len(), duration);
+        } else {
+            self.stats.failed_flushes += 1;
+            #[cfg(feature = "metrics")]
+            self.metrics.on_error();
+        }
+        match result {
+            Ok(()) => ingress_log!(
+                trace,
+                "Flushed {} rows, {} bytes in {:?}",
+                buf.row_count(),
+                buf.len(),
+                duration
+            ),
+            Err(ref err) if !self.connected => ingress_log!(
+                warn,
+                "Could not flush {} rows, {} bytes, the sender must now be closed: {}",
+                buf.row_count(),
+                buf.len(),
+                err
+            ),
+            Err(ref err) => ingress_log!(
+                warn,
+                "Could not flush {} rows, {} bytes: {}",
+                buf.row_count(),
+                buf.len(),
+                err
+            ),
+        }
+        notify(&self.observer, |observer| match result {
+            Ok(()) => observer.on_flush(buf.row_count(), buf.len(), duration),
+            Err(ref err) => observer.on_error(err.code()),
+        });
+        notify(&self.observer, |observer| {
+            observer.on_flush_end(result.as_ref().map(|_| ()), duration)
+        });
+        result
+    }
+
+    fn send(&mut self, buf: &Buffer, transactional: bool) -> Result<()> {
+        if !self.connected {
+            return Err(error::fmt!(
+                SocketError,
+                "Could not flush buffer: not connected to database."
+            ));
+        }
+        self.buffer_template.check_can_flush(buf)?;
+        if self.check_connection_on_flush {
+            self.check_connection()?;
+        }
+
+        self.send_bytes(buf.as_bytes(), transactional, buf.transactional())
+    }
+
+    /// Send the bytes over the connection, without validating them.
+    fn send_bytes(&mut self, bytes: &[u8], transactional: bool, single_table: bool) -> Result<()> {
+        self.try_send_bytes(bytes, transactional, single_table)?
+    }
+
+    /// As [`send_bytes`](Sender::send_bytes), but the bytes the server
+    /// refused for good, so that sending them again would fail the same way,
+    /// are an `Ok(Err(_))`.
+    #[allow(unused_variables)]
+    fn try_send_bytes(
+        &mut self,
+        bytes: &[u8],
+        transactional: bool,
+        single_table: bool,
+    ) -> Result<Result<()>> {
+        match self.handler {
+            ProtocolHandler::Socket(ref mut conn) => {
+                if transactional {
+                    return Err(error::fmt!(
+                        InvalidApiCall,
+                        "Transactional flushes are not supported for ILP over TCP."
+                    ));
+                }
+                write_all_counted(conn, bytes, self.write_chunk_size).map_err(|(sent, io_err)| {
+                    self.connected = false;
+                    self.failed_flush_sent = sent;
+                    if sent == 0 {
+                        map_io_to_socket_err("Could not flush buffer: ", io_err)
+                    } else {
+                        error::fmt!(
+                            SocketError,
+                            "Could not flush buffer: {} [{} of {} bytes sent, retrying may duplicate rows]",
+                            io_err,
+                            sent,
+                            bytes.len()
+                        )
+                        .with_io_source(io_err)
+                    }
+                })?;
+                // Sinks such as a `BufWriter` or a compressing writer hold on
+                // to the bytes until flushed.
+                if let Connection::Sink(sink) = conn {
+                    sink.flush().map_err(|io_err| {
+                        self.connected = false;
+                        map_io_to_socket_err("Could not flush buffer: ", io_err)
+                    })?;
+                }
+            }
+            #[cfg(feature = "ilp-over-http")]
+            ProtocolHandler::Http(ref state) => {
+                if transactional && !single_table {
+                    return Err(error::fmt!(
+                        InvalidApiCall,
+                        "Buffer contains lines for multiple tables. \
+                        Transactional flushes are only supported for buffers containing lines for a single table."
+                    ));
+                }
+                let request_min_throughput = *state.config.request_min_throughput;
+                let extra_time = if request_min_throughput > 0 {
+                    (bytes.len() as f64) / (request_min_throughput as f64)
+                } else {
+                    0.0f64
+                };
+                let timeout = *state.config.request_timeout + Duration::from_secs_f64(extra_time);
+                let request = state
+                    .agent
+                    .post(&state.url)
+                    .query_pairs([("precision", "n")])
+                    .timeout(timeout)
+                    .set("Content-Type", "text/plain; charset=utf-8");
+                let request = match state.auth.as_ref() {
+                    Some(auth) => request.set("Authorization", auth),
+                    None => request,
+                };
+                let response_or_err =
+                    http_send_with_retries(request, bytes, *state.config.retry_timeout);
+                match response_or_err {
+                    Ok(_response) => {
+                        // on success, there's no information in the response.
+                    }
+                    Err(ureq::Error::Status(http_status_code, response)) => {
+                        let err = parse_http_error(http_status_code, response);
+                        // Sending the same bytes again would fail the same way.
+                        if is_permanent_rejection_status(http_status_code)
+                            && err.code() == ErrorCode::ServerFlushError
+                        {
+                            dead_letter::reject(&state.config.dead_letter, bytes, &err);
+                            return Ok(Err(err));
+                        }
+                        return Err(err);
+                    }
+                    Err(ureq::Error::Transport(transport)) => {
+                        return Err(error::fmt!(
+                            SocketError,
+                            "Could not flush buffer: {}",
+                            transport
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    /// Send the batch of rows in the buffer to the QuestDB server, and, if the
+    /// `transactional` parameter is true, ensure the flush will be transactional.
+    ///
+    /// A flush is transactional iff all the rows belong to the same table. This allows
+    /// QuestDB to treat the flush as a single database transaction, because it doesn't
+    /// support transactions spanning multiple tables. Additionally, only ILP-over-HTTP
+    /// supports transactional flushes.
+    ///
+    /// If the flush wouldn't be transactional, this function returns an error and
+    /// doesn't flush any data.
+    ///
+    /// The function sends an HTTP request and waits for the response. If the server
+    /// responds with an error, it returns a descriptive error. In the case of a network
+    /// error, it retries until it has exhausted the retry time budget.
+    ///
+    /// All the data stays in the buffer. Clear the buffer before starting a new batch.
+    #[cfg(feature = "ilp-over-http")]
+    pub fn flush_and_keep_with_flags(&mut self, buf: &Buffer, transactional: bool) -> Result<()> {
+        self.flush_impl(buf, transactional)
+    }
+
+    /// Send the given buffer of rows to the QuestDB server.
+    ///
+    /// Flushing an empty buffer is a no-op.
+    ///
+    /// All the data stays in the buffer, so you can send the same batch again or
+    /// keep appending rows to it. Clear the buffer before starting a new batch.
+    ///
+    /// Like [Sender::flush], this returns an error if the buffer ends mid-row.
+    ///
+    /// To send and clear in one step, call [Sender::flush] instead.
+    pub fn flush_and_keep(&mut self, buf: &Buffer) -> Result<()> {
+        self.flush_impl(buf, false)
+    }
+
+    /// Complete the current row in the buffer with the designated timestamp,
+    /// as [`Buffer::at`] does, then flush the buffer if it crossed an
+    /// auto-flush threshold.
+    ///
+    /// The thresholds are set by [`SenderBuilder::auto_flush_rows`],
+    /// [`SenderBuilder::auto_flush_bytes`] and
+    /// [`SenderBuilder::auto_flush_interval`]. If the flush fails, this returns
+    /// its error and the buffer keeps its rows, as with [`Sender::flush`].
+    ///
+    /// If the row would grow the buffer past the sender's or the buffer's
+    /// maximum size and auto-flushing is enabled, the rows before it are
+    /// flushed first to make room. Should that flush fail, the row is
+    /// discarded and this returns its error. While disconnected, with a
+    /// [spill directory](SenderBuilder::spill_dir), the rows before it are
+    /// spilled instead, whether auto-flushing is enabled or not. If
+    /// auto-flushing is disabled, or the row alone exceeds the maximum size,
+    /// or spilling fails, the row is discarded and this returns a
+    /// [`BufferTooLarge`](ErrorCode::BufferTooLarge) error, unless the
+    /// [overflow policy](SenderBuilder::overflow_policy) drops the row
+    /// instead.
+    pub fn at<T>(&mut self, buf: &mut Buffer, timestamp: T) -> Result<()>
+    where
+        T: TryInto<Timestamp>,
+        Error: From<T::Error>,
+    {
+        let max_buf_size = buf.max_buf_size.min(self.buffer_template.max_buf_size);
+        let completed = if self.auto_flush_enabled() || self.spills_when_full() {
+            buf.at_capped(timestamp, usize::MAX)
+                .and_then(|()| self.make_room_for_last_row(buf, max_buf_size))
+        } else {
+            buf.at_capped(timestamp, max_buf_size)
+        };
+        if self.drop_on_overflow(completed)? {
+            return Ok(());
+        }
+        notify(&self.observer, |observer| {
+            observer.on_row_completed(buf.peek_last_row().len())
+        });
+        self.auto_flush(buf)
+    }
+
+    /// Complete the current row in the buffer without a designated timestamp,
+    /// as [`Buffer::at_now`] does, then flush the buffer if it crossed an
+    /// auto-flush threshold.
+    ///
+    /// See [`Sender::at`].
+    pub fn at_now(&mut self, buf: &mut Buffer) -> Result<()> {
+        let max_buf_size = buf.max_buf_size.min(self.buffer_template.max_buf_size);
+        let completed = if self.auto_flush_enabled() || self.spills_when_full() {
+            buf.at_now_capped(usize::MAX)
+                .and_then(|()| self.make_room_for_last_row(buf, max_buf_size))
+        } else {
+            buf.at_now_capped(max_buf_size)
+        };
+        if self.drop_on_overflow(completed)? {
+            return Ok(());
+        }
+        notify(&self.observer, |observer| {
+            observer.on_row_completed(buf.peek_last_row().len())
+        });
+        self.auto_flush(buf)
+    }
+
+    pub(super) fn auto_flush_enabled(&self) -> bool {
+        self.auto_flush_rows.is_some()
+            || self.auto_flush_bytes.is_some()
+            || self.auto_flush_interval.is_some()
+    }
+
+    /// Tell whether completing a row failed because it was too large and,
+    /// per the [`OverflowPolicy`], was dropped rather than reported.
+    ///
+    /// Either way, the row is no longer in the buffer.
+    fn drop_on_overflow(&mut self, completed: Result<()>) -> Result<bool> {
+        match completed {
+            Ok(()) => Ok(false),
+            Err(err)
+                if err.code() == ErrorCode::BufferTooLarge
+                    && self.overflow_policy == OverflowPolicy::DropNewest =>
+            {
+                self.stats.rows_dropped += 1;
+                notify(&self.observer, |observer| observer.on_rows_dropped(1));
+                Ok(true)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether a full buffer's rows go to the spill directory, rather than
+    /// to a flush that can only fail.
+    fn spills_when_full(&self) -> bool {
+        !self.connected && self.spill.is_some()
+    }
+
+    /// Should the just-completed row have grown the buffer past
+    /// `max_buf_size`, flush the rows before it, or spill them if
+    /// disconnected.
+    fn make_room_for_last_row(&mut self, buf: &mut Buffer, max_buf_size: usize) -> Result<()> {
+        if buf.len() <= max_buf_size {
+            return Ok(());
+        }
+        let (row, state) = buf.take_last_row();
+        if buf.is_empty() || row.len() > max_buf_size {
+            return Err(row_too_large(row.len(), buf.len(), max_buf_size));
+        }
+        if self.spills_when_full() {
+            self.spill(buf);
+            if !buf.is_empty() {
+                return Err(row_too_large(row.len(), buf.len(), max_buf_size));
+            }
+        } else {
+            self.flush(buf)?;
+        }
+        buf.restore_row(&row, state);
+        Ok(())
+    }
+
+    fn auto_flush(&mut self, buf: &mut Buffer) -> Result<()> {
+        if self.auto_flush_due(buf) {
+            self.flush(buf)?;
+        }
+        Ok(())
+    }
+
+    /// Tell whether the buffer crossed an auto-flush threshold.
+    pub(super) fn auto_flush_due(&self, buf: &Buffer) -> bool {
+        auto_flush_due(
+            buf,
+            self.auto_flush_rows,
+            self.auto_flush_bytes,
+            self.auto_flush_interval,
+        )
+    }
+
+    /// Send the given buffer of rows to the QuestDB server, clearing the buffer.
+    ///
+    /// After this function returns, the buffer is empty and ready for the next batch.
+    /// Flushing an empty buffer is a no-op.
+    /// If you want to preserve the buffer contents, call [Sender::flush_and_keep]. If
+    /// you want to ensure the flush is transactional, call
+    /// [Sender::flush_and_keep_with_flags].
+    ///
+    /// With ILP-over-HTTP, this function sends an HTTP request and waits for the
+    /// response. If the server responds with an error, it returns a descriptive error.
+    /// In the case of a network error, it retries until it has exhausted the retry time
+    /// budget.
+    ///
+    /// With ILP-over-TCP, the function blocks only until the buffer is flushed to the
+    /// underlying OS-level network socket, without waiting to actually send it to the
+    /// server. In the case of an error, the server will quietly disconnect: consult the
+    /// server logs for error messages.
+    ///
+    /// The bytes sent are exactly the buffer's contents: Each row ends with a
+    /// single newline and the flush adds or removes none, see
+    /// [concatenating chunks](Buffer#concatenating-chunks).
+    ///
+    /// If the flush fails, the buffer keeps its rows, so you can flush them again,
+    /// e.g. after a [reconnect](Sender::reconnect), unless they were written to
+    /// the [spill directory](SenderBuilder::spill_dir). A row left incomplete,
+    /// without a call to [`at`](Buffer::at) or [`at_now`](Buffer::at_now), fails
+    /// the flush and is discarded, while the complete rows before it stay. With
+    /// ILP-over-TCP, a write that fails partway through reports how many bytes
+    /// made it to the socket: Flushing again then sends those rows twice.
+    ///
+    /// HTTP should be the first choice, but use TCP if you need to continuously send
+    /// data to the server at a high rate.
+    ///
+    /// To improve the HTTP performance, send larger buffers (with more rows), and
+    /// consider parallelizing writes using multiple senders from multiple threads.
+    ///
+    /// The buffer keeps its capacity for the next batch, unless it exceeds the
+    /// [`buffer_shrink_threshold`](SenderBuilder::buffer_shrink_threshold).
+    pub fn flush(&mut self, buf: &mut Buffer) -> Result<()> {
+        if let Err(err) = self.flush_impl(buf, false) {
+            // Don't leave the incomplete row for the next batch to send.
+            if !buf.ends_at_row_boundary() {
+                buf.discard_row();
+            }
+            if err.code() == ErrorCode::SocketError {
+                self.spill(buf);
+            }
+            return Err(err);
+        }
+        buf.clear();
+        if self
+            .buffer_shrink_threshold
+            .is_some_and(|threshold| buf.capacity() > threshold)
+        {
+            buf.output.shrink_to(buf.initial_capacity);
+        }
+        Ok(())
+    }
+
+    /// Append the buffer's rows to the spill file, if configured, and clear
+    /// the buffer. Should that fail, the buffer keeps its rows.
+    fn spill(&mut self, buf: &mut Buffer) {
+        let Some(spill) = self.spill.as_mut() else {
+            return;
+        };
+        if buf.is_empty() {
+            return;
+        }
+        if self.failed_flush_sent > 0 {
+            // Replaying the spill file would send the rows that made it
+            // through a second time.
+            ingress_log!(
+                warn,
+                "Not spilling {} rows, {} bytes: {} bytes were already sent.",
+                buf.row_count(),
+                buf.len(),
+                self.failed_flush_sent
+            );
+            return;
+        }
+        match spill.append(buf.as_bytes(), buf.state.required_version) {
+            Ok(()) => {
+                ingress_log!(
+                    warn,
+                    "Spilled {} rows, {} bytes to {:?}.",
+                    buf.row_count(),
+                    buf.len(),
+                    spill.dir()
+                );
+                self.stats.rows_spilled += buf.row_count() as u64;
+                buf.clear();
+            }
+            Err(io_err) => ingress_log!(
+                warn,
+                "Could not spill {} rows, {} bytes to {:?}: {}",
+                buf.row_count(),
+                buf.len(),
+                spill.dir(),
+                io_err
+            ),
+        }
+    }
+
+    /// The bytes of ILP written to the [spill directory](SenderBuilder::spill_dir)
+    /// by this sender, across reconnects.
+    pub fn spilled_bytes(&self) -> u64 {
+        self.spill.as_ref().map_or(0, SpillWriter::spilled_bytes)
+    }
+
+    /// The bytes of spilled records waiting to be replayed, including those
+    /// left in the [spill directory](SenderBuilder::spill_dir) by earlier
+    /// senders.
+    pub fn pending_spill(&self) -> u64 {
+        self.spill.as_ref().map_or(0, SpillWriter::backlog)
+    }
+
+    /// Send the rows waiting in the [spill directory](SenderBuilder::spill_dir)
+    /// now, rather than before the next flush's rows.
+    ///
+    /// Each spill file is deleted once sent in full. Should sending fail, the
+    /// rows not sent yet stay in the directory for the next attempt, which
+    /// resumes after the last row sent, even across a crash. Rows the server
+    /// refuses for good are set aside rather than failing the replay, see
+    /// [`SenderBuilder::spill_dir`]. Replaying nothing is a no-op.
+    pub fn replay_now(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(error::fmt!(
+                SocketError,
+                "Could not replay spilled rows: not connected to database."
+            ));
+        }
+        self.replay_spill()
+    }
+
+    /// Replay the spilled rows, if any and if connected.
+    fn replay_spill(&mut self) -> Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+        let Some(mut spill) = self.spill.take() else {
+            return Ok(());
+        };
+        let result = if spill.backlog() == 0 {
+            Ok(())
+        } else {
+            let backlog = spill.backlog();
+            let result = spill.replay(|record| self.send_spilled(record));
+            if result.is_ok() {
+                ingress_log!(
+                    debug,
+                    "Replayed {} bytes of spill files from {:?}.",
+                    backlog,
+                    spill.dir()
+                );
+            }
+            result
+        };
+        self.spill = Some(spill);
+        result
+    }
+
+    fn send_spilled(&mut self, record: &SpillRecord) -> Result<Replayed> {
+        if record.protocol_version() > self.buffer_template.protocol_version {
+            return Ok(Replayed::Refused(error::fmt!(
+                InvalidApiCall,
+                "Could not replay spilled rows: They require ILP protocol version {}, but the sender is pinned to version {}.",
+                record.protocol_version() as u8,
+                self.buffer_template.protocol_version as u8
+            )));
+        }
+        Ok(match self.try_send_bytes(record.payload(), false, false)? {
+            Ok(()) => Replayed::Sent,
+            Err(err) => Replayed::Refused(err),
+        })
+    }
+
+    /// Send previously captured ILP, e.g. written by [`Buffer::flush_to`],
+    /// without going through a [`Buffer`].
+    ///
+    /// The bytes must be UTF-8 and end with a newline, so a truncated last line
+    /// is rejected rather than concatenated with whatever is sent next. Other
+    /// than that, they are sent as-is: Any malformed rows are reported by the
+    /// server as with a regular flush.
+    ///
+    /// As with [`flush`](Sender::flush), this fails if the sender
+    /// [must be closed](Sender::must_close) and sending an empty slice is a
+    /// no-op. Raw sends aren't included in the [`stats`](Sender::stats) and
+    /// aren't reported to the [`FlushObserver`].
+    pub fn send_raw(&mut self, bytes: &[u8]) -> Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if !self.connected {
+            return Err(error::fmt!(
+                SocketError,
+                "Could not send raw ILP: not connected to database."
+            ));
+        }
+        if std::str::from_utf8(bytes).is_err() {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                "Could not send raw ILP: The bytes are not valid UTF-8."
+            ));
+        }
+
+        // A newline preceded by an odd number of backslashes is escaped and
+        // doesn't terminate the line.
+        let trailing_backslashes = bytes[..bytes.len() - 1]
+            .iter()
+            .rev()
+            .take_while(|&&b| b == b'\\')
+            .count();
+        if bytes[bytes.len() - 1] != b'\n' || trailing_backslashes % 2 == 1 {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                "Could not send raw ILP: The last line is not terminated by a newline."
+            ));
+        }
+        if bytes.len() > self.buffer_template.max_buf_size {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                "Could not send raw ILP: Size of {} bytes exceeds maximum configured allowed size of {} bytes.",
+                bytes.len(),
+                self.buffer_template.max_buf_size
+            ));
+        }
+        if self.check_connection_on_flush {
+            self.check_connection()?;
+        }
+        self.send_bytes(bytes, false, false)
+    }
+
+    /// The size of the socket's send buffer (`SO_SNDBUF`), in bytes, as applied
+    /// by the operating system.
+    ///
+    /// This may differ from the size requested with
+    /// [`SenderBuilder::send_buffer_size`]: Linux, for example, doubles it.
+    ///
+    /// Returns `None` for senders that don't hold a socket, such as
+    /// ILP-over-HTTP senders and those created by [`Sender::from_sink`].
+    pub fn send_buffer_size(&self) -> Option<usize> {
+        self.socket()?.send_buffer_size().ok()
+    }
+
+    /// The size of the socket's receive buffer (`SO_RCVBUF`), in bytes, as
+    /// applied by the operating system.
+    ///
+    /// Returns `None` for senders that don't hold a socket, such as
+    /// ILP-over-HTTP senders and those created by [`Sender::from_sink`].
+    pub fn recv_buffer_size(&self) -> Option<usize> {
+        self.socket()?.recv_buffer_size().ok()
+    }
+
+    fn socket(&self) -> Option<&Socket> {
+        match &self.handler {
+            ProtocolHandler::Socket(Connection::Direct(sock)) => Some(sock),
+            ProtocolHandler::Socket(Connection::Tls(stream)) => Some(&stream.sock),
+            _ => None,
+        }
+    }
+
+    /// The address of the server this sender is connected to.
+    ///
+    /// Returns `None` for senders that don't hold a TCP connection, such as
+    /// ILP-over-HTTP senders and those created by [`Sender::from_sink`].
+    pub fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.socket()?.peer_addr().ok()?.as_socket()
+    }
+
+    /// How long each phase of connecting took, for the connection established
+    /// by [`SenderBuilder::build`] or the last [`reconnect`](Sender::reconnect).
+    ///
+    /// With failover addresses, these are the timings of the address that
+    /// accepted the connection.
+    ///
+    /// Returns `None` for ILP-over-HTTP senders, which connect on demand, and
+    /// for those created by [`Sender::from_sink`] or [`Sender::from_stream`].
+    pub fn connect_timings(&self) -> Option<ConnectTimings> {
+        self.connect_timings
+    }
+
+    /// Replace the sender's connection with a new one, set up with the same
+    /// configuration.
+    ///
+    /// This resolves the server's host name again, connects and, where
+    /// configured, performs the TLS handshake and authenticates. If the sender
+    /// [must be closed](Sender::must_close) after a failed flush, a successful
+    /// reconnect makes it usable again.
+    ///
+    /// Buffers are unaffected: A buffer whose flush failed still holds its rows,
+    /// so flushing it again after reconnecting sends them. With ILP over TCP,
+    /// part of those rows may have reached the server before the connection
+    /// broke, in which case they are sent twice.
+    ///
+    /// With failover addresses, see [`SenderBuilder::addr`], this reconnects to
+    /// the [current address](Sender::current_host). Once that fails as many
+    /// consecutive times as set by [`SenderBuilder::failover_after`], it tries
+    /// the following addresses in turn.
+    ///
+    /// Senders created by [`Sender::from_sink`] or [`Sender::from_stream`] can't
+    /// reconnect and return an
+    /// [`InvalidApiCall`](crate::ErrorCode::InvalidApiCall) error.
+    pub fn reconnect(&mut self) -> Result<()> {
+        let Some(builder) = self.builder.take() else {
+            return Err(error::fmt!(
+                InvalidApiCall,
+                "Could not reconnect: The sender was not built by a SenderBuilder."
+            ));
+        };
+
+        // Should reconnecting fail, the sender remains unusable.
+        self.connected = false;
+        let host_count = builder.failover_addrs.len() + 1;
+        let mut host_failures = self.host_failures;
+        let result = builder.build_at(self.host_index).or_else(|err| {
+            host_failures += 1;
+            if host_failures < *builder.failover_after {
+                return Err(err);
+            }
+            host_failures = 0;
+            self.host_index = (self.host_index + 1) % host_count;
+            builder.build_from(self.host_index)
+        });
+        self.builder = Some(builder);
+        self.host_failures = host_failures;
+        if let Err(ref err) = result {
+            ingress_log!(
+                warn,
+                "Could not reconnect, the sender must now be closed: {}",
+                err
+            );
+        }
+        let mut sender = result?;
+        sender.stats = self.stats;
+        sender.spill = self.spill.take();
+        *self = sender;
+        Ok(())
+    }
+
+    /// The address this sender connects to, among those configured with
+    /// [`SenderBuilder::addr`].
+    ///
+    /// Returns `None` for senders created by [`Sender::from_sink`] or
+    /// [`Sender::from_stream`].
+    pub fn current_host(&self) -> Option<Service> {
+        let builder = self.builder.as_ref()?;
+        builder.services().into_iter().nth(self.host_index)
+    }
+
+    /// Check, without blocking, whether the server closed the connection or the
+    /// socket is in error.
+    ///
+    /// With ILP over TCP, the server closes the connection when it rejects the
+    /// data, e.g. because of a parse or authentication error. Writes to the
+    /// closed socket may keep succeeding for a while, so call this to find out
+    /// before buffering more rows. It peeks at the socket without consuming any
+    /// data.
+    ///
+    /// On failure, the sender [must be closed](Sender::must_close) and the error
+    /// tells whether the server closed the connection or the socket failed.
+    ///
+    /// Always succeeds for senders without a socket, such as ILP-over-HTTP
+    /// senders, as long as they're usable.
+    pub fn check_connection(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(error::fmt!(
+                SocketError,
+                "Connection check failed: Not connected to database."
+            ));
+        }
+        let Some(sock) = self.socket() else {
+            return Ok(());
+        };
+        let peeked = sock.set_nonblocking(true).and_then(|_| {
+            let mut byte = [std::mem::MaybeUninit::<u8>::uninit()];
+            let peeked = sock.peek(&mut byte);
+            sock.set_nonblocking(false)?;
+            peeked
+        });
+        let err = match peeked {
+            Ok(0) => error::fmt!(
+                SocketError,
+                "Connection check failed: Connection closed by peer."
+            ),
+            Ok(_) => return Ok(()),
+            Err(io_err) if io_err.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(io_err) => map_io_to_socket_err("Connection check failed: Socket error: ", io_err),
+        };
+        self.connected = false;
+        ingress_log!(warn, "{}, the sender must now be closed.", err.msg());
+        Err(err)
+    }
+
+    /// Check that the server is still reachable, without sending any rows.
+    ///
+    /// Call this while idle between bursts of rows, to find out about a dead
+    /// connection before there's data to send.
+    ///
+    /// With ILP over TCP, this is [`check_connection`](Sender::check_connection):
+    /// It sends nothing, since ILP has no no-op message. To keep the connection
+    /// alive through NATs and firewalls as well, set
+    /// [`keepalive_idle`](SenderBuilder::keepalive_idle).
+    ///
+    /// With ILP over HTTP, this sends a `GET /ping` request, which also keeps a
+    /// pooled connection in use.
+    ///
+    /// On failure, [reconnect](Sender::reconnect) or drop the sender.
+    pub fn ping(&mut self) -> Result<()> {
+        match self.handler {
+            ProtocolHandler::Socket(_) => self.check_connection(),
+            #[cfg(feature = "ilp-over-http")]
+            ProtocolHandler::Http(ref state) => http_ping(state),
+        }
+    }
+
+    /// The number of rows this sender flushed successfully since it was built,
+    /// or since the last [`reset_stats`](Sender::reset_stats).
+    ///
+    /// Rows resent by [`flush_and_keep`](Sender::flush_and_keep) count each
+    /// time. See [`Buffer::row_count`] for the rows waiting in a buffer.
+    pub fn total_rows_sent(&self) -> u64 {
+        self.stats.rows_sent
+    }
+
+    /// Counters of the rows and bytes sent and of the flushes that succeeded
+    /// or failed, since the sender was built or since the last
+    /// [`reset_stats`](Sender::reset_stats).
+    ///
+    /// The counters are kept after an error, even once the sender
+    /// [must be closed](Sender::must_close), and across a
+    /// [`reconnect`](Sender::reconnect).
+    pub fn stats(&self) -> SenderStats {
+        self.stats
+    }
+
+    /// Reset the counters returned by [`stats`](Sender::stats), e.g. to
+    /// report them per time window.
+    pub fn reset_stats(&mut self) {
+        self.stats = SenderStats::default();
+    }
+
+    /// The ILP protocol version the sender is pinned to, see
+    /// [`SenderBuilder::protocol_version`].
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.buffer_template.protocol_version
+    }
+
+    /// Create a buffer that encodes rows for the sender's protocol version and
+    /// holds at most its [`max_buf_size`](SenderBuilder::max_buf_size).
+    pub fn new_buffer(&self) -> Buffer {
+        self.buffer_template.new_buffer()
+    }
+
+    /// Tell whether the sender is no longer usable and must be dropped.
+    ///
+    /// This happens when there was an earlier failure.
+    ///
+    /// This method is specific to ILP-over-TCP and is not relevant for ILP-over-HTTP.
+    pub fn must_close(&self) -> bool {
+        !self.connected
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,985 @@
+use super::*;
+use crate::ErrorCode;
+use tempfile::TempDir;
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn http_simple() {
+    let builder = SenderBuilder::from_conf("http::addr=localhost;").unwrap();
+    assert_eq!(builder.protocol, Protocol::Http);
+    assert_specified_eq(&builder.host, "localhost");
+    assert_specified_eq(&builder.port, Protocol::Http.default_port());
+    assert!(!builder.protocol.tls_enabled());
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn https_simple() {
+    let builder = SenderBuilder::from_conf("https::addr=localhost;").unwrap();
+    assert_eq!(builder.protocol, Protocol::Https);
+    assert_specified_eq(&builder.host, "localhost");
+    assert_specified_eq(&builder.port, Protocol::Https.default_port());
+    assert!(builder.protocol.tls_enabled());
+    assert_defaulted_eq(&builder.tls_ca, CertificateAuthority::WebpkiRoots);
+}
+
+#[test]
+fn tcp_simple() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;").unwrap();
+    assert_eq!(builder.protocol, Protocol::Tcp);
+    assert_specified_eq(&builder.port, Protocol::Tcp.default_port());
+    assert_specified_eq(&builder.host, "localhost");
+    assert!(!builder.protocol.tls_enabled());
+}
+
+#[test]
+fn tcps_simple() {
+    let builder = SenderBuilder::from_conf("tcps::addr=localhost;").unwrap();
+    assert_eq!(builder.protocol, Protocol::Tcps);
+    assert_specified_eq(&builder.host, "localhost");
+    assert_specified_eq(&builder.port, Protocol::Tcps.default_port());
+    assert!(builder.protocol.tls_enabled());
+    assert_defaulted_eq(&builder.tls_ca, CertificateAuthority::WebpkiRoots);
+}
+
+#[test]
+fn invalid_value() {
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost\n;"),
+        "Config parse error: invalid char '\\n' in value at position 19",
+    );
+}
+
+#[test]
+fn tcp_ipv6_addr() {
+    let builder = SenderBuilder::from_conf("tcp::addr=[::1]:9010;").unwrap();
+    assert_specified_eq(&builder.host, "::1");
+    assert_specified_eq(&builder.port, "9010");
+
+    let builder = SenderBuilder::from_conf("tcp::addr=[::1];").unwrap();
+    assert_specified_eq(&builder.host, "::1");
+    assert_specified_eq(&builder.port, Protocol::Tcp.default_port());
+}
+
+#[test]
+fn bad_addr() {
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost:;"),
+        "Invalid address \"localhost:\": Missing port after `:`.",
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=::1;"),
+        "Invalid address \"::1\": IPv6 hosts must be enclosed in brackets, e.g. \"[::1]:9009\".",
+    );
+}
+
+#[test]
+fn service_forms() {
+    assert_eq!(
+        Service::from(("localhost", 9009)),
+        Service::new("localhost", "9009")
+    );
+    assert_eq!(
+        Service::from(("localhost".to_string(), "qdb_ilp")),
+        Service::new("localhost", "qdb_ilp")
+    );
+    assert_eq!(
+        "localhost:9009".parse::<Service>().unwrap(),
+        Service::new("localhost", 9009)
+    );
+    assert_eq!(
+        Service::try_from("127.0.0.1:qdb_ilp").unwrap(),
+        Service::new("127.0.0.1", "qdb_ilp")
+    );
+    let service: Service = "[::1]:9009".parse().unwrap();
+    assert_eq!(service.host(), "::1");
+    assert_eq!(service.port(), "9009");
+    let service: Service = "[fe80::1:2]:80".to_string().try_into().unwrap();
+    assert_eq!(service.host(), "fe80::1:2");
+    assert_eq!(service.port(), "80");
+
+    let builder = SenderBuilder::from_addr(Protocol::Tcp, "example.com:9009").unwrap();
+    assert_specified_eq(&builder.host, "example.com");
+    assert_specified_eq(&builder.port, "9009");
+    let builder = SenderBuilder::from_addr(Protocol::Tcp, ("example.com", 9009)).unwrap();
+    assert_specified_eq(&builder.host, "example.com");
+    assert_specified_eq(&builder.port, "9009");
+}
+
+#[test]
+fn service_malformed() {
+    assert_conf_err(
+        "localhost".parse::<Service>(),
+        "Invalid address \"localhost\": Missing port, expected \"host:port\".",
+    );
+    assert_conf_err(
+        "host:".parse::<Service>(),
+        "Invalid address \"host:\": Missing port after `:`.",
+    );
+    assert_conf_err(
+        ":9009".parse::<Service>(),
+        "Invalid address \":9009\": Missing host.",
+    );
+    assert_conf_err(
+        "[::1]".parse::<Service>(),
+        "Invalid address \"[::1]\": Missing port, expected \"host:port\".",
+    );
+    assert_conf_err(
+        "[::1]:".parse::<Service>(),
+        "Invalid address \"[::1]:\": Missing port after `:`.",
+    );
+    assert_conf_err(
+        "[::1:9009".parse::<Service>(),
+        "Invalid address \"[::1:9009\": Missing closing `]` after IPv6 host.",
+    );
+    assert_conf_err(
+        "[::1]9009".parse::<Service>(),
+        "Invalid address \"[::1]9009\": Unexpected \"9009\" after IPv6 host, expected `:`.",
+    );
+    assert_conf_err(
+        "[]:9009".parse::<Service>(),
+        "Invalid address \"[]:9009\": Missing host.",
+    );
+    assert_conf_err(
+        "::1:9009".parse::<Service>(),
+        "Invalid address \"::1:9009\": IPv6 hosts must be enclosed in brackets, e.g. \"[::1]:9009\".",
+    );
+    assert_conf_err(
+        SenderBuilder::from_addr(Protocol::Tcp, "localhost"),
+        "Invalid address \"localhost\": Missing port, expected \"host:port\".",
+    );
+}
+
+#[test]
+fn specified_cant_change() {
+    let mut builder = SenderBuilder::from_conf("tcp::addr=localhost;").unwrap();
+    builder = builder.bind_interface("1.1.1.1").unwrap();
+    assert_conf_err(
+        builder.bind_interface("1.1.1.2"),
+        "\"bind_interface\" is already specified",
+    );
+}
+
+#[test]
+fn missing_addr() {
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::"),
+        "Missing \"addr\" parameter in config string",
+    );
+}
+
+#[test]
+fn unsupported_service() {
+    assert_conf_err(
+        SenderBuilder::from_conf("xaxa::addr=localhost;"),
+        "Unsupported protocol: xaxa",
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn http_basic_auth() {
+    let builder =
+        SenderBuilder::from_conf("http::addr=localhost;username=user123;password=pass321;")
+            .unwrap();
+    let auth = builder.build_auth().unwrap();
+    match auth.unwrap() {
+        AuthParams::Basic(BasicAuthParams { username, password }) => {
+            assert_eq!(username, "user123");
+            assert_eq!(password, "pass321");
+        }
+        _ => {
+            panic!("Expected AuthParams::Basic");
+        }
+    }
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn http_token_auth() {
+    let builder = SenderBuilder::from_conf("http::addr=localhost:9000;token=token123;").unwrap();
+    let auth = builder.build_auth().unwrap();
+    match auth.unwrap() {
+        AuthParams::Token(TokenAuthParams { token }) => {
+            assert_eq!(token, "token123");
+        }
+        _ => {
+            panic!("Expected AuthParams::Token");
+        }
+    }
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn incomplete_basic_auth() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;username=user123;")
+            .unwrap()
+            .build(),
+        "Basic authentication parameter \"username\" is present, but \"password\" is missing.",
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;password=pass321;")
+            .unwrap()
+            .build(),
+        "Basic authentication parameter \"password\" is present, but \"username\" is missing.",
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn misspelled_basic_auth() {
+    assert_conf_err(
+        Sender::from_conf("http::addr=localhost;username=user123;pass=pass321;"),
+        r##"Basic authentication parameter "username" is present, but "password" is missing."##,
+    );
+    assert_conf_err(
+        Sender::from_conf("http::addr=localhost;user=user123;password=pass321;"),
+        r##"Basic authentication parameter "password" is present, but "username" is missing."##,
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn inconsistent_http_auth() {
+    let expected_err_msg = r##"Inconsistent HTTP authentication parameters. Specify either "username" and "password", or just "token"."##;
+    assert_conf_err(
+        Sender::from_conf("http::addr=localhost;username=user123;token=token123;"),
+        expected_err_msg,
+    );
+    assert_conf_err(
+        Sender::from_conf("http::addr=localhost;password=pass321;token=token123;"),
+        expected_err_msg,
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn cant_use_basic_auth_with_tcp() {
+    let builder = SenderBuilder::new(Protocol::Tcp, "localhost", 9000)
+        .username("user123")
+        .unwrap()
+        .password("pass321")
+        .unwrap();
+    assert_conf_err(
+        builder.build_auth(),
+        "The \"basic_auth\" setting can only be used with the ILP/HTTP protocol.",
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn cant_use_token_auth_with_tcp() {
+    let builder = SenderBuilder::new(Protocol::Tcp, "localhost", 9000)
+        .token("token123")
+        .unwrap();
+    assert_conf_err(
+        builder.build_auth(),
+        "Token authentication only be used with the ILP/HTTP protocol.",
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn cant_use_ecdsa_auth_with_http() {
+    let builder = SenderBuilder::from_conf("http::addr=localhost;")
+        .unwrap()
+        .username("key_id123")
+        .unwrap()
+        .token("priv_key123")
+        .unwrap()
+        .token_x("pub_key1")
+        .unwrap()
+        .token_y("pub_key2")
+        .unwrap();
+    assert_conf_err(
+        builder.build_auth(),
+        "ECDSA authentication is only available with ILP/TCP and not available with ILP/HTTP.",
+    );
+}
+
+#[test]
+fn set_auth_specifies_tcp() {
+    let mut builder = SenderBuilder::new(Protocol::Tcp, "localhost", 9000);
+    assert_eq!(builder.protocol, Protocol::Tcp);
+    builder = builder
+        .username("key_id123")
+        .unwrap()
+        .token("priv_key123")
+        .unwrap()
+        .token_x("pub_key1")
+        .unwrap()
+        .token_y("pub_key2")
+        .unwrap();
+    assert_eq!(builder.protocol, Protocol::Tcp);
+}
+
+#[test]
+fn set_net_interface_specifies_tcp() {
+    let builder = SenderBuilder::new(Protocol::Tcp, "localhost", 9000);
+    assert_eq!(builder.protocol, Protocol::Tcp);
+    builder.bind_interface("55.88.0.4").unwrap();
+}
+
+#[test]
+fn bind_port_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;bind_port=9876;").unwrap();
+    assert_specified_eq(&builder.net_port, Some(9876));
+    assert_defaulted_eq(&builder.net_interface, None);
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn bind_port_requires_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;bind_port=9876;"),
+        "The \"bind_port\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[test]
+fn ip_tos_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;ip_tos=72;").unwrap();
+    assert_specified_eq(&builder.ip_tos, Some(72));
+}
+
+#[test]
+fn max_name_len_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;max_name_len=255;").unwrap();
+    assert_specified_eq(&builder.max_name_len, 255usize);
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;max_name_len=0;"),
+        "\"max_name_len\" must be at least 1 byte.",
+    );
+}
+
+#[test]
+fn validation_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;validation=strict;").unwrap();
+    assert_specified_eq(&builder.validation, Validation::Strict);
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;validation=off;").unwrap();
+    assert_specified_eq(&builder.validation, Validation::Off);
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;validation=lenient;"),
+        r#"Config parameter "validation" must be either "strict", "default" or "off"."#,
+    );
+}
+
+#[test]
+fn reject_control_chars_in_values_from_conf() {
+    let builder =
+        SenderBuilder::from_conf("tcp::addr=localhost;reject_control_chars_in_values=on;").unwrap();
+    assert_specified_eq(&builder.reject_control_chars_in_values, true);
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;reject_control_chars_in_values=yes;"),
+        r#"Config parameter "reject_control_chars_in_values" must be either "on" or "off"."#,
+    );
+}
+
+#[test]
+fn resolve_timeout_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;resolve_timeout=250;").unwrap();
+    assert_specified_eq(&builder.resolve_timeout, Some(Duration::from_millis(250)));
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn resolve_timeout_requires_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;resolve_timeout=250;"),
+        "The \"resolve_timeout\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[test]
+fn dns_ttl_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;dns_ttl=30000;").unwrap();
+    assert_specified_eq(&builder.dns_ttl, Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn failover_addrs_from_conf() {
+    let builder =
+        SenderBuilder::from_conf("tcp::addr=primary:9019,standby,[::1]:9029;failover_after=3;")
+            .unwrap();
+    assert_eq!(builder.host.as_str(), "primary");
+    assert_eq!(builder.port.as_str(), "9019");
+    assert_eq!(
+        builder.failover_addrs,
+        vec![Service::new("standby", 9009), Service::new("::1", 9029)]
+    );
+    assert_specified_eq(&builder.failover_after, 3u32);
+
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=primary,;"),
+        "Invalid address \"\": Missing host.",
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=primary;failover_after=0;"),
+        "\"failover_after\" must be at least 1.",
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn failover_addrs_require_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=primary,standby;"),
+        "The \"addr\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[test]
+fn nodelay_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;").unwrap();
+    assert_defaulted_eq(&builder.nodelay, true);
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;nodelay=off;").unwrap();
+    assert_specified_eq(&builder.nodelay, false);
+
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;nodelay=no;"),
+        r##"Config parameter "nodelay" must be either "on" or "off"."##,
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn nodelay_requires_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;nodelay=off;"),
+        "The \"nodelay\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[test]
+fn nodelay_applied_to_socket() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    for nodelay in [true, false] {
+        let sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port)
+            .nodelay(nodelay)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(sender.socket().unwrap().nodelay().unwrap(), nodelay);
+    }
+}
+
+#[test]
+fn keepalive_from_conf() {
+    let builder = SenderBuilder::from_conf(
+        "tcp::addr=localhost;keepalive_idle=30000;keepalive_interval=5000;keepalive_retries=3;",
+    )
+    .unwrap();
+    assert_specified_eq(&builder.keepalive_idle, Some(Duration::from_secs(30)));
+    assert_specified_eq(&builder.keepalive_interval, Some(Duration::from_secs(5)));
+    assert_specified_eq(&builder.keepalive_retries, Some(3));
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn keepalive_requires_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;keepalive_idle=30000;"),
+        "The \"keepalive_idle\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn keepalive_applied_to_socket() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port)
+        .keepalive_idle(Duration::from_secs(30))
+        .unwrap()
+        .keepalive_interval(Duration::from_secs(5))
+        .unwrap()
+        .keepalive_retries(3)
+        .unwrap()
+        .build()
+        .unwrap();
+    let sock = sender.socket().unwrap();
+    assert!(sock.keepalive().unwrap());
+    assert_eq!(sock.keepalive_time().unwrap(), Duration::from_secs(30));
+    assert_eq!(sock.keepalive_interval().unwrap(), Duration::from_secs(5));
+    assert_eq!(sock.keepalive_retries().unwrap(), 3);
+}
+
+#[test]
+fn keepalive_enabled_by_default() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port)
+        .build()
+        .unwrap();
+    assert!(sender.socket().unwrap().keepalive().unwrap());
+}
+
+#[cfg(all(unix, not(any(target_os = "openbsd", target_os = "haiku"))))]
+#[test]
+fn keepalive_idle_applied_to_socket() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port)
+        .keepalive_idle(Duration::from_secs(45))
+        .unwrap()
+        .build()
+        .unwrap();
+    let sock = sender.socket().unwrap();
+    assert!(sock.keepalive().unwrap());
+    assert_eq!(sock.keepalive_time().unwrap(), Duration::from_secs(45));
+}
+
+#[test]
+fn protocol_version_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;").unwrap();
+    assert_defaulted_eq(&builder.protocol_version, ProtocolVersion::V2);
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;protocol_version=1;").unwrap();
+    assert_specified_eq(&builder.protocol_version, ProtocolVersion::V1);
+
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;protocol_version=3;"),
+        r##"Config parameter "protocol_version" must be either "1" or "2"."##,
+    );
+}
+
+#[test]
+fn buffer_sizes_from_conf() {
+    let builder = SenderBuilder::from_conf(
+        "tcp::addr=localhost;send_buffer_size=1048576;recv_buffer_size=65536;",
+    )
+    .unwrap();
+    assert_specified_eq(&builder.send_buffer_size, Some(1048576));
+    assert_specified_eq(&builder.recv_buffer_size, Some(65536));
+}
+
+#[test]
+fn write_chunk_size_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;write_chunk_size=65536;").unwrap();
+    assert_specified_eq(&builder.write_chunk_size, 65536usize);
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;write_chunk_size=0;"),
+        "\"write_chunk_size\" must be greater than 0.",
+    );
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn buffer_sizes_require_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::addr=localhost;send_buffer_size=1048576;"),
+        "The \"send_buffer_size\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[test]
+fn unix_socket_from_conf() {
+    let builder = SenderBuilder::from_conf("tcp::unix_socket=/run/questdb.sock;").unwrap();
+    assert_specified_eq(
+        &builder.unix_socket,
+        Some(PathBuf::from("/run/questdb.sock")),
+    );
+    assert_eq!(builder.host.as_str(), "localhost");
+
+    let builder =
+        SenderBuilder::from_conf("tcps::addr=db.example.com;unix_socket=/run/questdb.sock;")
+            .unwrap();
+    assert_eq!(builder.host.as_str(), "db.example.com");
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn unix_socket_requires_tcp() {
+    assert_conf_err(
+        SenderBuilder::from_conf("http::unix_socket=/run/questdb.sock;"),
+        "The \"unix_socket\" setting can only be used with the TCP protocol.",
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn ip_tos_applied_to_socket() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port)
+        .ip_tos(18 << 2)
+        .unwrap()
+        .build()
+        .unwrap();
+    let ProtocolHandler::Socket(Connection::Direct(sock)) = &sender.handler else {
+        panic!("Expected a plain TCP connection");
+    };
+    assert_eq!(sock.tos().unwrap(), 18 << 2);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn ip_tos_applied_to_ipv6_socket() {
+    let listener = std::net::TcpListener::bind("[::1]:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sender = SenderBuilder::new(Protocol::Tcp, "::1", port)
+        .ip_tos(18 << 2)
+        .unwrap()
+        .build()
+        .unwrap();
+    let ProtocolHandler::Socket(Connection::Direct(sock)) = &sender.handler else {
+        panic!("Expected a plain TCP connection");
+    };
+    assert_eq!(sock.tclass_v6().unwrap(), 18 << 2);
+}
+
+#[test]
+fn tcp_ecdsa_auth() {
+    let builder = SenderBuilder::from_conf(
+        "tcp::addr=localhost:9000;username=user123;token=token123;token_x=xtok123;token_y=ytok123;",
+    )
+    .unwrap();
+    let auth = builder.build_auth().unwrap();
+    match auth.unwrap() {
+        AuthParams::Ecdsa(EcdsaAuthParams {
+            key_id,
+            priv_key,
+            pub_key_x,
+            pub_key_y,
+        }) => {
+            assert_eq!(key_id, "user123");
+            assert_eq!(priv_key, "token123");
+            assert_eq!(pub_key_x, "xtok123");
+            assert_eq!(pub_key_y, "ytok123");
+        }
+        #[cfg(feature = "ilp-over-http")]
+        _ => {
+            panic!("Expected AuthParams::Ecdsa");
+        }
+    }
+}
+
+#[test]
+fn incomplete_tcp_ecdsa_auth() {
+    let expected_err_msg = r##"Incomplete ECDSA authentication parameters. Specify either all or none of: "username", "token", "token_x", "token_y"."##;
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;username=user123;")
+            .unwrap()
+            .build(),
+        expected_err_msg,
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf("tcp::addr=localhost;username=user123;token=token123;")
+            .unwrap()
+            .build(),
+        expected_err_msg,
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf(
+            "tcp::addr=localhost;username=user123;token=token123;token_x=123;",
+        )
+        .unwrap()
+        .build(),
+        expected_err_msg,
+    );
+}
+
+#[test]
+fn misspelled_tcp_ecdsa_auth() {
+    assert_conf_err(
+        Sender::from_conf("tcp::addr=localhost;username=user123;tokenx=123;"),
+        "Incomplete ECDSA authentication parameters. Specify either all or none of: \"username\", \"token\", \"token_x\", \"token_y\"."
+    );
+}
+
+#[test]
+fn tcps_tls_verify_on() {
+    let builder = SenderBuilder::from_conf("tcps::addr=localhost;tls_verify=on;").unwrap();
+    assert!(builder.protocol.tls_enabled());
+    assert_defaulted_eq(&builder.tls_ca, CertificateAuthority::WebpkiRoots);
+}
+
+#[cfg(feature = "insecure-skip-verify")]
+#[test]
+fn tcps_tls_verify_unsafe_off() {
+    let builder = SenderBuilder::from_conf("tcps::addr=localhost;tls_verify=unsafe_off;").unwrap();
+    assert!(builder.protocol.tls_enabled());
+    assert_defaulted_eq(&builder.tls_ca, CertificateAuthority::WebpkiRoots);
+    assert_specified_eq(&builder.tls_verify, false);
+}
+
+#[test]
+fn tcps_tls_verify_invalid() {
+    assert_conf_err(
+        SenderBuilder::from_conf("tcps::addr=localhost;tls_verify=off;"),
+        r##"Config parameter "tls_verify" must be either "on" or "unsafe_off".'"##,
+    );
+}
+
+#[test]
+fn tcps_tls_roots_webpki() {
+    let builder = SenderBuilder::from_conf("tcps::addr=localhost;tls_ca=webpki_roots;").unwrap();
+    assert!(builder.protocol.tls_enabled());
+    assert_specified_eq(&builder.tls_ca, CertificateAuthority::WebpkiRoots);
+    assert_defaulted_eq(&builder.tls_roots, None);
+}
+
+#[cfg(feature = "tls-native-certs")]
+#[test]
+fn tcps_tls_roots_os() {
+    let builder = SenderBuilder::from_conf("tcps::addr=localhost;tls_ca=os_roots;").unwrap();
+    assert!(builder.protocol.tls_enabled());
+    assert_specified_eq(&builder.tls_ca, CertificateAuthority::OsRoots);
+    assert_defaulted_eq(&builder.tls_roots, None);
+}
+
+#[test]
+fn tcps_tls_roots_file() {
+    // Write a dummy file to test the file path
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("cacerts.pem");
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.write_all(b"dummy").unwrap();
+    let builder = SenderBuilder::from_conf(format!(
+        "tcps::addr=localhost;tls_roots={};",
+        path.to_str().unwrap()
+    ))
+    .unwrap();
+    assert_specified_eq(&builder.tls_ca, CertificateAuthority::PemFile);
+    assert_specified_eq(&builder.tls_roots, path);
+}
+
+#[test]
+fn tcps_tls_roots_file_missing() {
+    let err =
+        SenderBuilder::from_conf("tcps::addr=localhost;tls_roots=/some/invalid/path/cacerts.pem;")
+            .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert!(err
+        .msg()
+        .contains("Could not open root certificate file from path"));
+}
+
+#[test]
+fn tcps_tls_roots_file_with_password() {
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("cacerts.pem");
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.write_all(b"dummy").unwrap();
+    let builder_or_err = SenderBuilder::from_conf(format!(
+        "tcps::addr=localhost;tls_roots={};tls_roots_password=extremely_secure;",
+        path.to_str().unwrap()
+    ));
+    assert_conf_err(builder_or_err, "\"tls_roots_password\" is not supported.");
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn http_request_min_throughput() {
+    let builder =
+        SenderBuilder::from_conf("http::addr=localhost;request_min_throughput=100;").unwrap();
+    let Some(http_config) = builder.http else {
+        panic!("Expected Some(HttpConfig)");
+    };
+    assert_specified_eq(&http_config.request_min_throughput, 100u64);
+    assert_defaulted_eq(&http_config.request_timeout, Duration::from_millis(10000));
+    assert_defaulted_eq(&http_config.retry_timeout, Duration::from_millis(10000));
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn http_request_timeout() {
+    let builder = SenderBuilder::from_conf("http::addr=localhost;request_timeout=100;").unwrap();
+    let Some(http_config) = builder.http else {
+        panic!("Expected Some(HttpConfig)");
+    };
+    assert_defaulted_eq(&http_config.request_min_throughput, 102400u64);
+    assert_specified_eq(&http_config.request_timeout, Duration::from_millis(100));
+    assert_defaulted_eq(&http_config.retry_timeout, Duration::from_millis(10000));
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn http_retry_timeout() {
+    let builder = SenderBuilder::from_conf("http::addr=localhost;retry_timeout=100;").unwrap();
+    let Some(http_config) = builder.http else {
+        panic!("Expected Some(HttpConfig)");
+    };
+    assert_defaulted_eq(&http_config.request_min_throughput, 102400u64);
+    assert_defaulted_eq(&http_config.request_timeout, Duration::from_millis(10000));
+    assert_specified_eq(&http_config.retry_timeout, Duration::from_millis(100));
+}
+
+#[test]
+fn auto_flush_off() {
+    let builder = SenderBuilder::from_conf("tcps::addr=localhost;auto_flush=off;").unwrap();
+    assert_specified_eq(&builder.auto_flush, AutoFlush::Off);
+}
+
+#[test]
+fn auto_flush_from_conf() {
+    let builder = SenderBuilder::from_conf(
+        "tcps::addr=localhost;auto_flush=on;auto_flush_rows=100;auto_flush_bytes=4096;\
+            auto_flush_interval=500;",
+    )
+    .unwrap();
+    assert_specified_eq(&builder.auto_flush, AutoFlush::On);
+    assert_specified_eq(&builder.auto_flush_rows, 100usize);
+    assert_specified_eq(&builder.auto_flush_bytes, 4096usize);
+    assert_specified_eq(&builder.auto_flush_interval, Duration::from_millis(500));
+
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;").unwrap();
+    assert_defaulted_eq(&builder.auto_flush, AutoFlush::On);
+    assert_defaulted_eq(&builder.auto_flush_rows, None);
+    assert_defaulted_eq(&builder.auto_flush_bytes, None);
+    assert_defaulted_eq(&builder.auto_flush_interval, None);
+}
+
+#[test]
+fn auto_flush_interval_by_row_age() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let interval = Duration::from_millis(50);
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port)
+        .auto_flush_interval(interval)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut buffer = Buffer::new();
+    buffer.table("test").unwrap().symbol("t1", "v1").unwrap();
+    sender.at_now(&mut buffer).unwrap();
+    buffer.table("test").unwrap().symbol("t1", "v2").unwrap();
+    sender.at_now(&mut buffer).unwrap();
+    assert_eq!(buffer.row_count(), 2);
+
+    std::thread::sleep(interval);
+    buffer.table("test").unwrap().symbol("t1", "v3").unwrap();
+    sender.at_now(&mut buffer).unwrap();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.state.first_row_at, None);
+
+    // The age restarts from the first row after the flush.
+    buffer.table("test").unwrap().symbol("t1", "v4").unwrap();
+    sender.at_now(&mut buffer).unwrap();
+    assert!(buffer.state.first_row_at.is_some());
+    buffer.table("test").unwrap().symbol("t1", "v5").unwrap();
+    sender.at_now(&mut buffer).unwrap();
+    assert_eq!(buffer.row_count(), 2);
+
+    std::thread::sleep(interval);
+    buffer.table("test").unwrap().symbol("t1", "v6").unwrap();
+    sender.at_now(&mut buffer).unwrap();
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn buffer_shrink_threshold_from_conf() {
+    let builder =
+        SenderBuilder::from_conf("tcp::addr=localhost;buffer_shrink_threshold=65536;").unwrap();
+    assert_specified_eq(&builder.buffer_shrink_threshold, 65536usize);
+
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost;").unwrap();
+    assert_defaulted_eq(&builder.buffer_shrink_threshold, None);
+}
+
+#[test]
+fn auto_flush_bad_value() {
+    assert_conf_err(
+        SenderBuilder::from_conf("tcps::addr=localhost;auto_flush=yes;"),
+        r##"Config parameter "auto_flush" must be either "on" or "off"."##,
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf("tcps::addr=localhost;auto_flush_rows=0;"),
+        "\"auto_flush_rows\" must be greater than 0.",
+    );
+    assert_conf_err(
+        SenderBuilder::from_conf("tcps::addr=localhost;auto_flush_interval=0;"),
+        "\"auto_flush_interval\" must be greater than 0.",
+    );
+}
+
+/// A sink that accepts the given number of bytes, then fails.
+struct FailAfter(usize);
+
+impl io::Write for FailAfter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.0 == 0 {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"));
+        }
+        let len = buf.len().min(self.0);
+        self.0 -= len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn spill_skips_partially_sent_flush() {
+    let dir = TempDir::new().unwrap();
+    let spilling = |sink: FailAfter| {
+        let mut sender = Sender::from_sink(sink);
+        sender.spill = Some(SpillWriter::open(dir.path().to_owned(), u64::MAX).unwrap());
+        sender
+    };
+    let mut buffer = Buffer::new();
+    for value in ["v1", "v2"] {
+        buffer
+            .table("test")
+            .unwrap()
+            .symbol("t1", value)
+            .unwrap()
+            .at_now()
+            .unwrap();
+    }
+    let expected = buffer.as_str().to_owned();
+
+    // The first row and part of the second were written: Replaying a spill of
+    // both would send the first row twice.
+    let mut sender = spilling(FailAfter(15));
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(buffer.as_str(), expected);
+    assert_eq!(sender.spilled_bytes(), 0);
+    assert_eq!(sender.stats().rows_spilled, 0);
+    assert!(spill_files(dir.path()).unwrap().is_empty());
+
+    // Nothing was written, so the rows are spilled.
+    let mut sender = spilling(FailAfter(0));
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(buffer.is_empty());
+    assert_eq!(sender.spilled_bytes(), expected.len() as u64);
+    assert_eq!(sender.stats().rows_spilled, 2);
+}
+
+fn assert_specified_eq<V: PartialEq + Debug, IntoV: Into<V>>(
+    actual: &ConfigSetting<V>,
+    expected: IntoV,
+) {
+    let expected = expected.into();
+    if let ConfigSetting::Specified(actual_value) = actual {
+        assert_eq!(actual_value, &expected);
+    } else {
+        panic!("Expected Specified({:?}), but got {:?}", expected, actual);
+    }
+}
+
+fn assert_defaulted_eq<V: PartialEq + std::fmt::Debug, IntoV: Into<V>>(
+    actual: &ConfigSetting<V>,
+    expected: IntoV,
+) {
+    let expected = expected.into();
+    if let ConfigSetting::Defaulted(actual_value) = actual {
+        assert_eq!(actual_value, &expected);
+    } else {
+        panic!("Expected Defaulted({:?}), but got {:?}", expected, actual);
+    }
+}
+
+fn assert_conf_err<T, M: AsRef<str>>(result: Result<T>, expect_msg: M) {
+    let Err(err) = result else {
+        panic!("Got Ok, expected ConfigError: {}", expect_msg.as_ref());
+    };
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(err.msg(), expect_msg.as_ref());
+}
@@ -0,0 +1,222 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::Result;
+use crate::ingress::{
+    Buffer, ConnectTimings, ProtocolVersion, Sender, SenderStats, Service, Timestamp,
+};
+
+/// A [`Sender`] that can be shared across threads.
+///
+/// Each method locks the underlying sender for the duration of the call, so
+/// concurrent flushes are serialized. The type is `Send + Sync` and is
+/// typically wrapped in an `Arc`.
+///
+/// This is the easy path. For throughput, prefer having each thread fill its
+/// own [`Buffer`] and hand it to a single flushing thread, or use a
+/// [`SenderPool`](crate::ingress::SenderPool): with a `SharedSender`, threads
+/// wait on each other for the whole network round trip of every flush.
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use std::sync::Arc;
+/// use questdb::ingress::{Sender, SharedSender};
+///
+/// # fn main() -> Result<()> {
+/// let sender = Arc::new(SharedSender::new(Sender::from_conf("tcp::addr=localhost:9009;")?));
+/// let handles: Vec<_> = (0..4)
+///     .map(|i| {
+///         let sender = Arc::clone(&sender);
+///         std::thread::spawn(move || -> Result<()> {
+///             let mut buffer = sender.new_buffer();
+///             buffer.table("x")?.column_i64("thread", i)?.at_now()?;
+///             sender.flush(&mut buffer)
+///         })
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SharedSender {
+    sender: Mutex<Sender>,
+}
+
+impl SharedSender {
+    /// Wrap a sender so it can be shared across threads.
+    pub fn new(sender: Sender) -> Self {
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// Unwrap the underlying sender.
+    pub fn into_inner(self) -> Sender {
+        self.sender
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Lock the underlying sender for a sequence of calls.
+    ///
+    /// Other threads block until the returned guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Sender> {
+        // A panic while holding the lock can't leave the sender in a state that
+        // is unsafe to use: at worst it must be closed, which it reports itself.
+        self.sender
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// See [`Sender::flush`].
+    pub fn flush(&self, buf: &mut Buffer) -> Result<()> {
+        self.lock().flush(buf)
+    }
+
+    /// See [`Sender::flush_and_keep`].
+    pub fn flush_and_keep(&self, buf: &Buffer) -> Result<()> {
+        self.lock().flush_and_keep(buf)
+    }
+
+    /// See [`Sender::flush_and_keep_with_flags`].
+    #[cfg(feature = "ilp-over-http")]
+    pub fn flush_and_keep_with_flags(&self, buf: &Buffer, transactional: bool) -> Result<()> {
+        self.lock().flush_and_keep_with_flags(buf, transactional)
+    }
+
+    /// See [`Sender::send_raw`].
+    pub fn send_raw(&self, bytes: &[u8]) -> Result<()> {
+        self.lock().send_raw(bytes)
+    }
+
+    /// See [`Sender::at`].
+    pub fn at<T>(&self, buf: &mut Buffer, timestamp: T) -> Result<()>
+    where
+        T: TryInto<Timestamp>,
+        crate::Error: From<T::Error>,
+    {
+        self.lock().at(buf, timestamp)
+    }
+
+    /// See [`Sender::at_now`].
+    pub fn at_now(&self, buf: &mut Buffer) -> Result<()> {
+        self.lock().at_now(buf)
+    }
+
+    /// See [`Sender::reconnect`].
+    pub fn reconnect(&self) -> Result<()> {
+        self.lock().reconnect()
+    }
+
+    /// See [`Sender::check_connection`].
+    pub fn check_connection(&self) -> Result<()> {
+        self.lock().check_connection()
+    }
+
+    /// See [`Sender::ping`].
+    pub fn ping(&self) -> Result<()> {
+        self.lock().ping()
+    }
+
+    /// See [`Sender::must_close`].
+    pub fn must_close(&self) -> bool {
+        self.lock().must_close()
+    }
+
+    /// See [`Sender::current_host`].
+    pub fn current_host(&self) -> Option<Service> {
+        self.lock().current_host()
+    }
+
+    /// See [`Sender::peer_addr`].
+    pub fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.lock().peer_addr()
+    }
+
+    /// See [`Sender::send_buffer_size`].
+    pub fn send_buffer_size(&self) -> Option<usize> {
+        self.lock().send_buffer_size()
+    }
+
+    /// See [`Sender::recv_buffer_size`].
+    pub fn recv_buffer_size(&self) -> Option<usize> {
+        self.lock().recv_buffer_size()
+    }
+
+    /// See [`Sender::connect_timings`].
+    pub fn connect_timings(&self) -> Option<ConnectTimings> {
+        self.lock().connect_timings()
+    }
+
+    /// See [`Sender::total_rows_sent`].
+    pub fn total_rows_sent(&self) -> u64 {
+        self.lock().total_rows_sent()
+    }
+
+    /// See [`Sender::stats`].
+    pub fn stats(&self) -> SenderStats {
+        self.lock().stats()
+    }
+
+    /// See [`Sender::reset_stats`].
+    pub fn reset_stats(&self) {
+        self.lock().reset_stats()
+    }
+
+    /// See [`Sender::spilled_bytes`].
+    pub fn spilled_bytes(&self) -> u64 {
+        self.lock().spilled_bytes()
+    }
+
+    /// See [`Sender::pending_spill`].
+    pub fn pending_spill(&self) -> u64 {
+        self.lock().pending_spill()
+    }
+
+    /// See [`Sender::replay_now`].
+    pub fn replay_now(&self) -> Result<()> {
+        self.lock().replay_now()
+    }
+
+    /// See [`Sender::protocol_version`].
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.lock().protocol_version()
+    }
+
+    /// See [`Sender::new_buffer`].
+    pub fn new_buffer(&self) -> Buffer {
+        self.lock().new_buffer()
+    }
+}
+
+impl From<Sender> for SharedSender {
+    fn from(sender: Sender) -> Self {
+        Self::new(sender)
+    }
+}
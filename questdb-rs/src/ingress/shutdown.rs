@@ -0,0 +1,184 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::ingress::observer::notify;
+use crate::ingress::FlushObserver;
+
+/// The point in time by which a worker's shutdown must complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// A deadline at the given instant.
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    /// A deadline the given duration from now.
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    /// The instant of the deadline.
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Tell whether the deadline has passed.
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+/// What became of the rows sent to a worker over its lifetime, as returned
+/// when shutting it down.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShutdownReport {
+    /// The rows sent to the database.
+    pub rows_flushed: u64,
+
+    /// The rows dropped, because a flush failed or because they were still
+    /// queued when the shutdown deadline passed.
+    pub rows_dropped: u64,
+
+    /// The rows of failed flushes written to the sender's
+    /// [spill directory](crate::ingress::SenderBuilder::spill_dir).
+    pub rows_spilled: u64,
+
+    /// The error of the latest failed flush, if any.
+    pub last_error: Option<Error>,
+}
+
+/// Tracks a worker's rows and whether it's shutting down, shared between its
+/// handles and the worker itself.
+#[derive(Default)]
+pub(crate) struct WorkerState {
+    closing: AtomicBool,
+    deadline: Mutex<Option<Deadline>>,
+    rows_queued: AtomicU64,
+    rows_flushed: AtomicU64,
+    rows_dropped: AtomicU64,
+    rows_spilled: AtomicU64,
+    last_error: Mutex<Option<Error>>,
+
+    /// Told about dropped rows.
+    observer: Option<Arc<dyn FlushObserver>>,
+}
+
+impl Debug for WorkerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerState")
+            .field("closing", &self.closing)
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WorkerState {
+    pub(crate) fn with_observer(observer: Option<Arc<dyn FlushObserver>>) -> Self {
+        Self {
+            observer,
+            ..Self::default()
+        }
+    }
+
+    /// Stop accepting rows. Should several deadlines be given, the earliest
+    /// one applies.
+    pub(crate) fn close(&self, deadline: Option<Deadline>) {
+        if let Some(deadline) = deadline {
+            let mut current = self.deadline.lock().unwrap();
+            *current = Some(current.map_or(deadline, |current| current.min(deadline)));
+        }
+        self.closing.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Deadline> {
+        *self.deadline.lock().unwrap()
+    }
+
+    pub(crate) fn past_deadline(&self) -> bool {
+        self.deadline()
+            .is_some_and(|deadline| deadline.has_passed())
+    }
+
+    /// Count rows about to be queued. Undo with [`unqueued`](Self::unqueued)
+    /// if they couldn't be.
+    pub(crate) fn queued(&self, rows: usize) {
+        self.rows_queued.fetch_add(rows as u64, Ordering::SeqCst);
+    }
+
+    pub(crate) fn unqueued(&self, rows: usize) {
+        self.rows_queued.fetch_sub(rows as u64, Ordering::SeqCst);
+    }
+
+    pub(crate) fn flushed(&self, rows: usize) {
+        self.rows_flushed.fetch_add(rows as u64, Ordering::SeqCst);
+    }
+
+    pub(crate) fn dropped(&self, rows: usize) {
+        if rows == 0 {
+            return;
+        }
+        self.rows_dropped.fetch_add(rows as u64, Ordering::SeqCst);
+        notify(&self.observer, |observer| observer.on_rows_dropped(rows));
+    }
+
+    /// Count the rows of a failed flush written to the spill directory.
+    pub(crate) fn spilled(&self, rows: usize) {
+        self.rows_spilled.fetch_add(rows as u64, Ordering::SeqCst);
+    }
+
+    pub(crate) fn failed(&self, rows: usize, err: &Error) {
+        self.dropped(rows);
+        *self.last_error.lock().unwrap() = Some(err.clone());
+    }
+
+    /// The rows neither flushed, dropped nor spilled yet count as dropped:
+    /// Once the deadline has passed, the worker won't flush them.
+    pub(crate) fn report(&self) -> ShutdownReport {
+        let flushed = self.rows_flushed.load(Ordering::SeqCst);
+        let dropped = self.rows_dropped.load(Ordering::SeqCst);
+        let spilled = self.rows_spilled.load(Ordering::SeqCst);
+        let pending = self
+            .rows_queued
+            .load(Ordering::SeqCst)
+            .saturating_sub(flushed + dropped + spilled);
+        ShutdownReport {
+            rows_flushed: flushed,
+            rows_dropped: dropped + pending,
+            rows_spilled: spilled,
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+}
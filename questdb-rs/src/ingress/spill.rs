@@ -0,0 +1,649 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Spill files hold the rows a [`Sender`](crate::ingress::Sender) couldn't
+//! flush, see [`SenderBuilder::spill_dir`](crate::ingress::SenderBuilder::spill_dir).
+//!
+//! A spill file starts with a header:
+//!
+//! | Bytes | Content                              |
+//! |-------|--------------------------------------|
+//! | 8     | The magic bytes `QDBSPILL`.          |
+//! | 4     | The format version, little-endian.   |
+//!
+//! followed by records, one per spilled buffer:
+//!
+//! | Bytes | Content                                                 |
+//! |-------|---------------------------------------------------------|
+//! | 4     | The length of the ILP, little-endian.                   |
+//! | 4     | The CRC-32 of the next two fields, little-endian.       |
+//! | 1     | The ILP protocol version the rows require.              |
+//! | N     | The ILP.                                                |
+//!
+//! A record is appended in a single write. Should the process crash midway,
+//! the torn record fails its checksum or is cut short, and reading stops
+//! there. A writer never appends after a failed write, so a torn record is
+//! always the last one in its file.
+//!
+//! Replaying a file records the offset of the next record to send in a
+//! checkpoint file next to it, e.g. `00000000000000000001.offset` for
+//! `00000000000000000001.qdbspill`, so that a replay interrupted by a crash
+//! resumes where it stopped. Once replayed, the file is deleted, then its
+//! checkpoint. A file that can't be read in full is renamed to
+//! `00000000000000000001.corrupt` instead, and no longer replayed.
+//!
+//! A record the server refuses for good, so that sending it again would fail
+//! the same way, is appended to `00000000000000000001.rejected`, itself a
+//! spill file, and the replay moves on to the next record.
+//!
+//! A writer holds an exclusive advisory lock on the file it appends to, and a
+//! replay locks each file before reading it, skipping those it can't lock. So
+//! senders sharing a directory never replay a file that another one is still
+//! writing or replaying.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{self, Result};
+use crate::ingress::ProtocolVersion;
+
+const MAGIC: &[u8; 8] = b"QDBSPILL";
+
+/// Bump on any change to the layout of the header or the records.
+const FORMAT_VERSION: u32 = 1;
+
+const HEADER_LEN: u64 = 12;
+const RECORD_HEADER_LEN: usize = 9;
+const FILE_EXTENSION: &str = "qdbspill";
+
+/// The spill files in the directory, oldest first.
+///
+/// Files are named after a sequence number, e.g. `00000000000000000001.qdbspill`,
+/// which increases with each file a [`Sender`](crate::ingress::Sender)
+/// starts. A missing directory has no spill files.
+pub fn spill_files<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if let Some(seq) = spill_file_seq(&path) {
+            files.push((seq, path));
+        }
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+fn spill_file_seq(path: &Path) -> Option<u64> {
+    if path.extension()? != FILE_EXTENSION {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+fn spill_file_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{seq:020}.{FILE_EXTENSION}"))
+}
+
+fn checkpoint_path(path: &Path) -> PathBuf {
+    path.with_extension("offset")
+}
+
+/// The offset recorded by the file's checkpoint, if any.
+fn read_checkpoint(path: &Path) -> io::Result<Option<u64>> {
+    match fs::read(checkpoint_path(path)) {
+        Ok(data) => Ok(data.try_into().ok().map(u64::from_le_bytes)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Atomically replace the file's checkpoint.
+fn write_checkpoint(path: &Path, offset: u64) -> io::Result<()> {
+    let checkpoint = checkpoint_path(path);
+    let tmp = checkpoint.with_extension("offset.tmp");
+    fs::write(&tmp, offset.to_le_bytes())?;
+    fs::rename(&tmp, &checkpoint)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// The size of the spill files in a directory.
+struct DirSize {
+    /// All their bytes.
+    total: u64,
+
+    /// The bytes of the records not replayed yet.
+    backlog: u64,
+}
+
+impl DirSize {
+    fn scan(dir: &Path) -> io::Result<Self> {
+        let mut size = Self {
+            total: 0,
+            backlog: 0,
+        };
+        for path in spill_files(dir)? {
+            let len = fs::metadata(&path)?.len();
+            let start = read_checkpoint(&path)?.unwrap_or(HEADER_LEN);
+            size.total += len;
+            size.backlog += len.saturating_sub(start);
+        }
+        Ok(size)
+    }
+}
+
+fn file_header() -> [u8; HEADER_LEN as usize] {
+    let mut header = [0u8; HEADER_LEN as usize];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header
+}
+
+fn crc(protocol_version: u8, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[protocol_version]);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+fn encode_record(len: u32, payload: &[u8], protocol_version: ProtocolVersion) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&crc(protocol_version as u8, payload).to_le_bytes());
+    record.push(protocol_version as u8);
+    record.extend_from_slice(payload);
+    record
+}
+
+/// What became of a replayed record.
+pub(crate) enum Replayed {
+    Sent,
+
+    /// The server refused the record for good: Sending it again would fail
+    /// the same way.
+    Refused(crate::Error),
+}
+
+/// Appends the rows a sender couldn't flush to a spill file of its own, and
+/// replays the directory's spill files.
+#[derive(Debug)]
+pub(crate) struct SpillWriter {
+    dir: PathBuf,
+    max_bytes: u64,
+
+    /// The file being appended to, started by the first spill.
+    file: Option<File>,
+
+    /// The size of all the spill files in the directory, as of when it was
+    /// last scanned, plus the bytes appended since.
+    dir_bytes: u64,
+
+    /// The bytes of records waiting to be replayed, tracked the same way.
+    backlog: u64,
+
+    /// The ILP bytes appended over the writer's lifetime.
+    spilled_bytes: u64,
+}
+
+impl SpillWriter {
+    /// Take stock of the files earlier senders left in the directory.
+    pub(crate) fn open(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        let size = DirSize::scan(&dir).map_err(|io_err| {
+            error::fmt!(
+                SpillError,
+                "Could not read spill directory {:?}: {}",
+                dir,
+                io_err
+            )
+            .with_io_source(io_err)
+        })?;
+        Ok(Self {
+            dir,
+            max_bytes,
+            file: None,
+            dir_bytes: size.total,
+            backlog: size.backlog,
+            spilled_bytes: 0,
+        })
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn spilled_bytes(&self) -> u64 {
+        self.spilled_bytes
+    }
+
+    pub(crate) fn backlog(&self) -> u64 {
+        self.backlog
+    }
+
+    /// Durably append a record, unless the directory's spill files would then
+    /// exceed `max_bytes`.
+    pub(crate) fn append(
+        &mut self,
+        payload: &[u8],
+        protocol_version: ProtocolVersion,
+    ) -> io::Result<()> {
+        let len = u32::try_from(payload.len()).map_err(|_| {
+            io::Error::other(format!(
+                "{} bytes are too many for a single record",
+                payload.len()
+            ))
+        })?;
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => self.start_file()?,
+        };
+        let record_len = (RECORD_HEADER_LEN + payload.len()) as u64;
+        if self.dir_bytes + record_len > self.max_bytes {
+            self.file = Some(file);
+            return Err(io::Error::other(format!(
+                "{} more bytes would exceed the limit of {} bytes",
+                record_len, self.max_bytes
+            )));
+        }
+
+        let record = encode_record(len, payload, protocol_version);
+        // Should the write fail, the record may be torn: Appending after it
+        // would make the next records unreadable, so the next spill starts a
+        // new file instead.
+        file.write_all(&record)?;
+        file.sync_data()?;
+        self.file = Some(file);
+        self.dir_bytes += record_len;
+        self.backlog += record_len;
+        self.spilled_bytes += payload.len() as u64;
+        Ok(())
+    }
+
+    fn start_file(&mut self) -> io::Result<File> {
+        fs::create_dir_all(&self.dir)?;
+        let size = DirSize::scan(&self.dir)?;
+        let mut last_seq = spill_files(&self.dir)?
+            .last()
+            .and_then(|path| spill_file_seq(path))
+            .unwrap_or(0);
+
+        // Another sender spilling to the same directory may claim a sequence
+        // number first.
+        let mut file = loop {
+            last_seq += 1;
+            match OpenOptions::new()
+                .append(true)
+                .create_new(true)
+                .open(spill_file_path(&self.dir, last_seq))
+            {
+                Ok(file) => break file,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        // Keep replays off the file for as long as it's appended to. Until
+        // then, it's empty and replays skip it anyway.
+        file.lock()?;
+
+        // A replay may have left the checkpoint of a deleted file by the
+        // same name.
+        remove_if_exists(&checkpoint_path(&spill_file_path(&self.dir, last_seq)))?;
+        file.write_all(&file_header())?;
+        self.dir_bytes = size.total + HEADER_LEN;
+        self.backlog = size.backlog;
+        Ok(file)
+    }
+
+    /// Send the records of the directory's spill files, oldest first,
+    /// deleting each file once sent in full. A refused record is set aside
+    /// in the file's `.rejected` file. Stops at the first record `send`
+    /// fails on.
+    ///
+    /// Files locked by another writer or replay are skipped, as are empty
+    /// ones, which a writer may be about to lock.
+    pub(crate) fn replay<F>(&mut self, mut send: F) -> Result<()>
+    where
+        F: FnMut(&SpillRecord) -> Result<Replayed>,
+    {
+        // Rows spilled from now on go behind the backlog.
+        self.file = None;
+        let result = spill_files(&self.dir)
+            .map_err(|io_err| replay_error(&self.dir, io_err))
+            .and_then(|files| {
+                files
+                    .iter()
+                    .try_for_each(|path| replay_file(path, &mut send))
+            });
+        let size = DirSize::scan(&self.dir).map_err(|io_err| replay_error(&self.dir, io_err))?;
+        self.dir_bytes = size.total;
+        self.backlog = size.backlog;
+        result
+    }
+}
+
+fn replay_error(path: &Path, io_err: io::Error) -> crate::Error {
+    error::fmt!(SpillError, "Could not replay {:?}: {}", path, io_err).with_io_source(io_err)
+}
+
+fn replay_file<F>(path: &Path, send: &mut F) -> Result<()>
+where
+    F: FnMut(&SpillRecord) -> Result<Replayed>,
+{
+    let io_err = |io_err| replay_error(path, io_err);
+    let file = match File::open(path) {
+        Ok(file) => file,
+        // Replayed by another sender since the directory was listed.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(io_err(err)),
+    };
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            ingress_log!(
+                debug,
+                "Skipping spill file {:?}, as another sender holds it.",
+                path
+            );
+            return Ok(());
+        }
+        Err(TryLockError::Error(err)) => return Err(io_err(err)),
+    }
+    if file.metadata().map_err(io_err)?.len() == 0 {
+        return Ok(());
+    }
+    let mut reader = match SpillReader::from_file(path.to_path_buf(), file) {
+        Ok(reader) => reader,
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            return set_aside(path, &err.to_string()).map_err(io_err);
+        }
+        Err(err) => return Err(io_err(err)),
+    };
+    if let Some(offset) = read_checkpoint(path).map_err(io_err)? {
+        reader.seek_to(offset).map_err(io_err)?;
+    }
+    while let Some(record) = reader.next_record().map_err(io_err)? {
+        if let Replayed::Refused(err) = send(&record)? {
+            reject(path, &record, &err).map_err(io_err)?;
+        }
+        write_checkpoint(path, reader.offset()).map_err(io_err)?;
+    }
+    if reader.is_corrupt() {
+        set_aside(path, "it can't be read in full").map_err(io_err)?;
+    } else {
+        fs::remove_file(path).map_err(io_err)?;
+    }
+    remove_if_exists(&checkpoint_path(path)).map_err(io_err)
+}
+
+/// Keep a file that can't be replayed for inspection, out of the way of
+/// the next replays.
+fn set_aside(path: &Path, reason: &str) -> io::Result<()> {
+    let corrupt = path.with_extension("corrupt");
+    ingress_log!(
+        warn,
+        "Renaming spill file {:?} to {:?}, as {}.",
+        path,
+        corrupt,
+        reason
+    );
+    fs::rename(path, &corrupt)?;
+    remove_if_exists(&checkpoint_path(path))
+}
+
+/// Append a record the server refused to the file's `.rejected` file, so
+/// that it's kept for inspection but no longer replayed.
+fn reject(path: &Path, record: &SpillRecord, err: &crate::Error) -> io::Result<()> {
+    let rejected = path.with_extension("rejected");
+    ingress_log!(
+        warn,
+        "Moving a spilled record of {} bytes from {:?} to {:?}, as it was refused: {}",
+        record.payload.len(),
+        path,
+        rejected,
+        err
+    );
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&rejected)?;
+    if file.metadata()?.len() == 0 {
+        file.write_all(&file_header())?;
+    }
+    // The record was read back from a spill file, so its length fits.
+    let len = record.payload.len() as u32;
+    file.write_all(&encode_record(
+        len,
+        &record.payload,
+        record.protocol_version,
+    ))?;
+    file.sync_data()
+}
+
+/// One spilled buffer's worth of complete rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillRecord {
+    protocol_version: ProtocolVersion,
+    payload: Vec<u8>,
+}
+
+impl SpillRecord {
+    /// The ILP protocol version the rows were encoded for.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// The rows, as ILP.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Take the rows, as ILP.
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+}
+
+/// Reads back the records of a spill file.
+///
+/// Reading stops at the first record that's cut short or fails its checksum,
+/// as left by a crash mid-write: Nothing after it is trusted. Tell such a file
+/// apart with [`is_corrupt`](SpillReader::is_corrupt).
+///
+/// ```no_run
+/// use questdb::ingress::{spill_files, SpillReader};
+///
+/// # fn main() -> std::io::Result<()> {
+/// for path in spill_files("/var/spill")? {
+///     let mut reader = SpillReader::open(&path)?;
+///     while let Some(record) = reader.next_record()? {
+///         println!("{}", String::from_utf8_lossy(record.payload()));
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SpillReader {
+    path: PathBuf,
+    reader: BufReader<File>,
+    file_len: u64,
+    offset: u64,
+    corrupt: bool,
+}
+
+impl SpillReader {
+    /// Open a spill file, failing with [`io::ErrorKind::InvalidData`] if it
+    /// isn't one or has a format version this library can't read.
+    ///
+    /// A file whose header was cut short has no records and is
+    /// [corrupt](SpillReader::is_corrupt).
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        Self::from_file(path, file)
+    }
+
+    fn from_file(path: PathBuf, file: File) -> io::Result<Self> {
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut header = [0u8; HEADER_LEN as usize];
+        let read = read_fully(&mut reader, &mut header)?;
+        let mut spill_reader = Self {
+            path,
+            reader,
+            file_len,
+            offset: HEADER_LEN,
+            corrupt: false,
+        };
+        if read < header.len() && header[..read] == file_header()[..read] {
+            // A crash right after starting the file.
+            spill_reader.offset = 0;
+            spill_reader.stop("its header is cut short");
+            return Ok(spill_reader);
+        }
+        if read < header.len() || &header[..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a spill file",
+            ));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported spill file format version {}, expected {}",
+                    version, FORMAT_VERSION
+                ),
+            ));
+        }
+        Ok(spill_reader)
+    }
+
+    /// Read the next record, or `None` at the end of the file or of its
+    /// readable records.
+    pub fn next_record(&mut self) -> io::Result<Option<SpillRecord>> {
+        if self.corrupt {
+            return Ok(None);
+        }
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        let read = read_fully(&mut self.reader, &mut header)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as u64;
+        let expected_crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let record_len = RECORD_HEADER_LEN as u64 + len;
+        if read < header.len() || self.offset + record_len > self.file_len {
+            return Ok(self.stop("it's cut short"));
+        }
+        let mut payload = vec![0u8; len as usize];
+        if read_fully(&mut self.reader, &mut payload)? < payload.len() {
+            return Ok(self.stop("it's cut short"));
+        }
+        if crc(header[8], &payload) != expected_crc {
+            return Ok(self.stop("its checksum doesn't match"));
+        }
+        let protocol_version = match header[8] {
+            1 => ProtocolVersion::V1,
+            2 => ProtocolVersion::V2,
+            _ => return Ok(self.stop("its protocol version is unknown")),
+        };
+        self.offset += record_len;
+        Ok(Some(SpillRecord {
+            protocol_version,
+            payload,
+        }))
+    }
+
+    /// The offset of the next record.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Continue reading from the given offset, as recorded by a checkpoint.
+    /// An offset outside the file's records is ignored.
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        if offset < HEADER_LEN || offset > self.file_len || self.corrupt {
+            ingress_log!(
+                warn,
+                "Replaying spill file {:?} from the start, as its checkpoint offset {} is invalid.",
+                self.path,
+                offset
+            );
+            return Ok(());
+        }
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        Ok(())
+    }
+
+    /// The path of the spill file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Tell whether reading stopped at a record that was cut short or
+    /// corrupt, rather than at the end of the file.
+    pub fn is_corrupt(&self) -> bool {
+        self.corrupt
+    }
+
+    fn stop(&mut self, reason: &str) -> Option<SpillRecord> {
+        ingress_log!(
+            warn,
+            "Ignoring spill file {:?} from offset {}, as {}.",
+            self.path,
+            self.offset,
+            reason
+        );
+        self.corrupt = true;
+        None
+    }
+}
+
+/// Read until the buffer is full or the end of the file, returning the
+/// number of bytes read.
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
@@ -0,0 +1,179 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::error::{self, Error, Result};
+use crate::ingress::{Buffer, Sender};
+
+/// How a [`TeeSender`] treats destinations that fail to flush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeePolicy {
+    /// Stop at the first destination that fails, skipping the remaining ones.
+    /// The flush fails.
+    FailFast,
+
+    /// Flush to every destination. The flush succeeds if at least one of them
+    /// succeeded.
+    BestEffort,
+
+    /// Flush to every destination. The flush succeeds only if all of them
+    /// succeeded.
+    RequireAll,
+}
+
+/// Sends the same rows to several destinations, e.g. to dual-write to an old
+/// and a new cluster during a migration.
+///
+/// Rows are written once to a single, shared [`Buffer`]. On flush, its
+/// contents are sent to each wrapped [`Sender`] in turn. A failure on one
+/// destination doesn't affect what's sent to the others.
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{Sender, TeePolicy, TeeSender};
+///
+/// # fn main() -> Result<()> {
+/// let mut tee = TeeSender::new(
+///     vec![
+///         Sender::from_conf("tcp::addr=old-cluster:9009;")?,
+///         Sender::from_conf("tcp::addr=new-cluster:9009;")?,
+///     ],
+///     TeePolicy::RequireAll,
+/// )?;
+/// tee.buffer().table("x")?.symbol("a", "b")?.at_now()?;
+/// tee.flush()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TeeSender {
+    senders: Vec<Sender>,
+    policy: TeePolicy,
+    buffer: Buffer,
+}
+
+impl TeeSender {
+    /// Wrap the given senders, which must not be empty.
+    pub fn new(senders: Vec<Sender>, policy: TeePolicy) -> Result<Self> {
+        if senders.is_empty() {
+            return Err(error::fmt!(
+                ConfigError,
+                "A tee sender needs at least one destination."
+            ));
+        }
+        Ok(Self {
+            senders,
+            policy,
+            buffer: Buffer::new(),
+        })
+    }
+
+    /// The policy for destinations that fail to flush.
+    pub fn policy(&self) -> TeePolicy {
+        self.policy
+    }
+
+    /// The shared buffer, to add rows to.
+    pub fn buffer(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    /// The wrapped senders, in the order they're flushed to.
+    pub fn senders(&self) -> &[Sender] {
+        &self.senders
+    }
+
+    /// The wrapped senders, e.g. to [reconnect](Sender::reconnect) one of them.
+    pub fn senders_mut(&mut self) -> &mut [Sender] {
+        &mut self.senders
+    }
+
+    /// Unwrap the senders, dropping any rows not flushed yet.
+    pub fn into_senders(self) -> Vec<Sender> {
+        self.senders
+    }
+
+    /// Send the shared buffer's rows to every destination, as the policy
+    /// dictates, keeping the buffer's contents.
+    ///
+    /// Should the flush succeed, the result holds each destination's outcome,
+    /// in order: With [`TeePolicy::BestEffort`], some of them may be errors.
+    /// Should it fail, the error lists each destination that failed.
+    ///
+    /// Note that retrying a failed flush sends the rows again to the
+    /// destinations that had succeeded.
+    pub fn flush_and_keep(&mut self) -> Result<Vec<Result<()>>> {
+        let mut results = Vec::with_capacity(self.senders.len());
+        for sender in self.senders.iter_mut() {
+            let result = sender.flush_and_keep(&self.buffer);
+            let failed = result.is_err();
+            results.push(result);
+            if failed && self.policy == TeePolicy::FailFast {
+                break;
+            }
+        }
+
+        let failures = results.iter().filter(|result| result.is_err()).count();
+        let succeeded = match self.policy {
+            TeePolicy::FailFast | TeePolicy::RequireAll => failures == 0,
+            TeePolicy::BestEffort => failures < results.len(),
+        };
+        if succeeded {
+            Ok(results)
+        } else {
+            Err(self.flush_error(&results))
+        }
+    }
+
+    /// Send the shared buffer's rows to every destination, as the policy
+    /// dictates, and clear the buffer if the flush succeeded.
+    ///
+    /// See [`flush_and_keep`](TeeSender::flush_and_keep) for the result.
+    pub fn flush(&mut self) -> Result<Vec<Result<()>>> {
+        let results = self.flush_and_keep()?;
+        self.buffer.clear();
+        Ok(results)
+    }
+
+    fn flush_error(&self, results: &[Result<()>]) -> Error {
+        let mut code = None;
+        let failures: Vec<String> = results
+            .iter()
+            .zip(self.senders.iter())
+            .filter_map(|(result, sender)| {
+                let err = result.as_ref().err()?;
+                code.get_or_insert(err.code());
+                Some(format!("{:?}: {}", sender, err.msg()))
+            })
+            .collect();
+        Error::new(
+            code.unwrap(),
+            format!(
+                "Could not flush to {} of {} destinations: {}",
+                failures.len(),
+                self.senders.len(),
+                failures.join("; ")
+            ),
+        )
+    }
+}
@@ -0,0 +1,95 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Helpers to test code that sends data through a
+//! [`Sender`](crate::ingress::Sender), without a QuestDB instance.
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// An in-memory [`IlpSink`](crate::ingress::IlpSink) that records the ILP
+/// messages flushed to it.
+///
+/// Clones share the same contents, so keep a clone to inspect what the sender
+/// wrote after handing the sink over to
+/// [`Sender::from_sink`](crate::ingress::Sender::from_sink).
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::{testing::InMemorySink, Buffer, Sender};
+///
+/// # fn main() -> Result<()> {
+/// let sink = InMemorySink::new();
+/// let mut sender = Sender::from_sink(sink.clone());
+/// let mut buffer = Buffer::new();
+/// buffer.table("x")?.symbol("a", "b")?.at_now()?;
+/// sender.flush(&mut buffer)?;
+/// assert_eq!(sink.contents_str(), "x,a=b\n");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySink {
+    contents: Arc<Mutex<Vec<u8>>>,
+}
+
+impl InMemorySink {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bytes written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.lock().clone()
+    }
+
+    /// The bytes written so far, as a string.
+    ///
+    /// ILP messages are UTF-8, so this is lossless for anything a sender
+    /// writes.
+    pub fn contents_str(&self) -> String {
+        String::from_utf8_lossy(&self.lock()).into_owned()
+    }
+
+    /// Discard the bytes written so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.contents.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl io::Write for InMemorySink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -4,6 +4,9 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 #[cfg(feature = "chrono_timestamp")]
 use chrono::{DateTime, TimeZone};
 
+#[cfg(feature = "time_timestamp")]
+use time::OffsetDateTime;
+
 /// Convert a `SystemTime` to a `Duration` to/from the UNIX epoch.
 /// Returns a tuple of (is_negative, duration).
 #[inline]
@@ -31,6 +34,26 @@ fn sys_time_convert(
     }
 }
 
+/// Narrow a date-time, given as nanoseconds since the UNIX epoch, to a timestamp
+/// in units of `nanos_per_unit` nanoseconds, rounding down like `chrono`'s
+/// `timestamp_micros`.
+///
+/// Shared by the conversions from the `chrono` and `time` crates' date-times.
+#[cfg(any(feature = "chrono_timestamp", feature = "time_timestamp"))]
+fn datetime_to_i64(
+    nanos: i128,
+    nanos_per_unit: i128,
+    dt: &impl std::fmt::Debug,
+) -> crate::Result<i64> {
+    i64::try_from(nanos.div_euclid(nanos_per_unit))
+        .map_err(|_| error::fmt!(InvalidTimestamp, "Timestamp {:?} is out of range", dt))
+}
+
+#[cfg(feature = "chrono_timestamp")]
+fn chrono_to_nanos<T: TimeZone>(dt: &DateTime<T>) -> i128 {
+    dt.timestamp() as i128 * 1_000_000_000 + dt.timestamp_subsec_nanos() as i128
+}
+
 #[inline]
 fn extract_current_timestamp(extract_fn: impl FnOnce(Duration) -> u128) -> crate::Result<i64> {
     let time = SystemTime::now();
@@ -87,7 +110,20 @@ fn extract_current_timestamp(extract_fn: impl FnOnce(Duration) -> u128) -> crate
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Copy, Clone, Debug)]
+///
+/// or
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::TimestampMicros;
+///
+/// # fn main() -> Result<()> {
+/// # #[cfg(feature = "time_timestamp")]
+/// let ts = TimestampMicros::from_offset_datetime(time::OffsetDateTime::now_utc())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct TimestampMicros(i64);
 
 impl TimestampMicros {
@@ -107,6 +143,16 @@ impl TimestampMicros {
         Self::new(dt.timestamp_micros())
     }
 
+    /// Create a timestamp from a `time::OffsetDateTime`, rounded down to
+    /// microseconds.
+    ///
+    /// Returns an [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp)
+    /// error if the date-time is out of the range of microseconds.
+    #[cfg(feature = "time_timestamp")]
+    pub fn from_offset_datetime(dt: OffsetDateTime) -> crate::Result<Self> {
+        datetime_to_i64(dt.unix_timestamp_nanos(), 1_000, &dt).map(Self)
+    }
+
     pub fn from_systemtime(time: SystemTime) -> crate::Result<Self> {
         sys_time_convert(time, |d| d.as_micros()).map(Self)
     }
@@ -168,7 +214,20 @@ impl TimestampMicros {
 /// # }
 /// ```
 ///
-#[derive(Copy, Clone, Debug)]
+/// or
+///
+/// ```
+/// # use questdb::Result;
+/// use questdb::ingress::TimestampNanos;
+///
+/// # fn main() -> Result<()> {
+/// # #[cfg(feature = "time_timestamp")]
+/// let ts = TimestampNanos::from_offset_datetime(time::OffsetDateTime::now_utc())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct TimestampNanos(i64);
 
 impl TimestampNanos {
@@ -185,20 +244,41 @@ impl TimestampNanos {
 
     #[cfg(feature = "chrono_timestamp")]
     pub fn from_datetime<T: TimeZone>(dt: DateTime<T>) -> crate::Result<Self> {
-        match dt.timestamp_nanos_opt() {
-            Some(nanos) => Ok(Self::new(nanos)),
-            None => Err(error::fmt!(
-                InvalidTimestamp,
-                "Timestamp {:?} is out of range",
-                dt
-            )),
-        }
+        datetime_to_i64(chrono_to_nanos(&dt), 1, &dt).map(Self)
+    }
+
+    /// Create a timestamp from a `time::OffsetDateTime`.
+    ///
+    /// Returns an [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp)
+    /// error if the date-time is out of the range of nanoseconds, i.e. before
+    /// 1677 or after 2262.
+    #[cfg(feature = "time_timestamp")]
+    pub fn from_offset_datetime(dt: OffsetDateTime) -> crate::Result<Self> {
+        datetime_to_i64(dt.unix_timestamp_nanos(), 1, &dt).map(Self)
     }
 
     pub fn from_systemtime(time: SystemTime) -> crate::Result<Self> {
         sys_time_convert(time, |d| d.as_nanos()).map(Self)
     }
 
+    /// Create a new timestamp from the given number of microseconds
+    /// since the UNIX epoch (UTC).
+    ///
+    /// Returns an [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp)
+    /// error if the timestamp is out of the range of nanoseconds.
+    pub fn from_micros(micros: i64) -> crate::Result<Self> {
+        scale_to_nanos(micros, 1_000, "micros")
+    }
+
+    /// Create a new timestamp from the given number of milliseconds
+    /// since the UNIX epoch (UTC).
+    ///
+    /// Returns an [`InvalidTimestamp`](crate::ErrorCode::InvalidTimestamp)
+    /// error if the timestamp is out of the range of nanoseconds.
+    pub fn from_millis(millis: i64) -> crate::Result<Self> {
+        scale_to_nanos(millis, 1_000_000, "millis")
+    }
+
     /// Get the numeric value of the timestamp.
     pub fn as_i64(&self) -> i64 {
         self.0
@@ -221,6 +301,20 @@ impl TryFrom<TimestampMicros> for TimestampNanos {
     }
 }
 
+/// Convert a timestamp in units of `nanos_per_unit` nanoseconds to
+/// nanoseconds, failing rather than wrapping around on overflow.
+fn scale_to_nanos(value: i64, nanos_per_unit: i64, unit: &str) -> crate::Result<TimestampNanos> {
+    match value.checked_mul(nanos_per_unit) {
+        Some(nanos) => Ok(TimestampNanos(nanos)),
+        None => Err(error::fmt!(
+            InvalidTimestamp,
+            "Timestamp {} {} is out of range",
+            value,
+            unit
+        )),
+    }
+}
+
 impl From<TimestampNanos> for TimestampMicros {
     fn from(ts: TimestampNanos) -> Self {
         Self(ts.as_i64() / 1000i64)
@@ -235,8 +329,8 @@ impl From<TimestampNanos> for TimestampMicros {
 /// Both of these types can `try_into()` the `Timestamp` type.
 ///
 /// Both of these can be constructed from `std::time::SystemTime`,
-/// or from `chrono::DateTime`.
-#[derive(Copy, Clone, Debug)]
+/// from `chrono::DateTime` or from `time::OffsetDateTime`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Timestamp {
     Micros(TimestampMicros),
     Nanos(TimestampNanos),
@@ -0,0 +1,634 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::{self, Error, Result};
+use crate::ingress::shutdown::WorkerState;
+use crate::ingress::{
+    Buffer, BufferTemplate, Deadline, OverflowPolicy, Sender, SenderBuilder, ShutdownReport,
+};
+
+/// Settings for a [`SenderWorker`].
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    queue_capacity: usize,
+    shutdown_timeout: Duration,
+    reconnect_attempts: u32,
+    reconnect_interval: Duration,
+    overflow_policy: OverflowPolicy,
+}
+
+impl WorkerConfig {
+    /// The default settings: A queue of 64 buffers, which blocks producers
+    /// while it's full, 10 seconds to drain it on shutdown, and 3 reconnect
+    /// attempts, 100 milliseconds apart.
+    pub fn new() -> Self {
+        Self {
+            queue_capacity: 64,
+            shutdown_timeout: Duration::from_secs(10),
+            reconnect_attempts: 3,
+            reconnect_interval: Duration::from_millis(100),
+            overflow_policy: OverflowPolicy::Block,
+        }
+    }
+
+    /// How many buffers may wait in the queue before the
+    /// [overflow policy](WorkerConfig::overflow_policy) applies. Must be at
+    /// least 1.
+    pub fn queue_capacity(mut self, value: usize) -> Self {
+        self.queue_capacity = value;
+        self
+    }
+
+    /// What [`WorkerHandle::send`] does while the queue is full, see
+    /// [`OverflowPolicy`]. The default is to block.
+    ///
+    /// Dropped rows are counted in the [`ShutdownReport`].
+    pub fn overflow_policy(mut self, value: OverflowPolicy) -> Self {
+        self.overflow_policy = value;
+        self
+    }
+
+    /// How long [`SenderWorker::shutdown`] keeps flushing the queued buffers.
+    /// The rows still queued after that are dropped.
+    pub fn shutdown_timeout(mut self, value: Duration) -> Self {
+        self.shutdown_timeout = value;
+        self
+    }
+
+    /// How many times to [reconnect](Sender::reconnect) and retry a flush
+    /// after the connection is lost, before dropping its rows.
+    pub fn reconnect_attempts(mut self, value: u32) -> Self {
+        self.reconnect_attempts = value;
+        self
+    }
+
+    /// How long to wait before each reconnect attempt.
+    pub fn reconnect_interval(mut self, value: Duration) -> Self {
+        self.reconnect_interval = value;
+        self
+    }
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+enum Message {
+    // Boxed, as a buffer is much larger than the other messages.
+    Rows(Box<Buffer>),
+    Flush(SyncSender<Result<()>>),
+    Shutdown,
+}
+
+/// The worker's queue. Unlike a channel, it lets producers evict the oldest
+/// buffers, see [`OverflowPolicy::DropOldest`].
+///
+/// Only buffers count towards the capacity.
+#[derive(Debug)]
+struct Queue {
+    capacity: usize,
+    inner: Mutex<QueueInner>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueInner {
+    messages: VecDeque<Message>,
+    buffers: usize,
+
+    /// Set once the worker no longer takes messages.
+    closed: bool,
+}
+
+impl Queue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueInner> {
+        self.inner.lock().unwrap()
+    }
+
+    /// Queue a message regardless of the capacity, unless the queue is closed.
+    fn push(&self, message: Message) -> Result<()> {
+        let mut inner = self.lock();
+        if inner.closed {
+            return Err(stopped());
+        }
+        inner.push(message);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Take the next message, waiting up to `timeout`, if given.
+    fn recv(&self, timeout: Option<Duration>) -> Option<Message> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut inner = self.lock();
+        loop {
+            if let Some(message) = inner.pop() {
+                self.changed.notify_all();
+                return Some(message);
+            }
+            inner = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        return None;
+                    }
+                    self.changed.wait_timeout(inner, timeout).unwrap().0
+                }
+                None => self.changed.wait(inner).unwrap(),
+            };
+        }
+    }
+
+    /// Take the next message without waiting, or close the queue if it's
+    /// empty.
+    fn next_or_close(&self) -> Option<Message> {
+        let mut inner = self.lock();
+        let message = inner.pop();
+        if message.is_none() {
+            inner.closed = true;
+        }
+        self.changed.notify_all();
+        message
+    }
+
+    /// Close the queue, dropping its messages, so that waiting callers of
+    /// [`WorkerHandle::flush_sync`] fail.
+    fn close(&self) {
+        let mut inner = self.lock();
+        inner.closed = true;
+        inner.messages.clear();
+        inner.buffers = 0;
+        self.changed.notify_all();
+    }
+}
+
+impl QueueInner {
+    fn push(&mut self, message: Message) {
+        if let Message::Rows(_) = message {
+            self.buffers += 1;
+        }
+        self.messages.push_back(message);
+    }
+
+    fn pop(&mut self) -> Option<Message> {
+        let message = self.messages.pop_front()?;
+        if let Message::Rows(_) = message {
+            self.buffers -= 1;
+        }
+        Some(message)
+    }
+
+    fn pop_oldest_buffer(&mut self) -> Option<Buffer> {
+        let index = self
+            .messages
+            .iter()
+            .position(|message| matches!(message, Message::Rows(_)))?;
+        self.buffers -= 1;
+        match self.messages.remove(index) {
+            Some(Message::Rows(buf)) => Some(*buf),
+            _ => None,
+        }
+    }
+}
+
+/// A [`Sender`] that flushes on a dedicated background thread.
+///
+/// Producers send complete rows via a cheap, cloneable [`WorkerHandle`]: The
+/// buffers are queued on a bounded queue and the worker thread appends them
+/// into its own buffer, which it flushes per the sender's auto-flush settings,
+/// see [`SenderBuilder::auto_flush_rows`]. Without auto-flush settings, each
+/// buffer is flushed as it arrives.
+///
+/// Should the connection be lost, the worker reconnects and retries the
+/// flush, see [`WorkerConfig::reconnect_attempts`]. Errors are reported by the
+/// next call to [`WorkerHandle::flush_sync`] or [`SenderWorker::shutdown`].
+///
+/// Dropping the worker without shutting it down flushes the queued rows for
+/// up to the [shutdown timeout](WorkerConfig::shutdown_timeout), logging a
+/// warning.
+///
+/// ```no_run
+/// # use questdb::Result;
+/// use questdb::ingress::{SenderBuilder, SenderWorker, TimestampNanos, WorkerConfig};
+///
+/// # fn main() -> Result<()> {
+/// let builder = SenderBuilder::from_conf("tcp::addr=localhost:9009;auto_flush_rows=1000;")?;
+/// let worker = SenderWorker::spawn(&builder, WorkerConfig::new())?;
+/// let handle = worker.handle();
+/// let mut buffer = handle.new_buffer();
+/// buffer.table("x")?.symbol("a", "b")?.at(TimestampNanos::now())?;
+/// handle.send(buffer)?;
+/// worker.shutdown()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SenderWorker {
+    handle: WorkerHandle,
+    shutdown_timeout: Duration,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl SenderWorker {
+    /// Build a sender and start flushing its rows on a new thread.
+    ///
+    /// This connects before returning, so connection errors are reported
+    /// here.
+    pub fn spawn(builder: &SenderBuilder, config: WorkerConfig) -> Result<Self> {
+        Self::from_sender(builder.build()?, config)
+    }
+
+    /// Start flushing the rows of an existing sender on a new thread.
+    pub fn from_sender(sender: Sender, config: WorkerConfig) -> Result<Self> {
+        if config.queue_capacity == 0 {
+            return Err(error::fmt!(
+                ConfigError,
+                "Worker queue capacity must be at least 1."
+            ));
+        }
+        let handle = WorkerHandle {
+            queue: Arc::new(Queue::new(config.queue_capacity)),
+            state: Arc::new(WorkerState::with_observer(sender.observer.clone())),
+            stopped: Arc::default(),
+            overflow_policy: config.overflow_policy,
+            buffer_template: sender.buffer_template,
+        };
+        let worker = Worker {
+            pending: sender.new_buffer(),
+            sender,
+            queue: Arc::clone(&handle.queue),
+            state: Arc::clone(&handle.state),
+            reconnect_attempts: config.reconnect_attempts,
+            reconnect_interval: config.reconnect_interval,
+            error: None,
+        };
+        let queue = Arc::clone(&handle.queue);
+        let stopped = Arc::clone(&handle.stopped);
+        let thread = thread::Builder::new()
+            .name("questdb-sender-worker".to_owned())
+            .spawn(move || {
+                // Dropping the worker closes the connection before the
+                // handles are told it has stopped.
+                let result = worker.run();
+                queue.close();
+                *stopped.0.lock().unwrap() = true;
+                stopped.1.notify_all();
+                result
+            })
+            .map_err(|io_err| {
+                error::fmt!(SocketError, "Could not start worker thread: {}", io_err)
+            })?;
+        Ok(Self {
+            handle,
+            shutdown_timeout: config.shutdown_timeout,
+            thread: Some(thread),
+        })
+    }
+
+    /// A handle to send rows to the worker.
+    pub fn handle(&self) -> WorkerHandle {
+        self.handle.clone()
+    }
+
+    /// Flush the queued rows and stop the worker thread.
+    ///
+    /// Rows still queued once the [shutdown
+    /// timeout](WorkerConfig::shutdown_timeout) has passed are dropped, and
+    /// this returns an error counting them. Otherwise, it returns the first
+    /// error not yet reported by [`WorkerHandle::flush_sync`], if any.
+    ///
+    /// Buffers sent via other handles after this call are rejected.
+    ///
+    /// Unlike [`WorkerHandle::shutdown`], this waits for the thread to exit,
+    /// even if it's blocked writing past the timeout.
+    pub fn shutdown(mut self) -> Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        self.handle.close(Deadline::after(self.shutdown_timeout));
+        thread
+            .join()
+            .unwrap_or_else(|_| Err(error::fmt!(SocketError, "The worker thread panicked.")))
+    }
+}
+
+impl Drop for SenderWorker {
+    fn drop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        ingress_log!(
+            warn,
+            "Sender worker dropped without a shutdown: Flushing the queued rows for up to {:?}.",
+            self.shutdown_timeout
+        );
+        let report = self.handle.shutdown(Deadline::after(self.shutdown_timeout));
+        if report.rows_dropped > 0 {
+            ingress_log!(warn, "Sender worker dropped {} rows.", report.rows_dropped);
+        }
+        // If the thread is blocked writing to an unresponsive server, leave
+        // it behind rather than blocking the caller.
+        if self.handle.is_stopped() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Sends rows to a [`SenderWorker`].
+///
+/// Handles are cheap to clone and can be shared across threads.
+#[derive(Debug, Clone)]
+pub struct WorkerHandle {
+    queue: Arc<Queue>,
+    state: Arc<WorkerState>,
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    overflow_policy: OverflowPolicy,
+    buffer_template: BufferTemplate,
+}
+
+impl WorkerHandle {
+    /// Create a buffer that encodes rows for the worker's sender, see
+    /// [`Sender::new_buffer`].
+    pub fn new_buffer(&self) -> Buffer {
+        self.buffer_template.new_buffer()
+    }
+
+    /// Queue the buffer's rows to be flushed.
+    ///
+    /// While the queue is full, this applies the
+    /// [overflow policy](WorkerConfig::overflow_policy): By default, it blocks.
+    ///
+    /// The buffer must end at a row boundary. This returns once the rows are
+    /// queued, not sent: Call [`flush_sync`](WorkerHandle::flush_sync) to wait
+    /// until they are.
+    pub fn send(&self, buf: Buffer) -> Result<()> {
+        self.enqueue(buf, true).map(|_| ())
+    }
+
+    /// Queue the buffer's rows to be flushed, without blocking.
+    ///
+    /// If the queue is full and the [overflow
+    /// policy](WorkerConfig::overflow_policy) is to block, this gives the
+    /// buffer back instead, so you can retry later or handle the backpressure
+    /// otherwise. The other policies apply as for
+    /// [`send`](WorkerHandle::send).
+    pub fn try_send(&self, buf: Buffer) -> Result<Option<Buffer>> {
+        self.enqueue(buf, false)
+    }
+
+    /// Flush all the rows queued so far, blocking until they are sent.
+    ///
+    /// This returns the first error the worker ran into since the last call,
+    /// if any, even if these rows were sent.
+    pub fn flush_sync(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.queue.push(Message::Flush(reply_tx))?;
+        reply_rx.recv().map_err(|_| stopped())?
+    }
+
+    /// Stop accepting rows, flush the queued ones and close the connection.
+    ///
+    /// This returns once the worker has stopped, or at the deadline
+    /// otherwise: Rows still queued by then are dropped. Should the worker be
+    /// blocked writing to an unresponsive server, the rows it hasn't sent yet
+    /// are reported as dropped too.
+    ///
+    /// Shutting down via several handles at once is fine: Each call returns
+    /// a report, and the earliest deadline applies.
+    pub fn shutdown(&self, deadline: Deadline) -> ShutdownReport {
+        self.close(deadline);
+        let (lock, cvar) = &*self.stopped;
+        let timeout = deadline.instant().saturating_duration_since(Instant::now());
+        let _stopped = cvar
+            .wait_timeout_while(lock.lock().unwrap(), timeout, |stopped| !*stopped)
+            .unwrap();
+        self.state.report()
+    }
+
+    fn close(&self, deadline: Deadline) {
+        self.state.close(Some(deadline));
+        // Wakes the worker if it's idle. Fails only if it has stopped already.
+        let _ = self.queue.push(Message::Shutdown);
+    }
+
+    fn is_stopped(&self) -> bool {
+        *self.stopped.0.lock().unwrap()
+    }
+
+    fn check(&self, buf: &Buffer) -> Result<()> {
+        if self.state.is_closing() {
+            return Err(stopped());
+        }
+        self.buffer_template.check_can_flush(buf)
+    }
+
+    /// Queue the buffer, applying the overflow policy while the queue is full.
+    /// Gives the buffer back if it would block, but mustn't.
+    fn enqueue(&self, buf: Buffer, block: bool) -> Result<Option<Buffer>> {
+        self.check(&buf)?;
+        let mut inner = self.queue.lock();
+        loop {
+            if inner.closed {
+                return Err(stopped());
+            }
+            if inner.buffers < self.queue.capacity {
+                break;
+            }
+            match self.overflow_policy {
+                OverflowPolicy::Block if block => {
+                    inner = self.queue.changed.wait(inner).unwrap();
+                }
+                OverflowPolicy::Block => return Ok(Some(buf)),
+                OverflowPolicy::DropNewest => {
+                    self.state.queued(buf.row_count());
+                    self.state.dropped(buf.row_count());
+                    return Ok(None);
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = inner.pop_oldest_buffer() {
+                        self.state.dropped(oldest.row_count());
+                    }
+                }
+                OverflowPolicy::Error => {
+                    return Err(error::fmt!(
+                        QueueFull,
+                        "Could not queue {} rows: The sender worker's queue is full.",
+                        buf.row_count()
+                    ));
+                }
+            }
+        }
+        self.state.queued(buf.row_count());
+        inner.push(Message::Rows(Box::new(buf)));
+        self.queue.changed.notify_all();
+        Ok(None)
+    }
+}
+
+fn stopped() -> Error {
+    error::fmt!(InvalidApiCall, "The sender worker was shut down.")
+}
+
+struct Worker {
+    sender: Sender,
+    pending: Buffer,
+    queue: Arc<Queue>,
+    state: Arc<WorkerState>,
+    reconnect_attempts: u32,
+    reconnect_interval: Duration,
+
+    /// The first error not yet reported.
+    error: Option<Error>,
+}
+
+impl Worker {
+    fn run(mut self) -> Result<()> {
+        let mut dropped_rows = 0;
+        loop {
+            let message = if self.state.is_closing() {
+                match self.queue.next_or_close() {
+                    Some(message) => message,
+                    None => break,
+                }
+            } else {
+                match self.queue.recv(self.flush_timeout()) {
+                    Some(message) => message,
+                    None => {
+                        self.flush();
+                        continue;
+                    }
+                }
+            };
+            match message {
+                Message::Rows(buf) if self.state.past_deadline() => {
+                    dropped_rows += buf.row_count();
+                    self.state.dropped(buf.row_count());
+                }
+                Message::Rows(buf) => {
+                    if self.pending.len() + buf.len() > self.sender.buffer_template.max_buf_size {
+                        self.flush();
+                    }
+                    self.pending.append_rows(&buf);
+                    if !self.sender.auto_flush_enabled()
+                        || self.sender.auto_flush_due(&self.pending)
+                    {
+                        self.flush();
+                    }
+                }
+                Message::Flush(reply) => {
+                    self.flush();
+                    let _ = reply.send(self.take_error());
+                }
+                // The queue is drained before stopping.
+                Message::Shutdown => {}
+            }
+        }
+
+        if self.state.past_deadline() {
+            dropped_rows += self.pending.row_count();
+            self.state.dropped(self.pending.row_count());
+        } else {
+            self.flush();
+        }
+        if dropped_rows > 0 {
+            return Err(error::fmt!(
+                SocketError,
+                "Could not flush all rows before the shutdown timeout: Dropped {} rows.",
+                dropped_rows
+            ));
+        }
+        self.take_error()
+    }
+
+    /// How long until the pending rows are due for an interval auto-flush.
+    fn flush_timeout(&self) -> Option<Duration> {
+        self.sender
+            .auto_flush_interval
+            .zip(self.pending.state.first_row_at)
+            .map(|(interval, first_row_at)| interval.saturating_sub(first_row_at.elapsed()))
+    }
+
+    /// Flush the pending rows, reconnecting as configured if the connection
+    /// is lost. Should that fail, the rows are dropped, unless the sender
+    /// spilled them.
+    fn flush(&mut self) {
+        let rows = self.pending.row_count();
+        let mut spilled = 0;
+        let mut attempts = 0;
+        let err = loop {
+            let err = match self.sender.flush(&mut self.pending) {
+                // Once spilled, there's nothing left to flush: Only the
+                // connection is retried.
+                Ok(()) if spilled > 0 => {
+                    self.state.spilled(spilled);
+                    return;
+                }
+                Ok(()) => {
+                    self.state.flushed(rows);
+                    return;
+                }
+                Err(err) => err,
+            };
+            if self.pending.is_empty() {
+                spilled = rows;
+            }
+            if !self.sender.must_close() || attempts == self.reconnect_attempts {
+                break err;
+            }
+            attempts += 1;
+            thread::sleep(self.reconnect_interval);
+            if let Err(reconnect_err) = self.sender.reconnect() {
+                if attempts == self.reconnect_attempts {
+                    break reconnect_err;
+                }
+            }
+        };
+        self.pending.clear();
+        self.state.spilled(spilled);
+        self.state.failed(rows - spilled, &err);
+        self.error.get_or_insert(err);
+    }
+
+    fn take_error(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
@@ -22,9 +22,17 @@
  *
  ******************************************************************************/
 
-#![doc = include_str!("../README.md")]
+#![cfg_attr(feature = "net", doc = include_str!("../README.md"))]
+#![cfg_attr(
+    not(feature = "net"),
+    doc = "Client library for [QuestDB](https://questdb.io/), built without its \
+           `net` feature: It serializes rows into an [`ingress::Buffer`] as \
+           InfluxDB Line Protocol (ILP), leaving it to you to send them. Enable \
+           `net` for the `Sender`, which connects to QuestDB and sends them."
+)]
 
 mod error;
+#[cfg(feature = "net")]
 mod gai;
 pub mod ingress;
 
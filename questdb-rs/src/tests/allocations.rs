@@ -0,0 +1,163 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+//! Checks that the steady-state hot path doesn't allocate, by counting the
+//! allocations made on the test's own thread.
+
+use crate::ingress::{Buffer, TimestampMicros, TimestampNanos};
+use crate::tests::TestResult;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // Allocations made while the thread is torn down aren't counted.
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations `f` made on this thread.
+fn allocations<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (ALLOCATIONS.with(Cell::get) - before, result)
+}
+
+const ROWS: i64 = 10_000;
+
+fn write_rows(buffer: &mut Buffer) -> crate::Result<()> {
+    for row in 0..ROWS {
+        buffer
+            .table(if row % 2 == 0 { "trades" } else { "quotes" })?
+            .symbol("symbol", "ETH-USD")?
+            .symbol("side", "sell")?
+            .column_bool("filled", row % 3 == 0)?
+            .column_i64("count", row)?
+            .column_f64("price", 2615.54 + row as f64)?
+            .column_str("note", "needs \"escaping\",\nsometimes")?
+            .column_ts("ts", TimestampMicros::new(row))?
+            .at(TimestampNanos::new(row))?;
+        buffer
+            .table("heartbeats")?
+            .column_i64("row", row)?
+            .at_now()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_buffer_steady_state_does_not_allocate() -> TestResult {
+    let mut buffer = Buffer::new();
+    let (warm_up, result) = allocations(|| write_rows(&mut buffer));
+    result?;
+    assert!(warm_up > 0);
+    let len = buffer.len();
+    buffer.clear();
+
+    let (count, result) = allocations(|| write_rows(&mut buffer));
+    result?;
+    assert_eq!(count, 0);
+    assert_eq!(buffer.len(), len);
+    assert_eq!(buffer.row_count(), 2 * ROWS as usize);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_with_capacity_does_not_allocate() -> TestResult {
+    let mut buffer = Buffer::with_capacity(64 * 1024);
+    buffer.table("x")?.symbol("a", "b")?.at_now()?;
+    buffer.clear();
+    let (count, result) = allocations(|| -> crate::Result<()> {
+        for row in 0..ROWS {
+            buffer.table("x")?.column_i64("a", row)?.at_now()?;
+            if buffer.len() > 32 * 1024 {
+                buffer.clear();
+            }
+        }
+        Ok(())
+    });
+    result?;
+    assert_eq!(count, 0);
+    Ok(())
+}
+
+#[test]
+fn test_errors_allocate() -> TestResult {
+    let mut buffer = Buffer::with_capacity(1024);
+    let (count, result) = allocations(|| buffer.symbol("a", "b").map(|_| ()));
+    assert!(result.is_err());
+    assert!(count > 0);
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_flush_steady_state_does_not_allocate() -> TestResult {
+    use crate::ingress::Sender;
+
+    let mut sender = Sender::from_sink(std::io::sink());
+    let mut buffer = sender.new_buffer();
+    write_rows(&mut buffer)?;
+    sender.flush(&mut buffer)?;
+
+    let (count, result) = allocations(|| -> crate::Result<()> {
+        for _ in 0..3 {
+            write_rows(&mut buffer)?;
+            sender.flush(&mut buffer)?;
+        }
+        Ok(())
+    });
+    result?;
+    assert_eq!(count, 0);
+    assert!(buffer.is_empty());
+    Ok(())
+}
@@ -0,0 +1,384 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{
+    AsyncSenderWorker, Deadline, OverflowPolicy, Protocol, SenderBuilder, TimestampNanos,
+};
+use crate::tests::TestResult;
+use crate::ErrorCode;
+
+use base64ct::{Base64, Base64UrlUnpadded, Encoding};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+
+async fn listen() -> std::io::Result<(TcpListener, SenderBuilder)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let builder = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port);
+    Ok((listener, builder))
+}
+
+async fn read_to_end(mut conn: TcpStream) -> std::io::Result<String> {
+    let mut received = String::new();
+    conn.read_to_string(&mut received).await?;
+    Ok(received)
+}
+
+#[tokio::test]
+async fn test_async_flush() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let (sender, accepted) = tokio::join!(builder.build_async(), listener.accept());
+    let mut sender = sender?;
+    let (conn, _) = accepted?;
+
+    let mut buffer = sender.new_buffer();
+    buffer
+        .table("test")?
+        .symbol("t1", "v1")?
+        .column_f64("f1", 0.5)?
+        .at(TimestampNanos::new(10000000))?;
+    sender.flush(&mut buffer).await?;
+    assert!(buffer.is_empty());
+    buffer.table("test")?.column_i64("i1", 1)?.at_now()?;
+    sender.flush_and_keep(&buffer).await?;
+    assert!(!buffer.is_empty());
+    assert!(!sender.must_close());
+    drop(sender);
+
+    assert_eq!(
+        read_to_end(conn).await?,
+        "test,t1=v1 f1=0.5 10000000\ntest i1=1i\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_flush_incomplete_row() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let (sender, accepted) = tokio::join!(builder.build_async(), listener.accept());
+    let mut sender = sender?;
+    let _conn = accepted?;
+
+    let mut buffer = sender.new_buffer();
+    buffer.table("test")?.column_i64("i1", 1)?;
+    let err = sender.flush(&mut buffer).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_build_no_failover() -> TestResult {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost:9009,localhost:9010;")?;
+    let err = builder.build_async().await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "The async sender doesn't support failover addresses."
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_build_no_spill() -> TestResult {
+    let builder = SenderBuilder::from_conf("tcp::addr=localhost:9009;spill_dir=/tmp/spill;")?;
+    let err = builder.build_async().await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "The async sender doesn't support spilling to disk."
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_connect_error() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let port = listener.local_addr()?.port();
+    drop(listener);
+
+    let err = builder.build_async().await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err
+        .msg()
+        .starts_with(&format!("Could not connect to \"127.0.0.1:{port}\": ")));
+
+    let builder = SenderBuilder::new(Protocol::Tcp, "questdb.invalid", port);
+    let err = builder.build_async().await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CouldNotResolveAddr);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_flush_cancelled() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let builder = builder.max_buf_size(64 * 1024 * 1024)?;
+    let (sender, accepted) = tokio::join!(builder.build_async(), listener.accept());
+    let mut sender = sender?;
+    // Never read, so that the socket buffers fill up and the flush stalls.
+    let _conn = accepted?;
+
+    let mut buffer = sender.new_buffer();
+    let value = "x".repeat(16 * 1024 * 1024);
+    buffer.table("test")?.column_str("s", &value)?.at_now()?;
+    let flush = sender.flush_and_keep(&buffer);
+    assert!(tokio::time::timeout(Duration::from_millis(200), flush)
+        .await
+        .is_err());
+
+    assert!(sender.must_close());
+    let err = sender.flush(&mut buffer).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: not connected to database."
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_auth() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let builder = builder
+        .username("testUser1")?
+        .token("5UjEMuA0Pj5pjK8a-fa24dyIf-Es5mYny3oE_Wmus48")?
+        .token_x("fLKYEaoEb9lrn3nkwLDA-M_xnuFOdSt9y0Z7_vWSHLU")?
+        .token_y("Dt5tbS1dEDMSYfym3fgMv0B99szno-dFc1rYF9t0aac")?;
+
+    let server = async {
+        let (conn, _) = listener.accept().await?;
+        let mut reader = BufReader::new(conn);
+        let mut key_id = String::new();
+        reader.read_line(&mut key_id).await?;
+        assert_eq!(key_id, "testUser1\n");
+
+        let challenge = b"some-challenge";
+        reader.get_mut().write_all(challenge).await?;
+        reader.get_mut().write_all(b"\n").await?;
+        let mut signature = String::new();
+        reader.read_line(&mut signature).await?;
+
+        let mut public_key = vec![0x04];
+        public_key.extend(Base64UrlUnpadded::decode_vec(
+            "fLKYEaoEb9lrn3nkwLDA-M_xnuFOdSt9y0Z7_vWSHLU",
+        )?);
+        public_key.extend(Base64UrlUnpadded::decode_vec(
+            "Dt5tbS1dEDMSYfym3fgMv0B99szno-dFc1rYF9t0aac",
+        )?);
+        let signature = Base64::decode_vec(signature.trim_end())?;
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key)
+            .verify(challenge, &signature)
+            .expect("bad signature");
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).await?;
+        Ok::<_, crate::tests::TestError>(received)
+    };
+    let client = async {
+        let mut sender = builder.build_async().await?;
+        let mut buffer = sender.new_buffer();
+        buffer.table("test")?.column_i64("i1", 1)?.at_now()?;
+        sender.flush(&mut buffer).await?;
+        Ok::<_, crate::tests::TestError>(())
+    };
+    let (received, sent) = tokio::join!(server, client);
+    sent?;
+    assert_eq!(received?, "test i1=1i\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_worker_interval_flush() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let builder = builder.auto_flush_interval(Duration::from_secs(1))?;
+    let (worker, accepted) = tokio::join!(AsyncSenderWorker::spawn(&builder, 8), listener.accept());
+    let worker = worker?;
+    let (mut conn, _) = accepted?;
+
+    tokio::time::pause();
+    let start = Instant::now();
+    let mut buffer = worker.new_buffer();
+    buffer.table("test")?.column_i64("i1", 1)?.at_now()?;
+    worker.send(buffer).await?;
+
+    // The clock only advances to the interval deadline once the runtime is
+    // idle, so the read completes after the worker's timed flush.
+    let mut received = [0u8; 64];
+    let len = conn.read(&mut received).await?;
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(&received[..len], b"test i1=1i\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_worker_close() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let builder = builder.auto_flush_rows(1000)?;
+    let (worker, accepted) = tokio::join!(AsyncSenderWorker::spawn(&builder, 8), listener.accept());
+    let worker = worker?;
+    let (conn, _) = accepted?;
+
+    let other = worker.clone();
+    for value in 0..3 {
+        let mut buffer = other.new_buffer();
+        buffer.table("test")?.column_i64("i1", value)?.at_now()?;
+        other.send(buffer).await?;
+    }
+    worker.close().await?;
+
+    assert_eq!(
+        read_to_end(conn).await?,
+        "test i1=0i\ntest i1=1i\ntest i1=2i\n"
+    );
+    let mut buffer = other.new_buffer();
+    buffer.table("test")?.column_i64("i1", 3)?.at_now()?;
+    let err = other.send(buffer).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(err.msg(), "The sender worker was closed.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_worker_flush_error() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let (worker, accepted) = tokio::join!(AsyncSenderWorker::spawn(&builder, 8), listener.accept());
+    let worker = worker?;
+    let mut health = worker.health();
+    assert!(health.borrow_and_update().is_none());
+
+    // The server goes away, so the writes eventually fail and reconnecting is
+    // refused.
+    drop(accepted?);
+    drop(listener);
+    let flushes = async {
+        loop {
+            let mut buffer = worker.new_buffer();
+            buffer.table("test")?.column_i64("i1", 1)?.at_now()?;
+            worker.send(buffer).await?;
+            if let Err(err) = worker.flush().await {
+                return Ok::<_, crate::tests::TestError>(err);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    let err = tokio::time::timeout(Duration::from_secs(10), flushes).await??;
+    assert_eq!(err.code(), ErrorCode::SocketError);
+
+    assert!(health.has_changed()?);
+    let latest = health.borrow_and_update().clone();
+    assert_eq!(latest.map(|err| err.code()), Some(ErrorCode::SocketError));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_worker_shutdown_deadline() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let builder = builder.max_buf_size(64 * 1024 * 1024)?;
+    let (worker, accepted) = tokio::join!(AsyncSenderWorker::spawn(&builder, 8), listener.accept());
+    let worker = worker?;
+    // Never read, so that the socket buffers fill up and the flush stalls.
+    let _conn = accepted?;
+
+    let value = "x".repeat(16 * 1024 * 1024);
+    for _ in 0..2 {
+        let mut buffer = worker.new_buffer();
+        buffer.table("test")?.column_str("s", &value)?.at_now()?;
+        worker.send(buffer).await?;
+    }
+    let start = std::time::Instant::now();
+    let report = worker
+        .shutdown(Deadline::after(Duration::from_millis(200)))
+        .await;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(report.rows_flushed, 0);
+    assert_eq!(report.rows_dropped, 2);
+
+    let mut buffer = worker.new_buffer();
+    buffer.table("test")?.column_i64("i1", 1)?.at_now()?;
+    let err = worker.send(buffer).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_worker_overflow_policy() -> TestResult {
+    let cases = [
+        (OverflowPolicy::DropNewest, None),
+        (OverflowPolicy::Error, Some(ErrorCode::QueueFull)),
+    ];
+    for (policy, error) in cases {
+        let (listener, builder) = listen().await?;
+        let builder = builder.auto_flush_rows(1000)?;
+        let (worker, accepted) =
+            tokio::join!(AsyncSenderWorker::spawn(&builder, 1), listener.accept());
+        let worker = worker?.with_overflow_policy(policy)?;
+        let (conn, _) = accepted?;
+
+        // The task only runs once this one awaits something pending, so the
+        // first buffer fills the queue.
+        let mut results = Vec::new();
+        for value in 0..2 {
+            let mut buffer = worker.new_buffer();
+            buffer.table("test")?.column_i64("i1", value)?.at_now()?;
+            results.push(worker.send(buffer).await);
+        }
+        assert!(results[0].is_ok(), "{policy:?}");
+        assert_eq!(
+            results[1].as_ref().err().map(|err| err.code()),
+            error,
+            "{policy:?}"
+        );
+
+        let report = worker
+            .shutdown(Deadline::after(Duration::from_secs(10)))
+            .await;
+        assert_eq!(report.rows_flushed, 1, "{policy:?}");
+        assert_eq!(
+            report.rows_dropped,
+            u64::from(error.is_none()),
+            "{policy:?}"
+        );
+        assert_eq!(read_to_end(conn).await?, "test i1=0i\n", "{policy:?}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_worker_overflow_policy_drop_oldest() -> TestResult {
+    let (listener, builder) = listen().await?;
+    let (worker, _accepted) =
+        tokio::join!(AsyncSenderWorker::spawn(&builder, 1), listener.accept());
+    let err = worker?
+        .with_overflow_policy(OverflowPolicy::DropOldest)
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "An async sender worker's overflow policy can't be `DropOldest`."
+    );
+    Ok(())
+}
@@ -0,0 +1,1513 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::{
+    ingress::{
+        decoder::parse_lines, Buffer, ColumnName, ColumnValue, IntoColumnName, IntoTableName,
+        NumericSymbols, OwnedColumnName, OwnedTableName, ProtocolVersion, TableName, Timestamp,
+        TimestampMicros, TimestampNanos, Validation, RESERVED_NAME_CHARS,
+    },
+    ErrorCode,
+};
+
+use crate::tests::TestResult;
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[test]
+fn test_buffer_too_large_rollback() -> TestResult {
+    let mut buffer = Buffer::with_max_buf_size(20);
+    let err = buffer
+        .table("test")?
+        .symbol("t1", "a long symbol value")?
+        .at_now()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert!(buffer.is_empty());
+    assert!(buffer.transactional());
+
+    // The buffer accepts a smaller row, then rejects one for another table.
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let before = buffer.as_str().to_owned();
+    let err = buffer
+        .table("other")?
+        .symbol("t1", "v1")?
+        .at_now()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert_eq!(buffer.as_str(), before);
+    assert_eq!(buffer.row_count(), 1);
+    assert!(buffer.transactional());
+    buffer.table("test")?.symbol("t1", "v2")?;
+    Ok(())
+}
+
+#[test]
+fn test_column_int() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .column_int("a", 1u8)?
+        .column_int("b", -2i16)?
+        .column_int("c", u32::MAX)?
+        .column_int("d", i64::MIN)?
+        .at_now()?;
+    assert_eq!(
+        buffer.as_str(),
+        "test a=1i,b=-2i,c=4294967295i,d=-9223372036854775808i\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_symbols_and_columns() -> TestResult {
+    let tags = BTreeMap::from([
+        ("city".to_owned(), "London".to_owned()),
+        ("sensor".to_owned(), "a b".to_owned()),
+    ]);
+    let fields = BTreeMap::from([
+        ("f", ColumnValue::F64(0.5)),
+        ("i", ColumnValue::I64(-3)),
+        ("ok", ColumnValue::Bool(true)),
+        ("s", ColumnValue::Str("x\"y")),
+        ("ts", TimestampMicros::new(10).into()),
+    ]);
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .symbols(&tags)?
+        .columns(&fields)?
+        .at_now()?;
+    assert_eq!(
+        buffer.as_str(),
+        "test,city=London,sensor=a\\ b f=0.5,i=-3i,ok=t,s=\"x\\\"y\",ts=10t\n"
+    );
+
+    // Symbols can't follow columns, and pairs before a bad one are recorded.
+    buffer.table("test")?.columns([("c1", 1i64)])?;
+    let err = buffer.symbols([("t1", "v1")]).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    let err = buffer
+        .columns([
+            ("c2", ColumnValue::I64(2)),
+            ("bad.name", ColumnValue::I64(3)),
+        ])
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    buffer.at_now()?;
+    assert!(buffer.as_str().ends_with("test c1=1i,c2=2i\n"));
+    Ok(())
+}
+
+#[test]
+fn test_column_value() -> TestResult {
+    let name = "n".to_owned();
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .column("b", false)?
+        .column("i", 7u8)?
+        .column("j", -2i32)?
+        .column("f", 1.5f32)?
+        .column("s", &name)?
+        .column("ts", TimestampNanos::new(5000))?
+        .column("v", ColumnValue::I64(i64::MAX))?
+        .at_now()?;
+    assert_eq!(
+        buffer.as_str(),
+        "test b=f,i=7i,j=-2i,f=1.5,s=\"n\",ts=5t,v=9223372036854775807i\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_column_opt() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .symbol("t", "a")?
+        .column_opt("i", Some(3u8))?
+        .column_opt("f", None::<f64>)?
+        .column_opt("s", Some("x"))?
+        .at_now()?;
+
+    // A row of symbols with every column skipped still completes.
+    buffer
+        .table("test")?
+        .symbol("t", "b")?
+        .column_opt("i", None::<i64>)?
+        .column_opt("s", None::<&str>)?
+        .at(TimestampNanos::new(10))?;
+    assert_eq!(buffer.as_str(), "test,t=a i=3i,s=\"x\"\ntest,t=b 10\n");
+    assert_eq!(buffer.row_count(), 2);
+
+    // Skipping doesn't permit a symbol after a written column, nor a row
+    // with no fields at all.
+    buffer.table("test")?.column_i64("i", 1)?;
+    assert_eq!(
+        buffer
+            .column_opt("f", None::<f64>)?
+            .symbol("t", "c")
+            .unwrap_err()
+            .code(),
+        ErrorCode::InvalidApiCall
+    );
+    buffer.clear();
+    buffer.table("test")?.column_opt("i", None::<i64>)?;
+    assert_eq!(
+        buffer.at_now().unwrap_err().code(),
+        ErrorCode::InvalidApiCall
+    );
+    assert_eq!(
+        buffer.column_opt("", None::<i64>).unwrap_err().code(),
+        ErrorCode::InvalidName
+    );
+    Ok(())
+}
+
+#[test]
+fn test_empty_symbol_value() -> TestResult {
+    let mut buffer = Buffer::new();
+    assert!(!buffer.rejects_empty_symbols());
+    buffer
+        .table("test")?
+        .symbol("t", "")?
+        .column_str("s", "")?
+        .at_now()?;
+    assert_eq!(buffer.as_str(), "test,t= s=\"\"\n");
+
+    buffer.clear();
+    buffer.set_reject_empty_symbols(true);
+    buffer.table("test")?.symbol("t1", "a")?;
+    let err = buffer.symbol("t2", "").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "Bad value for symbol \"t2\": Symbol values must not be empty. \
+         Skip the symbol to record a null instead."
+    );
+
+    // The rejected symbol leaves the row intact.
+    buffer.column_str("s", "")?.at_now()?;
+    assert_eq!(buffer.as_str(), "test,t1=a s=\"\"\n");
+
+    buffer.clear();
+    assert!(buffer.rejects_empty_symbols());
+    Ok(())
+}
+
+#[test]
+fn test_numeric_symbol_value() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("price", "123.4")?.at_now()?;
+    assert_eq!(buffer.as_str(), "test,price=123.4\n");
+
+    let warned = Arc::new(Mutex::new(Vec::new()));
+    let warnings = Arc::clone(&warned);
+    buffer.clear();
+    buffer.set_numeric_symbols(NumericSymbols::Warn(Arc::new(move |name, value| {
+        warnings.lock().unwrap().push(format!("{name}={value}"));
+    })));
+    for value in ["-5", "1e3", "abc", "1a", "inf", "NaN", " 1", ""] {
+        buffer.table("test")?.symbol("sym", value)?.at_now()?;
+    }
+    assert_eq!(*warned.lock().unwrap(), ["sym=-5", "sym=1e3"]);
+    assert_eq!(buffer.row_count(), 8);
+
+    buffer.clear();
+    buffer.set_numeric_symbols(NumericSymbols::Reject);
+    buffer.table("test")?.symbol("t1", "a")?;
+    let err = buffer.symbol("price", ".5").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "Bad value for symbol \"price\": \".5\" is a number. \
+         Record it with a numeric column method instead, such as `column_f64`."
+    );
+
+    // The rejected symbol leaves the row intact.
+    buffer.column_f64("price", 0.5)?.at_now()?;
+    assert_eq!(buffer.as_str(), "test,t1=a price=0.5\n");
+
+    buffer.clear();
+    assert!(matches!(buffer.numeric_symbols(), NumericSymbols::Reject));
+    Ok(())
+}
+
+#[test]
+fn test_table_name_too_long() -> TestResult {
+    let mut buffer = Buffer::with_max_name_len(4);
+    let name = "a name too long";
+    let err = buffer.table(name).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    assert_eq!(
+        err.msg(),
+        r#"Bad name: "a name too long": Too long (15 bytes, max 4 bytes)"#
+    );
+    Ok(())
+}
+
+#[test]
+fn test_name_new_max_len() -> TestResult {
+    let long_name = "a".repeat(128);
+    assert!(TableName::new(&long_name[..127]).is_ok());
+    let err = TableName::new(&long_name).err().unwrap();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    assert_eq!(
+        err.msg(),
+        format!("Bad name: {long_name:?}: Too long (128 bytes, max 127 bytes)")
+    );
+
+    assert!(ColumnName::new_with_max_len("abcd", 4).is_ok());
+    let err = ColumnName::new_with_max_len("abcde", 4).err().unwrap();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    assert_eq!(
+        err.msg(),
+        r#"Bad name: "abcde": Too long (5 bytes, max 4 bytes)"#
+    );
+    assert!(TableName::new_with_max_len(&long_name, 200).is_ok());
+
+    // Plain strings are checked against the buffer's own limit.
+    let mut buffer = Buffer::with_max_name_len(200);
+    buffer
+        .table(long_name.as_str())?
+        .column_i64(long_name.as_str(), 1)?
+        .at_now()?;
+    Ok(())
+}
+
+#[test]
+fn test_name_max_len_counts_bytes() -> TestResult {
+    // "é" is two bytes, so it fits entirely or straddles the limit.
+    let fits = format!("{}é", "a".repeat(125));
+    let straddles = format!("{}é", "a".repeat(126));
+    assert_eq!(fits.len(), 127);
+    assert_eq!(straddles.len(), 128);
+    assert_eq!(straddles.chars().count(), 127);
+
+    assert!(TableName::new(&fits).is_ok());
+    assert!(ColumnName::new(&fits).is_ok());
+    let err = TableName::new(&straddles).err().unwrap();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    assert_eq!(
+        err.msg(),
+        format!("Bad name: {straddles:?}: Too long (128 bytes, max 127 bytes)")
+    );
+    assert!(ColumnName::new(&straddles).is_err());
+    assert!(!TableName::is_valid(&straddles));
+
+    let mut buffer = Buffer::new();
+    buffer
+        .table(fits.as_str())?
+        .column_i64(fits.as_str(), 1)?
+        .at_now()?;
+    let err = buffer.table(straddles.as_str()).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    let err = buffer
+        .table("t")?
+        .column_i64(straddles.as_str(), 1)
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    Ok(())
+}
+
+/// Whether QuestDB's `TableUtils.isValidTableName` or `isValidColumnName`
+/// allows the character within a name.
+fn server_allows_name_char(c: char, is_table: bool) -> bool {
+    match c {
+        '?' | ',' | '\'' | '"' | '\\' | '/' | ':' | ')' | '(' | '+' | '*' | '%' | '~' => false,
+        '\0'..='\u{8}' | '\t' | '\n' | '\u{b}' | '\u{c}' | '\r' | '\u{e}' | '\u{f}' => false,
+        '\u{7f}' | '\u{feff}' => false,
+        '.' | '-' => is_table,
+        _ => true,
+    }
+}
+
+#[test]
+fn test_name_char_matrix() {
+    let chars = (0u8..=0x7f).map(char::from).chain([
+        'é', '€', '\u{80}', '\u{85}', '\u{9f}', '\u{a0}', '\u{2003}', '\u{feff}', '😀',
+    ]);
+    for c in chars {
+        for is_table in [true, false] {
+            // The client also rejects all control characters, as they make
+            // the table or column hard to query.
+            let allowed = server_allows_name_char(c, is_table) && !c.is_control();
+            let valid = |name: &str| {
+                if is_table {
+                    TableName::new(name).is_ok()
+                } else {
+                    ColumnName::new(name).is_ok()
+                }
+            };
+
+            let inner = format!("a{c}b");
+            assert_eq!(valid(&inner), allowed, "{inner:?}, table: {is_table}");
+
+            // The client also rejects leading and trailing whitespace, and
+            // table names can't start or end with a dot.
+            let at_edge = allowed && !c.is_whitespace() && c != '.';
+            for name in [format!("{c}a"), format!("a{c}"), format!("é{c}")] {
+                assert_eq!(valid(&name), at_edge, "{name:?}, table: {is_table}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_name_control_chars() {
+    for (c, code_point) in [
+        ('\0', "U+0000"),
+        ('\u{1}', "U+0001"),
+        ('\u{10}', "U+0010"),
+        ('\u{1b}', "U+001B"),
+        ('\u{1f}', "U+001F"),
+        ('\u{7f}', "U+007F"),
+        ('\u{80}', "U+0080"),
+        ('\u{85}', "U+0085"),
+        ('\u{9f}', "U+009F"),
+    ] {
+        let name = format!("é{c}x");
+        let err = TableName::new(&name).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidName);
+        assert_eq!(
+            err.msg(),
+            format!(
+                "Bad string {name:?}: Table names can't contain the control character \
+                 {code_point}, which was found at byte position 2."
+            )
+        );
+        let err = ColumnName::new(&name).err().unwrap();
+        assert!(err.msg().contains(code_point), "{}", err.msg());
+        assert!(!TableName::is_valid(&name));
+        assert!(Buffer::new().table(name.as_str()).is_err());
+    }
+    assert!(TableName::new("a\u{a0}b").is_ok());
+}
+
+#[test]
+fn test_value_control_chars() -> TestResult {
+    let rejected = [
+        "\0",
+        "a\u{1}b",
+        "\t",
+        "\u{1b}[0m",
+        "\u{7f}",
+        "\u{85}",
+        "é\u{9f}",
+    ];
+    let accepted = ["", "plain", "multi\nline\r\n", "\u{a0}é😀"];
+
+    let mut buffer = Buffer::new();
+    assert!(!buffer.rejects_control_chars_in_values());
+    for value in rejected.iter().chain(&accepted) {
+        buffer
+            .table("t")?
+            .symbol("s", value)?
+            .column_str("c", value)?
+            .at_now()?;
+    }
+
+    buffer.clear();
+    buffer.set_reject_control_chars_in_values(true);
+    assert!(buffer.rejects_control_chars_in_values());
+    for value in accepted {
+        buffer
+            .table("t")?
+            .symbol("s", value)?
+            .column_str("c", value)?
+            .at_now()?;
+    }
+    let before = buffer.as_str().to_owned();
+    for value in rejected {
+        let err = buffer.table("t")?.symbol("s", value).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidValue, "{value:?}");
+        let err = buffer.symbol("s", "x")?.column_str("c", value).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidValue, "{value:?}");
+        assert!(buffer.column_opt("c", Some(value)).is_err());
+        // The rejected values weren't written.
+        buffer.at_now()?;
+        assert!(buffer.as_str().ends_with("t,s=x\n"));
+    }
+    assert!(buffer.as_str().starts_with(&before));
+
+    let err = buffer.table("t")?.column_str("c", "a\u{1}b").unwrap_err();
+    assert_eq!(
+        err.msg(),
+        r#"Bad value for column "c": Found the control character U+0001 at byte position 1."#
+    );
+    let err = buffer.symbol("s", "é\u{9f}").unwrap_err();
+    assert_eq!(
+        err.msg(),
+        r#"Bad value for symbol "s": Found the control character U+009F at byte position 2."#
+    );
+    Ok(())
+}
+
+#[test]
+fn test_name_error_byte_position() {
+    let err = TableName::new("é.").err().unwrap();
+    assert_eq!(
+        err.msg(),
+        r#"Bad string "é.": Found invalid dot `.` at byte position 2."#
+    );
+    let err = TableName::new("é..a").err().unwrap();
+    assert_eq!(
+        err.msg(),
+        r#"Bad string "é..a": Found invalid dot `.` at byte position 3."#
+    );
+    let err = ColumnName::new("éé-a").err().unwrap();
+    assert_eq!(
+        err.msg(),
+        concat!(
+            r#"Bad string "éé-a": Column names can't contain a '-' character, "#,
+            "which was found at byte position 4."
+        )
+    );
+    assert!(TableName::new("cpu-metrics").is_ok());
+}
+
+#[test]
+fn test_validation_modes() -> TestResult {
+    let long_name = "a".repeat(128);
+    // The name, and whether tables and columns of that name pass in the
+    // strict, default and off modes.
+    let cases: [(&str, [bool; 3], [bool; 3]); 10] = [
+        ("trades", [true, true, true], [true, true, true]),
+        ("cpu-metrics", [true, true, true], [false, false, true]),
+        ("trades.2024", [true, true, true], [false, false, true]),
+        ("côté", [false, true, true], [false, true, true]),
+        ("a\u{10}b", [false, false, true], [false, false, true]),
+        ("a,b", [false, false, true], [false, false, true]),
+        ("a..b", [false, false, true], [false, false, true]),
+        (" trades", [false, false, true], [false, false, true]),
+        ("", [false, false, false], [false, false, false]),
+        (&long_name, [false, false, false], [false, false, false]),
+    ];
+    let modes = [Validation::Strict, Validation::Default, Validation::Off];
+    for (name, table_passes, column_passes) in cases {
+        for (mode, (table_passes, column_passes)) in modes
+            .into_iter()
+            .zip(table_passes.into_iter().zip(column_passes))
+        {
+            let mut buffer = Buffer::new();
+            buffer.set_validation(mode);
+            let result = buffer.table(name);
+            assert_eq!(result.is_ok(), table_passes, "{name:?}, {mode:?}");
+            if let Err(err) = result {
+                assert_eq!(err.code(), ErrorCode::InvalidName);
+            }
+
+            let mut buffer = Buffer::new();
+            buffer.set_validation(mode);
+            let result = buffer.table("t")?.column_i64(name, 1);
+            assert_eq!(result.is_ok(), column_passes, "{name:?}, {mode:?}");
+
+            let mut buffer = Buffer::new();
+            buffer.set_validation(mode);
+            let result =
+                buffer.table_rows(name, [1], |buffer, x| buffer.column_i64("x", x)?.at_now());
+            assert_eq!(result.is_ok(), table_passes, "{name:?}, {mode:?}");
+        }
+    }
+
+    // Names validated up front pass the default rules, but not the strict
+    // ones if they contain non-ASCII characters.
+    let mut buffer = Buffer::new();
+    buffer.set_validation(Validation::Strict);
+    let err = buffer.table(TableName::new("côté")?).unwrap_err();
+    assert_eq!(
+        err.msg(),
+        concat!(
+            r#"Bad string "côté": Table names can't contain a 'ô' character "#,
+            "in strict validation mode, which was found at byte position 1."
+        )
+    );
+    let owned = OwnedColumnName::new("prix €")?;
+    assert!(buffer.table("t")?.column_i64(&owned, 1).is_err());
+    buffer.clear();
+
+    // Converting a name with `TryFrom` validates it, whatever the mode of the
+    // buffer it's meant for.
+    assert!(TableName::try_from("a,b").is_err());
+    assert!(ColumnName::try_from("cpu-metrics").is_err());
+
+    // The mode never applies to values.
+    buffer
+        .table("t")?
+        .symbol("s", "côté, \u{10}\n")?
+        .column_str("c", "côté, \u{10}\n")?
+        .at_now()?;
+    assert_eq!(buffer.validation(), Validation::Strict);
+    assert_eq!(Buffer::new().validation(), Validation::Default);
+    Ok(())
+}
+
+#[test]
+fn test_name_is_valid() {
+    let control_chars = ('\0'..='\u{1f}').chain('\u{7f}'..='\u{9f}');
+    for c in RESERVED_NAME_CHARS.iter().copied().chain(control_chars) {
+        let name = format!("a{c}b");
+        assert!(!TableName::is_valid(&name), "{name:?}");
+        assert!(TableName::new(&name).is_err(), "{name:?}");
+        assert!(!ColumnName::is_valid(&name), "{name:?}");
+        assert!(ColumnName::new(&name).is_err(), "{name:?}");
+    }
+
+    let long_name = "a".repeat(128);
+    for name in [
+        "",
+        " a",
+        "a ",
+        ".a",
+        "a.",
+        "a..b",
+        "a.b",
+        "a-b",
+        "a_b",
+        "a b",
+        "1a",
+        "héllo",
+        &long_name,
+        &long_name[..127],
+    ] {
+        assert_eq!(
+            TableName::is_valid(name),
+            TableName::new(name).is_ok(),
+            "{name:?}"
+        );
+        assert_eq!(
+            ColumnName::is_valid(name),
+            ColumnName::new(name).is_ok(),
+            "{name:?}"
+        );
+    }
+    assert!(TableName::is_valid("a.b"));
+    assert!(!ColumnName::is_valid("a.b"));
+    assert!(TableName::is_valid("a-b"));
+    assert!(!ColumnName::is_valid("a-b"));
+}
+
+#[test]
+fn test_chained_calls_return_the_buffer() -> TestResult {
+    let mut buffer = Buffer::new();
+    let row = buffer.table("t")?.symbol("a", "b")?;
+    assert_eq!(row.peek_last_row(), "t,a=b");
+    let row = row.column_i64("x", 1)?;
+    assert_eq!(row.peek_last_row(), "t,a=b x=1i");
+    assert_eq!(row.row_count(), 0);
+    row.at_now()?;
+    assert_eq!(buffer.row_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_lines() -> TestResult {
+    let mut buffer = Buffer::new();
+    assert_eq!(buffer.lines().count(), 0);
+
+    buffer
+        .table("t")?
+        .symbol("a", "x\ny")?
+        .at(TimestampNanos::new(1))?;
+    buffer.table("t")?.column_str("b", "\\\n")?.at_now()?;
+    buffer.table("u")?.column_i64("c", 3)?;
+    let lines: Vec<&str> = buffer.lines().collect();
+    assert_eq!(lines, ["t,a=x\\\ny 1", "t b=\"\\\\\\\n\""]);
+    assert_eq!(buffer.lines().count(), buffer.row_count());
+
+    // The lines are what a flush would send.
+    buffer.at_now()?;
+    let joined: String = buffer.lines().map(|line| format!("{line}\n")).collect();
+    assert_eq!(joined, buffer.as_str());
+    buffer.clear();
+    assert_eq!(buffer.lines().next(), None);
+    Ok(())
+}
+
+#[test]
+fn test_into_name_traits() -> TestResult {
+    fn write_row<'a>(
+        buffer: &mut Buffer,
+        table: impl IntoTableName<'a>,
+        column: impl IntoColumnName<'a>,
+    ) -> crate::Result<()> {
+        buffer.table(table)?.column_i64(column, 1)?.at_now()
+    }
+
+    let owned_table = OwnedTableName::new("t3")?;
+    let owned_column = OwnedColumnName::new("c3")?;
+    let mut buffer = Buffer::new();
+    write_row(&mut buffer, "t1", "c1")?;
+    write_row(&mut buffer, TableName::new("t2")?, ColumnName::new("c2")?)?;
+    write_row(&mut buffer, &owned_table, &owned_column)?;
+    assert_eq!(buffer.as_str(), "t1 c1=1i\nt2 c2=1i\nt3 c3=1i\n");
+
+    // Errors from validating plain strings flow through.
+    let err = write_row(&mut buffer, "t,4", "c4").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    let err = write_row(&mut buffer, "t4", "c.4").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    Ok(())
+}
+
+#[test]
+fn test_name_new_unchecked() -> TestResult {
+    let table = TableName::new_unchecked("trades");
+    let price = ColumnName::new_unchecked("price");
+    let mut buffer = Buffer::new();
+    buffer.table(table)?.column_f64(price, 1.5)?.at_now()?;
+    assert_eq!(buffer.as_str(), "trades price=1.5\n");
+
+    // The length is still checked against the buffer's limit.
+    let mut buffer = Buffer::with_max_name_len(4);
+    let err = buffer.table(table).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    Ok(())
+}
+
+#[test]
+fn test_name_macros() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table(crate::table_name!("trades.2024"))?
+        .symbol(crate::column_name!("côté"), "buy")?
+        .column_f64(crate::column_name!("price"), 2615.54)?
+        .at_now()?;
+    assert_eq!(buffer.as_str(), "trades.2024,côté=buy price=2615.54\n");
+    Ok(())
+}
+
+#[test]
+fn test_name_new_const_matches_new() {
+    let long_name = "a".repeat(128);
+    let names = [
+        "trades",
+        "côté",
+        "trades.2024",
+        ".trades",
+        "trades.",
+        "trades..2024",
+        "best-bid",
+        "a b",
+        " trades",
+        "trades\u{2003}",
+        "\u{2003}trades",
+        "tr\u{feff}ades",
+        "what?",
+        "tab\tle",
+        "",
+        &long_name[..127],
+        &long_name,
+    ];
+    for name in names {
+        let table = std::panic::catch_unwind(|| TableName::new_const(name));
+        assert_eq!(table.is_ok(), TableName::new(name).is_ok(), "{name:?}");
+        let column = std::panic::catch_unwind(|| ColumnName::new_const(name));
+        assert_eq!(column.is_ok(), ColumnName::new(name).is_ok(), "{name:?}");
+    }
+}
+
+#[test]
+fn test_owned_names() -> TestResult {
+    let table = OwnedTableName::new("my trades.v2")?;
+    let side = OwnedColumnName::new("side=buy")?;
+    let price = OwnedColumnName::new("price usd")?;
+    assert_eq!(table.as_str(), "my trades.v2");
+
+    let mut owned = Buffer::new();
+    let mut plain = Buffer::new();
+    for _ in 0..2 {
+        owned
+            .table(&table)?
+            .symbol(&side, "x")?
+            .column_f64(&price, 1.5)?
+            .at_now()?;
+        plain
+            .table(table.as_str())?
+            .symbol(side.as_str(), "x")?
+            .column_f64(price.as_str(), 1.5)?
+            .at_now()?;
+    }
+    assert_eq!(owned.as_str(), plain.as_str());
+    assert_eq!(
+        owned.peek_last_row(),
+        "my\\ trades.v2,side\\=buy=x price\\ usd=1.5\n"
+    );
+    assert!(owned.transactional());
+
+    // Invalid names are rejected up front, and long ones by the buffer.
+    let err = OwnedColumnName::new("a.b").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    let err = OwnedTableName::new_with_max_len("trades", 4).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    let err = Buffer::with_max_name_len(4).table(&table).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "Invalid table name passed to `TableName::new_unchecked`: \"a..b\"")]
+fn test_table_name_new_unchecked_invalid() {
+    TableName::new_unchecked("a..b");
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "Invalid column name passed to `ColumnName::new_unchecked`: \"a.b\"")]
+fn test_column_name_new_unchecked_invalid() {
+    ColumnName::new_unchecked("a.b");
+}
+
+#[test]
+fn test_name_leading_trailing_whitespace() -> TestResult {
+    for (name, msg) in [
+        (
+            " x",
+            r#"Bad string " x": {} names can't start with whitespace."#,
+        ),
+        (
+            "x ",
+            r#"Bad string "x ": {} names can't end with whitespace."#,
+        ),
+        (
+            "\tx",
+            r#"Bad string "\tx": {} names can't start with whitespace."#,
+        ),
+        (
+            "x\u{a0}",
+            r#"Bad string "x\u{a0}": {} names can't end with whitespace."#,
+        ),
+        (
+            " ",
+            r#"Bad string " ": {} names can't start with whitespace."#,
+        ),
+    ] {
+        let err = TableName::new(name).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidName);
+        assert_eq!(err.msg(), msg.replace("{}", "Table"));
+        let err = ColumnName::new(name).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidName);
+        assert_eq!(err.msg(), msg.replace("{}", "Column"));
+    }
+
+    // Inner whitespace and leading digits are fine.
+    let mut buffer = Buffer::new();
+    buffer
+        .table("my table")?
+        .symbol("1sym", "v1")?
+        .column_i64("2 col", 1)?
+        .at_now()?;
+    assert_eq!(buffer.as_str(), "my\\ table,1sym=v1 2\\ col=1i\n");
+
+    let err = buffer.table(" my table").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    Ok(())
+}
+
+#[test]
+fn test_table_rows_interleaved() -> TestResult {
+    let mut buffer = Buffer::new();
+    for batch in 0..2 {
+        let written = buffer.table_rows("trades", [1.5, 2.5], |buffer, price| {
+            buffer
+                .symbol("batch", batch.to_string())?
+                .column_f64("price", price)?
+                .at(TimestampNanos::new(1))
+        })?;
+        assert_eq!(written, 2);
+        buffer.table_rows("quotes", [7], |buffer, bid| {
+            buffer.column_i64("bid", bid)?.at(TimestampNanos::new(2))
+        })?;
+    }
+    assert_eq!(buffer.row_count(), 6);
+    assert!(!buffer.transactional());
+    assert_eq!(
+        buffer.as_str(),
+        "trades,batch=0 price=1.5 1\n\
+         trades,batch=0 price=2.5 1\n\
+         quotes bid=7i 2\n\
+         trades,batch=1 price=1.5 1\n\
+         trades,batch=1 price=2.5 1\n\
+         quotes bid=7i 2\n"
+    );
+    let tables: Vec<String> = parse_lines(buffer.as_str())
+        .map(|row| row.map(|row| row.table().to_owned()))
+        .collect::<crate::Result<_>>()?;
+    assert_eq!(
+        tables,
+        ["trades", "trades", "quotes", "trades", "trades", "quotes"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_table_rows_error_keeps_other_rows() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table_rows("quotes", [1], |buffer, bid| {
+        buffer.column_i64("bid", bid)?.at_now()
+    })?;
+
+    // The third row fails half-way: The first two stay, and so do the quotes.
+    let err = buffer
+        .table_rows(
+            "trades",
+            ["a", "b", "bad\u{feff}name", "d"],
+            |buffer, name| buffer.symbol("s", "v")?.column_i64(name, 1)?.at_now(),
+        )
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    assert!(buffer.ends_at_row_boundary());
+    assert_eq!(buffer.row_count(), 3);
+    assert_eq!(
+        buffer.as_str(),
+        "quotes bid=1i\ntrades,s=v a=1i\ntrades,s=v b=1i\n"
+    );
+
+    // So does a row left incomplete.
+    let err = buffer
+        .table_rows("trades", [1], |buffer, value| {
+            buffer.column_i64("c", value)?;
+            Ok(())
+        })
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "Row 0 for table \"trades\" was not completed: Call `at` or `at_now` at the end of each row."
+    );
+    assert_eq!(buffer.row_count(), 3);
+    assert!(buffer.ends_at_row_boundary());
+
+    // The table name is checked once, before any row.
+    let err = buffer
+        .table_rows("bad,table", [1], |buffer, _| buffer.at_now())
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+    assert_eq!(buffer.row_count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_row_count() -> TestResult {
+    let mut buffer = Buffer::new();
+    assert_eq!(buffer.row_count(), 0);
+
+    buffer.table("x")?.symbol("y", "z1")?.at_now()?;
+    buffer
+        .table("x")?
+        .symbol("y", "z2")?
+        .at(TimestampNanos::now())?;
+    assert_eq!(buffer.row_count(), 2);
+
+    buffer.set_marker()?;
+
+    buffer.table("x")?.symbol("y", "z3")?.at_now()?;
+    buffer
+        .table("x")?
+        .symbol("y", "z4")?
+        .at(TimestampNanos::now())?;
+    buffer.table("x")?.symbol("y", "z5")?.at_now()?;
+    assert_eq!(buffer.row_count(), 5);
+
+    buffer.rewind_to_marker()?;
+    assert_eq!(buffer.row_count(), 2);
+
+    buffer.clear();
+    assert_eq!(buffer.row_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_timestamp_overloads() -> TestResult {
+    let tbl_name = TableName::new("tbl_name")?;
+
+    let mut buffer = Buffer::new();
+    buffer
+        .table(tbl_name)?
+        .column_ts("a", TimestampMicros::new(12345))?
+        .column_ts("b", TimestampMicros::new(-100000000))?
+        .column_ts("c", TimestampNanos::new(12345678))?
+        .column_ts("d", TimestampNanos::new(-12345678))?
+        .column_ts("e", Timestamp::Micros(TimestampMicros::new(-1)))?
+        .column_ts("f", Timestamp::Nanos(TimestampNanos::new(-10000)))?
+        .at(TimestampMicros::new(1))?;
+    buffer
+        .table(tbl_name)?
+        .column_ts(
+            "a",
+            TimestampMicros::from_systemtime(
+                SystemTime::UNIX_EPOCH
+                    .checked_add(Duration::from_secs(1))
+                    .unwrap(),
+            )?,
+        )?
+        .at(TimestampNanos::from_systemtime(
+            SystemTime::UNIX_EPOCH
+                .checked_add(Duration::from_secs(5))
+                .unwrap(),
+        )?)?;
+
+    let exp = concat!(
+        "tbl_name a=12345t,b=-100000000t,c=12345t,d=-12345t,e=-1t,f=-10t 1000\n",
+        "tbl_name a=1000000t 5000000000\n"
+    );
+    assert_eq!(buffer.as_str(), exp);
+
+    Ok(())
+}
+
+#[test]
+fn test_at_units() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("tbl_name")?
+        .symbol("a", "b")?
+        .at(TimestampNanos::new(1659548315647000000))?;
+    buffer
+        .table("tbl_name")?
+        .symbol("a", "b")?
+        .at_micros(1659548315647000)?;
+    buffer
+        .table("tbl_name")?
+        .symbol("a", "b")?
+        .at_millis(1659548315647)?;
+    assert_eq!(
+        buffer.as_str(),
+        "tbl_name,a=b 1659548315647000000\n".repeat(3)
+    );
+
+    // Scaling to nanos would overflow.
+    buffer.table("tbl_name")?.symbol("a", "b")?;
+    let err = buffer.at_millis(i64::MAX / 1_000_000 + 1).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    assert_eq!(err.msg(), "Timestamp 9223372036855 millis is out of range");
+    let err = buffer.at_micros(i64::MIN).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    assert_eq!(
+        err.msg(),
+        "Timestamp -9223372036854775808 micros is out of range"
+    );
+    let err = TimestampNanos::try_from(TimestampMicros::new(i64::MIN)).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    assert_eq!(
+        err.msg(),
+        "Timestamp TimestampMicros(-9223372036854775808) is out of range"
+    );
+
+    // The row is still incomplete.
+    buffer.at_millis(i64::MAX / 1_000_000)?;
+    assert_eq!(buffer.row_count(), 4);
+    assert_eq!(buffer.peek_last_row(), "tbl_name,a=b 9223372036854000000\n");
+
+    // Outside of a row, the call is rejected before looking at the value.
+    let err = buffer.at_millis(i64::MAX).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    Ok(())
+}
+
+#[test]
+fn test_split_completed() -> TestResult {
+    let mut buffer = Buffer::new();
+    assert_eq!(buffer.split_completed(), "");
+
+    buffer
+        .table("t1")?
+        .column_i64("a", 1)?
+        .at(TimestampNanos::new(1))?;
+    buffer
+        .table("t1")?
+        .column_i64("a", 2)?
+        .at(TimestampNanos::new(2))?;
+    buffer.set_marker()?;
+    buffer.table("t2")?.symbol("s", "x")?;
+    assert_eq!(buffer.split_completed(), "t1 a=1i 1\nt1 a=2i 2\n");
+
+    // The row being written stays behind and can be completed.
+    assert_eq!(buffer.as_str(), "t2,s=x");
+    assert_eq!(buffer.row_count(), 0);
+    assert_eq!(
+        buffer.rewind_to_marker().unwrap_err().code(),
+        ErrorCode::InvalidApiCall
+    );
+    buffer.column_bool("b", true)?.at(TimestampNanos::new(3))?;
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(buffer.peek_last_row(), "t2,s=x b=t 3\n");
+
+    // Splitting at a row boundary leaves the buffer empty.
+    assert_eq!(buffer.split_completed(), "t2,s=x b=t 3\n");
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.row_count(), 0);
+    assert!(buffer.transactional());
+    let err = buffer.symbol("s", "y").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    Ok(())
+}
+
+#[test]
+fn test_split_off_oversized_row() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("t")?
+        .column_str("s", "x".repeat(100))?
+        .at(TimestampNanos::new(1))?;
+    buffer
+        .table("t")?
+        .column_i64("a", 2)?
+        .at(TimestampNanos::new(2))?;
+    buffer.set_marker()?;
+
+    let head = buffer.split_off_rows(10);
+    assert_eq!(head.row_count(), 1);
+    assert_eq!(head.len(), 109);
+    assert_eq!(head.as_str(), format!("t s=\"{}\" 1\n", "x".repeat(100)));
+    assert_eq!(buffer.as_str(), "t a=2i 2\n");
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(
+        buffer.rewind_to_marker().unwrap_err().code(),
+        ErrorCode::InvalidApiCall
+    );
+    Ok(())
+}
+
+#[test]
+fn test_buffer_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Buffer>();
+}
+
+#[test]
+fn test_buffer_clone_keeps_capacity() -> TestResult {
+    let mut heartbeat = Buffer::with_capacity(1024);
+    assert!(heartbeat.capacity() >= 1024);
+    heartbeat
+        .table("heartbeat")?
+        .symbol("host", "a")?
+        .at(TimestampNanos::new(1))?;
+    heartbeat.set_marker()?;
+
+    let mut clone = heartbeat.clone();
+    assert!(clone.capacity() >= 1024);
+    assert_eq!(clone.as_str(), heartbeat.as_str());
+    assert_eq!(clone.row_count(), 1);
+    clone
+        .table("heartbeat")?
+        .symbol("host", "b")?
+        .at(TimestampNanos::new(2))?;
+    clone.rewind_to_marker()?;
+    assert_eq!(clone.as_str(), "heartbeat,host=a 1\n");
+    Ok(())
+}
+
+#[test]
+fn test_append_buffers_partial_row() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("t1")?.column_i64("a", 1)?.at_now()?;
+    buffer.set_marker()?;
+    let mut other = Buffer::new();
+    other.table("t2")?.column_i64("b", 2)?;
+
+    let err = buffer.append(&mut other).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    let err = other.append(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(buffer.as_str(), "t1 a=1i\n");
+    assert_eq!(other.as_str(), "t2 b=2i");
+
+    // Rewinding to the marker also drops the appended rows.
+    other.at_now()?;
+    buffer.append(&mut other)?;
+    assert_eq!(buffer.as_str(), "t1 a=1i\nt2 b=2i\n");
+    assert_eq!(buffer.row_count(), 2);
+    assert!(!buffer.transactional());
+    buffer.rewind_to_marker()?;
+    assert_eq!(buffer.as_str(), "t1 a=1i\n");
+    assert_eq!(buffer.row_count(), 1);
+
+    let mut small = Buffer::with_max_buf_size(10);
+    small.table("t")?.column_i64("a", 1)?.at_now()?;
+    let mut other = Buffer::new();
+    other.table("t")?.column_i64("a", 2)?.at_now()?;
+    let err = small.append(&mut other).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert_eq!(small.row_count(), 1);
+    assert_eq!(other.row_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_timestamp_protocol_v2() -> TestResult {
+    assert_eq!(Buffer::new().protocol_version(), ProtocolVersion::V1);
+
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V2);
+    assert_eq!(buffer.protocol_version(), ProtocolVersion::V2);
+    buffer
+        .table("tbl_name")?
+        .column_ts("a", TimestampMicros::new(12345))?
+        .column_ts("b", TimestampMicros::new(-100000000))?
+        .column_ts("c", TimestampNanos::new(12345678))?
+        .column_ts("d", TimestampNanos::new(-12345678))?
+        .column_ts("e", Timestamp::Micros(TimestampMicros::new(-1)))?
+        .column_ts("f", Timestamp::Nanos(TimestampNanos::new(-10000)))?
+        .at(TimestampMicros::new(1))?;
+
+    let exp = "tbl_name a=12345t,b=-100000000t,c=12345678n,d=-12345678n,e=-1t,f=-10000n 1000\n";
+    assert_eq!(buffer.as_str(), exp);
+    Ok(())
+}
+
+#[test]
+fn test_protocol_version_integer_suffix() -> TestResult {
+    assert_eq!(Buffer::new().protocol_version(), ProtocolVersion::V1);
+    for version in [ProtocolVersion::V1, ProtocolVersion::V2] {
+        let mut buffer = Buffer::with_protocol_version(version);
+        buffer
+            .table("test")?
+            .column_i64("a", 42)?
+            .column_i64("b", i64::MIN)?
+            .column_int("c", 7u8)?
+            .column_f64("d", 42.0)?
+            .at(TimestampNanos::new(1000))?;
+        assert_eq!(
+            buffer.as_str(),
+            "test a=42i,b=-9223372036854775808i,c=7i,d=42.0 1000\n",
+            "{version:?}"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "chrono_timestamp")]
+#[test]
+fn test_chrono_timestamp() -> TestResult {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    let tbl_name = TableName::new("tbl_name")?;
+    let ts: DateTime<Utc> = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 1).unwrap();
+    let ts = TimestampNanos::from_datetime(ts)?;
+
+    let mut buffer = Buffer::new();
+    buffer.table(tbl_name)?.column_ts("a", ts)?.at(ts)?;
+
+    let exp = "tbl_name a=1000000t 1000000000\n";
+    assert_eq!(buffer.as_str(), exp);
+
+    Ok(())
+}
+
+#[cfg(feature = "chrono_timestamp")]
+#[test]
+fn test_chrono_datetime_methods() -> TestResult {
+    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+
+    let ts: DateTime<Utc> = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+    let before_epoch: DateTime<Utc> = Utc.timestamp_opt(-1, 999_999_999).unwrap();
+    let offset = FixedOffset::east_opt(3600)
+        .unwrap()
+        .timestamp_opt(1, 500)
+        .unwrap();
+
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V2);
+    buffer
+        .table("t")?
+        .column_ts_datetime("a", ts)?
+        .column_ts_datetime("b", before_epoch)?
+        .column_ts_datetime("c", offset)?
+        .at_datetime(ts)?;
+    assert_eq!(
+        buffer.as_str(),
+        "t a=1700000000123456789n,b=-1n,c=1000000500n 1700000000123456789\n"
+    );
+
+    // Protocol V1 truncates timestamp columns to microseconds.
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V1);
+    buffer
+        .table("t")?
+        .column_ts_datetime("a", ts)?
+        .at_datetime(ts)?;
+    assert_eq!(
+        buffer.as_str(),
+        "t a=1700000000123456t 1700000000123456789\n"
+    );
+
+    let mut buffer = Buffer::new();
+    buffer.table("t")?.column_i64("x", 1)?;
+    let err = buffer.at_datetime(before_epoch).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    let too_late: DateTime<Utc> = Utc.with_ymd_and_hms(2263, 1, 1, 0, 0, 0).unwrap();
+    let err = buffer.at_datetime(too_late).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    let err = buffer.column_ts_datetime("y", too_late).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+
+    // The row is still incomplete and can be completed.
+    assert_eq!(buffer.as_str(), "t x=1i");
+    buffer.at_datetime(ts)?;
+    assert_eq!(buffer.row_count(), 1);
+    let err = buffer.column_ts_datetime("z", ts).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    Ok(())
+}
+
+#[cfg(feature = "time_timestamp")]
+#[test]
+fn test_time_offset_datetime_methods() -> TestResult {
+    use time::{Date, Duration as TimeDuration, Month, OffsetDateTime, UtcOffset};
+
+    let ts = OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_456_789)?;
+    let before_epoch = OffsetDateTime::UNIX_EPOCH - TimeDuration::nanoseconds(1);
+    let offset = OffsetDateTime::from_unix_timestamp_nanos(1_000_000_500)?
+        .to_offset(UtcOffset::from_hms(1, 0, 0)?);
+
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V2);
+    buffer
+        .table("t")?
+        .column_ts_offset_datetime("a", ts)?
+        .column_ts_offset_datetime("b", before_epoch)?
+        .column_ts_offset_datetime("c", offset)?
+        .at_offset_datetime(ts)?;
+    assert_eq!(
+        buffer.as_str(),
+        "t a=1700000000123456789n,b=-1n,c=1000000500n 1700000000123456789\n"
+    );
+
+    // Protocol V1 truncates timestamp columns to microseconds.
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V1);
+    buffer
+        .table("t")?
+        .column_ts_offset_datetime("a", ts)?
+        .at_offset_datetime(ts)?;
+    assert_eq!(
+        buffer.as_str(),
+        "t a=1700000000123456t 1700000000123456789\n"
+    );
+
+    // Before the epoch, sub-microsecond parts round down, as with `chrono`.
+    assert_eq!(
+        TimestampMicros::from_offset_datetime(before_epoch)?,
+        TimestampMicros::new(-1)
+    );
+    let sub_micros_before_epoch = OffsetDateTime::from_unix_timestamp_nanos(-1_000_001_500)?;
+    assert_eq!(
+        TimestampMicros::from_offset_datetime(sub_micros_before_epoch)?,
+        TimestampMicros::new(-1_000_002)
+    );
+    #[cfg(feature = "chrono_timestamp")]
+    assert_eq!(
+        TimestampMicros::from_offset_datetime(sub_micros_before_epoch)?,
+        TimestampMicros::from_datetime(chrono::DateTime::from_timestamp_nanos(-1_000_001_500))
+    );
+
+    let mut buffer = Buffer::new();
+    buffer.table("t")?.column_i64("x", 1)?;
+    let err = buffer.at_offset_datetime(before_epoch).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    let too_late = Date::from_calendar_date(2263, Month::January, 1)?
+        .midnight()
+        .assume_utc();
+    let err = buffer.at_offset_datetime(too_late).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    let err = buffer.column_ts_offset_datetime("y", too_late).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidTimestamp);
+    assert_eq!(
+        TimestampMicros::from_offset_datetime(too_late)?.as_i64(),
+        9_246_182_400_000_000
+    );
+
+    // The row is still incomplete and can be completed.
+    assert_eq!(buffer.as_str(), "t x=1i");
+    buffer.at_offset_datetime(ts)?;
+    assert_eq!(buffer.row_count(), 1);
+    Ok(())
+}
+
+macro_rules! column_name_too_long_test_impl {
+    ($column_fn:ident, $value:expr) => {{
+        let mut buffer = Buffer::with_max_name_len(4);
+        let name = "a name too long";
+        let err = buffer.table("tbl")?.$column_fn(name, $value).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidName);
+        assert_eq!(
+            err.msg(),
+            r#"Bad name: "a name too long": Too long (15 bytes, max 4 bytes)"#
+        );
+        Ok(())
+    }};
+}
+
+#[test]
+fn test_symbol_column_name_too_long() -> TestResult {
+    column_name_too_long_test_impl!(symbol, "v1")
+}
+
+#[test]
+fn test_bool_column_name_too_long() -> TestResult {
+    column_name_too_long_test_impl!(column_bool, true)
+}
+
+#[test]
+fn test_i64_column_name_too_long() -> TestResult {
+    column_name_too_long_test_impl!(column_i64, 1)
+}
+
+#[test]
+fn test_f64_column_name_too_long() -> TestResult {
+    column_name_too_long_test_impl!(column_f64, 0.5)
+}
+
+#[test]
+fn test_str_column_name_too_long() -> TestResult {
+    column_name_too_long_test_impl!(column_str, "value")
+}
+
+#[test]
+fn test_buffer_introspection() -> TestResult {
+    let mut buffer = Buffer::with_max_buf_size(40);
+    assert_eq!(buffer.peek_last_row(), "");
+    assert!(buffer.ends_at_row_boundary());
+
+    buffer.table("test")?;
+    assert_eq!(buffer.peek_last_row(), "test");
+    assert!(!buffer.ends_at_row_boundary());
+    buffer.symbol("t1", "v1")?;
+    assert_eq!(buffer.peek_last_row(), "test,t1=v1");
+    buffer.column_i64("c1", 1)?;
+    assert_eq!(buffer.peek_last_row(), "test,t1=v1 c1=1i");
+    assert!(!buffer.ends_at_row_boundary());
+    buffer.at_now()?;
+    assert_eq!(buffer.peek_last_row(), "test,t1=v1 c1=1i\n");
+    assert!(buffer.ends_at_row_boundary());
+
+    buffer.set_marker()?;
+    buffer.table("test")?.symbol("t1", "v2")?;
+    assert_eq!(buffer.peek_last_row(), "test,t1=v2");
+    buffer.rewind_to_marker()?;
+    assert_eq!(buffer.peek_last_row(), "test,t1=v1 c1=1i\n");
+    assert!(buffer.ends_at_row_boundary());
+
+    // A rejected row leaves the previous row as the last one.
+    buffer.table("test")?.symbol("t1", "a much longer value")?;
+    buffer.at_now().unwrap_err();
+    assert_eq!(buffer.peek_last_row(), "test,t1=v1 c1=1i\n");
+    assert!(buffer.ends_at_row_boundary());
+
+    // The views don't affect later writes.
+    buffer.table("test")?.symbol("t1", "v3")?.at_now()?;
+    assert_eq!(buffer.as_str(), "test,t1=v1 c1=1i\ntest,t1=v3\n");
+    assert_eq!(buffer.peek_last_row(), "test,t1=v3\n");
+
+    buffer.clear();
+    assert_eq!(buffer.peek_last_row(), "");
+    assert!(buffer.ends_at_row_boundary());
+    Ok(())
+}
+
+#[test]
+fn test_committed_size() -> TestResult {
+    let mut buffer = Buffer::new();
+    assert_eq!(buffer.committed_size(), 0);
+    buffer.table("test")?.symbol("t1", "v1")?;
+    assert_eq!(buffer.committed_size(), 0);
+    assert_eq!(buffer.len(), 10);
+    buffer.at_now()?;
+    assert_eq!(buffer.committed_size(), 11);
+
+    buffer.table("test")?.symbol("t1", "v2")?;
+    assert_eq!(buffer.committed_size(), 11);
+    assert_eq!(buffer.len(), 21);
+    buffer.at_now()?;
+    assert_eq!(buffer.committed_size(), 22);
+    assert_eq!(buffer.committed_size(), buffer.len());
+
+    buffer.clear();
+    assert_eq!(buffer.committed_size(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_flush_to_incomplete_row() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    let mut file = Vec::new();
+    let err = buffer.flush_to(&mut file).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "State error: Bad call to `flush`, should have called `symbol`, `column` or `at` instead."
+    );
+    assert!(file.is_empty());
+    assert_eq!(buffer.len(), 10);
+    Ok(())
+}
+
+#[test]
+fn test_at_server_time() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v1")?.at_server_time()?;
+    assert_eq!(buffer.as_str(), "test,t1=v1\ntest,t1=v1\n");
+    assert_eq!(buffer.row_count(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_column_raw() -> TestResult {
+    let mut buffer = Buffer::new();
+    unsafe {
+        buffer
+            .table("test")?
+            .symbol("t1", "v1")?
+            .column_raw("c 1", "42i")?
+            .column_raw("c2", r#""a \"b\"""#)?
+            .at_now()?;
+        buffer.table("test")?.column_raw("c3", "t")?.at_now()?;
+    }
+    assert_eq!(
+        buffer.as_str(),
+        "test,t1=v1 c\\ 1=42i,c2=\"a \\\"b\\\"\"\ntest c3=t\n"
+    );
+    assert_eq!(buffer.row_count(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_column_raw_state() -> TestResult {
+    let mut buffer = Buffer::new();
+    let err = unsafe { buffer.column_raw("c1", "1i") }.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+
+    buffer.table("test")?;
+    let err = unsafe { buffer.column_raw("a.b", "1i") }.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidName);
+
+    unsafe { buffer.column_raw("c1", "1i")? };
+    let err = buffer.symbol("t1", "v1").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    buffer.at_now()?;
+    Ok(())
+}
@@ -0,0 +1,154 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{Buffer, BufferPool, ProtocolVersion, TimestampNanos};
+use crate::tests::TestResult;
+
+use std::sync::Arc;
+
+#[test]
+fn test_buffer_pool_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BufferPool>();
+}
+
+#[test]
+fn test_buffer_pool_reuse() -> TestResult {
+    let pool = BufferPool::new(2, 1024);
+    assert_eq!(pool.idle_count(), 0);
+
+    let mut buffer = pool.get();
+    assert!(buffer.capacity() >= 1024);
+    buffer.table("t")?.column_i64("a", 1)?.at_now()?;
+    buffer.set_marker()?;
+    let ptr = buffer.as_str().as_ptr();
+    let capacity = buffer.capacity();
+    drop(buffer);
+    assert_eq!(pool.idle_count(), 1);
+
+    // The same allocation comes back, cleared.
+    let buffer = pool.get();
+    assert_eq!(pool.idle_count(), 0);
+    assert_eq!(buffer.as_str().as_ptr(), ptr);
+    assert_eq!(buffer.capacity(), capacity);
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.row_count(), 0);
+
+    // Only up to `max_pooled` buffers are kept.
+    let others = [pool.get(), pool.get()];
+    drop(buffer);
+    drop(others);
+    assert_eq!(pool.idle_count(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_pool_drops_oversized() -> TestResult {
+    let pool = BufferPool::new(4, 16).with_max_capacity(256);
+    let mut buffer = pool.get();
+    buffer
+        .table("t")?
+        .column_str("s", "x".repeat(1000))?
+        .at_now()?;
+    assert!(buffer.capacity() > 256);
+    drop(buffer);
+    assert_eq!(pool.idle_count(), 0);
+
+    let mut buffer = pool.get();
+    buffer.table("t")?.column_i64("a", 1)?.at_now()?;
+    assert!(buffer.capacity() <= 256);
+    drop(buffer);
+    assert_eq!(pool.idle_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_pool_across_threads() -> TestResult {
+    let pool = BufferPool::new(4, 1024);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..4i64)
+            .map(|worker| {
+                let pool = &pool;
+                scope.spawn(move || -> crate::Result<()> {
+                    for row in 0..10 {
+                        let mut buffer = pool.get();
+                        assert!(buffer.is_empty());
+                        buffer
+                            .table("t")?
+                            .column_i64("w", worker)?
+                            .at(TimestampNanos::new(row))?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().unwrap())
+    })?;
+    assert!(pool.idle_count() <= 4);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_pool_buffer_moves_to_thread() -> TestResult {
+    let pool = Arc::new(BufferPool::new(4, 1024));
+    let mut buffer = pool.get();
+    buffer.table("t")?.column_i64("a", 1)?.at_now()?;
+    let ptr = buffer.as_str().as_ptr();
+    std::thread::spawn(move || -> crate::Result<()> {
+        buffer.table("t")?.column_i64("a", 2)?.at_now()?;
+        assert_eq!(buffer.row_count(), 2);
+        Ok(())
+    })
+    .join()
+    .unwrap()?;
+
+    // Dropped on the other thread, the buffer returned to the pool.
+    assert_eq!(pool.idle_count(), 1);
+    assert_eq!(pool.get().as_str().as_ptr(), ptr);
+
+    // Unless taken out of it.
+    let buffer = pool.get().into_inner();
+    drop(buffer);
+    assert_eq!(pool.idle_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_pool_template() -> TestResult {
+    let mut template = Buffer::with_max_buf_size(4096);
+    template.table("t")?.column_i64("a", 1)?.at_now()?;
+    let pool = BufferPool::new(2, 1024).with_template(template);
+
+    let buffer = pool.get();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.max_buf_size(), 4096);
+    assert!(buffer.capacity() >= 1024);
+
+    let pool =
+        BufferPool::new(2, 1024).with_template(Buffer::with_protocol_version(ProtocolVersion::V2));
+    assert_eq!(pool.get().protocol_version(), ProtocolVersion::V2);
+    Ok(())
+}
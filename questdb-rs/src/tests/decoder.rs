@@ -0,0 +1,329 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::decoder::{parse_lines, validate, ParsedRow, ParsedValue};
+use crate::ingress::{Buffer, ProtocolVersion, TimestampMicros, TimestampNanos};
+use crate::tests::TestResult;
+use crate::{ErrorCode, Result};
+use proptest::prelude::*;
+
+fn parse_all(ilp: &str) -> Result<Vec<ParsedRow>> {
+    parse_lines(ilp).collect()
+}
+
+#[test]
+fn test_round_trip() -> TestResult {
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V2);
+    buffer
+        .table("my table")?
+        .symbol("sym bol", "a,b=c d\\")?
+        .symbol("empty", "")?
+        .column_bool("b", false)?
+        .column_i64("i", -42)?
+        .column_f64("f", -1.5e-7)?
+        .column_f64("inf", f64::NEG_INFINITY)?
+        .column_str("s", "say \"hi\",\n\\ = ok")?
+        .column_ts("us", TimestampMicros::new(12345))?
+        .column_ts("ns", TimestampNanos::new(-1))?
+        .at(TimestampNanos::new(1700000000000000000))?;
+    buffer.table("t")?.symbol("a", "b")?.at_now()?;
+    buffer
+        .table("t")?
+        .symbol("a", "é")?
+        .at(TimestampNanos::new(1))?;
+    buffer.table("t")?.column_f64("nan", f64::NAN)?.at_now()?;
+
+    let rows = parse_all(buffer.as_str())?;
+    assert_eq!(rows.len(), 4);
+
+    let row = &rows[0];
+    assert_eq!(row.table(), "my table");
+    assert_eq!(
+        row.symbols(),
+        [
+            ("sym bol".to_owned(), "a,b=c d\\".to_owned()),
+            ("empty".to_owned(), "".to_owned())
+        ]
+    );
+    assert_eq!(
+        row.columns(),
+        [
+            ("b".to_owned(), ParsedValue::Bool(false)),
+            ("i".to_owned(), ParsedValue::I64(-42)),
+            ("f".to_owned(), ParsedValue::F64(-1.5e-7)),
+            ("inf".to_owned(), ParsedValue::F64(f64::NEG_INFINITY)),
+            (
+                "s".to_owned(),
+                ParsedValue::Str("say \"hi\",\n\\ = ok".to_owned())
+            ),
+            (
+                "us".to_owned(),
+                ParsedValue::Timestamp(TimestampMicros::new(12345).into())
+            ),
+            (
+                "ns".to_owned(),
+                ParsedValue::Timestamp(TimestampNanos::new(-1).into())
+            ),
+        ]
+    );
+    assert_eq!(
+        row.timestamp(),
+        Some(TimestampNanos::new(1700000000000000000))
+    );
+
+    assert_eq!(rows[1].symbol("a"), Some("b"));
+    assert!(rows[1].columns().is_empty());
+    assert_eq!(rows[1].timestamp(), None);
+    assert_eq!(rows[2].symbol("a"), Some("é"));
+    assert_eq!(rows[2].timestamp(), Some(TimestampNanos::new(1)));
+    assert!(matches!(
+        rows[3].column("nan"),
+        Some(ParsedValue::F64(value)) if value.is_nan()
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_value_types() -> TestResult {
+    let rows = parse_all(concat!(
+        "t a=t,b=TRUE,c=False,d=12,e=1e3,f=Infinity,g=0i,h=\"\"\n",
+        "\n",
+        "t,s=x 5\n",
+    ))?;
+    assert_eq!(rows.len(), 2);
+    let values: Vec<&ParsedValue> = rows[0].columns().iter().map(|(_, value)| value).collect();
+    assert_eq!(
+        values,
+        [
+            &ParsedValue::Bool(true),
+            &ParsedValue::Bool(true),
+            &ParsedValue::Bool(false),
+            &ParsedValue::F64(12.0),
+            &ParsedValue::F64(1000.0),
+            &ParsedValue::F64(f64::INFINITY),
+            &ParsedValue::I64(0),
+            &ParsedValue::Str(String::new()),
+        ]
+    );
+    assert_eq!(rows[1].timestamp(), Some(TimestampNanos::new(5)));
+    Ok(())
+}
+
+#[test]
+fn test_malformed() -> TestResult {
+    let cases = [
+        (
+            "t\n",
+            "Malformed ILP at byte 1 of the line starting at byte 0: expected a symbol or a column.",
+        ),
+        (
+            ",a=b\n",
+            "Malformed ILP at byte 0 of the line starting at byte 0: expected a table name.",
+        ),
+        (
+            "t,a b=1i\n",
+            "Malformed ILP at byte 3 of the line starting at byte 0: expected `=` after the symbol name.",
+        ),
+        (
+            "t a=1x\n",
+            "Malformed ILP at byte 4 of the line starting at byte 0: bad value \"1x\" for column \"a\".",
+        ),
+        (
+            "t a=\"open\n",
+            "Malformed ILP at byte 4 of the line starting at byte 0: the string isn't closed.",
+        ),
+        (
+            "t a=1i 12x\n",
+            "Malformed ILP at byte 7 of the line starting at byte 0: bad designated timestamp \"12x\".",
+        ),
+        (
+            "t a=1i 1 2\n",
+            "Malformed ILP at byte 8 of the line starting at byte 0: expected the end of the line.",
+        ),
+        (
+            "t a=1i",
+            "Malformed ILP at byte 6 of the line starting at byte 0: the line doesn't end with a newline.",
+        ),
+        (
+            "t\\",
+            "Malformed ILP at byte 2 of the line starting at byte 0: a backslash ends the input.",
+        ),
+    ];
+    for (ilp, msg) in cases {
+        let err = parse_all(ilp).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::MalformedIlp);
+        assert_eq!(err.msg(), msg, "parsing {:?}", ilp);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_resume_after_malformed_line() -> TestResult {
+    let ilp = "t a=1i\nt,a\\\nb=c d=oops\nt a=2i\n";
+    let rows: Vec<Result<ParsedRow>> = parse_lines(ilp).collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(
+        rows[0].as_ref().unwrap().column("a"),
+        Some(&ParsedValue::I64(1))
+    );
+    assert_eq!(
+        rows[1].as_ref().unwrap_err().msg(),
+        "Malformed ILP at byte 11 of the line starting at byte 7: bad value \"oops\" for column \"d\"."
+    );
+    assert_eq!(
+        rows[2].as_ref().unwrap().column("a"),
+        Some(&ParsedValue::I64(2))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_validate() -> TestResult {
+    let stats = validate("")?;
+    assert_eq!(stats.rows(), 0);
+    assert_eq!(stats.tables().count(), 0);
+
+    let mut buffer = Buffer::new();
+    for table in ["b", "a b", "b"] {
+        buffer
+            .table(table)?
+            .symbol("s", "x\ny")?
+            .column_str("c", "1\n2")?
+            .at_now()?;
+    }
+    let stats = validate(buffer.as_str())?;
+    assert_eq!(stats.rows(), 3);
+    assert_eq!(stats.tables().collect::<Vec<_>>(), [("a b", 1), ("b", 2)]);
+    assert_eq!(stats.rows_for("b"), 2);
+    assert_eq!(stats.rows_for("c"), 0);
+    Ok(())
+}
+
+#[test]
+fn test_validate_errors() -> TestResult {
+    let cases = [
+        (
+            "t a=1i\nt a=1i b=2i\n",
+            "Malformed ILP at line 2, column 8: bad designated timestamp \"b=2i\".",
+        ),
+        (
+            "t a=1i\nt a=1i",
+            "Malformed ILP at line 2, column 7: the line doesn't end with a newline.",
+        ),
+        (
+            "t a=12x 1\n",
+            "Malformed ILP at line 1, column 5: bad value \"12x\" for column \"a\".",
+        ),
+        // Lines and columns count the escaped line breaks and chars.
+        (
+            "t,s=a\\\nb é=\"x\\\ny\",😁=1q\n",
+            "Malformed ILP at line 3, column 6: bad value \"1q\" for column \"😁\".",
+        ),
+    ];
+    for (ilp, msg) in cases {
+        let err = validate(ilp).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::MalformedIlp);
+        assert_eq!(err.msg(), msg, "validating {:?}", ilp);
+    }
+    Ok(())
+}
+
+/// The validator and the decoder must agree on any input, and never panic.
+fn check_agrees(ilp: &str) -> std::result::Result<(), TestCaseError> {
+    let rows: Vec<Result<ParsedRow>> = parse_lines(ilp).collect();
+    match validate(ilp) {
+        Ok(stats) => {
+            prop_assert!(rows.iter().all(|row| row.is_ok()), "{:?}", ilp);
+            prop_assert_eq!(stats.rows(), rows.len(), "{:?}", ilp);
+        }
+        Err(err) => {
+            prop_assert_eq!(err.code(), ErrorCode::MalformedIlp);
+            prop_assert!(rows.iter().any(|row| row.is_err()), "{:?}", ilp);
+        }
+    }
+    Ok(())
+}
+
+/// Characters that make up ILP, to mutate a valid row with.
+const ILP_CHARS: &[char] = &[
+    ' ', ',', '=', '"', '\\', '\n', 't', 'i', 'n', '1', '-', '.', 'é',
+];
+
+/// A row with a value of every type.
+fn valid_ilp() -> Result<Vec<char>> {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("t")?
+        .symbol("s", "a b")?
+        .column_str("c", "x\"y")?
+        .column_i64("i", -1)?
+        .column_f64("f", 1.5)?
+        .column_bool("b", true)?
+        .column_ts("ts", TimestampMicros::new(1))?
+        .at(TimestampNanos::new(2))?;
+    Ok(buffer.as_str().chars().collect())
+}
+
+#[derive(Debug, Clone)]
+enum Mutation {
+    Remove(prop::sample::Index),
+    Insert(prop::sample::Index, char),
+    Replace(prop::sample::Index, char),
+}
+
+fn mutation_strategy() -> impl Strategy<Value = Mutation> {
+    let c = || prop::sample::select(ILP_CHARS);
+    prop_oneof![
+        any::<prop::sample::Index>().prop_map(Mutation::Remove),
+        (any::<prop::sample::Index>(), c()).prop_map(|(i, c)| Mutation::Insert(i, c)),
+        (any::<prop::sample::Index>(), c()).prop_map(|(i, c)| Mutation::Replace(i, c)),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn test_fuzz_random_bytes(bytes in prop::collection::vec(any::<u8>(), 0..40)) {
+        check_agrees(&String::from_utf8_lossy(&bytes))?;
+    }
+
+    #[test]
+    fn test_fuzz_mutated_ilp(mutations in prop::collection::vec(mutation_strategy(), 1..=3)) {
+        let mut chars = valid_ilp()?;
+        for mutation in mutations {
+            match mutation {
+                Mutation::Remove(index) => {
+                    chars.remove(index.index(chars.len()));
+                }
+                Mutation::Insert(index, c) => chars.insert(index.index(chars.len() + 1), c),
+                Mutation::Replace(index, c) => {
+                    let index = index.index(chars.len());
+                    chars[index] = c;
+                }
+            }
+        }
+        check_agrees(&chars.into_iter().collect::<String>())?;
+    }
+}
@@ -0,0 +1,136 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::Buffer;
+use crate::tests::TestResult;
+use crate::{Error, ErrorCode};
+use std::error::Error as _;
+use std::io;
+
+const ALL_CODES: [ErrorCode; 17] = [
+    ErrorCode::CouldNotResolveAddr,
+    ErrorCode::InvalidApiCall,
+    ErrorCode::SocketError,
+    ErrorCode::InvalidUtf8,
+    ErrorCode::InvalidName,
+    ErrorCode::InvalidTimestamp,
+    ErrorCode::AuthError,
+    ErrorCode::TlsError,
+    ErrorCode::HttpNotSupported,
+    ErrorCode::ServerFlushError,
+    ErrorCode::ConfigError,
+    ErrorCode::BufferTooLarge,
+    ErrorCode::IoError,
+    ErrorCode::SpillError,
+    ErrorCode::MalformedIlp,
+    ErrorCode::InvalidValue,
+    ErrorCode::QueueFull,
+];
+
+#[test]
+fn test_error_code_values() {
+    // These values are part of the public API: Never change them.
+    let values: Vec<i32> = ALL_CODES.iter().map(|code| code.as_i32()).collect();
+    assert_eq!(values, (0..17).collect::<Vec<i32>>());
+    assert_eq!(ErrorCode::ConfigError.as_i32(), 10);
+}
+
+#[test]
+fn test_error_code_from_i32() {
+    for code in ALL_CODES {
+        assert_eq!(ErrorCode::from_i32(code.as_i32()), Some(code));
+    }
+    assert_eq!(ErrorCode::from_i32(-1), None);
+    assert_eq!(ErrorCode::from_i32(17), None);
+    assert_eq!(ErrorCode::from_i32(i32::MAX), None);
+}
+
+/// A writer that always fails with the given kind of error.
+struct FailingWriter(io::ErrorKind);
+
+impl io::Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.0, "injected"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_error_without_io_source() {
+    let err = Error::new(ErrorCode::InvalidApiCall, "bad call");
+    assert!(err.source().is_none());
+    assert!(err.io_error().is_none());
+    assert_eq!(err.io_error_kind(), None);
+}
+
+#[test]
+fn test_io_error_source() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("t")?.column_i64("a", 1)?.at_now()?;
+    let err = buffer
+        .flush_to(&mut FailingWriter(io::ErrorKind::ConnectionReset))
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::IoError);
+    assert_eq!(err.msg(), "Could not flush buffer: injected");
+    assert_eq!(err.io_error_kind(), Some(io::ErrorKind::ConnectionReset));
+    let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::ConnectionReset);
+
+    // Clones share the source, which equality ignores.
+    let clone = err.clone();
+    assert_eq!(clone.io_error_kind(), Some(io::ErrorKind::ConnectionReset));
+    assert_eq!(clone, err);
+    assert_eq!(err, Error::new(ErrorCode::IoError, err.msg()));
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_socket_error_source() -> TestResult {
+    use crate::ingress::Sender;
+
+    let mut sender = Sender::from_sink(FailingWriter(io::ErrorKind::WouldBlock));
+    let mut buffer = sender.new_buffer();
+    buffer.table("t")?.column_i64("a", 1)?.at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(err.io_error_kind(), Some(io::ErrorKind::WouldBlock));
+    assert_eq!(err.io_error().unwrap().to_string(), "injected");
+    Ok(())
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn test_dead_letter_file_error_source() -> TestResult {
+    use crate::ingress::DeadLetterFile;
+
+    let dir = tempfile::TempDir::new()?;
+    let err = DeadLetterFile::open(dir.path().join("missing").join("rejected.jsonl")).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(err.io_error_kind(), Some(io::ErrorKind::NotFound));
+    Ok(())
+}
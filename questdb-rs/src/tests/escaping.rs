@@ -0,0 +1,357 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::decoder::{parse_lines, ParsedRow, ParsedValue};
+use crate::ingress::{
+    must_escape_quoted, must_escape_unquoted, write_escaped_quoted, write_escaped_unquoted, Buffer,
+    ColumnName, TableName,
+};
+use crate::tests::TestResult;
+use crate::Result;
+use proptest::prelude::*;
+
+/// Parse ILP back into its rows, which must all be well-formed.
+fn parse_rows(ilp: &str) -> Vec<ParsedRow> {
+    parse_lines(ilp)
+        .collect::<Result<Vec<_>>>()
+        .unwrap_or_else(|err| panic!("{err}: {ilp:?}"))
+}
+
+/// Characters that stress the escaping: ILP separators, quotes, control
+/// characters and multi-byte UTF-8.
+const TRICKY_CHARS: &[char] = &[
+    ' ', ',', '=', '\n', '\r', '\t', '"', '\\', '\0', '\u{1}', '\u{1b}', '\u{7f}', 'a', 'Z', '0',
+    'é', '€', '😁',
+];
+
+fn tricky_string_strategy() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(TRICKY_CHARS), 0..12)
+        .prop_map(|chars| chars.into_iter().collect())
+}
+
+/// The former two-pass escaping, which counted the bytes to escape before
+/// writing: The single-pass one must produce the same bytes.
+fn reference_write_escaped<Q, C>(
+    check_escape_fn: C,
+    quoting_fn: Q,
+    output_vec: &mut Vec<u8>,
+    s: &str,
+) where
+    C: Fn(u8) -> bool,
+    Q: Fn(&mut Vec<u8>),
+{
+    let mut to_escape = 0usize;
+    for b in s.bytes() {
+        if check_escape_fn(b) {
+            to_escape += 1;
+        }
+    }
+
+    quoting_fn(output_vec);
+
+    if to_escape == 0 {
+        output_vec.extend_from_slice(s.as_bytes());
+    } else {
+        output_vec.reserve(s.len() + to_escape);
+        for b in s.bytes() {
+            if check_escape_fn(b) {
+                output_vec.push(b'\\');
+            }
+            output_vec.push(b);
+        }
+    }
+
+    quoting_fn(output_vec);
+}
+
+fn assert_escapes_like_reference(s: &str) {
+    // Written after some existing output, as in a buffer.
+    let mut expected = b"x".to_vec();
+    reference_write_escaped(must_escape_unquoted, |_output| (), &mut expected, s);
+    let mut actual = b"x".to_vec();
+    write_escaped_unquoted(&mut actual, s);
+    assert_eq!(actual, expected, "{s:?}");
+
+    let mut expected = b"x".to_vec();
+    reference_write_escaped(
+        must_escape_quoted,
+        |output| output.push(b'"'),
+        &mut expected,
+        s,
+    );
+    let mut actual = b"x".to_vec();
+    write_escaped_quoted(&mut actual, s);
+    assert_eq!(actual, expected, "{s:?}");
+}
+
+#[test]
+fn test_escape_matches_reference() {
+    for s in ["", "\\", "\\\\", " ", "abc", "\"\"", "a b,c=d\n", "😁\t€\r"] {
+        assert_escapes_like_reference(s);
+    }
+    for b in 0u8..128 {
+        assert_escapes_like_reference(&format!("{}", b as char));
+        assert_escapes_like_reference(&format!("ab{}cd{}", b as char, b as char));
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_escape_matches_reference_fuzz(
+        s in tricky_string_strategy(),
+        run_len in prop::option::of(0usize..100),
+    ) {
+        // Long clean runs, between and around the tricky characters.
+        let s = match run_len {
+            Some(run_len) => s.replace('a', &"a".repeat(run_len)),
+            None => s,
+        };
+        assert_escapes_like_reference(&s);
+    }
+}
+
+#[test]
+fn test_escape_every_ascii_char() -> TestResult {
+    for b in 0u8..128 {
+        let value = format!("a{}b", b as char);
+        let mut buffer = Buffer::new();
+        buffer
+            .table("test")?
+            .symbol("sym", value.as_str())?
+            .column_str("str", value.as_str())?
+            .at_now()?;
+        let rows = parse_rows(buffer.as_str());
+        assert_eq!(rows.len(), 1, "{:?}", buffer.as_str());
+        assert_eq!(rows[0].symbols(), [("sym".to_owned(), value.clone())]);
+        assert_eq!(
+            rows[0].columns(),
+            [("str".to_owned(), ParsedValue::Str(value))]
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_escape_tab() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .symbol("sym", "a\tb")?
+        .column_str("str", "a\tb=c")?
+        .at_now()?;
+    assert_eq!(buffer.as_str(), "test,sym=a\\\tb str=\"a\tb=c\"\n");
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn test_escape_round_trip(
+        symbol in tricky_string_strategy(),
+        string in tricky_string_strategy(),
+    ) {
+        let mut buffer = Buffer::new();
+        buffer
+            .table("test")?
+            .symbol("sym", symbol.as_str())?
+            .column_str("str", string.as_str())?
+            .column_i64("num", 1)?
+            .at_now()?;
+        let rows = parse_rows(buffer.as_str());
+        prop_assert_eq!(rows.len(), 1, "{:?}", buffer.as_str());
+        prop_assert_eq!(rows[0].table(), "test");
+        prop_assert_eq!(rows[0].symbols(), [("sym".to_owned(), symbol)]);
+        prop_assert_eq!(
+            rows[0].columns(),
+            [
+                ("str".to_owned(), ParsedValue::Str(string)),
+                ("num".to_owned(), ParsedValue::I64(1))
+            ]
+        );
+    }
+}
+
+/// Characters for table and column names, including some that the name
+/// validation rejects.
+const NAME_CHARS: &[char] = &[
+    ' ', '=', '.', '-', '_', ',', '"', '\\', '\t', '\u{1b}', 'a', 'Z', '0', 'é', '€', '😁',
+];
+
+/// A name built from [`NAME_CHARS`], for names that the validation accepts.
+///
+/// Only the characters allowed within a name are drawn, so that few names are
+/// rejected for where their characters are, such as a leading space.
+fn name_strategy(is_valid: fn(&str) -> bool) -> impl Strategy<Value = String> {
+    let chars: Vec<char> = NAME_CHARS
+        .iter()
+        .copied()
+        .filter(|c| is_valid(&format!("a{c}a")))
+        .collect();
+    prop::collection::vec(prop::sample::select(chars), 1..=8)
+        .prop_map(|chars| chars.into_iter().collect::<String>())
+        .prop_filter("invalid name", move |name| is_valid(name))
+}
+
+#[derive(Debug, Clone)]
+enum ColumnValue {
+    Str(String),
+    I64(i64),
+    Bool(bool),
+}
+
+fn column_value_strategy() -> impl Strategy<Value = ColumnValue> {
+    prop_oneof![
+        tricky_string_strategy().prop_map(ColumnValue::Str),
+        (-500_000i64..500_000).prop_map(ColumnValue::I64),
+        any::<bool>().prop_map(ColumnValue::Bool),
+    ]
+}
+
+/// The parts of a row, to compare the rows written with those parsed back.
+#[derive(Debug, PartialEq)]
+struct RowParts {
+    table: String,
+    symbols: Vec<(String, String)>,
+    columns: Vec<(String, ParsedValue)>,
+}
+
+impl From<&ParsedRow> for RowParts {
+    fn from(row: &ParsedRow) -> Self {
+        Self {
+            table: row.table().to_owned(),
+            symbols: row.symbols().to_vec(),
+            columns: row.columns().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RandomRow {
+    table: String,
+    symbols: Vec<(String, String)>,
+    columns: Vec<(String, ColumnValue)>,
+}
+
+fn row_strategy() -> impl Strategy<Value = RandomRow> {
+    let table = name_strategy(|name| TableName::new(name).is_ok());
+    let column = || name_strategy(|name| ColumnName::new(name).is_ok());
+    (
+        table,
+        prop::collection::vec((column(), tricky_string_strategy()), 0..3),
+        prop::collection::vec((column(), column_value_strategy()), 0..3),
+    )
+        .prop_filter(
+            "a row needs a symbol or a column",
+            |(_, symbols, columns)| !symbols.is_empty() || !columns.is_empty(),
+        )
+        .prop_map(|(table, symbols, columns)| RandomRow {
+            table,
+            symbols,
+            columns,
+        })
+}
+
+/// Write the row to the buffer and return what it should parse back as.
+fn write_row(row: &RandomRow, buffer: &mut Buffer) -> Result<RowParts> {
+    buffer.table(row.table.as_str())?;
+    for (name, value) in &row.symbols {
+        buffer.symbol(name.as_str(), value.as_str())?;
+    }
+    let mut columns = Vec::new();
+    for (name, value) in &row.columns {
+        let parsed = match value {
+            ColumnValue::Str(value) => {
+                buffer.column_str(name.as_str(), value.as_str())?;
+                ParsedValue::Str(value.clone())
+            }
+            ColumnValue::I64(value) => {
+                buffer.column_i64(name.as_str(), *value)?;
+                ParsedValue::I64(*value)
+            }
+            ColumnValue::Bool(value) => {
+                buffer.column_bool(name.as_str(), *value)?;
+                ParsedValue::Bool(*value)
+            }
+        };
+        columns.push((name.clone(), parsed));
+    }
+    buffer.at_now()?;
+    Ok(RowParts {
+        table: row.table.clone(),
+        symbols: row.symbols.clone(),
+        columns,
+    })
+}
+
+proptest! {
+    #[test]
+    fn test_escape_fuzz(rows in prop::collection::vec(row_strategy(), 1..=4)) {
+        let mut buffer = Buffer::new();
+        let exp: Vec<RowParts> = rows
+            .iter()
+            .map(|row| write_row(row, &mut buffer))
+            .collect::<Result<_>>()?;
+        let parsed: Vec<RowParts> = parse_rows(buffer.as_str())
+            .iter()
+            .map(RowParts::from)
+            .collect();
+        prop_assert_eq!(parsed, exp, "{:?}", buffer.as_str());
+    }
+}
+
+#[test]
+fn test_escape_quoting_boundaries() -> TestResult {
+    let values = [
+        "",
+        "\"",
+        "\"\"",
+        "\\",
+        "\\\"",
+        "\"\\",
+        "a\\",
+        "\\\\\"",
+        " \"",
+        "=\"",
+        "😁\"€",
+    ];
+    for value in values {
+        let mut buffer = Buffer::new();
+        buffer
+            .table("test")?
+            .symbol("sym", value)?
+            .column_str("str", value)?
+            .column_str("after", "x")?
+            .at_now()?;
+        let rows = parse_rows(buffer.as_str());
+        assert_eq!(rows.len(), 1, "{:?}", buffer.as_str());
+        assert_eq!(rows[0].symbols(), [("sym".to_owned(), value.to_owned())]);
+        assert_eq!(
+            rows[0].columns(),
+            [
+                ("str".to_owned(), ParsedValue::Str(value.to_owned())),
+                ("after".to_owned(), ParsedValue::Str("x".to_owned()))
+            ]
+        );
+    }
+    Ok(())
+}
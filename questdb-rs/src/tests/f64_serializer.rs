@@ -142,3 +142,22 @@ fn test_f2s_min() {
 fn test_f2s_max() {
     assert_eq!(f2s(f64::MAX), "1.7976931348623157e308");
 }
+
+#[test]
+fn test_f2s_large_magnitudes() {
+    // Up to 16 digits, large numbers are written out in full.
+    assert_eq!(f2s(1e15), "1000000000000000.0");
+    assert_eq!(f2s(1e16), "1e16");
+    assert_eq!(f2s(1e30), "1e30");
+    assert_eq!(f2s(-1e30), "-1e30");
+    assert_eq!(f2s(123456789012345680000.0), "1.2345678901234568e20");
+    assert_eq!(f2s(1.5e300), "1.5e300");
+}
+
+#[test]
+fn test_f2s_small_magnitudes() {
+    assert_eq!(f2s(0.00001), "0.00001");
+    assert_eq!(f2s(1e-30), "1e-30");
+    assert_eq!(f2s(-1.25e-30), "-1.25e-30");
+    assert_eq!(f2s(5e-324), "5e-324");
+}
@@ -22,15 +22,37 @@
  *
  ******************************************************************************/
 
-use crate::ingress::{Buffer, Protocol, SenderBuilder, TimestampNanos};
+use crate::ingress::{
+    Buffer, DeadLetterFile, DeadLetterSink, Protocol, SenderBuilder, TimestampNanos,
+};
 use crate::tests::mock::{certs_dir, HttpResponse, MockServer};
-use crate::ErrorCode;
+use crate::{Error, ErrorCode};
 use std::io;
 use std::io::ErrorKind;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::tests::TestResult;
 
+#[test]
+fn test_flush_empty() -> TestResult {
+    let server = MockServer::new()?;
+    let mut sender = server
+        .lsb_http()
+        .request_timeout(Duration::from_millis(50))?
+        .retry_timeout(Duration::from_millis(0))?
+        .build()?;
+    assert_eq!(sender.connect_timings(), None);
+
+    // The server never responds, so any request would fail with a timeout.
+    let mut buffer = Buffer::new();
+    sender.flush(&mut buffer)?;
+    sender.flush_and_keep_with_flags(&buffer, true)?;
+    assert!(buffer.is_empty());
+    Ok(())
+}
+
 #[test]
 fn test_two_lines() -> TestResult {
     let mut buffer = Buffer::new();
@@ -77,6 +99,61 @@ fn test_two_lines() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_ipv6_host() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.column_i64("x", 1)?.at_now()?;
+
+    let mut server = MockServer::new_ipv6()?;
+    let mut sender = server.lsb_http().build()?;
+
+    let server_thread = std::thread::spawn(move || -> io::Result<()> {
+        server.accept()?;
+        let req = server.recv_http_q()?;
+        assert_eq!(req.path(), "/write?precision=n");
+        assert_eq!(req.body_str().unwrap(), "test x=1i\n");
+        server.send_http_response_q(HttpResponse::empty())?;
+        Ok(())
+    });
+
+    let res = sender.flush(&mut buffer);
+    server_thread.join().unwrap()?;
+    res?;
+    Ok(())
+}
+
+#[test]
+fn test_ping() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_http().build()?;
+
+    let server_thread = std::thread::spawn(move || -> io::Result<()> {
+        server.accept()?;
+        let req = server.recv_http_q()?;
+        assert_eq!(req.method(), "GET");
+        assert_eq!(req.path(), "/ping");
+        server.send_http_response_q(HttpResponse::empty())?;
+        Ok(())
+    });
+
+    let res = sender.ping();
+    server_thread.join().unwrap()?;
+    res?;
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_ping_no_connection() -> TestResult {
+    let mut sender = SenderBuilder::new(Protocol::Http, "127.0.0.1", 1).build()?;
+    let err = sender.ping().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err
+        .msg()
+        .starts_with("Ping failed: http://127.0.0.1:1/ping: Connection Failed"));
+    Ok(())
+}
+
 #[test]
 fn test_text_plain_error() -> TestResult {
     let mut buffer = Buffer::new();
@@ -222,6 +299,136 @@ fn test_json_error() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_dead_letter_file() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .symbol("sym", "bol")?
+        .column_f64("x", 1.0)?
+        .at(TimestampNanos::new(10000000))?;
+    buffer
+        .table("test")?
+        .column_f64("sym", 2.0)?
+        .at(TimestampNanos::new(10000001))?;
+    let ilp = buffer.as_str().to_owned();
+
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("rejected.jsonl");
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_http()
+        .dead_letter_sink(Arc::new(DeadLetterFile::open(&path)?))?
+        .build()?;
+
+    let server_thread = std::thread::spawn(move || -> io::Result<()> {
+        server.accept()?;
+        for _ in 0..2 {
+            server.recv_http_q()?;
+            server.send_http_response_q(
+                HttpResponse::empty()
+                    .with_status(400, "Bad Request")
+                    .with_body_json(&serde_json::json!({
+                        "code": "invalid",
+                        "message": "failed to parse line protocol: invalid field format",
+                        "errorId": "ABC-2",
+                        "line": 2,
+                    })),
+            )?;
+        }
+        Ok(())
+    });
+
+    let err = sender.flush_and_keep(&buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ServerFlushError);
+    assert!(sender.flush(&mut buffer).is_err());
+    server_thread.join().unwrap()?;
+
+    // Each rejection appends a line.
+    let contents = std::fs::read_to_string(&path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        let envelope: serde_json::Value = serde_json::from_str(line)?;
+        assert!(envelope["timestamp"].as_u64().unwrap() > 0);
+        assert_eq!(envelope["code"], "ServerFlushError");
+        assert_eq!(
+            envelope["message"],
+            "Could not flush buffer: failed to parse line protocol: invalid field format [id: ABC-2, code: invalid, line: 2]"
+        );
+        assert_eq!(envelope["payload"], ilp.as_str());
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct CountingSink(AtomicUsize);
+
+impl DeadLetterSink for CountingSink {
+    fn reject(&self, _ilp_bytes: &[u8], _err: &Error) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_dead_letter_skips_transient_errors() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .symbol("t1", "v1")?
+        .column_f64("f1", 0.5)?
+        .at(TimestampNanos::new(10000000))?;
+
+    let sink = Arc::new(CountingSink::default());
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_http()
+        .retry_timeout(Duration::from_millis(0))?
+        .dead_letter_sink(sink.clone())?
+        .build()?;
+
+    let statuses = [
+        (503, "Service Unavailable"),
+        (429, "Too Many Requests"),
+        (502, "Bad Gateway"),
+        (408, "Request Timeout"),
+    ];
+    let server_thread = std::thread::spawn(move || -> io::Result<()> {
+        server.accept()?;
+        for (code, reason) in statuses {
+            server.recv_http_q()?;
+            server.send_http_response_q(
+                HttpResponse::empty()
+                    .with_status(code, reason)
+                    .with_body_str("try again later"),
+            )?;
+        }
+        Ok(())
+    });
+
+    for _ in statuses {
+        let err = sender.flush_and_keep(&buffer).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ServerFlushError);
+        assert_eq!(sink.0.load(Ordering::SeqCst), 0);
+    }
+    server_thread.join().unwrap()?;
+
+    // The server is gone, so the next flush fails with a network error.
+    let err = sender.flush_and_keep(&buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(sink.0.load(Ordering::SeqCst), 0);
+
+    let err = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", 1)
+        .dead_letter_sink(sink)
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "dead_letter_sink is supported only in ILP over HTTP."
+    );
+    Ok(())
+}
+
 #[test]
 fn test_no_connection() -> TestResult {
     let mut buffer = Buffer::new();
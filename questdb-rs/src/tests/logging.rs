@@ -0,0 +1,151 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{Buffer, Sender};
+use crate::tests::mock::{MockServer, MockSink};
+use crate::tests::TestResult;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io;
+use std::sync::{Mutex, Once};
+use std::thread::ThreadId;
+
+struct Captured {
+    thread: ThreadId,
+    level: Level,
+    msg: String,
+}
+
+/// Records the messages logged under the `questdb::ingress` target. Tests run
+/// in parallel, so each test only looks at the messages logged on its own
+/// thread.
+struct CapturingLogger {
+    records: Mutex<Vec<Captured>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == "questdb::ingress"
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.records.lock().unwrap().push(Captured {
+            thread: std::thread::current().id(),
+            level: record.level(),
+            msg: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    records: Mutex::new(Vec::new()),
+};
+
+/// Install the logger and return a function listing this thread's messages
+/// logged since.
+fn capture() -> impl Fn() -> Vec<(Level, String)> {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+    let thread = std::thread::current().id();
+    let skip = LOGGER
+        .records
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|captured| captured.thread == thread)
+        .count();
+    move || {
+        LOGGER
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|captured| captured.thread == thread)
+            .skip(skip)
+            .map(|captured| (captured.level, captured.msg.clone()))
+            .collect()
+    }
+}
+
+#[test]
+fn test_log_connect_and_flush() -> TestResult {
+    let logged = capture();
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+    let connect_logs = logged();
+    assert!(connect_logs.iter().all(|(level, _)| *level == Level::Debug));
+    assert!(connect_logs
+        .iter()
+        .any(|(_, msg)| msg.starts_with("Connected to ")));
+    assert!(connect_logs
+        .iter()
+        .any(|(_, msg)| msg.starts_with("Created Sender[host=")));
+
+    // Building rows logs nothing.
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.column_i64("c1", 1)?.at_now()?;
+    assert_eq!(logged().len(), connect_logs.len());
+
+    sender.flush(&mut buffer)?;
+    let flush_logs = logged().split_off(connect_logs.len());
+    assert_eq!(flush_logs.len(), 1);
+    assert_eq!(flush_logs[0].0, Level::Trace);
+    assert!(flush_logs[0].1.starts_with("Flushed 2 rows, 22 bytes in "));
+    Ok(())
+}
+
+#[test]
+fn test_log_flush_failure_warns_once() -> TestResult {
+    let logged = capture();
+    let sink = MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink);
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    assert!(sender.flush(&mut buffer).is_err());
+
+    let warnings: Vec<_> = logged()
+        .into_iter()
+        .filter(|(level, _)| *level == Level::Warn)
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![(
+            Level::Warn,
+            "Could not flush 1 rows, 11 bytes, the sender must now be closed: \
+            Could not flush buffer: injected mock sink error"
+                .to_owned()
+        )]
+    );
+    Ok(())
+}
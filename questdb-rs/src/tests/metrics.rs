@@ -0,0 +1,155 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{Buffer, Sender};
+use crate::tests::mock::{MockServer, MockSink};
+use crate::tests::TestResult;
+use crate::ErrorCode;
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+use std::collections::BTreeMap;
+
+/// The metrics' values by name and `sender` label.
+fn snapshot(snapshotter: &Snapshotter) -> BTreeMap<(String, Option<String>), DebugValue> {
+    snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _unit, _descr, value)| {
+            let label = key
+                .key()
+                .labels()
+                .find(|label| label.key() == "sender")
+                .map(|label| label.value().to_owned());
+            ((key.key().name().to_owned(), label), value)
+        })
+        .collect()
+}
+
+fn counter(
+    values: &BTreeMap<(String, Option<String>), DebugValue>,
+    name: &str,
+    label: Option<&str>,
+) -> u64 {
+    match values.get(&(name.to_owned(), label.map(str::to_owned))) {
+        Some(DebugValue::Counter(value)) => *value,
+        other => panic!("Expected counter {name}, got {other:?}"),
+    }
+}
+
+fn histogram_len(
+    values: &BTreeMap<(String, Option<String>), DebugValue>,
+    name: &str,
+    label: Option<&str>,
+) -> usize {
+    match values.get(&(name.to_owned(), label.map(str::to_owned))) {
+        Some(DebugValue::Histogram(samples)) => {
+            assert!(samples.iter().all(|sample| sample.0 >= 0.0));
+            samples.len()
+        }
+        other => panic!("Expected histogram {name}, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_metrics_labelled_senders() -> TestResult {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let mut server1 = MockServer::new()?;
+    let mut server2 = MockServer::new()?;
+
+    // The metrics are registered with the recorder when building the sender.
+    let (mut sender1, mut sender2) = ::metrics::with_local_recorder(&recorder, || {
+        let sender1 = server1.lsb_tcp().metrics_label("one")?.build()?;
+        let sender2 = server2.lsb_tcp().metrics_label("two")?.build()?;
+        crate::Result::Ok((sender1, sender2))
+    })?;
+    server1.accept()?;
+    server2.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    sender1.flush(&mut buffer)?;
+    buffer.table("test")?.symbol("t1", "v3")?.at_now()?;
+    sender1.flush(&mut buffer)?;
+
+    buffer.table("test")?.symbol("t1", "v4")?.at_now()?;
+    sender2.flush(&mut buffer)?;
+    buffer.table("test")?.symbol("t1", "v5")?;
+    let err = sender2.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+
+    let values = snapshot(&snapshotter);
+    let one = Some("one");
+    let two = Some("two");
+    assert_eq!(counter(&values, "questdb_client_rows_sent_total", one), 3);
+    assert_eq!(counter(&values, "questdb_client_bytes_sent_total", one), 33);
+    assert_eq!(
+        counter(&values, "questdb_client_flush_errors_total", one),
+        0
+    );
+    assert_eq!(
+        histogram_len(&values, "questdb_client_flush_duration_seconds", one),
+        2
+    );
+    assert_eq!(counter(&values, "questdb_client_rows_sent_total", two), 1);
+    assert_eq!(counter(&values, "questdb_client_bytes_sent_total", two), 11);
+    assert_eq!(
+        counter(&values, "questdb_client_flush_errors_total", two),
+        1
+    );
+    assert_eq!(
+        histogram_len(&values, "questdb_client_flush_duration_seconds", two),
+        1
+    );
+    Ok(())
+}
+
+#[test]
+fn test_metrics_unlabelled() -> TestResult {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let sink = MockSink::new();
+    let mut sender = ::metrics::with_local_recorder(&recorder, || Sender::from_sink(sink));
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+
+    let values = snapshot(&snapshotter);
+    assert_eq!(counter(&values, "questdb_client_rows_sent_total", None), 1);
+    assert_eq!(
+        counter(&values, "questdb_client_bytes_sent_total", None),
+        11
+    );
+    assert_eq!(
+        counter(&values, "questdb_client_flush_errors_total", None),
+        0
+    );
+    for (key, ..) in snapshotter.snapshot().into_vec() {
+        assert_eq!(key.key().labels().count(), 0);
+    }
+    Ok(())
+}
@@ -22,7 +22,8 @@
  *
  ******************************************************************************/
 
-use crate::ingress::{Protocol, SenderBuilder};
+use crate::ingress::{Buffer, Protocol, Sender, SenderBuilder};
+use crate::{Error, ErrorCode};
 
 use core::time::Duration;
 use mio::event::Event;
@@ -33,7 +34,7 @@ use socket2::{Domain, Protocol as SockProtocol, Socket, Type};
 use std::fs::File;
 use std::io::{self, BufReader, Read};
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 #[cfg(feature = "ilp-over-http")]
@@ -41,6 +42,78 @@ use std::io::Write;
 
 const CLIENT: Token = Token(0);
 
+#[derive(Debug, Default)]
+struct MockSinkState {
+    written: Vec<u8>,
+    write_calls: usize,
+    max_write_len: Option<usize>,
+    fail_after: Option<(usize, io::ErrorKind)>,
+}
+
+/// An in-memory `IlpSink` that records the written bytes.
+///
+/// It can simulate short writes and I/O errors.
+/// Clones share the same state, so a clone can be inspected after the original
+/// was handed over to a `Sender`.
+#[derive(Debug, Clone, Default)]
+pub struct MockSink {
+    state: Arc<Mutex<MockSinkState>>,
+}
+
+impl MockSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept at most `len` bytes per `write` call.
+    pub fn with_max_write_len(self, len: usize) -> Self {
+        self.state.lock().unwrap().max_write_len = Some(len);
+        self
+    }
+
+    /// Fail with the given error kind once `len` bytes have been written.
+    pub fn with_fail_after(self, len: usize, kind: io::ErrorKind) -> Self {
+        self.state.lock().unwrap().fail_after = Some((len, kind));
+        self
+    }
+
+    pub fn written(&self) -> Vec<u8> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    pub fn written_str(&self) -> String {
+        String::from_utf8(self.written()).unwrap()
+    }
+
+    pub fn write_calls(&self) -> usize {
+        self.state.lock().unwrap().write_calls
+    }
+}
+
+impl io::Write for MockSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.write_calls += 1;
+        let mut len = buf.len();
+        if let Some(max_write_len) = state.max_write_len {
+            len = len.min(max_write_len);
+        }
+        if let Some((fail_after, kind)) = state.fail_after {
+            let remaining = fail_after.saturating_sub(state.written.len());
+            if remaining == 0 {
+                return Err(io::Error::new(kind, "injected mock sink error"));
+            }
+            len = len.min(remaining);
+        }
+        state.written.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct MockServer {
     poll: Poll,
@@ -51,6 +124,25 @@ pub struct MockServer {
     pub host: &'static str,
     pub port: u16,
     pub msgs: Vec<String>,
+
+    /// All the bytes read from the client so far.
+    pub bytes_received: usize,
+}
+
+/// Keep flushing rows until the sender notices the server disconnected.
+pub fn expect_eventual_disconnect(sender: &mut Sender) {
+    let mut retry = || {
+        for _ in 0..1000 {
+            std::thread::sleep(Duration::from_millis(100));
+            let mut buffer = Buffer::new();
+            buffer.table("test_table")?.symbol("s1", "v1")?.at_now()?;
+            sender.flush(&mut buffer)?;
+        }
+        Ok(())
+    };
+
+    let err: Error = retry().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
 }
 
 pub fn certs_dir() -> std::path::PathBuf {
@@ -196,20 +288,34 @@ fn position(haystack: &[u8], needle: &[u8]) -> Option<usize> {
 
 impl MockServer {
     pub fn new() -> io::Result<Self> {
-        let listener = Socket::new(Domain::IPV4, Type::STREAM, Some(SockProtocol::TCP))?;
-        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        Self::listen("127.0.0.1:0", "localhost")
+    }
+
+    /// A server listening on the IPv6 loopback address `::1`.
+    pub fn new_ipv6() -> io::Result<Self> {
+        Self::listen("[::1]:0", "::1")
+    }
+
+    fn listen(address: &str, host: &'static str) -> io::Result<Self> {
+        let address: SocketAddr = address.parse().unwrap();
+        let listener = Socket::new(
+            Domain::for_address(address),
+            Type::STREAM,
+            Some(SockProtocol::TCP),
+        )?;
         listener.bind(&address.into())?;
         listener.listen(128)?;
-        let port = listener.local_addr()?.as_socket_ipv4().unwrap().port();
+        let port = listener.local_addr()?.as_socket().unwrap().port();
         Ok(Self {
             poll: Poll::new()?,
             events: Events::with_capacity(128),
             listener,
             client: None,
             tls_conn: None,
-            host: "localhost",
+            host,
             port,
             msgs: Vec::new(),
+            bytes_received: 0,
         })
     }
 
@@ -227,6 +333,16 @@ impl MockServer {
         Ok(())
     }
 
+    /// Drop the accepted client connection, closing it.
+    pub fn close_client(&mut self) {
+        self.client = None;
+        self.tls_conn = None;
+    }
+
+    pub fn client_addr(&self) -> io::Result<SocketAddr> {
+        self.client.as_ref().unwrap().peer_addr()
+    }
+
     pub fn accept_tls_sync(&mut self) -> io::Result<()> {
         self.accept()?;
         let client = self.client.as_mut().unwrap();
@@ -298,12 +414,14 @@ impl MockServer {
 
     fn do_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let client = self.client.as_mut().unwrap();
-        if let Some(tls_conn) = self.tls_conn.as_mut() {
+        let count = if let Some(tls_conn) = self.tls_conn.as_mut() {
             let mut stream = Stream::new(tls_conn, client);
-            stream.read(buf)
+            stream.read(buf)?
         } else {
-            client.read(buf)
-        }
+            client.read(buf)?
+        };
+        self.bytes_received += count;
+        Ok(count)
     }
 
     #[cfg(feature = "ilp-over-http")]
@@ -469,10 +587,10 @@ impl MockServer {
         let deadline = Instant::now() + Duration::from_secs_f64(wait_timeout_sec);
         let (pos, method, path) = self.recv_http_method(&mut accum, deadline)?;
         let (pos, headers) = self.recv_http_headers(pos, &mut accum, deadline)?;
+        // Requests without a body, such as `GET /ping`, omit the header.
         let content_length = headers
             .get("content-length")
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))?
-            .parse::<usize>()
+            .map_or(Ok(0), |len| len.parse::<usize>())
             .map_err(|_| {
                 io::Error::new(io::ErrorKind::InvalidData, "Invalid Content-Length header")
             })?;
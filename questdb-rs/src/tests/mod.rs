@@ -21,14 +21,50 @@
  *  limitations under the License.
  *
  ******************************************************************************/
+#[cfg(feature = "async-tokio")]
+mod async_tokio;
+
+mod allocations;
+mod buffer;
+mod buffer_pool;
+mod decoder;
+mod error;
+mod escaping;
 mod f64_serializer;
 
 #[cfg(feature = "ilp-over-http")]
 mod http;
 
+#[cfg(all(feature = "net", feature = "log"))]
+mod logging;
+
+#[cfg(feature = "metrics")]
+mod metrics;
+
+#[cfg(feature = "net")]
 mod mock;
+
+#[cfg(feature = "net")]
+mod pool;
+
+#[cfg(feature = "net")]
 mod sender;
 
+#[cfg(feature = "net")]
+mod shared;
+
+#[cfg(feature = "net")]
+mod spill;
+
+#[cfg(all(feature = "net", feature = "tracing"))]
+mod spans;
+
+#[cfg(feature = "net")]
+mod tee;
+
+#[cfg(feature = "worker")]
+mod worker;
+
 #[cfg(feature = "json_tests")]
 mod json_tests {
     include!(concat!(env!("OUT_DIR"), "/json_tests.rs"));
@@ -0,0 +1,155 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{spill_files, Buffer, SenderPool};
+use crate::tests::mock::{expect_eventual_disconnect, MockServer};
+use crate::tests::TestResult;
+use crate::ErrorCode;
+
+use std::time::Duration;
+
+#[test]
+fn test_pool_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SenderPool>();
+}
+
+#[test]
+fn test_pool_zero_size() -> TestResult {
+    let server = MockServer::new()?;
+    let err = SenderPool::new(server.lsb_tcp(), 0).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(err.msg(), "Sender pool size must be at least 1.");
+    Ok(())
+}
+
+#[test]
+fn test_pool_reuse() -> TestResult {
+    let mut server = MockServer::new()?;
+    let pool = SenderPool::new(server.lsb_tcp(), 1)?;
+    server.accept()?;
+    assert_eq!(pool.size(), 1);
+    assert_eq!(pool.idle_count(), 1);
+
+    let mut buffer = Buffer::new();
+    {
+        let mut sender = pool.acquire()?;
+        assert_eq!(pool.idle_count(), 0);
+        assert!(pool.try_acquire()?.is_none());
+        buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+        sender.flush(&mut buffer)?;
+    }
+    assert_eq!(pool.idle_count(), 1);
+
+    // The same connection is handed out again.
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    pool.try_acquire()?.unwrap().flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 2);
+    assert_eq!(server.msgs[0], "test,t1=v1\n");
+    assert_eq!(server.msgs[1], "test,t1=v2\n");
+    Ok(())
+}
+
+#[test]
+fn test_pool_reconnects_broken_sender() -> TestResult {
+    let mut server = MockServer::new()?;
+    let pool = SenderPool::new(server.lsb_tcp(), 1)?;
+    server.accept()?;
+
+    {
+        let mut sender = pool.acquire()?;
+        server.close_client();
+        expect_eventual_disconnect(&mut sender);
+    }
+
+    let mut sender = pool.acquire()?;
+    assert!(!sender.must_close());
+    server.accept()?;
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0], "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_pool_spills_per_sender() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let mut server = MockServer::new()?;
+    let pool = SenderPool::new(server.lsb_tcp().spill_dir(dir.path())?, 2)?;
+    let mut first = pool.acquire()?;
+    let mut second = pool.acquire()?;
+    server.accept()?;
+    server.accept()?;
+    server.close_client();
+
+    // Each sender spills to a subdirectory of its own.
+    expect_eventual_disconnect(&mut first);
+    expect_eventual_disconnect(&mut second);
+    assert!(spill_files(dir.path())?.is_empty());
+    for slot in ["0", "1"] {
+        assert_eq!(spill_files(dir.path().join(slot))?.len(), 1);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_pool_replaces_stale_sender() -> TestResult {
+    let mut server = MockServer::new()?;
+    let pool = SenderPool::new(server.lsb_tcp(), 1)?;
+    server.accept()?;
+
+    // The server closes the idle sender's connection: The first write to it
+    // would still succeed, but the pool notices and connects anew.
+    server.close_client();
+    let mut sender = pool.acquire()?;
+    assert!(!sender.must_close());
+    server.accept()?;
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0], "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_pool_blocks_until_released() -> TestResult {
+    let mut server = MockServer::new()?;
+    let pool = SenderPool::new(server.lsb_tcp(), 1)?;
+    server.accept()?;
+
+    std::thread::scope(|scope| -> TestResult {
+        let sender = pool.acquire()?;
+        let waiter = scope.spawn(|| pool.acquire().map(|_| ()));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        drop(sender);
+        waiter.join().unwrap()?;
+        Ok(())
+    })?;
+    assert_eq!(pool.idle_count(), 1);
+    Ok(())
+}
@@ -24,18 +24,26 @@
 
 use crate::{
     ingress::{
-        Buffer, CertificateAuthority, Sender, TableName, Timestamp, TimestampMicros, TimestampNanos,
+        decoder::{parse_lines, ParsedRow, ParsedValue},
+        resolve_all,
+        testing::InMemorySink,
+        AutoFlush, Buffer, CertificateAuthority, FlushObserver, OverflowPolicy, Protocol,
+        ProtocolVersion, Resolver, Sender, SenderBuilder, SenderStats, Service, StaticResolver,
+        TimestampMicros, TimestampNanos, Validation,
     },
     Error, ErrorCode,
 };
 
 use crate::tests::{
-    mock::{certs_dir, MockServer},
+    mock::{certs_dir, expect_eventual_disconnect, MockServer, MockSink},
     TestResult,
 };
 
 use core::time::Duration;
-use std::{io, time::SystemTime};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
 
 #[test]
 fn test_basics() -> TestResult {
@@ -81,6 +89,1423 @@ fn test_basics() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_flush_empty() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    sender.flush(&mut buffer)?;
+    sender.flush_and_keep(&buffer)?;
+    assert!(!sender.must_close());
+
+    // The server counts every byte it reads: Only the row's arrive.
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs, ["test,t1=v1\n"]);
+    assert_eq!(server.bytes_received, "test,t1=v1\n".len());
+    Ok(())
+}
+
+#[test]
+fn test_flush_and_keep() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let exp = "test,t1=v1\n";
+    sender.flush_and_keep(&buffer)?;
+    sender.flush_and_keep(&buffer)?;
+    assert_eq!(buffer.as_str(), exp);
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(server.recv_q()?, 2);
+    assert_eq!(server.msgs[0].as_str(), exp);
+    assert_eq!(server.msgs[1].as_str(), exp);
+
+    // The buffer accepts more rows afterwards.
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 2);
+    assert_eq!(server.msgs[2].as_str(), exp);
+    assert_eq!(server.msgs[3].as_str(), "test,t1=v2\n");
+    Ok(())
+}
+
+#[test]
+fn test_flush_mid_row() -> TestResult {
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer
+        .table("test")?
+        .symbol("t1", "v2")?
+        .column_i64("c", 1)?;
+
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "State error: Bad call to `flush`, should have called `column` or `at` instead."
+    );
+    assert!(sink.contents_str().is_empty());
+
+    // Only the incomplete row is discarded.
+    assert_eq!(buffer.as_str(), "test,t1=v1\n");
+    assert_eq!(buffer.row_count(), 1);
+    assert!(buffer.ends_at_row_boundary());
+    buffer.table("test")?.symbol("t1", "v3")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.contents_str(), "test,t1=v1\ntest,t1=v3\n");
+
+    buffer.table("test")?;
+    assert!(sender.flush(&mut buffer).is_err());
+    assert!(buffer.is_empty());
+    buffer.table("test")?.symbol("t1", "v4")?.at_now()?;
+    assert_eq!(buffer.as_str(), "test,t1=v4\n");
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_flush_and_keep_mid_row() -> TestResult {
+    let mut sender = Sender::from_sink(Vec::new());
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v2")?;
+    let before = buffer.as_str().to_owned();
+
+    let err = sender.flush_and_keep(&buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "State error: Bad call to `flush`, should have called `symbol`, `column` or `at` instead."
+    );
+    assert_eq!(buffer.as_str(), before);
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_auto_flush_rows() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().auto_flush_rows(2)?.build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    sender.at_now(&mut buffer)?;
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(server.recv_q()?, 0);
+
+    buffer.table("test")?.symbol("t1", "v2")?;
+    sender.at(&mut buffer, TimestampNanos::new(10))?;
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.row_count(), 0);
+    assert_eq!(server.recv_q()?, 2);
+    assert_eq!(server.msgs[0].as_str(), "test,t1=v1\n");
+    assert_eq!(server.msgs[1].as_str(), "test,t1=v2 10\n");
+    Ok(())
+}
+
+#[test]
+fn test_auto_flush_bytes() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().auto_flush_bytes(22)?.build()?;
+    server.accept()?;
+
+    // Each row is 11 bytes.
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    sender.at_now(&mut buffer)?;
+    assert_eq!(buffer.len(), 11);
+    buffer.table("test")?.symbol("t1", "v2")?;
+    assert_eq!(server.recv_q()?, 0);
+    sender.at_now(&mut buffer)?;
+    assert!(buffer.is_empty());
+    assert_eq!(server.recv_q()?, 2);
+    Ok(())
+}
+
+#[test]
+fn test_auto_flush_off() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .auto_flush_rows(1)?
+        .auto_flush(AutoFlush::Off)?
+        .build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    sender.at_now(&mut buffer)?;
+    buffer.table("test")?.symbol("t1", "v2")?;
+    sender.at_now(&mut buffer)?;
+    assert_eq!(buffer.row_count(), 2);
+    assert_eq!(server.recv_q()?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_auto_flush_error() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().auto_flush_rows(1)?.build()?;
+    server.accept()?;
+    server.close_client();
+    expect_eventual_disconnect(&mut sender);
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    let err = sender.at_now(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: not connected to database."
+    );
+
+    // The row was completed and kept.
+    assert_eq!(buffer.as_str(), "test,t1=v1\n");
+    assert_eq!(buffer.row_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_auto_flush_mid_row() -> TestResult {
+    let mut sender = Sender::from_sink(Vec::new());
+    let mut buffer = Buffer::new();
+    buffer.table("test")?;
+    let err = sender.at_now(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(buffer.row_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_too_large() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().max_buf_size(1024)?.build()?;
+    server.accept()?;
+
+    let mut buffer = sender.new_buffer();
+    assert_eq!(buffer.max_buf_size(), 1024);
+    let value = "x".repeat(100);
+    let mut rows = 0;
+    let err = loop {
+        buffer.table("test")?.symbol("t1", value.as_str())?;
+        match buffer.at(TimestampNanos::new(1)) {
+            Ok(()) => rows += 1,
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert_eq!(
+        err.msg(),
+        format!(
+            "Could not add row of 111 bytes: It would grow the buffer to {} bytes, \
+            exceeding the maximum of 1024 bytes. Flush the buffer first.",
+            rows * 111 + 111
+        )
+    );
+    assert_eq!(buffer.row_count(), rows);
+    assert_eq!(buffer.len(), rows * 111);
+    assert!(buffer.len() <= 1024);
+    assert!(!sender.must_close());
+
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, rows);
+    buffer.table("test")?.symbol("t1", value.as_str())?;
+    buffer.at(TimestampNanos::new(1))?;
+    assert_eq!(buffer.row_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_too_large_auto_flush() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .max_buf_size(1024)?
+        .auto_flush_rows(1000)?
+        .build()?;
+    server.accept()?;
+
+    // Each row is 111 bytes: The tenth one no longer fits.
+    let mut buffer = sender.new_buffer();
+    let value = "x".repeat(100);
+    for _ in 0..10 {
+        buffer.table("test")?.symbol("t1", value.as_str())?;
+        sender.at(&mut buffer, TimestampNanos::new(1))?;
+    }
+    assert_eq!(server.recv_q()?, 9);
+    assert_eq!(sender.stats().rows_sent, 9);
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(buffer.len(), 111);
+    assert!(buffer.transactional());
+    assert_eq!(buffer.peek_last_row(), server.msgs[0].as_str());
+
+    // A row that doesn't fit on its own is discarded without flushing.
+    let value = "x".repeat(1024);
+    buffer.table("test")?.symbol("t1", value.as_str())?;
+    let err = sender.at_now(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(buffer.len(), 111);
+    assert_eq!(server.recv_q()?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_too_large_drop_newest() -> TestResult {
+    let mut server = MockServer::new()?;
+    let observer = Arc::new(RecordingObserver::default());
+    let mut sender = server
+        .lsb_tcp()
+        .max_buf_size(1024)?
+        .overflow_policy(OverflowPolicy::DropNewest)?
+        .observer(observer.clone())?
+        .build()?;
+    server.accept()?;
+
+    // Each row is 111 bytes: The tenth one no longer fits and is dropped.
+    let mut buffer = sender.new_buffer();
+    for value in 0..10 {
+        let value = format!("{value}").repeat(100);
+        buffer.table("test")?.symbol("t1", value.as_str())?;
+        sender.at(&mut buffer, TimestampNanos::new(1))?;
+    }
+    assert_eq!(buffer.row_count(), 9);
+    assert!(buffer.peek_last_row().contains("8888"));
+    assert_eq!(sender.stats().rows_dropped, 1);
+    let events = observer.events.lock().unwrap();
+    assert_eq!(events.last().unwrap(), "dropped rows=1");
+    assert_eq!(events.len(), 10);
+    Ok(())
+}
+
+#[test]
+fn test_overflow_policy_conf() -> TestResult {
+    SenderBuilder::from_conf("tcp::addr=localhost:9009;overflow_policy=drop_newest;")?;
+    let err =
+        SenderBuilder::from_conf("tcp::addr=localhost:9009;overflow_policy=block;").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        r#"Config parameter "overflow_policy" must be either "error" or "drop_newest"."#
+    );
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .overflow_policy(OverflowPolicy::DropOldest)
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    Ok(())
+}
+
+#[test]
+fn test_sender_at_caps_buffer() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().max_buf_size(1024)?.build()?;
+    server.accept()?;
+
+    // The sender's cap applies even to an uncapped buffer.
+    let mut buffer = Buffer::new();
+    assert_eq!(buffer.max_buf_size(), usize::MAX);
+    buffer
+        .table("test")?
+        .symbol("t1", "x".repeat(2000).as_str())?;
+    let err = sender.at_now(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert!(buffer.is_empty());
+    Ok(())
+}
+
+fn fill_buffer(buffer: &mut Buffer, bytes: usize) -> TestResult {
+    let value = "x".repeat(100);
+    while buffer.len() < bytes {
+        buffer
+            .table("test")?
+            .symbol("t1", value.as_str())?
+            .at_now()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_buffer_shrink_threshold() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().buffer_shrink_threshold(4096)?.build()?;
+    server.accept()?;
+
+    // Small buffers keep their capacity.
+    let mut buffer = Buffer::new();
+    fill_buffer(&mut buffer, 1024)?;
+    let capacity = buffer.capacity();
+    assert!(capacity <= 4096);
+    sender.flush(&mut buffer)?;
+    assert_eq!(buffer.capacity(), capacity);
+
+    // Large ones are shrunk.
+    fill_buffer(&mut buffer, 16384)?;
+    assert!(buffer.capacity() > 4096);
+    sender.flush(&mut buffer)?;
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.capacity(), 0);
+
+    // Back to their initial capacity.
+    let mut buffer = Buffer::with_capacity(2048);
+    fill_buffer(&mut buffer, 16384)?;
+    assert!(buffer.capacity() > 4096);
+    sender.flush(&mut buffer)?;
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.capacity(), 2048);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_keeps_capacity() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    fill_buffer(&mut buffer, 16384)?;
+    let capacity = buffer.capacity();
+    sender.flush(&mut buffer)?;
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.capacity(), capacity);
+    Ok(())
+}
+
+#[test]
+fn test_sink_flush() -> TestResult {
+    let sink = MockSink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+
+    let mut buffer = Buffer::new();
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.write_calls(), 0);
+
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.column_i64("c1", 42)?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert!(buffer.is_empty());
+    assert_eq!(sink.written_str(), "test,t1=v1\ntest c1=42i\n");
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_sink_buffered() -> TestResult {
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(io::BufWriter::new(sink.clone()));
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.contents_str(), "test,t1=v1\n");
+
+    sink.clear();
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.contents(), b"test,t1=v2\n");
+    Ok(())
+}
+
+#[test]
+fn test_sink_short_writes() -> TestResult {
+    let sink = MockSink::new().with_max_write_len(3);
+    let mut sender = Sender::from_sink(sink.clone());
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.written_str(), "test,t1=v1\n");
+    assert_eq!(sink.write_calls(), 4);
+    Ok(())
+}
+
+#[test]
+fn test_sink_chunked_writes() -> TestResult {
+    let sink = MockSink::new().with_max_write_len(4096);
+    let mut sender = Sender::from_sink(sink.clone());
+
+    let mut buffer = Buffer::new();
+    for value in 0..100_000 {
+        buffer
+            .table("test")?
+            .column_i64("c1", value)?
+            .at(TimestampNanos::new(1))?;
+    }
+    let expected = buffer.as_str().to_owned();
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.written_str(), expected);
+    assert_eq!(sink.write_calls(), expected.len().div_ceil(4096));
+
+    // A failure part-way through reports the exact progress.
+    let sink = MockSink::new()
+        .with_max_write_len(4096)
+        .with_fail_after(10_000, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink);
+    buffer
+        .table("test")?
+        .column_str("c1", "x".repeat(20_000))?
+        .at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error \
+        [10000 of 20011 bytes sent, retrying may duplicate rows]"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_write_chunk_size() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().write_chunk_size(7)?.build()?;
+    server.accept()?;
+
+    let mut buffer = sender.new_buffer();
+    for value in 0..3 {
+        buffer
+            .table("test")?
+            .symbol("t1", "v1")?
+            .column_i64("c1", value)?
+            .at(TimestampNanos::new(1))?;
+    }
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 3);
+    assert_eq!(
+        server.msgs,
+        [
+            "test,t1=v1 c1=0i 1\n",
+            "test,t1=v1 c1=1i 1\n",
+            "test,t1=v1 c1=2i 1\n"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sink_write_error_nothing_sent() -> TestResult {
+    let sink = MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink);
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error"
+    );
+    assert_eq!(buffer.as_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_sink_partial_write_error() -> TestResult {
+    // Fail partway through, after several short writes.
+    let sink = MockSink::new()
+        .with_max_write_len(3)
+        .with_fail_after(8, io::ErrorKind::ConnectionReset);
+    let mut sender = Sender::from_sink(sink.clone());
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error \
+        [8 of 11 bytes sent, retrying may duplicate rows]"
+    );
+    assert_eq!(sink.written_str(), "test,t1=");
+    assert_eq!(buffer.as_str(), "test,t1=v1\n");
+
+    // The caller can re-flush the retained rows through a new sender.
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    sender.flush(&mut buffer)?;
+    assert_eq!(sink.contents_str(), "test,t1=v1\n");
+    assert!(buffer.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_sink_write_error() -> TestResult {
+    let sink = MockSink::new().with_fail_after(5, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink.clone());
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error \
+        [5 of 11 bytes sent, retrying may duplicate rows]"
+    );
+    assert!(sender.must_close());
+
+    // The buffer is retained on failure.
+    assert_eq!(buffer.as_str(), "test,t1=v1\n");
+    assert_eq!(sink.written_str(), "test,");
+
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: not connected to database."
+    );
+    Ok(())
+}
+
+#[test]
+fn test_from_stream() -> TestResult {
+    let mut server = MockServer::new()?;
+    let stream = std::net::TcpStream::connect((server.host, server.port))?;
+    server.accept()?;
+    let mut sender = Sender::from_stream(stream);
+    assert!(format!("{:?}", sender).starts_with("Sender[stream,peer="));
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0].as_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_static_resolver() -> TestResult {
+    let mut server = MockServer::new()?;
+    let resolver =
+        StaticResolver::new().with_host("questdb.invalid", [IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "questdb.invalid", server.port)
+        .resolver(Arc::new(resolver))?
+        .build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0].as_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_resolve_all() -> TestResult {
+    let addrs = resolve_all("localhost", 9009)?;
+    assert!(addrs.contains(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9009)));
+    let mut deduped = addrs.clone();
+    deduped.dedup();
+    assert_eq!(addrs, deduped);
+
+    let err = resolve_all("questdb.invalid", 9009).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CouldNotResolveAddr);
+    Ok(())
+}
+
+#[test]
+fn test_connect_ipv6() -> TestResult {
+    let mut server = MockServer::new_ipv6()?;
+    let mut sender = Sender::from_conf(format!("tcp::addr=[::1]:{};", server.port))?;
+    server.accept()?;
+    let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), server.port);
+    assert_eq!(sender.peer_addr(), Some(addr));
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0].as_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_from_socket_addr() -> TestResult {
+    let mut server = MockServer::new()?;
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), server.port);
+
+    // Neither the resolver nor a DNS cache is consulted.
+    let mut sender = SenderBuilder::from_socket_addr(Protocol::Tcp, addr)
+        .resolver(Arc::new(StaticResolver::new()))?
+        .dns_ttl(Duration::from_secs(60))?
+        .build()?;
+    server.accept()?;
+    assert_eq!(sender.peer_addr(), Some(addr));
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0].as_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_resolver_unknown_host() -> TestResult {
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .resolver(Arc::new(StaticResolver::new()))?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CouldNotResolveAddr);
+    assert_eq!(err.msg(), "Could not resolve \"localhost\": Unknown host.");
+
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", "qdb_ilp")
+        .resolver(Arc::new(StaticResolver::new()))?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "A custom resolver requires a numeric port, not \"qdb_ilp\"."
+    );
+    Ok(())
+}
+
+struct EmptyResolver;
+
+impl Resolver for EmptyResolver {
+    fn resolve(&self, _host: &str, _port: u16) -> crate::Result<Vec<SocketAddr>> {
+        Ok(Vec::new())
+    }
+}
+
+#[test]
+fn test_resolver_no_addrs() -> TestResult {
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .resolver(Arc::new(EmptyResolver))?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CouldNotResolveAddr);
+    assert_eq!(
+        err.msg(),
+        "Could not resolve \"localhost:9009\": No addresses found."
+    );
+    Ok(())
+}
+
+struct SlowResolver(Duration);
+
+impl Resolver for SlowResolver {
+    fn resolve(&self, _host: &str, port: u16) -> crate::Result<Vec<SocketAddr>> {
+        std::thread::sleep(self.0);
+        Ok(vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)])
+    }
+}
+
+#[test]
+fn test_resolve_timeout() -> TestResult {
+    let started = std::time::Instant::now();
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .resolver(Arc::new(SlowResolver(Duration::from_secs(5))))?
+        .resolve_timeout(Duration::from_millis(100))?
+        .build()
+        .unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(err.code(), ErrorCode::CouldNotResolveAddr);
+    assert!(err
+        .msg()
+        .starts_with("Could not resolve \"localhost:9009\": Timed out after "));
+    Ok(())
+}
+
+#[test]
+fn test_resolve_within_timeout() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .resolver(Arc::new(SlowResolver(Duration::from_millis(10))))?
+        .resolve_timeout(Duration::from_secs(5))?
+        .build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_resolver_tries_all_addrs() -> TestResult {
+    // The mock server only listens on 127.0.0.1, so 127.0.0.2 refuses the connection.
+    let mut server = MockServer::new()?;
+    let resolver = StaticResolver::new().with_host(
+        "questdb.invalid",
+        [
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+        ],
+    );
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "questdb.invalid", server.port)
+        .resolver(Arc::new(resolver))?
+        .build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    Ok(())
+}
+
+/// Resolves to each of the given addresses in turn, ignoring the requested port.
+struct SequenceResolver {
+    addrs: Vec<SocketAddr>,
+    calls: Mutex<usize>,
+}
+
+impl SequenceResolver {
+    fn new(addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            addrs,
+            calls: Mutex::new(0),
+        }
+    }
+
+    fn calls(&self) -> usize {
+        *self.calls.lock().unwrap()
+    }
+}
+
+impl Resolver for SequenceResolver {
+    fn resolve(&self, _host: &str, _port: u16) -> crate::Result<Vec<SocketAddr>> {
+        let mut calls = self.calls.lock().unwrap();
+        let addr = self.addrs[*calls % self.addrs.len()];
+        *calls += 1;
+        Ok(vec![addr])
+    }
+}
+
+#[test]
+fn test_reresolve_on_rebuild() -> TestResult {
+    let primary = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let replica = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let resolver = Arc::new(SequenceResolver::new(vec![
+        primary.local_addr()?,
+        replica.local_addr()?,
+    ]));
+    let builder =
+        SenderBuilder::new(Protocol::Tcp, "questdb.invalid", 9009).resolver(resolver.clone())?;
+
+    let sender = builder.build()?;
+    assert_eq!(sender.peer_addr(), Some(primary.local_addr()?));
+    let sender = builder.build()?;
+    assert_eq!(sender.peer_addr(), Some(replica.local_addr()?));
+    assert_eq!(resolver.calls(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_dns_ttl() -> TestResult {
+    let primary = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let replica = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let resolver = Arc::new(SequenceResolver::new(vec![
+        primary.local_addr()?,
+        replica.local_addr()?,
+    ]));
+    let builder = SenderBuilder::new(Protocol::Tcp, "questdb.invalid", 9009)
+        .resolver(resolver.clone())?
+        .dns_ttl(Duration::from_secs(3600))?;
+
+    // Clones of the builder share the cached resolution.
+    let sender1 = builder.build()?;
+    let sender2 = builder.clone().build()?;
+    assert_eq!(sender1.peer_addr(), Some(primary.local_addr()?));
+    assert_eq!(sender2.peer_addr(), Some(primary.local_addr()?));
+    assert_eq!(resolver.calls(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_dns_ttl_expired() -> TestResult {
+    let primary = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let replica = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let resolver = Arc::new(SequenceResolver::new(vec![
+        primary.local_addr()?,
+        replica.local_addr()?,
+    ]));
+    let builder = SenderBuilder::new(Protocol::Tcp, "questdb.invalid", 9009)
+        .resolver(resolver.clone())?
+        .dns_ttl(Duration::from_millis(50))?;
+
+    let sender = builder.build()?;
+    assert_eq!(sender.peer_addr(), Some(primary.local_addr()?));
+    std::thread::sleep(Duration::from_millis(100));
+    let sender = builder.build()?;
+    assert_eq!(sender.peer_addr(), Some(replica.local_addr()?));
+    assert_eq!(resolver.calls(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_peer_addr_sink() {
+    assert_eq!(Sender::from_sink(Vec::new()).peer_addr(), None);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_buffer_sizes() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = server
+        .lsb_tcp()
+        .send_buffer_size(256 * 1024)?
+        .recv_buffer_size(64 * 1024)?
+        .build()?;
+    server.accept()?;
+
+    // Linux doubles the requested sizes to leave room for bookkeeping.
+    assert!(sender.send_buffer_size().unwrap() >= 256 * 1024);
+    assert!(sender.recv_buffer_size().unwrap() >= 64 * 1024);
+    assert_eq!(Sender::from_sink(Vec::new()).send_buffer_size(), None);
+    Ok(())
+}
+
+/// Keep checking the connection until the sender notices it was closed.
+fn check_until_disconnect(sender: &mut Sender) -> crate::Error {
+    for _ in 0..500 {
+        if let Err(err) = sender.check_connection() {
+            return err;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Sender never noticed the disconnection");
+}
+
+#[test]
+fn test_check_connection() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+    sender.check_connection()?;
+    assert!(!sender.must_close());
+
+    server.close_client();
+    let err = check_until_disconnect(&mut sender);
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Connection check failed: Connection closed by peer."
+    );
+    assert!(sender.must_close());
+
+    let err = sender.check_connection().unwrap_err();
+    assert_eq!(
+        err.msg(),
+        "Connection check failed: Not connected to database."
+    );
+    Ok(())
+}
+
+#[test]
+fn test_check_connection_keeps_data() -> TestResult {
+    // Peeking doesn't consume what the server sent.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", port).build()?;
+    let (mut stream, _) = listener.accept()?;
+    io::Write::write_all(&mut stream, b"x")?;
+    std::thread::sleep(Duration::from_millis(50));
+    sender.check_connection()?;
+    sender.check_connection()?;
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_check_connection_on_flush() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().check_connection_on_flush(true)?.build()?;
+    server.accept()?;
+    server.close_client();
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let mut result = Ok(());
+    for _ in 0..500 {
+        result = sender.flush_and_keep(&buffer);
+        if result.is_err() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let err = result.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err.msg().starts_with("Connection check failed: "));
+    assert!(sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_ping() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+    sender.ping()?;
+    assert_eq!(server.recv(0.1)?, 0);
+
+    server.close_client();
+    let mut result = Ok(());
+    for _ in 0..500 {
+        result = sender.ping();
+        if result.is_err() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let err = result.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err.msg().starts_with("Connection check failed: "));
+    assert!(sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_check_connection_sink() -> TestResult {
+    Sender::from_sink(Vec::new()).check_connection()?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    flushes: Mutex<Vec<(usize, usize)>>,
+    errors: Mutex<Vec<ErrorCode>>,
+    events: Mutex<Vec<String>>,
+}
+
+impl RecordingObserver {
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl FlushObserver for RecordingObserver {
+    fn on_flush_start(&self, rows: usize, bytes: usize) {
+        self.record(format!("start bytes={bytes} rows={rows}"));
+    }
+
+    fn on_flush(&self, rows: usize, bytes: usize, _duration: Duration) {
+        self.flushes.lock().unwrap().push((rows, bytes));
+        self.record(format!("flush bytes={bytes} rows={rows}"));
+    }
+
+    fn on_error(&self, code: ErrorCode) {
+        self.errors.lock().unwrap().push(code);
+        self.record(format!("error {code:?}"));
+    }
+
+    fn on_flush_end(&self, result: Result<(), &crate::Error>, _duration: Duration) {
+        match result {
+            Ok(()) => self.record("end ok".to_owned()),
+            Err(err) => self.record(format!("end {:?}", err.code())),
+        }
+    }
+
+    fn on_row_completed(&self, bytes: usize) {
+        self.record(format!("row bytes={bytes}"));
+    }
+
+    fn on_rows_dropped(&self, rows: usize) {
+        self.record(format!("dropped rows={rows}"));
+    }
+}
+
+/// Panics in every callback.
+struct PanickingObserver;
+
+impl FlushObserver for PanickingObserver {
+    fn on_flush_start(&self, _rows: usize, _bytes: usize) {
+        panic!("on_flush_start");
+    }
+
+    fn on_flush(&self, _rows: usize, _bytes: usize, _duration: Duration) {
+        panic!("on_flush");
+    }
+
+    fn on_error(&self, _code: ErrorCode) {
+        panic!("on_error");
+    }
+
+    fn on_flush_end(&self, _result: Result<(), &crate::Error>, _duration: Duration) {
+        panic!("on_flush_end");
+    }
+
+    fn on_row_completed(&self, _bytes: usize) {
+        panic!("on_row_completed");
+    }
+}
+
+#[test]
+fn test_observer_on_flush() -> TestResult {
+    let mut server = MockServer::new()?;
+    let observer = Arc::new(RecordingObserver::default());
+    let mut sender = server.lsb_tcp().observer(observer.clone())?.build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 2);
+
+    // Flushing an empty buffer isn't reported.
+    sender.flush(&mut buffer)?;
+    assert_eq!(*observer.flushes.lock().unwrap(), vec![(2, 22)]);
+    assert!(observer.errors.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_observer_on_error() -> TestResult {
+    let mut server = MockServer::new()?;
+    let observer = Arc::new(RecordingObserver::default());
+    let mut sender = server.lsb_tcp().observer(observer.clone())?.build()?;
+    server.accept()?;
+    server.close_client();
+    expect_eventual_disconnect(&mut sender);
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    let errors = observer.errors.lock().unwrap();
+    assert!(errors.len() >= 2);
+    assert!(errors.iter().all(|&code| code == ErrorCode::SocketError));
+    Ok(())
+}
+
+#[test]
+fn test_observer_event_order() -> TestResult {
+    let mut server = MockServer::new()?;
+    let observer = Arc::new(RecordingObserver::default());
+    let mut sender = server.lsb_tcp().observer(observer.clone())?.build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    sender.at_now(&mut buffer)?;
+    sender.flush(&mut buffer)?;
+
+    // Rows completed on the buffer alone aren't reported.
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v3")?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    buffer.table("test")?.symbol("t1", "v3")?;
+    sender.at(&mut buffer, TimestampNanos::new(1))?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 3);
+
+    assert_eq!(
+        *observer.events.lock().unwrap(),
+        vec![
+            "row bytes=11",
+            "start bytes=11 rows=1",
+            "flush bytes=11 rows=1",
+            "end ok",
+            "start bytes=21 rows=1",
+            "error InvalidApiCall",
+            "end InvalidApiCall",
+            "row bytes=13",
+            "start bytes=24 rows=2",
+            "flush bytes=24 rows=2",
+            "end ok",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_observer_panics_are_caught() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .observer(Arc::new(PanickingObserver))?
+        .build()?;
+    server.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?;
+    sender.at_now(&mut buffer)?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0], "test,t1=v1\n");
+    assert_eq!(sender.stats().flushes, 1);
+    assert!(!sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_reconnect() -> TestResult {
+    let mut server1 = MockServer::new()?;
+    let mut server2 = MockServer::new()?;
+    let resolver = Arc::new(SequenceResolver::new(vec![
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), server1.port),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), server2.port),
+    ]));
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "questdb.invalid", 9009)
+        .resolver(resolver)?
+        .build()?;
+    server1.accept()?;
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server1.recv_q()?, 1);
+
+    // Kill the server and keep writing until the sender notices.
+    server1.close_client();
+    let mut failed = false;
+    for _ in 0..1000 {
+        buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+        if sender.flush(&mut buffer).is_err() {
+            failed = true;
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(failed);
+    assert!(sender.must_close());
+    assert_eq!(buffer.as_str(), "test,t1=v2\n");
+
+    sender.reconnect()?;
+    assert!(!sender.must_close());
+    server2.accept()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server2.recv_q()?, 1);
+    assert_eq!(server2.msgs[0].as_str(), "test,t1=v2\n");
+    Ok(())
+}
+
+#[test]
+fn test_reconnect_failure() -> TestResult {
+    let mut server = MockServer::new()?;
+    let port = server.port;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+    drop(server);
+
+    let err = sender.reconnect().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err
+        .msg()
+        .starts_with(&format!("Could not connect to \"localhost:{}\": ", port)));
+    assert!(sender.must_close());
+    Ok(())
+}
+
+#[test]
+fn test_reconnect_sink() {
+    let mut sender = Sender::from_sink(Vec::new());
+    let err = sender.reconnect().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "Could not reconnect: The sender was not built by a SenderBuilder."
+    );
+    assert!(!sender.must_close());
+}
+
+/// A local port nothing listens on.
+fn dead_port() -> io::Result<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+#[test]
+fn test_failover_skips_dead_host() -> TestResult {
+    let dead = dead_port()?;
+    let mut server = MockServer::new()?;
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", dead)
+        .addr(("127.0.0.1", server.port))?
+        .build()?;
+    server.accept()?;
+    assert_eq!(
+        sender.current_host(),
+        Some(Service::new("127.0.0.1", server.port))
+    );
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_failover_all_dead() -> TestResult {
+    let dead1 = dead_port()?;
+    let dead2 = dead_port()?;
+    let err = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", dead1)
+        .addr(("127.0.0.1", dead2))?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    let prefix = format!(
+        "Could not connect to any of the 2 addresses: \
+        \"127.0.0.1:{dead1}\": Could not connect to \"127.0.0.1:{dead1}\": "
+    );
+    assert!(err.msg().starts_with(&prefix), "{}", err.msg());
+    let second = format!("; \"127.0.0.1:{dead2}\": Could not connect to \"127.0.0.1:{dead2}\": ");
+    assert!(err.msg().contains(&second), "{}", err.msg());
+    Ok(())
+}
+
+#[test]
+fn test_failover_on_reconnect() -> TestResult {
+    let mut primary = MockServer::new()?;
+    let mut standby = MockServer::new()?;
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", primary.port)
+        .addr(("127.0.0.1", standby.port))?
+        .build()?;
+    primary.accept()?;
+    assert_eq!(
+        sender.current_host(),
+        Some(Service::new("127.0.0.1", primary.port))
+    );
+
+    primary.close_client();
+    drop(primary);
+    expect_eventual_disconnect(&mut sender);
+
+    sender.reconnect()?;
+    standby.accept()?;
+    assert_eq!(
+        sender.current_host(),
+        Some(Service::new("127.0.0.1", standby.port))
+    );
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(standby.recv_q()?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_failover_after() -> TestResult {
+    let mut primary = MockServer::new()?;
+    let primary_port = primary.port;
+    let mut standby = MockServer::new()?;
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "127.0.0.1", primary.port)
+        .addr(("127.0.0.1", standby.port))?
+        .failover_after(2)?
+        .build()?;
+    primary.accept()?;
+
+    primary.close_client();
+    drop(primary);
+    expect_eventual_disconnect(&mut sender);
+
+    // The first failure to reconnect sticks to the primary.
+    let err = sender.reconnect().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(sender.must_close());
+    assert_eq!(
+        sender.current_host(),
+        Some(Service::new("127.0.0.1", primary_port))
+    );
+
+    // The second moves on to the standby.
+    sender.reconnect()?;
+    standby.accept()?;
+    assert_eq!(
+        sender.current_host(),
+        Some(Service::new("127.0.0.1", standby.port))
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket() -> TestResult {
+    use std::io::Read;
+    use std::os::unix::net::UnixListener;
+
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("questdb.sock");
+    let listener = UnixListener::bind(&path)?;
+    let mut sender = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .unix_socket(&path)?
+        .build()?;
+    let (mut stream, _) = listener.accept()?;
+    assert_eq!(sender.peer_addr(), None);
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    drop(sender);
+
+    let mut received = String::new();
+    stream.read_to_string(&mut received)?;
+    assert_eq!(received, "test,t1=v1\n");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_missing() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("missing.sock");
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .unix_socket(&path)?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err
+        .msg()
+        .starts_with(&format!("Could not connect to {:?}: ", path)));
+    Ok(())
+}
+
+#[test]
+fn test_unix_socket_with_bind() -> TestResult {
+    let err = SenderBuilder::new(Protocol::Tcp, "localhost", 9009)
+        .unix_socket("/tmp/questdb.sock")?
+        .bind_port(0)?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(
+        err.msg(),
+        "Cannot bind to a local address when connecting over a Unix domain socket."
+    );
+    Ok(())
+}
+
+#[test]
+fn test_unix_socket_with_tcp_options() -> TestResult {
+    let builder =
+        || SenderBuilder::new(Protocol::Tcp, "localhost", 9009).unix_socket("/tmp/questdb.sock");
+    for (setting_name, err) in [
+        ("nodelay", builder()?.nodelay(false)?.build().unwrap_err()),
+        (
+            "keepalive_idle",
+            builder()?
+                .keepalive_idle(Duration::from_secs(30))?
+                .build()
+                .unwrap_err(),
+        ),
+        ("ip_tos", builder()?.ip_tos(18 << 2)?.build().unwrap_err()),
+    ] {
+        assert_eq!(err.code(), ErrorCode::ConfigError);
+        assert_eq!(
+            err.msg(),
+            format!(
+                "The {setting_name:?} setting can't be used when connecting over a Unix domain socket."
+            )
+        );
+    }
+    Ok(())
+}
+
 #[test]
 fn test_max_buf_size() -> TestResult {
     let max = 1024;
@@ -109,45 +1534,116 @@ fn test_max_buf_size() -> TestResult {
 }
 
 #[test]
-fn test_table_name_too_long() -> TestResult {
-    let mut buffer = Buffer::with_max_name_len(4);
-    let name = "a name too long";
-    let err = buffer.table(name).unwrap_err();
+fn test_bind_port() -> TestResult {
+    let port = {
+        let probe = std::net::TcpListener::bind("127.0.0.1:0")?;
+        probe.local_addr()?.port()
+    };
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .bind_interface("127.0.0.1")?
+        .bind_port(port)?
+        .build()?;
+    server.accept()?;
+    assert_eq!(server.client_addr()?.port(), port);
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0].as_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_bind_port_ipv6() -> TestResult {
+    let port = {
+        let probe = std::net::TcpListener::bind("[::1]:0")?;
+        probe.local_addr()?.port()
+    };
+    let mut server = MockServer::new_ipv6()?;
+    let mut sender = server.lsb_tcp().bind_port(port)?.build()?;
+    server.accept()?;
+    let addr = server.client_addr()?;
+    assert!(addr.is_ipv6());
+    assert_eq!(addr.port(), port);
+
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_bind_port_in_use() -> TestResult {
+    let server = MockServer::new()?;
+    let err = server
+        .lsb_tcp()
+        .bind_interface("127.0.0.1")?
+        .bind_port(server.port)?
+        .build()
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(err.msg().starts_with(&format!(
+        "Could not bind to local address \"127.0.0.1:{}\": ",
+        server.port
+    )));
+    Ok(())
+}
+
+#[test]
+fn test_sender_max_name_len() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = server.lsb_tcp().max_name_len(4)?.build()?;
+    server.accept()?;
+
+    let mut buffer = sender.new_buffer();
+    assert_eq!(buffer.max_name_len(), 4);
+    buffer
+        .table("abcd")?
+        .symbol("efgh", "a long value")?
+        .at_now()?;
+    let err = buffer.table("abcde").unwrap_err();
     assert_eq!(err.code(), ErrorCode::InvalidName);
     assert_eq!(
         err.msg(),
-        r#"Bad name: "a name too long": Too long (max 4 characters)"#
+        r#"Bad name: "abcde": Too long (5 bytes, max 4 bytes)"#
     );
+    assert_eq!(Buffer::new().max_name_len(), 127);
     Ok(())
 }
 
 #[test]
-fn test_row_count() -> TestResult {
-    let mut buffer = Buffer::new();
-    assert_eq!(buffer.row_count(), 0);
+fn test_sender_reject_control_chars_in_values() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = server
+        .lsb_tcp()
+        .reject_control_chars_in_values(true)?
+        .build()?;
+    server.accept()?;
 
-    buffer.table("x")?.symbol("y", "z1")?.at_now()?;
-    buffer
-        .table("x")?
-        .symbol("y", "z2")?
-        .at(TimestampNanos::now())?;
-    assert_eq!(buffer.row_count(), 2);
+    let mut buffer = sender.new_buffer();
+    assert!(buffer.rejects_control_chars_in_values());
+    let err = buffer.table("t")?.symbol("s", "\u{1}").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidValue);
+    Ok(())
+}
 
-    buffer.set_marker()?;
+#[test]
+fn test_sender_validation() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = server.lsb_tcp().validation(Validation::Off)?.build()?;
+    server.accept()?;
 
-    buffer.table("x")?.symbol("y", "z3")?.at_now()?;
+    let mut buffer = sender.new_buffer();
+    assert_eq!(buffer.validation(), Validation::Off);
     buffer
-        .table("x")?
-        .symbol("y", "z4")?
-        .at(TimestampNanos::now())?;
-    buffer.table("x")?.symbol("y", "z5")?.at_now()?;
-    assert_eq!(buffer.row_count(), 5);
-
-    buffer.rewind_to_marker()?;
-    assert_eq!(buffer.row_count(), 2);
-
-    buffer.clear();
-    assert_eq!(buffer.row_count(), 0);
+        .table("cpu (%)")?
+        .column_i64("load, 1m", 1)?
+        .at_now()?;
+    assert_eq!(buffer.as_str(), "cpu\\ (%) load\\,\\ 1m=1i\n");
     Ok(())
 }
 
@@ -254,99 +1750,507 @@ fn test_bad_key(
 }
 
 #[test]
-fn test_timestamp_overloads() -> TestResult {
-    let tbl_name = TableName::new("tbl_name")?;
+fn test_chunk_concatenation() -> TestResult {
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    let mut archive = String::new();
+    for chunk in 0..3 {
+        let mut buffer = sender.new_buffer();
+        assert!(!buffer.ends_with_complete_row());
+        buffer
+            .table("t")?
+            .symbol("s", "multi\nline")?
+            .column_str("c", "a\n\nb")?
+            .at(TimestampNanos::new(chunk))?;
+        buffer.table("t")?.column_i64("n", chunk)?;
+        assert!(!buffer.ends_with_complete_row());
+        buffer.at_now()?;
+        assert!(buffer.ends_with_complete_row());
+        assert!(buffer.as_str().ends_with('\n'));
+
+        // Line breaks within values are escaped, so the unescaped newlines
+        // split the rows. The flush sends the buffer's contents unchanged.
+        let rows: Vec<ParsedRow> = parse_lines(buffer.as_str()).collect::<crate::Result<_>>()?;
+        assert_eq!(rows.len(), buffer.row_count());
+        archive.push_str(buffer.as_str());
+        sender.flush(&mut buffer)?;
+        assert!(!buffer.ends_with_complete_row());
+    }
+    assert_eq!(sink.contents_str(), archive);
+    let rows: Vec<ParsedRow> = parse_lines(&archive).collect::<crate::Result<_>>()?;
+    assert_eq!(rows.len(), 6);
+    assert_eq!(
+        rows[4].column("c"),
+        Some(&ParsedValue::Str("a\n\nb".to_owned()))
+    );
+    assert!(!archive.contains("\n\n"));
+    Ok(())
+}
 
+#[test]
+fn test_split_off_rows() -> TestResult {
     let mut buffer = Buffer::new();
+    assert!(buffer.split_off_rows(100).is_empty());
+
+    // Rows of 9, 11 and 9 bytes, the second with an escaped newline.
+    buffer
+        .table("t")?
+        .column_i64("a", 1)?
+        .at(TimestampNanos::new(1))?;
     buffer
-        .table(tbl_name)?
-        .column_ts("a", TimestampMicros::new(12345))?
-        .column_ts("b", TimestampMicros::new(-100000000))?
-        .column_ts("c", TimestampNanos::new(12345678))?
-        .column_ts("d", TimestampNanos::new(-12345678))?
-        .column_ts("e", Timestamp::Micros(TimestampMicros::new(-1)))?
-        .column_ts("f", Timestamp::Nanos(TimestampNanos::new(-10000)))?
-        .at(TimestampMicros::new(1))?;
+        .table("t")?
+        .column_str("b", "\n")?
+        .at(TimestampNanos::new(2))?;
     buffer
-        .table(tbl_name)?
-        .column_ts(
-            "a",
-            TimestampMicros::from_systemtime(
-                SystemTime::UNIX_EPOCH
-                    .checked_add(Duration::from_secs(1))
-                    .unwrap(),
-            )?,
-        )?
-        .at(TimestampNanos::from_systemtime(
-            SystemTime::UNIX_EPOCH
-                .checked_add(Duration::from_secs(5))
-                .unwrap(),
-        )?)?;
-
-    let exp = concat!(
-        "tbl_name a=12345t,b=-100000000t,c=12345t,d=-12345t,e=-1t,f=-10t 1000\n",
-        "tbl_name a=1000000t 5000000000\n"
+        .table("t")?
+        .column_i64("a", 3)?
+        .at(TimestampNanos::new(3))?;
+    buffer.table("t")?.column_i64("a", 4)?;
+    assert_eq!(buffer.committed_size(), 29);
+
+    // One byte short of the second row's end.
+    let first = buffer.split_off_rows(19);
+    assert_eq!(first.as_str(), "t a=1i 1\n");
+    assert_eq!(first.row_count(), 1);
+
+    // Exactly at a row boundary.
+    let mut head = buffer.split_off_rows(11);
+    assert_eq!(head.as_str(), "t b=\"\\\n\" 2\n");
+    assert_eq!(head.row_count(), 1);
+    assert_eq!(head.peek_last_row(), "t b=\"\\\n\" 2\n");
+    assert!(head.transactional());
+    assert_eq!(buffer.as_str(), "t a=3i 3\nt a=4i");
+    assert_eq!(buffer.row_count(), 1);
+
+    // A row larger than the limit is still detached on its own.
+    assert!(buffer.split_off_rows(0).ends_with_complete_row());
+    assert_eq!(buffer.as_str(), "t a=4i");
+    assert_eq!(buffer.row_count(), 0);
+    assert!(buffer.split_off_rows(100).is_empty());
+
+    // The remainder completes its row and can be split in turn.
+    buffer.at(TimestampNanos::new(4))?;
+    assert_eq!(buffer.peek_last_row(), "t a=4i 4\n");
+    let tail = buffer.split_off_rows(9);
+    assert_eq!(tail.as_str(), "t a=4i 4\n");
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.row_count(), 0);
+    buffer
+        .table("t")?
+        .column_i64("a", 5)?
+        .at(TimestampNanos::new(5))?;
+
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    sender.flush(&mut head)?;
+    assert!(head.is_empty());
+    head.table("t")?
+        .column_i64("a", 6)?
+        .at(TimestampNanos::new(6))?;
+    assert_eq!(head.row_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_buffers_from_scoped_threads() -> TestResult {
+    let mut heartbeat = Buffer::new();
+    heartbeat
+        .table("heartbeat")?
+        .symbol("host", "a")?
+        .at(TimestampNanos::new(0))?;
+
+    let buffers = std::thread::scope(|scope| {
+        let workers: Vec<_> = (1..=3i64)
+            .map(|worker| {
+                let mut buffer = heartbeat.clone();
+                scope.spawn(move || -> crate::Result<Buffer> {
+                    buffer
+                        .table("t")?
+                        .column_i64("worker", worker)?
+                        .at(TimestampNanos::new(worker))?;
+                    Ok(buffer)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect::<crate::Result<Vec<_>>>()
+    })?;
+
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    for mut buffer in buffers {
+        sender.flush(&mut buffer)?;
+    }
+    assert_eq!(
+        sink.contents_str(),
+        concat!(
+            "heartbeat,host=a 0\nt worker=1i 1\n",
+            "heartbeat,host=a 0\nt worker=2i 2\n",
+            "heartbeat,host=a 0\nt worker=3i 3\n"
+        )
     );
-    assert_eq!(buffer.as_str(), exp);
+    assert_eq!(heartbeat.row_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_append_buffers() -> TestResult {
+    let workers: Vec<_> = (0..3i64)
+        .map(|worker| {
+            std::thread::spawn(move || -> crate::Result<Buffer> {
+                let mut buffer = Buffer::new();
+                for row in 0..4i64 {
+                    buffer
+                        .table("t")?
+                        .symbol("worker", worker.to_string())?
+                        .column_i64("row", row)?
+                        .at(TimestampNanos::new(worker * 10 + row))?;
+                }
+                Ok(buffer)
+            })
+        })
+        .collect();
+
+    let mut buffer = Buffer::new();
+    for worker in workers {
+        let mut other = worker.join().unwrap()?;
+        buffer.append(&mut other)?;
+        assert!(other.is_empty());
+        assert_eq!(other.row_count(), 0);
+    }
+    assert_eq!(buffer.row_count(), 12);
+    assert!(buffer.transactional());
+    assert_eq!(buffer.peek_last_row(), "t,worker=2 row=3i 23\n");
+
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    sender.flush(&mut buffer)?;
+    let rows = parse_lines(&sink.contents_str()).collect::<crate::Result<Vec<_>>>()?;
+    let timestamps: Vec<_> = rows
+        .iter()
+        .map(|row| row.timestamp().unwrap().as_i64())
+        .collect();
+    assert_eq!(timestamps, [0, 1, 2, 3, 10, 11, 12, 13, 20, 21, 22, 23]);
+    Ok(())
+}
+
+#[test]
+fn test_protocol_version_default() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+    assert_eq!(sender.protocol_version(), ProtocolVersion::V2);
+
+    // Buffers of either version can be flushed to a sender of the latest one.
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .column_ts("ts", TimestampNanos::new(1000))?
+        .at_now()?;
+    sender.flush(&mut buffer)?;
 
+    let mut buffer = sender.new_buffer();
+    assert_eq!(buffer.protocol_version(), ProtocolVersion::V2);
+    buffer
+        .table("test")?
+        .column_ts("ts", TimestampNanos::new(1000))?
+        .at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 2);
+    assert_eq!(server.msgs, vec!["test ts=1t\n", "test ts=1000n\n"]);
     Ok(())
 }
 
-#[cfg(feature = "chrono_timestamp")]
 #[test]
-fn test_chrono_timestamp() -> TestResult {
-    use chrono::{DateTime, TimeZone, Utc};
+fn test_protocol_version_pinned() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .protocol_version(ProtocolVersion::V1)?
+        .build()?;
+    server.accept()?;
+    assert_eq!(sender.protocol_version(), ProtocolVersion::V1);
+
+    // A latest-version buffer is fine as long as it uses no newer syntax.
+    let mut buffer = Buffer::with_protocol_version(ProtocolVersion::V2);
+    buffer
+        .table("test")?
+        .column_ts("ts", TimestampMicros::new(1))?
+        .at_now()?;
+    sender.flush(&mut buffer)?;
+
+    buffer
+        .table("test")?
+        .column_ts("ts", TimestampNanos::new(1))?
+        .at_now()?;
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: It requires ILP protocol version 2, \
+        but the sender is pinned to version 1. Create the buffer with Sender::new_buffer."
+    );
+    assert!(!sender.must_close());
 
-    let tbl_name = TableName::new("tbl_name")?;
-    let ts: DateTime<Utc> = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 1).unwrap();
-    let ts = TimestampNanos::from_datetime(ts)?;
+    let mut buffer = sender.new_buffer();
+    assert_eq!(buffer.protocol_version(), ProtocolVersion::V1);
+    buffer
+        .table("test")?
+        .column_ts("ts", TimestampNanos::new(1000))?
+        .at_now()?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(server.recv_q()?, 2);
+    assert_eq!(server.msgs, vec!["test ts=1t\n", "test ts=1t\n"]);
+    Ok(())
+}
 
+#[test]
+fn test_row_counts() -> TestResult {
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
     let mut buffer = Buffer::new();
-    buffer.table(tbl_name)?.column_ts("a", ts)?.at(ts)?;
+    assert_eq!(buffer.row_count(), 0);
+    assert_eq!(sender.total_rows_sent(), 0);
 
-    let exp = "tbl_name a=1000000t 1000000000\n";
-    assert_eq!(buffer.as_str(), exp);
+    // Partial and rejected rows don't count.
+    buffer.table("test")?.symbol("t1", "v1")?;
+    assert_eq!(buffer.row_count(), 0);
+    buffer.at_now()?;
+    buffer.table("test")?;
+    assert!(buffer.column_i64("bad.name", 1).is_err());
+    assert!(buffer.at(TimestampNanos::new(-1)).is_err());
+    assert_eq!(buffer.row_count(), 1);
+    buffer.column_i64("c1", 1)?.at_now()?;
+    assert_eq!(buffer.row_count(), 2);
+
+    // Rewinding restores the count at the marker.
+    buffer.set_marker()?;
+    buffer.table("test")?.symbol("t1", "v3")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v4")?;
+    assert_eq!(buffer.row_count(), 3);
+    buffer.rewind_to_marker()?;
+    assert_eq!(buffer.row_count(), 2);
+
+    sender.flush(&mut buffer)?;
+    assert_eq!(buffer.row_count(), 0);
+    assert_eq!(sender.total_rows_sent(), 2);
+
+    buffer.table("test")?.symbol("t1", "v5")?.at_now()?;
+    sender.flush_and_keep(&buffer)?;
+    sender.flush(&mut buffer)?;
+    assert_eq!(sender.total_rows_sent(), 4);
+
+    // A failed flush sends nothing.
+    let mut sender =
+        Sender::from_sink(MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe));
+    buffer.table("test")?.symbol("t1", "v6")?.at_now()?;
+    sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(buffer.row_count(), 1);
+    assert_eq!(sender.total_rows_sent(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_flush_to() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer
+        .table("test")?
+        .symbol("t1", "v1")?
+        .column_f64("f1", 0.5)?
+        .at(TimestampNanos::new(10000000))?;
+    buffer.table("test")?.column_str("s1", "a\"b")?.at_now()?;
+    let expected = buffer.as_str().to_owned();
 
+    // Written bytes match what a sender sends on the wire.
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    sender.flush_and_keep(&buffer)?;
+    let mut file = Vec::new();
+    buffer.flush_to(&mut file)?;
+    assert_eq!(file, expected.as_bytes());
+    assert_eq!(file, sink.contents());
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.row_count(), 0);
+
+    // Flushing an empty buffer writes nothing.
+    buffer.flush_to(&mut file)?;
+    assert_eq!(file, expected.as_bytes());
+    Ok(())
+}
+
+#[test]
+fn test_send_raw() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer
+        .table("test")?
+        .column_str("s1", "multi\nline\\")?
+        .at(TimestampNanos::new(1))?;
+    let mut captured = Vec::new();
+    buffer.flush_to(&mut captured)?;
+
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    sender.send_raw(&captured)?;
+    sender.send_raw(b"")?;
+    assert_eq!(sink.contents(), captured);
+    assert_eq!(sender.stats().flushes, 0);
     Ok(())
 }
 
-macro_rules! column_name_too_long_test_impl {
-    ($column_fn:ident, $value:expr) => {{
-        let mut buffer = Buffer::with_max_name_len(4);
-        let name = "a name too long";
-        let err = buffer.table("tbl")?.$column_fn(name, $value).unwrap_err();
-        assert_eq!(err.code(), ErrorCode::InvalidName);
+#[test]
+fn test_send_raw_unterminated() -> TestResult {
+    let sink = InMemorySink::new();
+    let mut sender = Sender::from_sink(sink.clone());
+    for bytes in [
+        &b"test,t1=v1\ntest,t1=v2"[..],
+        b"test s1=\"a\\\n",
+        b"test s1=\"a\\\\\\\n",
+    ] {
+        let err = sender.send_raw(bytes).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidApiCall);
         assert_eq!(
             err.msg(),
-            r#"Bad name: "a name too long": Too long (max 4 characters)"#
+            "Could not send raw ILP: The last line is not terminated by a newline."
         );
-        Ok(())
-    }};
+    }
+
+    // An escaped backslash doesn't escape the newline.
+    sender.send_raw(b"test s1=\"a\\\\\"\n")?;
+    sender.send_raw(b"test,t1=\\\\\n")?;
+
+    let err = sender.send_raw(b"test,t1=\xff\n").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(
+        err.msg(),
+        "Could not send raw ILP: The bytes are not valid UTF-8."
+    );
+    assert_eq!(sink.contents_str(), "test s1=\"a\\\\\"\ntest,t1=\\\\\n");
+    assert!(!sender.must_close());
+    Ok(())
 }
 
 #[test]
-fn test_symbol_column_name_too_long() -> TestResult {
-    column_name_too_long_test_impl!(symbol, "v1")
+fn test_send_raw_must_close() -> TestResult {
+    let sink = MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink);
+    let err = sender.send_raw(b"test,t1=v1\n").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(sender.must_close());
+
+    let err = sender.send_raw(b"test,t1=v1\n").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not send raw ILP: not connected to database."
+    );
+    Ok(())
 }
 
 #[test]
-fn test_bool_column_name_too_long() -> TestResult {
-    column_name_too_long_test_impl!(column_bool, true)
+fn test_flush_to_io_error() -> TestResult {
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let mut sink = MockSink::new().with_fail_after(5, io::ErrorKind::BrokenPipe);
+    let err = buffer.flush_to(&mut sink).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::IoError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error"
+    );
+    assert_eq!(buffer.as_str(), "test,t1=v1\n");
+    assert_eq!(buffer.row_count(), 1);
+    Ok(())
 }
 
 #[test]
-fn test_i64_column_name_too_long() -> TestResult {
-    column_name_too_long_test_impl!(column_i64, 1)
+fn test_stats() -> TestResult {
+    // The sink accepts the first 33 bytes: Three 11-byte rows.
+    let sink = MockSink::new().with_fail_after(33, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink);
+    assert_eq!(sender.stats(), SenderStats::default());
+
+    let mut buffer = Buffer::new();
+    sender.flush(&mut buffer)?;
+    assert_eq!(sender.stats(), SenderStats::default());
+
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    buffer.table("test")?.symbol("t1", "v2")?.at_now()?;
+    let before = Instant::now();
+    sender.flush(&mut buffer)?;
+    let stats = sender.stats();
+    assert_eq!(stats.rows_sent, 2);
+    assert_eq!(stats.bytes_sent, 22);
+    assert_eq!(stats.flushes, 1);
+    assert_eq!(stats.failed_flushes, 0);
+    let last_flush_at = stats.last_flush_at.unwrap();
+    assert!(last_flush_at >= before);
+
+    // A row in progress can't be flushed, and is discarded.
+    buffer.table("test")?.symbol("t1", "v3")?;
+    sender.flush(&mut buffer).unwrap_err();
+    buffer.table("test")?.symbol("t1", "v3")?.at_now()?;
+    sender.flush(&mut buffer)?;
+
+    // The sink fails from now on.
+    buffer.table("test")?.symbol("t1", "v4")?.at_now()?;
+    sender.flush(&mut buffer).unwrap_err();
+    sender.flush(&mut buffer).unwrap_err();
+    assert!(sender.must_close());
+    let stats = sender.stats();
+    assert_eq!(stats.rows_sent, 3);
+    assert_eq!(stats.bytes_sent, 33);
+    assert_eq!(stats.flushes, 2);
+    assert_eq!(stats.failed_flushes, 3);
+    assert!(stats.last_flush_at.unwrap() >= last_flush_at);
+    assert_eq!(sender.total_rows_sent(), 3);
+
+    sender.reset_stats();
+    assert_eq!(sender.stats(), SenderStats::default());
+    Ok(())
 }
 
 #[test]
-fn test_f64_column_name_too_long() -> TestResult {
-    column_name_too_long_test_impl!(column_f64, 0.5)
+fn test_stats_survive_reconnect() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().build()?;
+    server.accept()?;
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+
+    sender.reconnect()?;
+    server.accept()?;
+    assert_eq!(sender.stats().rows_sent, 1);
+    assert_eq!(sender.stats().flushes, 1);
+    Ok(())
 }
 
 #[test]
-fn test_str_column_name_too_long() -> TestResult {
-    column_name_too_long_test_impl!(column_str, "value")
+fn test_connect_timings() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .resolver(Arc::new(SlowResolver(Duration::from_millis(20))))?
+        .build()?;
+    server.accept()?;
+
+    let timings = sender.connect_timings().unwrap();
+    assert!(timings.resolve >= Duration::from_millis(20));
+    assert_eq!(timings.tls_handshake, Duration::ZERO);
+    assert_eq!(timings.auth, Duration::ZERO);
+    assert!(timings.total >= timings.resolve + timings.connect);
+
+    // Reconnecting records new timings.
+    sender.reconnect()?;
+    server.accept()?;
+    assert_ne!(sender.connect_timings(), Some(timings));
+    assert!(sender.connect_timings().unwrap().resolve >= Duration::from_millis(20));
+
+    assert_eq!(Sender::from_sink(Vec::new()).connect_timings(), None);
+    Ok(())
 }
 
 #[test]
@@ -359,6 +2263,7 @@ fn test_tls_with_file_ca() -> TestResult {
     let server_jh = server.accept_tls();
     let mut sender = lsb.build()?;
     let mut server: MockServer = server_jh.join().unwrap()?;
+    assert!(sender.connect_timings().unwrap().tls_handshake > Duration::ZERO);
 
     let mut buffer = Buffer::new();
     buffer
@@ -404,24 +2309,10 @@ fn test_tls_to_plain_server() -> TestResult {
                 .to_owned()
         )
     );
+    assert_eq!(err.io_error_kind(), Some(io::ErrorKind::TimedOut));
     Ok(())
 }
 
-fn expect_eventual_disconnect(sender: &mut Sender) {
-    let mut retry = || {
-        for _ in 0..1000 {
-            std::thread::sleep(Duration::from_millis(100));
-            let mut buffer = Buffer::new();
-            buffer.table("test_table")?.symbol("s1", "v1")?.at_now()?;
-            sender.flush(&mut buffer)?;
-        }
-        Ok(())
-    };
-
-    let err: Error = retry().unwrap_err();
-    assert_eq!(err.code(), ErrorCode::SocketError);
-}
-
 #[test]
 fn test_plain_to_tls_server() -> TestResult {
     let server = MockServer::new()?;
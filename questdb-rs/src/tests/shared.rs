@@ -0,0 +1,132 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{SharedSender, TimestampNanos};
+use crate::tests::mock::MockServer;
+use crate::tests::TestResult;
+
+use std::sync::Arc;
+
+#[test]
+fn test_shared_sender_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SharedSender>();
+}
+
+#[test]
+fn test_shared_sender_concurrent_flushes() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = Arc::new(SharedSender::new(server.lsb_tcp().build()?));
+    server.accept()?;
+
+    let handles: Vec<_> = (0..4i64)
+        .map(|thread| {
+            let sender = Arc::clone(&sender);
+            std::thread::spawn(move || -> crate::Result<()> {
+                let mut buffer = sender.new_buffer();
+                for row in 0..10i64 {
+                    buffer
+                        .table("test")?
+                        .column_i64("thread", thread)?
+                        .column_i64("row", row)?;
+                    sender.at(&mut buffer, TimestampNanos::new(row))?;
+                    sender.flush(&mut buffer)?;
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+
+    let mut received = 0;
+    while received < 40 {
+        let count = server.recv_q()?;
+        assert!(count > 0);
+        received += count;
+    }
+    assert_eq!(received, 40);
+    assert_eq!(sender.stats().rows_sent, 40);
+    assert_eq!(sender.stats().flushes, 40);
+
+    // Rows from different threads are never interleaved mid-row.
+    for thread in 0..4 {
+        let rows: Vec<_> = server
+            .msgs
+            .iter()
+            .filter(|msg| msg.starts_with(&format!("test thread={thread}i,")))
+            .collect();
+        assert_eq!(rows.len(), 10);
+        for (row, msg) in rows.into_iter().enumerate() {
+            assert_eq!(*msg, format!("test thread={thread}i,row={row}i {row}\n"));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_shared_sender_survives_poisoning() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = Arc::new(SharedSender::new(server.lsb_tcp().build()?));
+    server.accept()?;
+
+    let poisoner = Arc::clone(&sender);
+    let result = std::thread::spawn(move || {
+        let _guard = poisoner.lock();
+        panic!("poison the lock");
+    })
+    .join();
+    assert!(result.is_err());
+
+    let mut buffer = sender.new_buffer();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    sender.flush(&mut buffer)?;
+    assert!(!sender.must_close());
+
+    let sender = Arc::try_unwrap(sender).unwrap().into_inner();
+    assert_eq!(sender.total_rows_sent(), 1);
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0], "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_shared_sender_forwards_to_sender() -> TestResult {
+    let mut server = MockServer::new()?;
+    let sender = SharedSender::new(server.lsb_tcp().send_buffer_size(65536)?.build()?);
+    server.accept()?;
+
+    assert!(sender.send_buffer_size().unwrap() >= 65536);
+    assert!(sender.recv_buffer_size().is_some());
+    assert!(sender.connect_timings().is_some());
+    assert_eq!(sender.spilled_bytes(), 0);
+    assert_eq!(sender.pending_spill(), 0);
+    sender.replay_now()?;
+
+    sender.send_raw(b"test,t1=v1\n")?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0], "test,t1=v1\n");
+    Ok(())
+}
@@ -0,0 +1,178 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{Buffer, Sender};
+use crate::tests::mock::{MockServer, MockSink};
+use crate::tests::TestResult;
+
+use std::fmt::Debug;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+type Fields = Vec<(String, String)>;
+
+#[derive(Default)]
+struct FieldRecorder(Fields);
+
+impl Visit for FieldRecorder {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push((field.name().to_owned(), format!("{value:?}")));
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct CapturedEvent {
+    span: Option<String>,
+    level: Level,
+    fields: Fields,
+}
+
+/// Records the spans and events created under the `questdb::ingress` target.
+#[derive(Default)]
+struct Capture {
+    spans: Mutex<Vec<(String, Fields)>>,
+    events: Mutex<Vec<CapturedEvent>>,
+}
+
+struct CaptureLayer(Arc<Capture>);
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        if attrs.metadata().target() != "questdb::ingress" {
+            return;
+        }
+        let mut fields = FieldRecorder::default();
+        attrs.record(&mut fields);
+        self.0
+            .spans
+            .lock()
+            .unwrap()
+            .push((attrs.metadata().name().to_owned(), fields.0));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if event.metadata().target() != "questdb::ingress" {
+            return;
+        }
+        let mut fields = FieldRecorder::default();
+        event.record(&mut fields);
+        self.0.events.lock().unwrap().push(CapturedEvent {
+            span: ctx.event_span(event).map(|span| span.name().to_owned()),
+            level: *event.metadata().level(),
+            fields: fields.0,
+        });
+    }
+}
+
+/// Run `f` with a subscriber capturing the spans and events on this thread.
+fn capture<T>(f: impl FnOnce() -> T) -> (T, Arc<Capture>) {
+    let capture = Arc::new(Capture::default());
+    let subscriber = tracing_subscriber::registry().with(CaptureLayer(capture.clone()));
+    let result = tracing::subscriber::with_default(subscriber, f);
+    (result, capture)
+}
+
+fn fields(pairs: &[(&str, &str)]) -> Fields {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_span_connect_and_flush() -> TestResult {
+    let mut server = MockServer::new()?;
+    let port = server.port.to_string();
+    let (result, capture) = capture(|| -> crate::Result<()> {
+        let mut sender = server.lsb_tcp().build()?;
+        let mut buffer = Buffer::new();
+        buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+        buffer.table("test")?.column_i64("c1", 1)?.at_now()?;
+        sender.flush(&mut buffer)
+    });
+    result?;
+    server.accept()?;
+    assert_eq!(server.recv_q()?, 2);
+
+    assert_eq!(
+        *capture.spans.lock().unwrap(),
+        vec![
+            (
+                "connect".to_owned(),
+                fields(&[
+                    ("host", server.host),
+                    ("port", &port),
+                    ("protocol", "\"tcp\"")
+                ])
+            ),
+            (
+                "flush".to_owned(),
+                fields(&[("bytes", "22"), ("rows", "2"), ("transactional", "false")])
+            ),
+        ]
+    );
+    assert!(capture.events.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_span_failed_flush() -> TestResult {
+    let sink = MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe);
+    let mut sender = Sender::from_sink(sink);
+    let mut buffer = Buffer::new();
+    buffer.table("test")?.symbol("t1", "v1")?.at_now()?;
+    let (result, capture) = capture(|| sender.flush(&mut buffer));
+    assert!(result.is_err());
+
+    assert_eq!(
+        *capture.spans.lock().unwrap(),
+        vec![(
+            "flush".to_owned(),
+            fields(&[("bytes", "11"), ("rows", "1"), ("transactional", "false")])
+        )]
+    );
+    assert_eq!(
+        *capture.events.lock().unwrap(),
+        vec![CapturedEvent {
+            span: Some("flush".to_owned()),
+            level: Level::ERROR,
+            fields: fields(&[
+                (
+                    "message",
+                    "Could not flush buffer: injected mock sink error"
+                ),
+                ("code", "SocketError"),
+            ]),
+        }]
+    );
+    Ok(())
+}
@@ -0,0 +1,589 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::ingress::{
+    spill_files, Buffer, ProtocolVersion, Sender, SenderBuilder, SpillReader, TimestampNanos,
+};
+use crate::tests::mock::{expect_eventual_disconnect, MockServer};
+use crate::tests::{TestError, TestResult};
+use crate::ErrorCode;
+
+fn rows(table: &str, count: i64) -> Result<Buffer, TestError> {
+    let mut buffer = Buffer::new();
+    for value in 0..count {
+        buffer
+            .table(table)?
+            .symbol("t", "a")?
+            .column_i64("v", value)?
+            .at(TimestampNanos::new(value))?;
+    }
+    Ok(buffer)
+}
+
+fn read_all(path: &Path) -> Result<(Vec<String>, bool), TestError> {
+    let mut reader = SpillReader::open(path)?;
+    let mut payloads = Vec::new();
+    while let Some(record) = reader.next_record()? {
+        assert_eq!(record.protocol_version(), ProtocolVersion::V1);
+        payloads.push(String::from_utf8(record.into_payload())?);
+    }
+    Ok((payloads, reader.is_corrupt()))
+}
+
+/// A sender spilling to `dir`, whose connection the server dropped.
+fn disconnected(dir: &Path, extra_conf: &str) -> Result<(MockServer, Sender), TestError> {
+    let mut server = MockServer::new()?;
+    let mut sender = Sender::from_conf(format!(
+        "tcp::addr={}:{};spill_dir={};{}",
+        server.host,
+        server.port,
+        dir.display(),
+        extra_conf
+    ))?;
+    server.accept()?;
+    server.close_client();
+    expect_eventual_disconnect(&mut sender);
+    Ok((server, sender))
+}
+
+#[test]
+fn test_spill_after_disconnect() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, mut sender) = disconnected(dir.path(), "")?;
+
+    // The failed flush that revealed the disconnect spilled its row.
+    let mut spilled = vec!["test_table,s1=v1\n".to_owned()];
+    let files = spill_files(dir.path())?;
+    assert_eq!(files.len(), 1);
+    assert_eq!(read_all(&files[0])?, (spilled.clone(), false));
+
+    for count in [1, 3] {
+        let mut buffer = rows("spilled", count)?;
+        spilled.push(buffer.as_str().to_owned());
+        let err = sender.flush(&mut buffer).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::SocketError);
+        assert!(buffer.is_empty());
+    }
+    assert_eq!(spill_files(dir.path())?, files);
+    assert_eq!(read_all(&files[0])?, (spilled.clone(), false));
+    assert_eq!(
+        sender.spilled_bytes(),
+        spilled.iter().map(|ilp| ilp.len() as u64).sum::<u64>()
+    );
+    assert_eq!(sender.stats().rows_spilled, 5);
+
+    // Incomplete rows aren't spilled.
+    let mut buffer = rows("spilled", 1)?;
+    spilled.push(buffer.as_str().to_owned());
+    buffer.table("test")?.symbol("t", "a")?;
+    assert_eq!(
+        sender.flush(&mut buffer).unwrap_err().code(),
+        ErrorCode::SocketError
+    );
+    assert!(buffer.is_empty());
+    assert_eq!(read_all(&files[0])?, (spilled, false));
+
+    // Each sender spills to a file of its own.
+    let (_server, mut sender) = disconnected(dir.path(), "")?;
+    let mut buffer = rows("other", 1)?;
+    let _ = sender.flush(&mut buffer);
+    let files = spill_files(dir.path())?;
+    assert_eq!(files.len(), 2);
+    assert_eq!(read_all(&files[1])?.0.len(), 2);
+    Ok(())
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn test_spill_dead_address() -> TestResult {
+    use std::time::Duration;
+
+    let dir = tempfile::TempDir::new()?;
+    // The server is dropped at once, leaving nothing listening on its port.
+    let mut sender = MockServer::new()?
+        .lsb_http()
+        .retry_timeout(Duration::ZERO)?
+        .spill_dir(dir.path())?
+        .build()?;
+    let mut buffer = rows("test", 2)?;
+    let expected = buffer.as_str().to_owned();
+    let err = sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert!(buffer.is_empty());
+    assert_eq!(sender.spilled_bytes(), expected.len() as u64);
+
+    let files = spill_files(dir.path())?;
+    assert_eq!(files.len(), 1);
+    assert_eq!(read_all(&files[0])?, (vec![expected], false));
+    Ok(())
+}
+
+#[test]
+fn test_spill_max_bytes() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let buffer = rows("test", 1)?;
+    // The file header, plus one record of a row.
+    let max_bytes = 12 + 9 + buffer.len() as u64;
+    let max_bytes = max_bytes + "test_table,s1=v1\n".len() as u64 + 9;
+    let (_server, mut sender) = disconnected(dir.path(), &format!("spill_max_bytes={max_bytes};"))?;
+    let mut first = buffer.clone();
+    let _ = sender.flush(&mut first);
+    assert!(first.is_empty());
+
+    // Past the limit, the rows stay in the buffer.
+    let mut second = buffer.clone();
+    let err = sender.flush(&mut second).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(second.as_str(), buffer.as_str());
+    assert_eq!(sender.stats().rows_spilled, 2);
+    Ok(())
+}
+
+#[test]
+fn test_spill_when_full() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, mut sender) = disconnected(dir.path(), "max_buf_size=1024;")?;
+    let files = spill_files(dir.path())?;
+
+    // Completing a row that overfills the buffer spills the rows before it,
+    // even without auto-flushing.
+    let mut buffer = sender.new_buffer();
+    let mut ilp = String::new();
+    for value in 0..200 {
+        buffer
+            .table("full")?
+            .symbol("t", "a")?
+            .column_i64("v", value)?;
+        sender.at(&mut buffer, TimestampNanos::new(value))?;
+        ilp.push_str(buffer.peek_last_row());
+        assert!(buffer.len() <= 1024);
+    }
+    let (payloads, corrupt) = read_all(&files[0])?;
+    assert!(!corrupt);
+    assert!(payloads.len() > 2);
+    assert!(payloads[1..].iter().all(|payload| payload.len() <= 1024));
+    assert_eq!(payloads[1..].concat() + buffer.as_str(), ilp);
+    assert_eq!(
+        sender.stats().rows_spilled,
+        1 + 200 - buffer.row_count() as u64
+    );
+    Ok(())
+}
+
+#[test]
+fn test_spill_when_full_past_max_bytes() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, mut sender) = disconnected(dir.path(), "max_buf_size=1024;spill_max_bytes=64;")?;
+
+    // Should spilling fail, the row is rejected as it would be without a
+    // spill directory, and the buffer keeps the rows before it.
+    let mut buffer = sender.new_buffer();
+    let value = "x".repeat(600);
+    buffer.table("full")?.symbol("t", value.as_str())?;
+    sender.at_now(&mut buffer)?;
+    let kept = buffer.as_str().to_owned();
+    buffer.table("full")?.symbol("t", value.as_str())?;
+    let err = sender.at_now(&mut buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::BufferTooLarge);
+    assert_eq!(buffer.as_str(), kept);
+    assert_eq!(sender.stats().rows_spilled, 1);
+    Ok(())
+}
+
+#[test]
+fn test_spill_conf() -> TestResult {
+    let err = SenderBuilder::from_conf("tcp::addr=localhost:9009;spill_dir=;").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+
+    let err =
+        SenderBuilder::from_conf("tcp::addr=localhost:9009;spill_max_bytes=lots;").unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+
+    let err = SenderBuilder::from_conf("tcp::addr=localhost:9009;spill_dir=/tmp;")?
+        .spill_dir("/var/tmp")
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    Ok(())
+}
+
+/// Spill two records and return the path of their file.
+fn spill_two(dir: &Path) -> Result<(MockServer, std::path::PathBuf, Vec<String>), TestError> {
+    let (server, mut sender) = disconnected(dir, "")?;
+    let mut buffer = rows("test", 2)?;
+    let second = buffer.as_str().to_owned();
+    let _ = sender.flush(&mut buffer);
+    let path = spill_files(dir)?.remove(0);
+    Ok((server, path, vec!["test_table,s1=v1\n".to_owned(), second]))
+}
+
+#[test]
+fn test_spill_torn_record() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, path, expected) = spill_two(dir.path())?;
+    let len = fs::metadata(&path)?.len();
+
+    // A crash partway through writing the second record.
+    for cut in [1, 5, 20] {
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len - cut)?;
+        assert_eq!(read_all(&path)?, (expected[..1].to_vec(), true));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_spill_corrupt_record() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, path, expected) = spill_two(dir.path())?;
+    let mut data = fs::read(&path)?;
+    let last = data.len() - 2;
+    data[last] ^= 0xff;
+    fs::write(&path, &data)?;
+    assert_eq!(read_all(&path)?, (expected[..1].to_vec(), true));
+
+    // A record that fails its checksum hides any after it.
+    data[last] ^= 0xff;
+    data[12 + 9] ^= 0xff;
+    fs::write(&path, &data)?;
+    assert_eq!(read_all(&path)?, (Vec::new(), true));
+    Ok(())
+}
+
+#[test]
+fn test_spill_file_header() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("00000000000000000001.qdbspill");
+
+    // A crash right after starting the file.
+    fs::write(&path, b"QDBSP")?;
+    assert_eq!(read_all(&path)?, (Vec::new(), true));
+
+    fs::write(&path, b"not a spill file")?;
+    let err = SpillReader::open(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut file = fs::File::create(&path)?;
+    file.write_all(b"QDBSPILL")?;
+    file.write_all(&2u32.to_le_bytes())?;
+    let err = SpillReader::open(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(
+        err.to_string(),
+        "unsupported spill file format version 2, expected 1"
+    );
+
+    // Other files in the directory are ignored.
+    fs::write(dir.path().join("notes.txt"), b"")?;
+    assert_eq!(spill_files(dir.path())?, vec![path]);
+    assert!(spill_files(dir.path().join("missing"))?.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "worker")]
+#[test]
+fn test_worker_spill() -> TestResult {
+    use crate::ingress::{Deadline, SenderWorker, WorkerConfig};
+    use std::time::Duration;
+
+    let dir = tempfile::TempDir::new()?;
+    let (_server, sender) = disconnected(dir.path(), "")?;
+    let worker = SenderWorker::from_sender(sender, WorkerConfig::new().reconnect_attempts(0))?;
+    let handle = worker.handle();
+    handle.send(rows("test", 3)?)?;
+    assert_eq!(
+        handle.flush_sync().unwrap_err().code(),
+        ErrorCode::SocketError
+    );
+    let report = handle.shutdown(Deadline::after(Duration::from_secs(10)));
+    assert_eq!(report.rows_flushed, 0);
+    assert_eq!(report.rows_dropped, 0);
+    assert_eq!(report.rows_spilled, 3);
+
+    let files = spill_files(dir.path())?;
+    assert_eq!(read_all(&files[0])?.0.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_replay_after_reconnect() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (mut server, mut sender) = disconnected(dir.path(), "")?;
+    let mut spilled = "test_table,s1=v1\n".to_owned();
+    let mut buffer = rows("spilled", 2)?;
+    spilled.push_str(buffer.as_str());
+    sender.flush(&mut buffer).unwrap_err();
+    assert_eq!(sender.pending_spill(), 2 * 9 + spilled.len() as u64);
+
+    // The server is back: The backlog goes first, then the live rows.
+    server.msgs.clear();
+    sender.reconnect()?;
+    server.accept()?;
+    let mut buffer = rows("live", 2)?;
+    let live = buffer.as_str().to_owned();
+    sender.flush(&mut buffer)?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), spilled + &live);
+    assert_eq!(sender.pending_spill(), 0);
+    assert!(spill_files(dir.path())?.is_empty());
+    assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+
+    // Nothing left to replay.
+    sender.replay_now()?;
+    sender.flush(&mut rows("live", 1)?)?;
+    assert_eq!(server.recv_q()?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_replay_on_startup() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, path, expected) = spill_two(dir.path())?;
+    assert!(path.exists());
+
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().spill_dir(dir.path())?.build()?;
+    server.accept()?;
+    assert_eq!(
+        sender.pending_spill(),
+        expected.iter().map(|ilp| 9 + ilp.len() as u64).sum::<u64>()
+    );
+    sender.replay_now()?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), expected.concat());
+    assert_eq!(sender.pending_spill(), 0);
+    assert!(!path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_replay_resumes_from_checkpoint() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, path, expected) = spill_two(dir.path())?;
+
+    // A crash after replaying the first record.
+    let offset = 12 + 9 + expected[0].len() as u64;
+    fs::write(path.with_extension("offset"), offset.to_le_bytes())?;
+
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().spill_dir(dir.path())?.build()?;
+    server.accept()?;
+    assert_eq!(sender.pending_spill(), 9 + expected[1].len() as u64);
+    sender.replay_now()?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), expected[1]);
+    assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_replay_skips_files_of_live_senders() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, mut spilling) = disconnected(dir.path(), "")?;
+    let (_old_server, old) = disconnected(dir.path(), "")?;
+    let old_file = spill_files(dir.path())?[1].clone();
+    drop(old);
+    let file = spill_files(dir.path())?[0].clone();
+
+    // Another sender sharing the directory replays only the file no live
+    // sender holds, while the first one keeps spilling.
+    let mut server = MockServer::new()?;
+    let mut replaying = server.lsb_tcp().spill_dir(dir.path())?.build()?;
+    server.accept()?;
+    let mut spilled = vec!["test_table,s1=v1\n".to_owned()];
+    let mut buffer = rows("spilled", 1)?;
+    spilled.push(buffer.as_str().to_owned());
+    spilling.flush(&mut buffer).unwrap_err();
+    replaying.replay_now()?;
+    let mut buffer = rows("spilled", 2)?;
+    spilled.push(buffer.as_str().to_owned());
+    spilling.flush(&mut buffer).unwrap_err();
+    server.recv_q()?;
+    assert_eq!(server.msgs, vec!["test_table,s1=v1\n".to_owned()]);
+    assert!(!old_file.exists());
+    assert_eq!(spill_files(dir.path())?, vec![file.clone()]);
+    assert_eq!(read_all(&file)?, (spilled.clone(), false));
+
+    // Once the spilling sender is gone, its rows are replayed in full.
+    drop(spilling);
+    server.msgs.clear();
+    replaying.replay_now()?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), spilled.concat());
+    assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_replay_not_connected() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, mut sender) = disconnected(dir.path(), "")?;
+    let err = sender.replay_now().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not replay spilled rows: not connected to database."
+    );
+    assert_eq!(spill_files(dir.path())?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_replay_sets_aside_corrupt_file() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let (_server, path, expected) = spill_two(dir.path())?;
+    let len = fs::metadata(&path)?.len();
+    OpenOptions::new()
+        .write(true)
+        .open(&path)?
+        .set_len(len - 1)?;
+
+    let mut server = MockServer::new()?;
+    let mut sender = server.lsb_tcp().spill_dir(dir.path())?.build()?;
+    server.accept()?;
+    sender.replay_now()?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), expected[0]);
+    assert!(!path.exists());
+    assert!(path.with_extension("corrupt").exists());
+    assert_eq!(sender.pending_spill(), 0);
+    Ok(())
+}
+
+/// Write a spill file by hand, with a record per protocol version and ILP.
+fn write_spill(path: &Path, records: &[(u8, &str)]) -> io::Result<()> {
+    let mut data = b"QDBSPILL".to_vec();
+    data.extend_from_slice(&1u32.to_le_bytes());
+    for &(protocol_version, ilp) in records {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&[protocol_version]);
+        hasher.update(ilp.as_bytes());
+        data.extend_from_slice(&(ilp.len() as u32).to_le_bytes());
+        data.extend_from_slice(&hasher.finalize().to_le_bytes());
+        data.push(protocol_version);
+        data.extend_from_slice(ilp.as_bytes());
+    }
+    fs::write(path, data)
+}
+
+#[test]
+fn test_replay_sets_aside_newer_protocol_version() -> TestResult {
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("00000000000000000001.qdbspill");
+    write_spill(&path, &[(2, "newer x=1.0\n"), (1, "older x=2.0\n")])?;
+
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_tcp()
+        .protocol_version(ProtocolVersion::V1)?
+        .spill_dir(dir.path())?
+        .build()?;
+    server.accept()?;
+
+    // The record the sender can't send doesn't hold up the others, nor the
+    // live rows.
+    let mut buffer = rows("live", 1)?;
+    let live = buffer.as_str().to_owned();
+    sender.flush(&mut buffer)?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), "older x=2.0\n".to_owned() + &live);
+    assert_eq!(sender.pending_spill(), 0);
+    assert!(!path.exists());
+
+    let mut reader = SpillReader::open(path.with_extension("rejected"))?;
+    let record = reader.next_record()?.unwrap();
+    assert_eq!(record.protocol_version(), ProtocolVersion::V2);
+    assert_eq!(record.payload(), b"newer x=1.0\n");
+    assert_eq!(reader.next_record()?, None);
+    assert!(!reader.is_corrupt());
+
+    // It's not replayed again.
+    server.msgs.clear();
+    sender.flush(&mut rows("live", 1)?)?;
+    server.recv_q()?;
+    assert_eq!(server.msgs.concat(), live);
+    Ok(())
+}
+
+#[cfg(feature = "ilp-over-http")]
+#[test]
+fn test_replay_sets_aside_rejected_record() -> TestResult {
+    use crate::ingress::DeadLetterFile;
+    use crate::tests::mock::HttpResponse;
+    use std::sync::Arc;
+
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("00000000000000000001.qdbspill");
+    write_spill(&path, &[(1, "bad x=y\n")])?;
+    let dead_letters = dir.path().join("dead_letters.jsonl");
+
+    let mut server = MockServer::new()?;
+    let mut sender = server
+        .lsb_http()
+        .protocol_version(ProtocolVersion::V1)?
+        .dead_letter_sink(Arc::new(DeadLetterFile::open(&dead_letters)?))?
+        .spill_dir(dir.path())?
+        .build()?;
+    let mut buffer = rows("live", 1)?;
+    let live = buffer.as_str().to_owned();
+    let expected = live.clone();
+
+    let server_thread = std::thread::spawn(move || -> io::Result<Vec<String>> {
+        server.accept()?;
+        let mut bodies = Vec::new();
+        let request = server.recv_http_q()?;
+        bodies.push(String::from_utf8_lossy(request.body()).into_owned());
+        server.send_http_response_q(
+            HttpResponse::empty()
+                .with_status(400, "Bad Request")
+                .with_body_str("failed to parse line protocol"),
+        )?;
+        for _ in 0..2 {
+            let request = server.recv_http_q()?;
+            bodies.push(String::from_utf8_lossy(request.body()).into_owned());
+            server.send_http_response_q(HttpResponse::empty())?;
+        }
+        Ok(bodies)
+    });
+
+    // The server refuses the spilled record, but accepts the live rows.
+    sender.flush(&mut buffer)?;
+    sender.flush(&mut rows("live", 1)?)?;
+    let bodies = server_thread.join().unwrap()?;
+    assert_eq!(bodies, ["bad x=y\n", &expected, &expected]);
+    assert!(!path.exists());
+    assert_eq!(
+        read_all(&path.with_extension("rejected"))?,
+        (vec!["bad x=y\n".to_owned()], false)
+    );
+
+    // It's dead-lettered once.
+    assert_eq!(fs::read_to_string(&dead_letters)?.lines().count(), 1);
+    Ok(())
+}
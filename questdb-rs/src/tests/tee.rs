@@ -0,0 +1,132 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::{Sender, TeePolicy, TeeSender};
+use crate::tests::mock::{expect_eventual_disconnect, MockServer, MockSink};
+use crate::tests::{TestError, TestResult};
+use crate::ErrorCode;
+
+/// Two senders to live servers, the second of which dropped the connection.
+fn one_dropped(policy: TeePolicy) -> Result<(MockServer, TeeSender), TestError> {
+    let mut live = MockServer::new()?;
+    let mut dropping = MockServer::new()?;
+    let live_sender = live.lsb_tcp().build()?;
+    live.accept()?;
+    let mut dropping_sender = dropping.lsb_tcp().build()?;
+    dropping.accept()?;
+    dropping.close_client();
+    expect_eventual_disconnect(&mut dropping_sender);
+
+    let tee = TeeSender::new(vec![live_sender, dropping_sender], policy)?;
+    Ok((live, tee))
+}
+
+#[test]
+fn test_tee_no_destinations() {
+    let err = TeeSender::new(Vec::new(), TeePolicy::RequireAll).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    assert_eq!(err.msg(), "A tee sender needs at least one destination.");
+}
+
+#[test]
+fn test_tee_writes_to_all() -> TestResult {
+    let sink1 = MockSink::new();
+    let sink2 = MockSink::new();
+    let mut tee = TeeSender::new(
+        vec![
+            Sender::from_sink(sink1.clone()),
+            Sender::from_sink(sink2.clone()),
+        ],
+        TeePolicy::RequireAll,
+    )?;
+
+    tee.buffer().table("test")?.symbol("t1", "v1")?.at_now()?;
+    let results = tee.flush()?;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert!(tee.buffer().is_empty());
+    assert_eq!(sink1.written_str(), "test,t1=v1\n");
+    assert_eq!(sink2.written_str(), "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_tee_best_effort() -> TestResult {
+    let (mut live, mut tee) = one_dropped(TeePolicy::BestEffort)?;
+
+    tee.buffer().table("test")?.symbol("t1", "v1")?.at_now()?;
+    let results = tee.flush()?;
+    assert!(results[0].is_ok());
+    assert_eq!(
+        results[1].as_ref().unwrap_err().code(),
+        ErrorCode::SocketError
+    );
+    assert!(tee.buffer().is_empty());
+    assert_eq!(live.recv_q()?, 1);
+    assert_eq!(live.msgs[0], "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_tee_require_all() -> TestResult {
+    let (mut live, mut tee) = one_dropped(TeePolicy::RequireAll)?;
+
+    tee.buffer().table("test")?.symbol("t1", "v1")?.at_now()?;
+    let err = tee.flush().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        format!(
+            "Could not flush to 1 of 2 destinations: {:?}: \
+            Could not flush buffer: not connected to database.",
+            tee.senders()[1]
+        )
+    );
+
+    // The buffer is intact, and the live destination still got the rows.
+    assert_eq!(tee.buffer().as_str(), "test,t1=v1\n");
+    assert_eq!(live.recv_q()?, 1);
+    assert_eq!(live.msgs[0], "test,t1=v1\n");
+    Ok(())
+}
+
+#[test]
+fn test_tee_fail_fast() -> TestResult {
+    let failing = MockSink::new().with_fail_after(0, std::io::ErrorKind::BrokenPipe);
+    let skipped = MockSink::new();
+    let mut tee = TeeSender::new(
+        vec![
+            Sender::from_sink(failing),
+            Sender::from_sink(skipped.clone()),
+        ],
+        TeePolicy::FailFast,
+    )?;
+
+    tee.buffer().table("test")?.symbol("t1", "v1")?.at_now()?;
+    let err = tee.flush().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(tee.buffer().as_str(), "test,t1=v1\n");
+    assert_eq!(skipped.write_calls(), 0);
+    Ok(())
+}
@@ -0,0 +1,496 @@
+/*******************************************************************************
+ *     ___                  _   ____  ____
+ *    / _ \ _   _  ___  ___| |_|  _ \| __ )
+ *   | | | | | | |/ _ \/ __| __| | | |  _ \
+ *   | |_| | |_| |  __/\__ \ |_| |_| | |_) |
+ *    \__\_\\__,_|\___||___/\__|____/|____/
+ *
+ *  Copyright (c) 2014-2019 Appsicle
+ *  Copyright (c) 2019-2024 QuestDB
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+use crate::ingress::testing::InMemorySink;
+use crate::ingress::{
+    Buffer, Deadline, DoubleBufferedSender, OverflowPolicy, Sender, SenderWorker, ShutdownReport,
+    TimestampNanos, WorkerConfig, WorkerHandle,
+};
+use crate::tests::mock::{MockServer, MockSink};
+use crate::tests::TestResult;
+use crate::ErrorCode;
+
+use std::io;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// A sink whose writes block until the gate is opened, announcing each write.
+#[derive(Clone)]
+struct GatedSink {
+    sink: InMemorySink,
+    open: Arc<(Mutex<bool>, Condvar)>,
+    writes: mpsc::Sender<()>,
+}
+
+impl GatedSink {
+    fn new() -> (Self, mpsc::Receiver<()>) {
+        let (writes, rx) = mpsc::channel();
+        let sink = Self {
+            sink: InMemorySink::new(),
+            open: Arc::new((Mutex::new(false), Condvar::new())),
+            writes,
+        };
+        (sink, rx)
+    }
+
+    fn open(&self) {
+        *self.open.0.lock().unwrap() = true;
+        self.open.1.notify_all();
+    }
+}
+
+impl io::Write for GatedSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _ = self.writes.send(());
+        let (lock, cvar) = &*self.open;
+        let _open = cvar
+            .wait_while(lock.lock().unwrap(), |open| !*open)
+            .unwrap();
+        self.sink.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn one_row(handle: &WorkerHandle, value: i64) -> crate::Result<Buffer> {
+    let mut buffer = handle.new_buffer();
+    buffer
+        .table("test")?
+        .column_i64("c1", value)?
+        .at(TimestampNanos::new(1))?;
+    Ok(buffer)
+}
+
+#[test]
+fn test_worker_auto_flush() -> TestResult {
+    let mut server = MockServer::new()?;
+    let builder = server.lsb_tcp().auto_flush_rows(3)?;
+    let worker = SenderWorker::spawn(&builder, WorkerConfig::new())?;
+    server.accept()?;
+    let handle = worker.handle();
+
+    // The third row crosses the threshold.
+    for value in 0..3 {
+        handle.send(one_row(&handle, value)?)?;
+    }
+    let mut received = 0;
+    while received < 3 {
+        received += server.recv_q()?;
+    }
+    assert_eq!(server.msgs[2].as_str(), "test c1=2i 1\n");
+
+    // A single row waits for an explicit flush.
+    handle.send(one_row(&handle, 3)?)?;
+    handle.flush_sync()?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[3].as_str(), "test c1=3i 1\n");
+    worker.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn test_worker_backpressure() -> TestResult {
+    let (sink, writes) = GatedSink::new();
+    let sender = Sender::from_sink(sink.clone());
+    let worker = SenderWorker::from_sender(sender, WorkerConfig::new().queue_capacity(1))?;
+    let handle = worker.handle();
+
+    // The worker blocks writing the first buffer, the second fills the queue.
+    handle.send(one_row(&handle, 0)?)?;
+    writes.recv()?;
+    assert!(handle.try_send(one_row(&handle, 1)?)?.is_none());
+    let buffer = handle.try_send(one_row(&handle, 2)?)?;
+    let buffer = buffer.expect("the queue should be full");
+    assert_eq!(buffer.row_count(), 1);
+
+    sink.open();
+    handle.send(buffer)?;
+    handle.flush_sync()?;
+    assert_eq!(
+        sink.sink.contents_str(),
+        "test c1=0i 1\ntest c1=1i 1\ntest c1=2i 1\n"
+    );
+    worker.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn test_worker_shutdown_drains_queue() -> TestResult {
+    let sink = InMemorySink::new();
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink.clone()), WorkerConfig::new())?;
+    let handle = worker.handle();
+    for value in 0..5 {
+        handle.send(one_row(&handle, value)?)?;
+    }
+    worker.shutdown()?;
+    assert_eq!(sink.contents_str().lines().count(), 5);
+
+    let err = handle.send(one_row(&handle, 5)?).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    assert_eq!(err.msg(), "The sender worker was shut down.");
+    Ok(())
+}
+
+#[test]
+fn test_worker_shutdown_timeout() -> TestResult {
+    let (sink, writes) = GatedSink::new();
+    let config = WorkerConfig::new().shutdown_timeout(Duration::ZERO);
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink.clone()), config)?;
+    let handle = worker.handle();
+    handle.send(one_row(&handle, 0)?)?;
+    writes.recv()?;
+    handle.send(one_row(&handle, 1)?)?;
+
+    let opener = std::thread::spawn({
+        let sink = sink.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(50));
+            sink.open();
+        }
+    });
+    let err = worker.shutdown().unwrap_err();
+    opener.join().unwrap();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush all rows before the shutdown timeout: Dropped 1 rows."
+    );
+    assert_eq!(sink.sink.contents_str(), "test c1=0i 1\n");
+    Ok(())
+}
+
+#[test]
+fn test_worker_flush_error() -> TestResult {
+    let sink = MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe);
+    let config = WorkerConfig::new().reconnect_attempts(0);
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink), config)?;
+    let handle = worker.handle();
+    handle.send(one_row(&handle, 0)?)?;
+    let err = handle.flush_sync().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error"
+    );
+
+    // The error is reported once.
+    handle.flush_sync()?;
+    worker.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn test_worker_rejects_incomplete_row() -> TestResult {
+    let worker = SenderWorker::from_sender(Sender::from_sink(Vec::new()), WorkerConfig::new())?;
+    let handle = worker.handle();
+    let mut buffer = handle.new_buffer();
+    buffer.table("test")?.column_i64("c1", 0)?;
+    let err = handle.send(buffer).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+
+    let err = SenderWorker::from_sender(
+        Sender::from_sink(Vec::new()),
+        WorkerConfig::new().queue_capacity(0),
+    )
+    .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ConfigError);
+    Ok(())
+}
+
+#[test]
+fn test_worker_handle_shutdown() -> TestResult {
+    let sink = InMemorySink::new();
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink.clone()), WorkerConfig::new())?;
+    let handle = worker.handle();
+    for value in 0..5 {
+        handle.send(one_row(&handle, value)?)?;
+    }
+    let report = handle.shutdown(Deadline::after(Duration::from_secs(10)));
+    assert_eq!(
+        report,
+        ShutdownReport {
+            rows_flushed: 5,
+            rows_dropped: 0,
+            rows_spilled: 0,
+            last_error: None,
+        }
+    );
+    assert_eq!(sink.contents_str().lines().count(), 5);
+
+    let err = handle.send(one_row(&handle, 5)?).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidApiCall);
+    worker.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn test_worker_handle_shutdown_deadline() -> TestResult {
+    let (sink, writes) = GatedSink::new();
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink.clone()), WorkerConfig::new())?;
+    let handle = worker.handle();
+    handle.send(one_row(&handle, 0)?)?;
+    writes.recv()?;
+    handle.send(one_row(&handle, 1)?)?;
+    handle.send(one_row(&handle, 2)?)?;
+
+    // The worker is stuck writing, as if the server stopped reading.
+    let start = Instant::now();
+    let report = handle.shutdown(Deadline::after(Duration::from_millis(100)));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_secs(5));
+    assert_eq!(report.rows_flushed, 0);
+    assert_eq!(report.rows_dropped, 3);
+
+    // Once unblocked, the worker drops the rows queued past the deadline.
+    sink.open();
+    let err = worker.shutdown().unwrap_err();
+    assert_eq!(
+        err.msg(),
+        "Could not flush all rows before the shutdown timeout: Dropped 2 rows."
+    );
+    assert_eq!(sink.sink.contents_str(), "test c1=0i 1\n");
+    Ok(())
+}
+
+#[test]
+fn test_worker_concurrent_shutdown() -> TestResult {
+    let sink = InMemorySink::new();
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink.clone()), WorkerConfig::new())?;
+    let handles = [worker.handle(), worker.handle()];
+    for (value, handle) in handles.iter().enumerate() {
+        handle.send(one_row(handle, value as i64)?)?;
+    }
+    let reports = std::thread::scope(|scope| {
+        let threads = handles.map(|handle| {
+            scope.spawn(move || handle.shutdown(Deadline::after(Duration::from_secs(10))))
+        });
+        threads.map(|thread| thread.join().unwrap())
+    });
+    for report in reports {
+        assert_eq!(report.rows_flushed, 2);
+        assert_eq!(report.rows_dropped, 0);
+    }
+    assert_eq!(sink.contents_str().lines().count(), 2);
+    worker.shutdown()?;
+    Ok(())
+}
+
+/// Stall the worker writing row 0 and fill its queue of 2 with rows 1 and 2.
+fn stalled_worker(
+    policy: OverflowPolicy,
+) -> crate::Result<(GatedSink, SenderWorker, WorkerHandle)> {
+    let (sink, writes) = GatedSink::new();
+    let config = WorkerConfig::new()
+        .queue_capacity(2)
+        .overflow_policy(policy);
+    let worker = SenderWorker::from_sender(Sender::from_sink(sink.clone()), config)?;
+    let handle = worker.handle();
+    handle.send(one_row(&handle, 0)?)?;
+    writes.recv().unwrap();
+    handle.send(one_row(&handle, 1)?)?;
+    handle.send(one_row(&handle, 2)?)?;
+    Ok((sink, worker, handle))
+}
+
+#[test]
+fn test_worker_overflow_policy() -> TestResult {
+    let cases = [
+        (OverflowPolicy::DropNewest, None, [0, 1, 2]),
+        (OverflowPolicy::DropOldest, None, [0, 2, 3]),
+        (OverflowPolicy::Error, Some(ErrorCode::QueueFull), [0, 1, 2]),
+    ];
+    for (policy, error, survivors) in cases {
+        let (sink, worker, handle) = stalled_worker(policy)?;
+        let result = handle.send(one_row(&handle, 3)?);
+        assert_eq!(result.err().map(|err| err.code()), error, "{policy:?}");
+
+        sink.open();
+        handle.flush_sync()?;
+        let expected: String = survivors
+            .iter()
+            .map(|value| format!("test c1={value}i 1\n"))
+            .collect();
+        assert_eq!(sink.sink.contents_str(), expected, "{policy:?}");
+        let report = handle.shutdown(Deadline::after(Duration::from_secs(10)));
+        let dropped = if error.is_some() { 0 } else { 1 };
+        assert_eq!(report.rows_flushed, 3, "{policy:?}");
+        assert_eq!(report.rows_dropped, dropped, "{policy:?}");
+        worker.shutdown()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_worker_overflow_policy_block() -> TestResult {
+    let (sink, worker, handle) = stalled_worker(OverflowPolicy::Block)?;
+    assert!(handle.try_send(one_row(&handle, 3)?)?.is_some());
+    let blocked = std::thread::spawn({
+        let handle = handle.clone();
+        move || handle.send(one_row(&handle, 3)?)
+    });
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!blocked.is_finished());
+
+    sink.open();
+    blocked.join().unwrap()?;
+    handle.flush_sync()?;
+    assert_eq!(
+        sink.sink.contents_str(),
+        "test c1=0i 1\ntest c1=1i 1\ntest c1=2i 1\ntest c1=3i 1\n"
+    );
+    worker.shutdown()?;
+    Ok(())
+}
+
+fn add_row(sender: &mut DoubleBufferedSender, value: i64) -> crate::Result<()> {
+    sender.buffer().table("test")?.column_i64("c1", value)?;
+    sender.at(TimestampNanos::new(1))
+}
+
+#[test]
+fn test_double_buffered_builds_during_flush() -> TestResult {
+    let (sink, writes) = GatedSink::new();
+    let mut sender = DoubleBufferedSender::from_sender(Sender::from_sink(sink.clone()))?;
+
+    // The flush thread blocks writing the first batch, while the rows of the
+    // second one are built.
+    add_row(&mut sender, 0)?;
+    sender.flush()?;
+    writes.recv()?;
+    for value in 1..4 {
+        add_row(&mut sender, value)?;
+    }
+    assert_eq!(sender.buffer().row_count(), 3);
+    assert!(sink.sink.contents().is_empty());
+
+    // With both buffers full, the next flush waits for the first batch.
+    let opener = {
+        let sink = sink.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            sink.open();
+        })
+    };
+    let start = Instant::now();
+    sender.flush()?;
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    opener.join().unwrap();
+
+    add_row(&mut sender, 4)?;
+    sender.shutdown()?;
+    let expected: String = (0..5)
+        .map(|value| format!("test c1={value}i 1\n"))
+        .collect();
+    assert_eq!(sink.sink.contents_str(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_double_buffered_keeps_order() -> TestResult {
+    let mut server = MockServer::new()?;
+    let builder = server.lsb_tcp().auto_flush_rows(7)?;
+    let mut sender = DoubleBufferedSender::spawn(&builder)?;
+    server.accept()?;
+    let reader = std::thread::spawn(move || -> io::Result<Vec<String>> {
+        let mut received = 0;
+        while received < 100 {
+            // A slow server.
+            std::thread::sleep(Duration::from_millis(1));
+            received += server.recv_q()?;
+        }
+        Ok(server.msgs)
+    });
+
+    for value in 0..100 {
+        add_row(&mut sender, value)?;
+    }
+    sender.flush_sync()?;
+    let msgs = reader.join().unwrap()?;
+    let expected: Vec<String> = (0..100)
+        .map(|value| format!("test c1={value}i 1\n"))
+        .collect();
+    assert_eq!(msgs, expected);
+    sender.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn test_double_buffered_reconnects() -> TestResult {
+    let mut server = MockServer::new()?;
+    let mut sender = DoubleBufferedSender::spawn(&server.lsb_tcp())?;
+    server.accept()?;
+
+    // Kill the connection and keep flushing until the sender notices.
+    server.close_client();
+    let mut failed = false;
+    for value in 0..1000 {
+        sender
+            .buffer()
+            .table("test")?
+            .column_i64("c1", value)?
+            .at(TimestampNanos::new(1))?;
+        if sender.flush_sync().is_err() {
+            failed = true;
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(failed);
+
+    // The next batch goes over a new connection.
+    add_row(&mut sender, -1)?;
+    sender.flush_sync()?;
+    server.accept()?;
+    assert_eq!(server.recv_q()?, 1);
+    assert_eq!(server.msgs[0], "test c1=-1i 1\n");
+    sender.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn test_double_buffered_flush_error() -> TestResult {
+    let sink = MockSink::new().with_fail_after(0, io::ErrorKind::BrokenPipe);
+    let mut sender = DoubleBufferedSender::from_sender(Sender::from_sink(sink))?;
+    add_row(&mut sender, 0)?;
+    sender.flush()?;
+
+    // The failed batch is reported by the next flush, which keeps its rows.
+    add_row(&mut sender, 1)?;
+    let err = sender.flush().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SocketError);
+    assert_eq!(
+        err.msg(),
+        "Could not flush buffer: injected mock sink error"
+    );
+    assert_eq!(sender.buffer().row_count(), 1);
+
+    // The error is reported once.
+    sender.buffer().clear();
+    sender.flush_sync()?;
+    Ok(())
+}
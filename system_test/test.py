@@ -402,6 +402,29 @@ class TestSender(unittest.TestCase):
         scrubbed_dataset = [row[:-1] for row in resp['dataset']]
         self.assertEqual(scrubbed_dataset, exp_dataset)
 
+    def test_escaped_chars(self):
+        table_name = uuid.uuid4().hex
+        value = 'a b,c=d\te"f\\g\nh'
+        pending = None
+        with self._mk_linesender() as sender:
+            (sender
+                .table(table_name)
+                .symbol('sym', value)
+                .column('str', value)
+                .at_now())
+            pending = sender.buffer.peek()
+
+        resp = retry_check_table(table_name, log_ctx=pending)
+        exp_columns = [
+            {'name': 'sym', 'type': 'SYMBOL'},
+            {'name': 'str', 'type': 'STRING'},
+            {'name': 'timestamp', 'type': 'TIMESTAMP'}]
+        self.assertEqual(resp['columns'], exp_columns)
+
+        exp_dataset = [[value, value]]  # Comparison excludes timestamp column.
+        scrubbed_dataset = [row[:-1] for row in resp['dataset']]
+        self.assertEqual(scrubbed_dataset, exp_dataset)
+
     def test_floats(self):
         if QDB_FIXTURE.version <= (6, 1, 2):
             self.skipTest('Float issues support')